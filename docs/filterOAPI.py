@@ -9,6 +9,9 @@ whitelist_operation_ids = [
     "logout", # GET /logout
     # Account 
     "getAccount", # GET /account
+    # Transactions & Refills
+    "getCurrentAccountTransactions", # GET /account/transactions
+    "getSelfRefills", # GET /account/refills
     # Categories & Items
     "getCategories", # GET /categories
     "getCategory", # GET /categories/{categoryId}
@@ -36,7 +39,14 @@ whitelist_schemas = [
     "ItemPrices",
     "MenuItem",
     "MenuCategory",
-    "Fournisseur"
+    "Fournisseur",
+    # Transactions & Refills
+    "Transaction",
+    "TransactionItem",
+    "TransactionState",
+    "Refill",
+    "RefillState",
+    "RefillType"
 ]
 
 # Whitelist of security schemes to retain in the OpenAPI spec
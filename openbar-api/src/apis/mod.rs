@@ -117,6 +117,8 @@ pub mod accounts_api;
 pub mod auth_api;
 pub mod categories_api;
 pub mod items_api;
+pub mod refills_api;
+pub mod transactions_api;
 
 pub mod configuration;
 
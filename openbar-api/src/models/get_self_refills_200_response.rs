@@ -0,0 +1,40 @@
+/*
+ * OpenAPI - Bar
+ *
+ * No description provided (generated by Openapi Generator https://github.com/openapitools/openapi-generator)
+ *
+ * The version of the OpenAPI document: 1.0.11
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+use crate::models;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GetSelfRefills200Response {
+    #[serde(rename = "refills")]
+    pub refills: Vec<models::Refill>,
+    #[serde(rename = "page")]
+    pub page: i64,
+    #[serde(rename = "limit")]
+    pub limit: i64,
+    #[serde(rename = "max_page")]
+    pub max_page: i64,
+}
+
+impl GetSelfRefills200Response {
+    pub fn new(
+        refills: Vec<models::Refill>,
+        page: i64,
+        limit: i64,
+        max_page: i64,
+    ) -> GetSelfRefills200Response {
+        GetSelfRefills200Response {
+            refills,
+            page,
+            limit,
+            max_page,
+        }
+    }
+}
@@ -22,6 +22,10 @@ pub mod fournisseur;
 pub use self::fournisseur::Fournisseur;
 pub mod get_category_items_200_response;
 pub use self::get_category_items_200_response::GetCategoryItems200Response;
+pub mod get_current_account_transactions_200_response;
+pub use self::get_current_account_transactions_200_response::GetCurrentAccountTransactions200Response;
+pub mod get_self_refills_200_response;
+pub use self::get_self_refills_200_response::GetSelfRefills200Response;
 pub mod http_error;
 pub use self::http_error::HttpError;
 pub mod item;
@@ -36,3 +40,15 @@ pub mod menu_item;
 pub use self::menu_item::MenuItem;
 pub mod messages;
 pub use self::messages::Messages;
+pub mod refill;
+pub use self::refill::Refill;
+pub mod refill_state;
+pub use self::refill_state::RefillState;
+pub mod refill_type;
+pub use self::refill_type::RefillType;
+pub mod transaction;
+pub use self::transaction::Transaction;
+pub mod transaction_item;
+pub use self::transaction_item::TransactionItem;
+pub mod transaction_state;
+pub use self::transaction_state::TransactionState;
@@ -0,0 +1,57 @@
+/*
+ * OpenAPI - Bar
+ *
+ * No description provided (generated by Openapi Generator https://github.com/openapitools/openapi-generator)
+ *
+ * The version of the OpenAPI document: 1.0.11
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+use crate::models;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Refill {
+    #[serde(rename = "id")]
+    pub id: uuid::Uuid,
+    #[serde(rename = "account_id")]
+    pub account_id: uuid::Uuid,
+    #[serde(rename = "account_name", skip_serializing_if = "Option::is_none")]
+    pub account_name: Option<String>,
+    /// Amount of the refill, in cents
+    #[serde(rename = "amount")]
+    pub amount: i64,
+    #[serde(rename = "state")]
+    pub state: models::RefillState,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<models::RefillType>,
+    #[serde(rename = "issued_at")]
+    pub issued_at: i64,
+    #[serde(rename = "issued_by", skip_serializing_if = "Option::is_none")]
+    pub issued_by: Option<uuid::Uuid>,
+    #[serde(rename = "issued_by_name", skip_serializing_if = "Option::is_none")]
+    pub issued_by_name: Option<String>,
+}
+
+impl Refill {
+    pub fn new(
+        id: uuid::Uuid,
+        account_id: uuid::Uuid,
+        amount: i64,
+        state: models::RefillState,
+        issued_at: i64,
+    ) -> Refill {
+        Refill {
+            id,
+            account_id,
+            account_name: None,
+            amount,
+            state,
+            r#type: None,
+            issued_at,
+            issued_by: None,
+            issued_by_name: None,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+/*
+ * OpenAPI - Bar
+ *
+ * No description provided (generated by Openapi Generator https://github.com/openapitools/openapi-generator)
+ *
+ * The version of the OpenAPI document: 1.0.11
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+use crate::models;
+use serde::{Deserialize, Serialize};
+
+/// RefillState : State of the refill
+/// State of the refill
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum RefillState {
+    #[serde(rename = "valid")]
+    RefillValid,
+    #[serde(rename = "canceled")]
+    RefillCanceled,
+}
+
+impl std::fmt::Display for RefillState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::RefillValid => write!(f, "valid"),
+            Self::RefillCanceled => write!(f, "canceled"),
+        }
+    }
+}
+
+impl Default for RefillState {
+    fn default() -> RefillState {
+        Self::RefillValid
+    }
+}
@@ -0,0 +1,46 @@
+/*
+ * OpenAPI - Bar
+ *
+ * No description provided (generated by Openapi Generator https://github.com/openapitools/openapi-generator)
+ *
+ * The version of the OpenAPI document: 1.0.11
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+use crate::models;
+use serde::{Deserialize, Serialize};
+
+/// RefillType : Payment method of the refill
+/// Payment method of the refill
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum RefillType {
+    #[serde(rename = "other")]
+    RefillOther,
+    #[serde(rename = "cash")]
+    RefillCash,
+    #[serde(rename = "card")]
+    RefillCard,
+    #[serde(rename = "transfer")]
+    RefillTransfer,
+    #[serde(rename = "check")]
+    RefillCheck,
+}
+
+impl std::fmt::Display for RefillType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::RefillOther => write!(f, "other"),
+            Self::RefillCash => write!(f, "cash"),
+            Self::RefillCard => write!(f, "card"),
+            Self::RefillTransfer => write!(f, "transfer"),
+            Self::RefillCheck => write!(f, "check"),
+        }
+    }
+}
+
+impl Default for RefillType {
+    fn default() -> RefillType {
+        Self::RefillOther
+    }
+}
@@ -0,0 +1,58 @@
+/*
+ * OpenAPI - Bar
+ *
+ * No description provided (generated by Openapi Generator https://github.com/openapitools/openapi-generator)
+ *
+ * The version of the OpenAPI document: 1.0.11
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+use crate::models;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    #[serde(rename = "id")]
+    pub id: uuid::Uuid,
+    #[serde(rename = "account_id")]
+    pub account_id: uuid::Uuid,
+    #[serde(rename = "account_name", skip_serializing_if = "Option::is_none")]
+    pub account_name: Option<String>,
+    #[serde(rename = "author_id", skip_serializing_if = "Option::is_none")]
+    pub author_id: Option<uuid::Uuid>,
+    #[serde(rename = "author_name", skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(rename = "items")]
+    pub items: Vec<models::TransactionItem>,
+    /// Cost of the transaction, in cents
+    #[serde(rename = "total_cost")]
+    pub total_cost: i64,
+    #[serde(rename = "state")]
+    pub state: models::TransactionState,
+    #[serde(rename = "created_at")]
+    pub created_at: i64,
+}
+
+impl Transaction {
+    pub fn new(
+        id: uuid::Uuid,
+        account_id: uuid::Uuid,
+        items: Vec<models::TransactionItem>,
+        total_cost: i64,
+        state: models::TransactionState,
+        created_at: i64,
+    ) -> Transaction {
+        Transaction {
+            id,
+            account_id,
+            account_name: None,
+            author_id: None,
+            author_name: None,
+            items,
+            total_cost,
+            state,
+            created_at,
+        }
+    }
+}
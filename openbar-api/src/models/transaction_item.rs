@@ -0,0 +1,55 @@
+/*
+ * OpenAPI - Bar
+ *
+ * No description provided (generated by Openapi Generator https://github.com/openapitools/openapi-generator)
+ *
+ * The version of the OpenAPI document: 1.0.11
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+use crate::models;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransactionItem {
+    #[serde(rename = "item_id")]
+    pub item_id: uuid::Uuid,
+    /// Name of the item, when it was bought
+    #[serde(rename = "item_name")]
+    pub item_name: String,
+    #[serde(rename = "picture_uri", skip_serializing_if = "Option::is_none")]
+    pub picture_uri: Option<String>,
+    /// Number of units bought
+    #[serde(rename = "item_amount")]
+    pub item_amount: i64,
+    /// Cost of a unit, in cents
+    #[serde(rename = "unit_cost")]
+    pub unit_cost: i64,
+    /// Cost of all the units, in cents
+    #[serde(rename = "total_cost")]
+    pub total_cost: i64,
+    #[serde(rename = "state")]
+    pub state: models::TransactionState,
+}
+
+impl TransactionItem {
+    pub fn new(
+        item_id: uuid::Uuid,
+        item_name: String,
+        item_amount: i64,
+        unit_cost: i64,
+        total_cost: i64,
+        state: models::TransactionState,
+    ) -> TransactionItem {
+        TransactionItem {
+            item_id,
+            item_name,
+            picture_uri: None,
+            item_amount,
+            unit_cost,
+            total_cost,
+            state,
+        }
+    }
+}
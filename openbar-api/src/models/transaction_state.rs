@@ -0,0 +1,40 @@
+/*
+ * OpenAPI - Bar
+ *
+ * No description provided (generated by Openapi Generator https://github.com/openapitools/openapi-generator)
+ *
+ * The version of the OpenAPI document: 1.0.11
+ *
+ * Generated by: https://openapi-generator.tech
+ */
+
+use crate::models;
+use serde::{Deserialize, Serialize};
+
+/// TransactionState : State of the transaction
+/// State of the transaction
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum TransactionState {
+    #[serde(rename = "started")]
+    TransactionStarted,
+    #[serde(rename = "canceled")]
+    TransactionCanceled,
+    #[serde(rename = "finished")]
+    TransactionFinished,
+}
+
+impl std::fmt::Display for TransactionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::TransactionStarted => write!(f, "started"),
+            Self::TransactionCanceled => write!(f, "canceled"),
+            Self::TransactionFinished => write!(f, "finished"),
+        }
+    }
+}
+
+impl Default for TransactionState {
+    fn default() -> TransactionState {
+        Self::TransactionStarted
+    }
+}
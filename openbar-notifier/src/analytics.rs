@@ -0,0 +1,255 @@
+//! SQLite analytics sink of OpenBar Notifier
+//!
+//! Independently of the item stores, every item event and stock sample can be written into a
+//! normalized SQLite database, so that the consumption history can be queried with SQL:
+//! - `categories (instance, id, name)`;
+//! - `items (instance, id, category_id, name, is_menu)`, the last known name of each item;
+//! - `samples (instance, item_id, at, amount_left, buyable, price, promotion)`, one row each
+//!   time the stock, state, price or promotion of an item changes (it is constant in between);
+//! - `events (id, instance, item_id, at, kind, data)`, every detected event (`data` being the
+//!   event as JSON), whether it was notified or not;
+//! - `reports (instance, month, sent_at)`, the monthly reports sent (see [crate::report]).
+//!
+//! For instance, the units sold per item and day can be estimated with:
+//! ```sql
+//! SELECT item_id, date(at), SUM(sold) FROM (
+//!     SELECT item_id, at, MAX(LAG(amount_left) OVER w - amount_left, 0) AS sold
+//!     FROM samples WINDOW w AS (PARTITION BY instance, item_id ORDER BY at)
+//! ) GROUP BY item_id, date(at);
+//! ```
+//!
+//! The database requires the `analytics` feature, the observations being gathered regardless.
+
+use std::collections::HashMap;
+#[cfg(feature = "analytics")]
+use std::path::Path;
+
+#[cfg(feature = "analytics")]
+use chrono::{DateTime, Utc};
+use openbar_api::models::Item;
+#[cfg(feature = "analytics")]
+use openbar_api::models::ItemState;
+#[cfg(feature = "analytics")]
+use rusqlite::{Connection, params};
+use uuid::Uuid;
+
+use crate::event::ItemEvent;
+
+/// Schema of the analytics database
+#[cfg(feature = "analytics")]
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS categories (
+    instance TEXT NOT NULL,
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (instance, id)
+);
+CREATE TABLE IF NOT EXISTS items (
+    instance TEXT NOT NULL,
+    id TEXT NOT NULL,
+    category_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    is_menu INTEGER NOT NULL,
+    PRIMARY KEY (instance, id)
+);
+CREATE TABLE IF NOT EXISTS samples (
+    instance TEXT NOT NULL,
+    item_id TEXT NOT NULL,
+    at TEXT NOT NULL,
+    amount_left INTEGER NOT NULL,
+    buyable INTEGER NOT NULL,
+    price INTEGER NOT NULL,
+    promotion INTEGER,
+    PRIMARY KEY (instance, item_id, at)
+);
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    instance TEXT NOT NULL,
+    item_id TEXT NOT NULL,
+    at TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_by_item ON events (instance, item_id, at);
+CREATE TABLE IF NOT EXISTS reports (
+    instance TEXT NOT NULL,
+    month TEXT NOT NULL,
+    sent_at TEXT NOT NULL,
+    PRIMARY KEY (instance, month)
+);
+";
+
+/// The observations of a check of an instance, to record in the analytics database
+#[derive(Debug, Default)]
+pub struct Observations {
+    /// Category names, by category ID
+    pub categories: HashMap<Uuid, String>,
+    /// The observed items, along with whether they changed since the previous check
+    pub items: Vec<(Item, bool)>,
+    /// The detected item events
+    pub events: Vec<(Uuid, ItemEvent)>,
+}
+
+/// Analytics database
+#[cfg(feature = "analytics")]
+pub struct AnalyticsDb {
+    conn: Connection,
+}
+
+#[cfg(feature = "analytics")]
+impl AnalyticsDb {
+    /// Open (or create) the analytics database at `path`
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        AnalyticsDb::with_connection(Connection::open(path)?)
+    }
+
+    /// Open an in-memory analytics database (e.g. for tests)
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        AnalyticsDb::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(AnalyticsDb { conn })
+    }
+
+    /// Get the underlying connection, to run queries
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Record the observations of a check of `instance` made at `at`, in a single transaction
+    pub fn record(
+        &mut self,
+        instance: &str,
+        at: DateTime<Utc>,
+        observations: &Observations,
+    ) -> rusqlite::Result<()> {
+        let at = at.to_rfc3339();
+        let tx = self.conn.transaction()?;
+        {
+            let mut category = tx.prepare_cached(
+                "INSERT INTO categories (instance, id, name) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (instance, id) DO UPDATE SET name = excluded.name",
+            )?;
+            for (id, name) in &observations.categories {
+                category.execute(params![instance, id.to_string(), name])?;
+            }
+
+            let mut item = tx.prepare_cached(
+                "INSERT INTO items (instance, id, category_id, name, is_menu) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT (instance, id) DO UPDATE SET
+                     category_id = excluded.category_id, name = excluded.name, is_menu = excluded.is_menu",
+            )?;
+            let mut sample = tx.prepare_cached(
+                "INSERT OR REPLACE INTO samples (instance, item_id, at, amount_left, buyable, price, promotion)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for (observed, changed) in &observations.items {
+                let id = observed.id.to_string();
+                item.execute(params![
+                    instance,
+                    id,
+                    observed.category_id.to_string(),
+                    observed.name,
+                    observed.is_menu
+                ])?;
+                if *changed {
+                    sample.execute(params![
+                        instance,
+                        id,
+                        at,
+                        observed.amount_left,
+                        observed.state == ItemState::ItemBuyable,
+                        observed.prices.ceten,
+                        observed.promotion,
+                    ])?;
+                }
+            }
+
+            let mut event = tx.prepare_cached(
+                "INSERT INTO events (instance, item_id, at, kind, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for (item_id, e) in &observations.events {
+                let data = serde_json::to_string(e).unwrap_or_default();
+                event.execute(params![instance, item_id.to_string(), at, e.kind(), data])?;
+            }
+        }
+        tx.commit()
+    }
+}
+
+/// Check if an item changed in a way worth a new stock sample
+pub fn is_sample_changed(previous: Option<&Item>, fresh: &Item) -> bool {
+    previous.is_none_or(|p| {
+        p.amount_left != fresh.amount_left
+            || p.state != fresh.state
+            || p.prices.ceten != fresh.prices.ceten
+            || p.promotion != fresh.promotion
+    })
+}
+
+#[cfg(all(test, feature = "analytics"))]
+mod tests {
+    use openbar_api::models::ItemPrices;
+
+    use super::*;
+
+    fn item(amount_left: i64) -> Item {
+        Item::new(
+            Uuid::from_u128(1),
+            ItemPrices::new(100, 100, 100, 100, 100, 150),
+            amount_left,
+            10,
+            Uuid::from_u128(0),
+            "Beer".to_string(),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        )
+    }
+
+    #[test]
+    fn records_items_samples_and_events() {
+        let mut db = AnalyticsDb::open_in_memory().unwrap();
+        let first = Observations {
+            categories: HashMap::from([(Uuid::from_u128(0), "Beers".to_string())]),
+            items: vec![(item(5), true)],
+            events: vec![(Uuid::from_u128(1), ItemEvent::Added)],
+        };
+        db.record("bar", Utc::now(), &first).unwrap();
+        let second = Observations {
+            items: vec![(item(0), is_sample_changed(Some(&item(5)), &item(0)))],
+            events: vec![(Uuid::from_u128(1), ItemEvent::OutOfStock)],
+            ..first
+        };
+        db.record("bar", Utc::now() + chrono::Duration::seconds(1), &second)
+            .unwrap();
+        let third = Observations {
+            items: vec![(item(0), is_sample_changed(Some(&item(0)), &item(0)))],
+            events: Vec::new(),
+            ..second
+        };
+        db.record("bar", Utc::now() + chrono::Duration::seconds(2), &third)
+            .unwrap();
+
+        let conn = db.connection();
+        let count = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(count("categories"), 1);
+        assert_eq!(count("items"), 1);
+        assert_eq!(count("samples"), 2);
+        let kinds: Vec<String> = conn
+            .prepare("SELECT kind FROM events ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(kinds, ["added", "out_of_stock"]);
+    }
+}
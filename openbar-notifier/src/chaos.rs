@@ -0,0 +1,135 @@
+//! Failure injection of OpenBar Notifier, for testing
+//!
+//! Hidden development settings (`CHAOS_*`) inject failures and slow responses of the OpenBar
+//! API, and failures of the webhooks, at configurable rates. The draws come from a seeded
+//! generator, so that a scenario can be replayed deterministically (in integration tests or
+//! manual QA) to exercise the error handling, retries and backoffs.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::warn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+
+/// Failure injection configuration (all rates are probabilities, between 0 and 1)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ChaosConfig {
+    /// Rate of the OpenBar API requests failing
+    pub api_failure_rate: f64,
+    /// Rate of the OpenBar API requests being slowed down
+    pub api_slow_rate: f64,
+    /// Delay added to the slowed down requests (in milliseconds)
+    pub api_slow_ms: u64,
+    /// Rate of the webhook requests failing (with an HTTP 500 error)
+    pub webhook_failure_rate: f64,
+    /// Seed of the random generator
+    pub seed: u64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            api_failure_rate: 0.0,
+            api_slow_rate: 0.0,
+            api_slow_ms: 5000,
+            webhook_failure_rate: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl ChaosConfig {
+    /// Check if some failures are injected
+    pub fn is_enabled(&self) -> bool {
+        self.api_failure_rate > 0.0 || self.api_slow_rate > 0.0 || self.webhook_failure_rate > 0.0
+    }
+}
+
+/// Failure injector, shared by the OpenBar clients and the notifiers
+#[derive(Debug)]
+pub struct Chaos {
+    config: ChaosConfig,
+    rng: Mutex<StdRng>,
+}
+
+impl Chaos {
+    /// Create a failure injector, if some failures are injected
+    pub fn new(config: &ChaosConfig) -> Option<Arc<Chaos>> {
+        config.is_enabled().then(|| {
+            warn!("Failure injection enabled: {:?}", config);
+            Arc::new(Chaos {
+                config: config.clone(),
+                rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+            })
+        })
+    }
+
+    /// Get the configuration of the injector
+    pub fn config(&self) -> &ChaosConfig {
+        &self.config
+    }
+
+    /// Draw whether an injection with the given rate happens
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.lock().unwrap().random_bool(rate.min(1.0))
+    }
+
+    /// Inject a delay and/or a failure before an OpenBar API request (e.g. `categories`)
+    pub async fn api_request(&self, request: &str) -> std::io::Result<()> {
+        if self.roll(self.config.api_slow_rate) {
+            warn!(
+                "Injected delay of {}ms before the {} request",
+                self.config.api_slow_ms, request
+            );
+            tokio::time::sleep(Duration::from_millis(self.config.api_slow_ms)).await;
+        }
+        if self.roll(self.config.api_failure_rate) {
+            warn!("Injected failure of the {} request", request);
+            return Err(std::io::Error::other(format!(
+                "injected failure of the {} request",
+                request
+            )));
+        }
+        Ok(())
+    }
+
+    /// Draw whether a webhook request fails
+    pub fn webhook_failure(&self, url: &str) -> bool {
+        let failed = self.roll(self.config.webhook_failure_rate);
+        if failed {
+            warn!("Injected failure of the webhook request to {}", url);
+        }
+        failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn injections_are_deterministic() {
+        let config = ChaosConfig {
+            webhook_failure_rate: 0.5,
+            seed: 42,
+            ..ChaosConfig::default()
+        };
+        let draws = |chaos: Arc<Chaos>| -> Vec<bool> {
+            (0..32).map(|_| chaos.webhook_failure("url")).collect()
+        };
+        let first = draws(Chaos::new(&config).unwrap());
+        assert_eq!(first, draws(Chaos::new(&config).unwrap()));
+        assert!(first.contains(&true) && first.contains(&false));
+
+        assert!(Chaos::new(&ChaosConfig::default()).is_none());
+        let always = Chaos::new(&ChaosConfig {
+            webhook_failure_rate: 1.0,
+            ..ChaosConfig::default()
+        })
+        .unwrap();
+        assert!(always.webhook_failure("url"));
+    }
+}
@@ -0,0 +1,128 @@
+//! Time helpers for OpenBar Notifier
+//!
+//! Time-based features (debounce, cooldowns, retention) compare timestamps persisted
+//! in the store with the current wall-clock time, which may jump when the system clock
+//! is corrected (e.g. NTP on a Raspberry Pi without RTC). These helpers make such
+//! comparisons robust: durations are never negative, and timestamps from the future
+//! are brought back to the present. Durations within a single process should rather be
+//! measured with [std::time::Instant], which is monotonic.
+//!
+//! The current time is read from a [Clock]: the [SystemClock] in production, or a [TestClock]
+//! advanced by hand, so that the time-dependent behavior (cooldowns, debounce, digests, polling
+//! schedule) can be tested without sleeping.
+
+use std::sync::Mutex;
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    /// Current wall-clock time
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Current monotonic time, to measure durations within the process
+    fn instant(&self) -> Instant;
+}
+
+/// Clock of the system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Controllable clock, only moving when told to
+///
+/// Both its wall-clock and monotonic times move forward with [TestClock::advance]; the
+/// wall-clock time alone may also be set (e.g. backwards, like an NTP correction).
+#[derive(Debug)]
+pub struct TestClock {
+    state: Mutex<(DateTime<Utc>, Instant)>,
+}
+
+impl TestClock {
+    /// Create a clock stopped at the given time
+    pub fn new(now: DateTime<Utc>) -> Self {
+        TestClock {
+            state: Mutex::new((now, Instant::now())),
+        }
+    }
+
+    /// Move the clock forward
+    pub fn advance(&self, duration: std::time::Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.0 = after(state.0, duration);
+        state.1 += duration;
+    }
+
+    /// Set the wall-clock time, leaving the monotonic time unchanged
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.state.lock().unwrap().0 = now;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.state.lock().unwrap().0
+    }
+
+    fn instant(&self) -> Instant {
+        self.state.lock().unwrap().1
+    }
+}
+
+/// Duration elapsed between `since` and `now`, clamped to zero if `since` is in the future
+pub fn elapsed(since: DateTime<Utc>, now: DateTime<Utc>) -> Duration {
+    (now - since).max(Duration::zero())
+}
+
+/// Clamp a persisted timestamp to `now` if it is in the future (the clock went backwards)
+pub fn clamp_to_now(timestamp: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+    timestamp.min(now)
+}
+
+/// Timestamp `duration` after `now`, saturating at the maximal representable timestamp
+pub fn after(now: DateTime<Utc>, duration: std::time::Duration) -> DateTime<Utc> {
+    Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| now.checked_add_signed(duration))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_only_moves_when_told() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let clock = TestClock::new(start);
+        let instant = clock.instant();
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.instant(), instant);
+
+        clock.advance(std::time::Duration::from_secs(90));
+        assert_eq!(clock.now(), start + Duration::seconds(90));
+        assert_eq!(
+            clock.instant() - instant,
+            std::time::Duration::from_secs(90)
+        );
+
+        // The wall clock goes backwards, the monotonic one does not
+        clock.set(start - Duration::hours(1));
+        assert_eq!(elapsed(clock.now(), start), Duration::hours(1));
+        assert_eq!(elapsed(start, clock.now()), Duration::zero());
+        assert_eq!(
+            clock.instant() - instant,
+            std::time::Duration::from_secs(90)
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Corrections of the notifications reverted shortly after
+//!
+//! Despite debouncing, an item may flip back to its previous state shortly after being
+//! notified (e.g. back in stock, then sold out again ten minutes later). Within the correction
+//! window (`NOTIFY_CORRECTION_WINDOW_SECS`), such an event is notified as a compact correction
+//! of the earlier message (e.g. "Correction: Chips is out of stock again (10 min later)"),
+//! referencing it by the message ID given by its provider (see [crate::store::MessageRef]).
+//! The targets which did not get the earlier message are notified of a standalone event.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+use crate::event::{EventContext, ItemEvent};
+use crate::i18n;
+use crate::store::MessageRef;
+
+/// Correction of an earlier notification, reverted by an event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Correction {
+    /// Type of the corrected event (e.g. `become_buyable`)
+    pub event: String,
+    /// Time elapsed since the corrected notification, in seconds
+    pub after_secs: i64,
+    /// Identifier of the corrected message on the notified target, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+    /// Identifiers of the corrected messages, by target key
+    #[serde(skip)]
+    pub messages: BTreeMap<String, String>,
+}
+
+impl Correction {
+    /// Get the correction as notified to a target, if the target got the corrected message
+    pub fn for_target(&self, target_key: &str) -> Option<Correction> {
+        let message_id = self.messages.get(target_key)?;
+        Some(Correction {
+            message_id: Some(message_id.clone()),
+            messages: BTreeMap::new(),
+            ..self.clone()
+        })
+    }
+}
+
+/// Get the types of the events reverted by an event (e.g. an item becoming buyable, by its
+/// becoming unbuyable)
+pub fn reverted(event: &ItemEvent) -> &'static [&'static str] {
+    match event {
+        ItemEvent::BecomeBuyable => &["become_unbuyable", "out_of_stock"],
+        ItemEvent::BecomeUnbuyable | ItemEvent::OutOfStock => &["become_buyable"],
+        ItemEvent::PromotionEnded => &["promotion_started"],
+        _ => &[],
+    }
+}
+
+/// Find the correction made by an event at `now`, given the last messages notifying its item
+///
+/// The event corrects the most recent message it reverts, sent within the `window`.
+pub fn find(
+    event: &ItemEvent,
+    messages: &[MessageRef],
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Option<Correction> {
+    let reverted = reverted(event);
+    let recent: Vec<&MessageRef> = messages
+        .iter()
+        .filter(|m| reverted.contains(&m.event.as_str()) && clock::elapsed(m.at, now) <= window)
+        .collect();
+    let last = recent.iter().max_by_key(|m| m.at)?;
+    Some(Correction {
+        event: last.event.clone(),
+        after_secs: clock::elapsed(last.at, now).num_seconds(),
+        message_id: None,
+        messages: recent
+            .iter()
+            .filter(|m| m.event == last.event)
+            .map(|m| (m.target.clone(), m.message_id.clone()))
+            .collect(),
+    })
+}
+
+/// Render the correction made by an event as a single message line (including the trailing
+/// newline), in the given language
+pub fn render(ctx: &EventContext, correction: &Correction, language: &str) -> String {
+    let template = match ctx.event {
+        ItemEvent::BecomeBuyable => "- Correction: {name} is buyable again ({elapsed} later).\n",
+        ItemEvent::BecomeUnbuyable => {
+            "- Correction: {name} is unbuyable again ({elapsed} later).\n"
+        }
+        ItemEvent::OutOfStock => "- Correction: {name} is out of stock again ({elapsed} later).\n",
+        _ => "- Correction: {name} promotion already ended ({elapsed} later).\n",
+    };
+    let elapsed = if correction.after_secs < 3600 {
+        format!("{} min", (correction.after_secs / 60).max(1))
+    } else {
+        format!("{} h", correction.after_secs / 3600)
+    };
+    i18n::translate(language, template)
+        .replace("{name}", &ctx.item.name)
+        .replace("{elapsed}", &elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(target: &str, event: &str, id: &str, minutes_ago: i64) -> MessageRef {
+        MessageRef {
+            target: target.to_string(),
+            event: event.to_string(),
+            message_id: id.to_string(),
+            at: DateTime::UNIX_EPOCH + Duration::hours(1) - Duration::minutes(minutes_ago),
+        }
+    }
+
+    #[test]
+    fn corrects_the_last_reverted_message() {
+        let now = DateTime::UNIX_EPOCH + Duration::hours(1);
+        let window = Duration::minutes(30);
+        let messages = [
+            message("a", "become_buyable", "1", 10),
+            message("b", "become_buyable", "2", 10),
+            message("a", "out_of_stock", "3", 20),
+            message("c", "become_buyable", "4", 45),
+        ];
+
+        let correction = find(&ItemEvent::OutOfStock, &messages, now, window).unwrap();
+        assert_eq!(correction.event, "become_buyable");
+        assert_eq!(correction.after_secs, 600);
+        assert_eq!(
+            correction.for_target("b").unwrap().message_id.as_deref(),
+            Some("2")
+        );
+        // Out of the window
+        assert!(correction.for_target("c").is_none());
+
+        let correction = find(&ItemEvent::BecomeBuyable, &messages, now, window).unwrap();
+        assert_eq!(correction.event, "out_of_stock");
+        assert!(correction.for_target("b").is_none());
+
+        assert!(find(&ItemEvent::LowStock, &messages, now, window).is_none());
+        assert!(find(&ItemEvent::OutOfStock, &messages, now, Duration::minutes(5)).is_none());
+    }
+}
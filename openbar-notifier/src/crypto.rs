@@ -0,0 +1,123 @@
+//! Store encryption at rest for OpenBar Notifier
+//!
+//! The persisted store reveals purchasing patterns, so it can optionally be encrypted.
+//! Encryption is pluggable through the [StoreCipher] trait; [AesGcmCipher] is the
+//! provided implementation (AES-256-GCM with a random nonce per write).
+//!
+//! Encrypted files start with [ENCRYPTED_MAGIC], followed by the nonce and the ciphertext,
+//! so that plaintext stores can still be read (and are encrypted on the next save).
+
+use base64::Engine;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Header of the encrypted files
+pub const ENCRYPTED_MAGIC: &[u8] = b"OBNENC1\n";
+
+/// Errors of the store ciphers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CipherError {
+    /// The key is invalid (wrong length or encoding)
+    InvalidKey,
+    /// The data is not encrypted with the expected format
+    InvalidFormat,
+    /// Encryption failed
+    EncryptionFailed,
+    /// Decryption failed (wrong key or corrupted data)
+    DecryptionFailed,
+}
+
+impl std::fmt::Display for CipherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CipherError::InvalidKey => write!(f, "invalid encryption key"),
+            CipherError::InvalidFormat => write!(f, "invalid encrypted data format"),
+            CipherError::EncryptionFailed => write!(f, "encryption failed"),
+            CipherError::DecryptionFailed => {
+                write!(f, "decryption failed (wrong key or corrupted data)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CipherError {}
+
+/// A cipher used to encrypt the persisted data
+pub trait StoreCipher: Send + Sync {
+    /// Encrypt the data, the result must start with [ENCRYPTED_MAGIC]
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, CipherError>;
+    /// Decrypt data previously encrypted by [StoreCipher::encrypt]
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CipherError>;
+}
+
+/// Check if the data has been encrypted by a [StoreCipher]
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_MAGIC)
+}
+
+/// AES-256-GCM cipher
+pub struct AesGcmCipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl AesGcmCipher {
+    /// Create a cipher from a raw 32-byte key
+    pub fn new(key: &[u8]) -> Result<Self, CipherError> {
+        let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| CipherError::InvalidKey)?;
+        Ok(AesGcmCipher {
+            key: LessSafeKey::new(key),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    /// Create a cipher from a base64-encoded 32-byte key
+    ///
+    /// Such a key can be generated with `openssl rand -base64 32`.
+    pub fn from_base64(key: &str) -> Result<Self, CipherError> {
+        let key = base64::engine::general_purpose::STANDARD
+            .decode(key.trim())
+            .map_err(|_| CipherError::InvalidKey)?;
+        Self::new(&key)
+    }
+}
+
+impl StoreCipher for AesGcmCipher {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, CipherError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| CipherError::EncryptionFailed)?;
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(ENCRYPTED_MAGIC),
+                &mut in_out,
+            )
+            .map_err(|_| CipherError::EncryptionFailed)?;
+        let mut data = Vec::with_capacity(ENCRYPTED_MAGIC.len() + NONCE_LEN + in_out.len());
+        data.extend_from_slice(ENCRYPTED_MAGIC);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&in_out);
+        Ok(data)
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, CipherError> {
+        let data = data
+            .strip_prefix(ENCRYPTED_MAGIC)
+            .ok_or(CipherError::InvalidFormat)?;
+        if data.len() < NONCE_LEN {
+            return Err(CipherError::InvalidFormat);
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let nonce =
+            Nonce::try_assume_unique_for_key(nonce).map_err(|_| CipherError::InvalidFormat)?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(ENCRYPTED_MAGIC), &mut in_out)
+            .map_err(|_| CipherError::DecryptionFailed)?;
+        Ok(plaintext.to_vec())
+    }
+}
@@ -0,0 +1,119 @@
+//! State change debouncing for OpenBar Notifier
+//!
+//! When an item oscillates between two states at every poll (flapping),
+//! notifying every change would spam the targets. Instead, a state change is only
+//! notified once it has persisted for a number of consecutive polls or a duration.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+
+/// Debounce configuration
+///
+/// A state change is notified once it has been observed for `polls` consecutive polls,
+/// or for `secs` seconds, whichever comes first (a zero value disables the criterion).
+/// When both are zero, state changes are notified immediately.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct DebounceConfig {
+    /// Number of consecutive polls a state must be observed before being notified
+    pub polls: u32,
+    /// Duration (in seconds) a state must persist before being notified
+    pub secs: u64,
+}
+
+impl DebounceConfig {
+    /// Check if debouncing is disabled
+    pub fn is_disabled(&self) -> bool {
+        self.polls <= 1 && self.secs == 0
+    }
+}
+
+/// Per-item state tracking, persisted in the store
+///
+/// Generic over the tracked state, e.g. the [crate::lifecycle::Lifecycle] of the items.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateTracking<S> {
+    /// Last notified (or initial) state of the item
+    pub notified: Option<S>,
+    /// State observed at the previous poll
+    pub observed: Option<S>,
+    /// When the current state was first observed
+    pub since: Option<DateTime<Utc>>,
+    /// Number of consecutive polls the current state has been observed
+    pub polls: u32,
+}
+
+impl<S> Default for StateTracking<S> {
+    fn default() -> Self {
+        StateTracking {
+            notified: None,
+            observed: None,
+            since: None,
+            polls: 0,
+        }
+    }
+}
+
+impl<S: Copy + PartialEq> StateTracking<S> {
+    /// Start tracking an item in the given (settled) state
+    pub fn settled(state: S) -> Self {
+        StateTracking {
+            notified: Some(state),
+            observed: Some(state),
+            ..StateTracking::default()
+        }
+    }
+
+    /// Check if a state change is pending, observed but not notified yet
+    pub fn is_pending(&self) -> bool {
+        self.observed.is_some() && self.observed != self.notified
+    }
+
+    /// Bring the tracked timestamps back to `now` if they are in the future
+    pub fn clamp_to_now(&mut self, now: DateTime<Utc>) {
+        self.since = self.since.map(|since| clock::clamp_to_now(since, now));
+    }
+
+    /// Record an observation of the item state, and return the transition to notify, if any
+    /// (from the last notified state to the current one)
+    ///
+    /// `initial` is the state of the item at the previous poll, only used when the item was not
+    /// tracked yet. `current` is the newly observed state.
+    pub fn observe(
+        &mut self,
+        initial: S,
+        current: S,
+        now: DateTime<Utc>,
+        config: &DebounceConfig,
+    ) -> Option<(S, S)> {
+        let notified = *self.notified.get_or_insert(initial);
+        let previous = self.observed.replace(current).unwrap_or(initial);
+        if current != previous || self.since.is_none() {
+            self.since = Some(now);
+            self.polls = 1;
+        } else if current != notified {
+            // Only count the polls while a state change is pending, so that a settled
+            // item is left untouched (and the store is not needlessly rewritten)
+            self.polls = self.polls.saturating_add(1);
+        }
+        if current == notified {
+            // Back to the notified state (or never left it), nothing to notify
+            return None;
+        }
+        let elapsed_secs = self
+            .since
+            .map(|since| clock::elapsed(since, now).num_seconds())
+            .unwrap_or_default();
+        let settled = config.is_disabled()
+            || (config.polls > 0 && self.polls >= config.polls)
+            || (config.secs > 0 && elapsed_secs >= config.secs as i64);
+        if settled {
+            self.notified = Some(current);
+            Some((notified, current))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Delivery audit log for OpenBar Notifier
+//!
+//! The audit log keeps the most recent delivery attempts (in memory), so that they can be
+//! exposed through the daemon HTTP API (e.g. "last successful delivery: 3 minutes ago").
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::notifier::{Delivery, DeliveryResult};
+
+/// Default number of delivery records kept in the audit log
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// A delivery attempt, as recorded in the audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct DeliveryRecord {
+    /// Time of the delivery attempt
+    pub at: DateTime<Utc>,
+    /// Name of the instance the events come from
+    pub instance: String,
+    /// Target of the delivery (with its secrets redacted)
+    pub target: String,
+    /// Index of the message part
+    pub part: usize,
+    /// Number of message parts
+    pub parts: usize,
+    /// Number of events in the message
+    pub events: usize,
+    /// Result of the delivery
+    #[serde(flatten)]
+    pub result: DeliveryResult,
+}
+
+/// In-memory audit log of the recent delivery attempts
+#[derive(Debug)]
+pub struct DeliveryLog {
+    capacity: usize,
+    records: Mutex<VecDeque<DeliveryRecord>>,
+}
+
+impl Default for DeliveryLog {
+    fn default() -> Self {
+        DeliveryLog::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl DeliveryLog {
+    /// Create an audit log keeping at most `capacity` records
+    pub fn new(capacity: usize) -> Self {
+        DeliveryLog {
+            capacity,
+            records: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Record a delivery attempt
+    pub fn record(&self, instance: &str, delivery: &Delivery) {
+        let record = DeliveryRecord {
+            at: Utc::now(),
+            instance: instance.to_string(),
+            target: delivery.target.redacted_url(),
+            part: delivery.part,
+            parts: delivery.parts,
+            events: delivery.events.len(),
+            result: delivery.result.clone(),
+        };
+        let mut records = self.records.lock().unwrap();
+        if records.len() >= self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// Get the delivery records since the given time (all of them if `None`), oldest first
+    pub fn since(&self, since: Option<DateTime<Utc>>) -> Vec<DeliveryRecord> {
+        let records = self.records.lock().unwrap();
+        records
+            .iter()
+            .filter(|r| since.is_none_or(|since| r.at >= since))
+            .cloned()
+            .collect()
+    }
+}
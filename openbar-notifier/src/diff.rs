@@ -0,0 +1,621 @@
+//! Diff engine of OpenBar Notifier
+//!
+//! This module holds the rules deriving the item events from two versions of the catalog:
+//! the items known to the store, and the fresh items returned by the API.
+//!
+//! [diff_items] applies all of them to the items of a category (see [diff_item]), the
+//! availability events being the transitions of the item lifecycle (see [crate::lifecycle]),
+//! debounced by [crate::debounce]. Once all the items are up-to-date, [diff_menus] checks the
+//! availability of the menus.
+
+use std::collections::BTreeSet;
+
+use chrono::{DateTime, Utc};
+use openbar_api::models::{Item, ItemState};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::clock::Clock;
+use crate::debounce::DebounceConfig;
+use crate::event::{ItemEvent, Severity};
+use crate::lifecycle;
+use crate::sales::{self, PredictionConfig};
+use crate::store::{ItemMeta, ItemStore};
+
+/// Rules deriving the events of an item (e.g. given its watch rule, see [crate::watchlist])
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DiffRules {
+    /// Ratio of the stock under which the item is low on stock (zero disables the low stock
+    /// state)
+    pub low_stock_ratio: f64,
+    /// Debounce of the availability transitions
+    pub debounce: DebounceConfig,
+    /// Thresholds of the stock drops
+    pub stock_drop: StockDropConfig,
+    /// Horizon of the predicted stock-outs
+    pub prediction: PredictionConfig,
+    /// Follow the stock changes, every sale being one
+    pub stock_changes: bool,
+}
+
+/// Get the events of an item compared to its previous version (`None` if the item is new)
+///
+/// The availability events are not included (see [lifecycle::observe]).
+pub fn item_changes(previous: Option<&Item>, fresh: &Item) -> Vec<ItemEvent> {
+    let Some(previous) = previous else {
+        return Vec::new();
+    };
+    let mut events = Vec::new();
+    if fresh.is_menu && menu_components(previous) != menu_components(fresh) {
+        events.push(ItemEvent::MenuChanged);
+    }
+    let promotion = |item: &Item| item.promotion.filter(|discount| *discount > 0);
+    match (promotion(previous), promotion(fresh)) {
+        (before, Some(discount)) if before != Some(discount) => {
+            events.push(ItemEvent::PromotionStarted { discount })
+        }
+        (Some(_), None) => events.push(ItemEvent::PromotionEnded),
+        _ => {}
+    }
+    events
+}
+
+/// Get the event of an item projected to run out of stock within the horizon of `prediction`,
+/// at its sales rate (if known)
+///
+/// `warned` tells if the item was already warned about: the warning is only re-armed once the
+/// projection goes beyond twice the horizon (e.g. after a restock), or the stock ran out.
+/// Returns the event, and whether the item is (still) warned about.
+pub fn predicted_stock_out_event(
+    item: &Item,
+    rate_per_day: Option<f64>,
+    prediction: &PredictionConfig,
+    warned: bool,
+) -> (Option<ItemEvent>, bool) {
+    if item.amount_left <= 0 || item.state == ItemState::ItemNotBuyable {
+        return (None, false);
+    }
+    let Some(hours_left) = rate_per_day.and_then(|rate| sales::hours_left(item.amount_left, rate))
+    else {
+        return (None, false);
+    };
+    let horizon = prediction.days * 24.0;
+    match hours_left as f64 {
+        hours if hours <= horizon && !warned => {
+            (Some(ItemEvent::PredictedStockOut { hours_left }), true)
+        }
+        hours => (None, warned && hours <= 2.0 * horizon),
+    }
+}
+
+/// Configuration of the stock drop events
+///
+/// A stock drop is notified when the stock of an item decreased since the previous poll by more
+/// than `percent` percent of its previous stock, or by more than `amount` units (a zero value
+/// disables the criterion).
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct StockDropConfig {
+    /// Minimal drop, as a percentage of the previous stock
+    pub percent: f64,
+    /// Minimal drop, in units
+    pub amount: i64,
+    /// Severity of the stock drops
+    pub severity: Severity,
+}
+
+/// Get the stock drop event of an item compared to its previous version, if the drop exceeds
+/// one of the thresholds
+pub fn stock_dropped_event(
+    previous: Option<&Item>,
+    fresh: &Item,
+    config: &StockDropConfig,
+) -> Option<ItemEvent> {
+    let previous = previous?.amount_left;
+    let dropped = previous - fresh.amount_left.max(0);
+    if dropped <= 0 {
+        return None;
+    }
+    let by_percent =
+        config.percent > 0.0 && dropped as f64 > previous as f64 * config.percent / 100.0;
+    let by_amount = config.amount > 0 && dropped > config.amount;
+    (by_percent || by_amount).then_some(ItemEvent::StockDropped { dropped })
+}
+
+/// Get the stock change event of an item compared to its previous version, if its stock changed
+pub fn stock_changed_event(previous: Option<&Item>, fresh: &Item) -> Option<ItemEvent> {
+    let change = fresh.amount_left - previous?.amount_left;
+    (change != 0).then_some(ItemEvent::StockChanged { change })
+}
+
+/// Get the hash of the items of a category, as returned by the API, checked with the given
+/// low-stock ratio (hex-encoded SHA-256 of their JSON representation)
+///
+/// An unchanged hash means that none of the items changed since the previous check (nor the
+/// low-stock ratio, e.g. during a special event), so that their diff can be skipped.
+pub fn items_hash(items: &[Item], low_stock_ratio: f64) -> String {
+    let json = serde_json::to_vec(&(items, low_stock_ratio)).unwrap_or_default();
+    ring::digest::digest(&ring::digest::SHA256, &json)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Get the components of a menu that are unavailable (unbuyable or out of stock)
+///
+/// Components are looked up with `find`, unknown ones are ignored.
+pub fn unavailable_components<'a>(
+    menu: &Item,
+    find: impl Fn(Uuid) -> Option<&'a Item>,
+) -> Vec<&'a Item> {
+    menu.menu_items
+        .iter()
+        .flatten()
+        .filter_map(|component| find(component.id))
+        .filter(|item| item.state == ItemState::ItemNotBuyable || item.amount_left == 0)
+        .collect()
+}
+
+/// Get the event of a menu with the given unavailable components, if it just became unavailable
+pub fn menu_event(unavailable: &[&Item], was_unavailable: bool) -> Option<ItemEvent> {
+    (!unavailable.is_empty() && !was_unavailable).then(|| ItemEvent::MenuUnavailable {
+        components: unavailable.iter().map(|c| c.name.clone()).collect(),
+    })
+}
+
+/// Get the set of component item IDs of a menu
+pub fn menu_components(item: &Item) -> BTreeSet<Uuid> {
+    item.menu_items.iter().flatten().map(|c| c.id).collect()
+}
+
+/// Derive the events of an item compared to its previous version (`None` if the item is new)
+///
+/// The metadata the events depend on are updated: the lifecycle of the item, its sales and its
+/// stock-out warning.
+pub fn diff_item(
+    meta: &mut ItemMeta,
+    previous: Option<&Item>,
+    fresh: &Item,
+    now: DateTime<Utc>,
+    rules: &DiffRules,
+) -> Vec<ItemEvent> {
+    let mut events = lifecycle::observe(
+        &mut meta.lifecycle,
+        previous,
+        Some(fresh),
+        now,
+        rules.low_stock_ratio,
+        &rules.debounce,
+    );
+    events.extend(item_changes(previous, fresh));
+    meta.sales.observe(now, fresh.amount_left);
+    let (predicted, warned) = predicted_stock_out_event(
+        fresh,
+        meta.sales.rate_per_day(now),
+        &rules.prediction,
+        meta.stock_out_predicted,
+    );
+    meta.stock_out_predicted = warned;
+    events.extend(predicted);
+    events.extend(stock_dropped_event(previous, fresh, &rules.stock_drop));
+    if rules.stock_changes {
+        events.extend(stock_changed_event(previous, fresh));
+    }
+    events
+}
+
+/// Derive the events of the fresh items, compared to the items known to the store, with the
+/// rules of each item
+///
+/// Only the metadata of the items are updated (see [diff_item]), not the items themselves. The
+/// removed items are not detected (the fresh items may be a part of the catalog only), nor the
+/// unavailable menus (see [diff_menus]).
+pub fn diff_items(
+    store: &mut ItemStore,
+    fresh: &[Item],
+    clock: &dyn Clock,
+    rules: impl Fn(&Item) -> DiffRules,
+) -> Vec<(Uuid, ItemEvent)> {
+    let now = clock.now();
+    let mut events = Vec::new();
+    for item in fresh {
+        let previous = store.find(item.id).cloned();
+        let rules = rules(item);
+        let changes = store.update_meta(item.id, |meta| {
+            diff_item(meta, previous.as_ref(), item, now, &rules)
+        });
+        events.extend(changes.into_iter().map(|e| (item.id, e)));
+    }
+    events
+}
+
+/// Derive the events of the menus of the store whose components became unavailable, recording
+/// the availability of every menu
+///
+/// The components are looked up in the store, which should be up-to-date.
+pub fn diff_menus(store: &mut ItemStore) -> Vec<(Uuid, ItemEvent)> {
+    let menus: Vec<(Uuid, bool, Option<ItemEvent>)> = store
+        .iter()
+        .filter(|item| item.is_menu)
+        .map(|menu| {
+            let unavailable = store.unavailable_components(menu);
+            let was_unavailable = store.meta(menu.id).is_some_and(|m| m.menu_unavailable);
+            let event = menu_event(&unavailable, was_unavailable);
+            (menu.id, !unavailable.is_empty(), event)
+        })
+        .collect();
+    let mut events = Vec::new();
+    for (menu_id, unavailable, event) in menus {
+        store.update_meta(menu_id, |meta| meta.menu_unavailable = unavailable);
+        events.extend(event.map(|e| (menu_id, e)));
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{ItemPrices, MenuItem};
+
+    use super::*;
+    use crate::clock::{SystemClock, TestClock};
+
+    fn item(n: u128, amount_left: i64, state: ItemState) -> Item {
+        Item::new(
+            Uuid::from_u128(n),
+            ItemPrices::new(100, 100, 100, 100, 100, 150),
+            amount_left,
+            10,
+            Uuid::from_u128(0),
+            format!("Item {}", n),
+            String::new(),
+            false,
+            state,
+        )
+    }
+
+    fn menu(n: u128, components: &[&Item]) -> Item {
+        let mut menu = item(n, 10, ItemState::ItemBuyable);
+        menu.is_menu = true;
+        menu.menu_items = Some(
+            components
+                .iter()
+                .map(|c| MenuItem::new(c.id, c.name.clone(), String::new(), 1))
+                .collect(),
+        );
+        menu
+    }
+
+    fn store(items: &[Item]) -> ItemStore {
+        let mut store = ItemStore::new();
+        for item in items {
+            store.append(item.clone());
+        }
+        store
+    }
+
+    /// Diff the fresh items as a check does: the items, then the menus once they are stored
+    fn check(store: &mut ItemStore, fresh: &[Item]) -> Vec<(Uuid, ItemEvent)> {
+        let mut events = diff_items(store, fresh, &SystemClock, |_| DiffRules::default());
+        for item in fresh {
+            if store.replace(item.clone()).is_err() {
+                store.append(item.clone());
+            }
+        }
+        events.extend(diff_menus(store));
+        events
+    }
+
+    fn kinds(events: &[(Uuid, ItemEvent)]) -> Vec<(u128, &'static str)> {
+        events
+            .iter()
+            .map(|(id, e)| (id.as_u128(), e.kind()))
+            .collect()
+    }
+
+    #[test]
+    fn new_item_is_added() {
+        let fresh = [item(1, 5, ItemState::ItemBuyable)];
+        let events = check(&mut ItemStore::new(), &fresh);
+        assert_eq!(kinds(&events), [(1, "added")]);
+    }
+
+    #[test]
+    fn new_unbuyable_item_is_only_added() {
+        let fresh = [item(1, 0, ItemState::ItemNotBuyable)];
+        let events = check(&mut ItemStore::new(), &fresh);
+        assert_eq!(kinds(&events), [(1, "added")]);
+    }
+
+    #[test]
+    fn unchanged_item_has_no_event() {
+        let known = item(1, 5, ItemState::ItemBuyable);
+        let mut store = store(std::slice::from_ref(&known));
+        let events = check(&mut store, &[known]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn stock_decrease_has_no_event() {
+        let mut store = store(&[item(1, 5, ItemState::ItemBuyable)]);
+        let events = check(&mut store, &[item(1, 2, ItemState::ItemBuyable)]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn last_unit_sold_is_out_of_stock() {
+        let mut store = store(&[item(1, 5, ItemState::ItemBuyable)]);
+        let events = check(&mut store, &[item(1, 0, ItemState::ItemBuyable)]);
+        assert_eq!(kinds(&events), [(1, "out_of_stock")]);
+    }
+
+    #[test]
+    fn already_out_of_stock_is_not_notified_again() {
+        let mut store = store(&[item(1, 0, ItemState::ItemBuyable)]);
+        let events = check(&mut store, &[item(1, 0, ItemState::ItemBuyable)]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn unbuyable_to_buyable_becomes_buyable() {
+        let mut store = store(&[item(1, 0, ItemState::ItemNotBuyable)]);
+        let events = check(&mut store, &[item(1, 5, ItemState::ItemBuyable)]);
+        assert_eq!(kinds(&events), [(1, "become_buyable")]);
+    }
+
+    #[test]
+    fn buyable_to_unbuyable_becomes_unbuyable() {
+        let mut store = store(&[item(1, 5, ItemState::ItemBuyable)]);
+        let events = check(&mut store, &[item(1, 5, ItemState::ItemNotBuyable)]);
+        assert_eq!(kinds(&events), [(1, "become_unbuyable")]);
+    }
+
+    #[test]
+    fn sold_out_and_unbuyable_at_once() {
+        let mut store = store(&[item(1, 5, ItemState::ItemBuyable)]);
+        let events = check(&mut store, &[item(1, 0, ItemState::ItemNotBuyable)]);
+        assert_eq!(
+            kinds(&events),
+            [(1, "out_of_stock"), (1, "become_unbuyable")]
+        );
+    }
+
+    #[test]
+    fn promotion_start_change_and_end() {
+        let regular = item(1, 5, ItemState::ItemBuyable);
+        let mut promoted = regular.clone();
+        promoted.promotion = Some(3000);
+        let events = check(
+            &mut store(std::slice::from_ref(&regular)),
+            &[promoted.clone()],
+        );
+        assert_eq!(
+            events[..],
+            [(
+                Uuid::from_u128(1),
+                ItemEvent::PromotionStarted { discount: 3000 }
+            )]
+        );
+
+        let mut changed = promoted.clone();
+        changed.promotion = Some(5000);
+        let events = check(&mut store(&[promoted.clone()]), &[changed]);
+        assert_eq!(kinds(&events), [(1, "promotion_started")]);
+
+        let events = check(&mut store(&[promoted.clone()]), &[promoted.clone()]);
+        assert!(events.is_empty());
+
+        let events = check(&mut store(&[promoted]), &[regular]);
+        assert_eq!(kinds(&events), [(1, "promotion_ended")]);
+    }
+
+    #[test]
+    fn zero_promotion_is_no_promotion() {
+        let regular = item(1, 5, ItemState::ItemBuyable);
+        let mut zero = regular.clone();
+        zero.promotion = Some(0);
+        let events = check(&mut store(&[regular]), &[zero]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn predicted_stock_out_is_warned_once() {
+        let prediction = PredictionConfig::default();
+        let low = item(1, 10, ItemState::ItemBuyable);
+        // 10 units at 10/day: 24 hours left
+        let (event, warned) = predicted_stock_out_event(&low, Some(10.0), &prediction, false);
+        assert_eq!(event, Some(ItemEvent::PredictedStockOut { hours_left: 24 }));
+        assert!(warned);
+        let (event, warned) = predicted_stock_out_event(&low, Some(10.0), &prediction, true);
+        assert_eq!((event, warned), (None, true));
+        // Slower sales, still within twice the horizon: not re-armed yet
+        let (event, warned) = predicted_stock_out_event(&low, Some(3.0), &prediction, true);
+        assert_eq!((event, warned), (None, true));
+        // Restocked: re-armed
+        let restocked = item(1, 100, ItemState::ItemBuyable);
+        let (event, warned) = predicted_stock_out_event(&restocked, Some(10.0), &prediction, true);
+        assert_eq!((event, warned), (None, false));
+    }
+
+    #[test]
+    fn no_prediction_without_sales() {
+        let prediction = PredictionConfig::default();
+        let low = item(1, 1, ItemState::ItemBuyable);
+        assert_eq!(
+            predicted_stock_out_event(&low, None, &prediction, false),
+            (None, false)
+        );
+        assert_eq!(
+            predicted_stock_out_event(&low, Some(0.0), &prediction, false),
+            (None, false)
+        );
+        let sold_out = item(1, 0, ItemState::ItemBuyable);
+        assert_eq!(
+            predicted_stock_out_event(&sold_out, Some(10.0), &prediction, true),
+            (None, false)
+        );
+    }
+
+    #[test]
+    fn menu_composition_change() {
+        let a = item(1, 5, ItemState::ItemBuyable);
+        let b = item(2, 5, ItemState::ItemBuyable);
+        let mut store = store(&[a.clone(), b.clone(), menu(10, &[&a])]);
+        let events = check(&mut store, &[a.clone(), b.clone(), menu(10, &[&a, &b])]);
+        assert_eq!(kinds(&events), [(10, "menu_changed")]);
+    }
+
+    #[test]
+    fn menu_with_same_components_is_unchanged() {
+        let a = item(1, 5, ItemState::ItemBuyable);
+        let mut store = store(&[a.clone(), menu(10, &[&a])]);
+        let events = check(&mut store, &[a.clone(), menu(10, &[&a])]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn menu_unavailable_when_component_unbuyable() {
+        let a = item(1, 5, ItemState::ItemBuyable);
+        let mut store = store(&[a.clone(), menu(10, &[&a])]);
+        let unbuyable = item(1, 5, ItemState::ItemNotBuyable);
+        let events = check(&mut store, &[unbuyable, menu(10, &[&a])]);
+        assert_eq!(
+            kinds(&events),
+            [(1, "become_unbuyable"), (10, "menu_unavailable")]
+        );
+        let (_, event) = &events[1];
+        assert_eq!(
+            *event,
+            ItemEvent::MenuUnavailable {
+                components: vec!["Item 1".to_string()]
+            }
+        );
+    }
+
+    #[test]
+    fn menu_unavailable_when_component_out_of_stock() {
+        let a = item(1, 5, ItemState::ItemBuyable);
+        let mut store = store(&[a.clone(), menu(10, &[&a])]);
+        let events = check(
+            &mut store,
+            &[item(1, 0, ItemState::ItemBuyable), menu(10, &[&a])],
+        );
+        assert_eq!(
+            kinds(&events),
+            [(1, "out_of_stock"), (10, "menu_unavailable")]
+        );
+    }
+
+    #[test]
+    fn menu_already_unavailable_is_not_notified_again() {
+        let a = item(1, 0, ItemState::ItemNotBuyable);
+        let mut store = store(&[a.clone(), menu(10, &[&a])]);
+        store.update_meta(Uuid::from_u128(10), |meta| meta.menu_unavailable = true);
+        let events = check(&mut store, &[a.clone(), menu(10, &[&a])]);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn menu_components_found_in_store() {
+        let a = item(1, 0, ItemState::ItemNotBuyable);
+        let mut store = store(std::slice::from_ref(&a));
+        let events = check(&mut store, &[menu(10, &[&a])]);
+        assert_eq!(kinds(&events), [(10, "added"), (10, "menu_unavailable")]);
+    }
+
+    #[test]
+    fn unknown_menu_components_are_ignored() {
+        let a = item(1, 0, ItemState::ItemNotBuyable);
+        let events = check(&mut ItemStore::new(), &[menu(10, &[&a])]);
+        assert_eq!(kinds(&events), [(10, "added")]);
+    }
+
+    #[test]
+    fn stock_events_follow_the_rules_of_the_items() {
+        let clock = TestClock::new(Utc::now());
+        let rules = |item: &Item| DiffRules {
+            stock_drop: StockDropConfig {
+                amount: 5,
+                ..StockDropConfig::default()
+            },
+            stock_changes: item.id == Uuid::from_u128(1),
+            ..DiffRules::default()
+        };
+        let known = [
+            item(1, 50, ItemState::ItemBuyable),
+            item(2, 50, ItemState::ItemBuyable),
+        ];
+        let mut store = store(&known);
+        let events = diff_items(&mut store, &known, &clock, rules);
+        assert!(events.is_empty());
+
+        // 10 units sold in 12 hours: 48 hours left, within the default horizon
+        clock.advance(std::time::Duration::from_secs(12 * 3600));
+        let fresh = [
+            item(1, 40, ItemState::ItemBuyable),
+            item(2, 48, ItemState::ItemBuyable),
+        ];
+        let events = diff_items(&mut store, &fresh, &clock, rules);
+        assert_eq!(
+            events,
+            [
+                (
+                    Uuid::from_u128(1),
+                    ItemEvent::PredictedStockOut { hours_left: 48 }
+                ),
+                (Uuid::from_u128(1), ItemEvent::StockDropped { dropped: 10 }),
+                (Uuid::from_u128(1), ItemEvent::StockChanged { change: -10 }),
+            ]
+        );
+        assert!(store.meta(Uuid::from_u128(1)).unwrap().stock_out_predicted);
+    }
+
+    #[test]
+    fn sharp_stock_drops_are_detected() {
+        let before = item(1, 50, ItemState::ItemBuyable);
+        let config = StockDropConfig {
+            percent: 30.0,
+            amount: 0,
+            ..StockDropConfig::default()
+        };
+        let event = |amount_left, config: &StockDropConfig| {
+            stock_dropped_event(
+                Some(&before),
+                &item(1, amount_left, ItemState::ItemBuyable),
+                config,
+            )
+        };
+        assert_eq!(
+            event(20, &config),
+            Some(ItemEvent::StockDropped { dropped: 30 })
+        );
+        assert_eq!(event(35, &config), None);
+        assert_eq!(event(60, &config), None);
+        let by_amount = StockDropConfig {
+            amount: 10,
+            ..StockDropConfig::default()
+        };
+        assert_eq!(
+            event(35, &by_amount),
+            Some(ItemEvent::StockDropped { dropped: 15 })
+        );
+        assert_eq!(event(20, &StockDropConfig::default()), None);
+        assert_eq!(
+            stock_dropped_event(None, &before, &config),
+            None,
+            "new items have no previous stock"
+        );
+    }
+
+    #[test]
+    fn stock_changes_are_detected() {
+        let before = item(1, 50, ItemState::ItemBuyable);
+        let change = |amount_left| {
+            stock_changed_event(Some(&before), &item(1, amount_left, ItemState::ItemBuyable))
+        };
+        assert_eq!(change(48), Some(ItemEvent::StockChanged { change: -2 }));
+        assert_eq!(change(60), Some(ItemEvent::StockChanged { change: 10 }));
+        assert_eq!(change(50), None);
+        assert_eq!(stock_changed_event(None, &before), None);
+    }
+}
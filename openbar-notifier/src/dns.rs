@@ -0,0 +1,240 @@
+//! DNS-over-HTTPS resolver of OpenBar Notifier
+//!
+//! On networks with a broken resolver (e.g. a flaky clubroom DNS), the host names of the
+//! instances and targets can be resolved over HTTPS instead (`HTTP_CLIENT_DNS_OVER_HTTPS`),
+//! with the [RFC 8484](https://www.rfc-editor.org/rfc/rfc8484) wire format understood by any
+//! public DoH server (e.g. `https://1.1.1.1/dns-query` or `https://9.9.9.9/dns-query`). The URL
+//! of the server should use an IP address, its own host name being resolved by the system.
+//!
+//! The answers are cached for their TTL, and the system resolver is used as a fallback when the
+//! DoH server cannot be reached.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// Record type of the IPv4 addresses
+const TYPE_A: u16 = 1;
+/// Record type of the IPv6 addresses
+const TYPE_AAAA: u16 = 28;
+/// Longest time an answer is cached, whatever its TTL
+const MAX_TTL: Duration = Duration::from_secs(3600);
+
+/// Resolver of the host names over HTTPS (see the [module documentation](self))
+#[derive(Clone)]
+pub struct DohResolver {
+    inner: Arc<DohInner>,
+}
+
+struct DohInner {
+    /// HTTP client querying the DoH server (resolving with the system)
+    http: reqwest::Client,
+    /// URL of the DoH server
+    url: String,
+    /// Cached addresses, with their expiration, by host name
+    cache: Mutex<HashMap<String, (Instant, Vec<IpAddr>)>>,
+}
+
+impl DohResolver {
+    /// Create a resolver querying the DoH server at `url` with the given HTTP client
+    pub fn new(http: reqwest::Client, url: &str) -> Self {
+        DohResolver {
+            inner: Arc::new(DohInner {
+                http,
+                url: url.to_string(),
+                cache: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Resolve a host name, from the cache if possible
+    async fn lookup(&self, host: &str) -> std::io::Result<Vec<IpAddr>> {
+        if let Some((expires, addrs)) = self.inner.cache.lock().unwrap().get(host)
+            && Instant::now() < *expires
+        {
+            return Ok(addrs.clone());
+        }
+        let (v4, v6) = tokio::join!(self.query(host, TYPE_A), self.query(host, TYPE_AAAA));
+        let mut addrs = Vec::new();
+        let mut ttl = MAX_TTL;
+        for answer in [v4, v6] {
+            match answer {
+                Ok(answer) => {
+                    if !answer.addrs.is_empty() {
+                        ttl = ttl.min(answer.ttl);
+                    }
+                    addrs.extend(answer.addrs);
+                }
+                Err(e) => warn!("Error resolving {} over HTTPS: {}", host, e),
+            }
+        }
+        if addrs.is_empty() {
+            debug!("Resolving {} with the system resolver", host);
+            return Ok(tokio::net::lookup_host((host, 0))
+                .await?
+                .map(|addr| addr.ip())
+                .collect());
+        }
+        self.inner
+            .cache
+            .lock()
+            .unwrap()
+            .insert(host.to_string(), (Instant::now() + ttl, addrs.clone()));
+        Ok(addrs)
+    }
+
+    /// Query the records of a type of a host name
+    async fn query(&self, host: &str, record_type: u16) -> Result<Answer, String> {
+        let response = self
+            .inner
+            .http
+            .post(&self.inner.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/dns-message")
+            .header(reqwest::header::ACCEPT, "application/dns-message")
+            .body(encode_query(host, record_type)?)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?;
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        decode_answer(&body)
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            // The port is set by the HTTP client
+            let addrs: Addrs = Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// Addresses answered by a DoH server
+#[derive(Debug, PartialEq)]
+struct Answer {
+    addrs: Vec<IpAddr>,
+    /// Lowest TTL of the answered records
+    ttl: Duration,
+}
+
+/// Encode a recursive DNS query of the records of a type of a host name
+fn encode_query(host: &str, record_type: u16) -> Result<Vec<u8>, String> {
+    // ID 0 (cache-friendly, see RFC 8484), recursion desired, a single question
+    let mut query = vec![0, 0, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("invalid host name '{}'", host));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    // Class IN
+    query.extend_from_slice(&1u16.to_be_bytes());
+    Ok(query)
+}
+
+/// Decode the A and AAAA records of a DNS response
+fn decode_answer(message: &[u8]) -> Result<Answer, String> {
+    let truncated = || "truncated DNS response".to_string();
+    let u16_at = |pos: usize| {
+        message
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(truncated)
+    };
+    let rcode = u16_at(2)? & 0x000f;
+    if rcode != 0 {
+        return Err(format!("DNS error (rcode {})", rcode));
+    }
+    let questions = u16_at(4)?;
+    let answers = u16_at(6)?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = skip_name(message, pos).ok_or_else(truncated)? + 4;
+    }
+    let mut answer = Answer {
+        addrs: Vec::new(),
+        ttl: MAX_TTL,
+    };
+    for _ in 0..answers {
+        pos = skip_name(message, pos).ok_or_else(truncated)?;
+        let record_type = u16_at(pos)?;
+        let ttl = message
+            .get(pos + 4..pos + 8)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(truncated)?;
+        let len = u16_at(pos + 8)? as usize;
+        let data = message
+            .get(pos + 10..pos + 10 + len)
+            .ok_or_else(truncated)?;
+        let addr = match (record_type, data.len()) {
+            (TYPE_A, 4) => Some(IpAddr::V4(Ipv4Addr::new(
+                data[0], data[1], data[2], data[3],
+            ))),
+            (TYPE_AAAA, 16) => {
+                let octets: [u8; 16] = data.try_into().unwrap();
+                Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            }
+            // e.g. the CNAME records leading to the addresses
+            _ => None,
+        };
+        if let Some(addr) = addr {
+            answer.addrs.push(addr);
+            answer.ttl = answer.ttl.min(Duration::from_secs(ttl as u64));
+        }
+        pos += 10 + len;
+    }
+    Ok(answer)
+}
+
+/// Get the position following a (possibly compressed) domain name
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)? as usize;
+        match len {
+            0 => return Some(pos + 1),
+            // Pointer to an earlier name
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len => pos += 1 + len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_the_answered_addresses() {
+        let mut message = encode_query("openbar.example.org", TYPE_A).unwrap();
+        assert_eq!(&message[12..21], b"\x07openbar\x07");
+        // Response with two answers: a CNAME, then the address of its target
+        message[2] |= 0x80;
+        message[7] = 2;
+        message.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0, 60, 0, 2, 0xc0, 12]);
+        message.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 1, 44, 0, 4, 10, 0, 0, 1]);
+
+        assert_eq!(
+            decode_answer(&message).unwrap(),
+            Answer {
+                addrs: vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))],
+                ttl: Duration::from_secs(300),
+            }
+        );
+
+        assert!(decode_answer(&message[..message.len() - 2]).is_err());
+        // NXDOMAIN
+        message[3] |= 3;
+        assert!(decode_answer(&message).is_err());
+    }
+}
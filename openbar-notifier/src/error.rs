@@ -0,0 +1,127 @@
+//! Errors of OpenBar Notifier
+//!
+//! The failures of the notifier are sorted into classes (see [NotifierError]): the
+//! configuration, the webconfig of an instance, its login, the fetching of its items, its
+//! store and the delivery of its notifications. The embedding applications (and the CLI) can
+//! react to each class, e.g. with a distinct exit code.
+//!
+//! The exit codes of the CLI (see [NotifierError::exit_code]), for cron or systemd to alert on:
+//!
+//! | Code | Failure |
+//! |------|---------|
+//! | 0 | None |
+//! | 1 | Other failure (e.g. the store could not be loaded, a file could not be written) |
+//! | 2 | Invalid configuration (including a malformed webconfig, e.g. a wrong instance URL) |
+//! | 3 | Login failure |
+//! | 4 | OpenBar API (or WebUI) unreachable, or failing |
+//! | 5 | Some notifications could not be delivered |
+
+use crate::config::GlobalConfigLoadError;
+use crate::openbar::LoginError;
+use crate::openbar::webconfig::WebConfigError;
+use crate::runner::CheckOutcome;
+
+/// Errors of OpenBar Notifier, by failure class
+#[derive(Debug, thiserror::Error)]
+pub enum NotifierError {
+    /// The configuration could not be loaded, or a runner could not be created from it
+    #[error("configuration error: {0}")]
+    Config(String),
+    /// The webconfig of an instance could not be retrieved, or is malformed
+    #[error("webconfig error of instance {instance}: {source}")]
+    Webconfig {
+        instance: String,
+        #[source]
+        source: WebConfigError,
+    },
+    /// The login to an instance failed, with every strategy
+    #[error("login error of instance {instance}: {source}")]
+    Auth {
+        instance: String,
+        #[source]
+        source: LoginError,
+    },
+    /// The items of an instance could not be (completely) fetched from its API
+    #[error("fetch error of instance {instance}: {message}")]
+    Fetch { instance: String, message: String },
+    /// The store of an instance could not be locked or loaded
+    #[error("store error of instance {instance}: {message}")]
+    Store { instance: String, message: String },
+    /// Some notifications of an instance could not be delivered to their targets
+    #[error("dispatch error of instance {instance}: {undelivered} message(s) undelivered")]
+    Dispatch {
+        instance: String,
+        undelivered: usize,
+    },
+    /// A file could not be read or written (e.g. the output of an export)
+    #[error("{0}")]
+    Io(String),
+}
+
+impl NotifierError {
+    /// Get the outcome of an instance check failing with the error
+    ///
+    /// A check whose notifications could not all be delivered still succeeded, as far as the
+    /// backoff of the instance is concerned.
+    pub fn outcome(&self) -> CheckOutcome {
+        match self {
+            NotifierError::Config(_) => CheckOutcome::Misconfigured,
+            NotifierError::Webconfig { source, .. } if !source.is_transient() => {
+                CheckOutcome::Misconfigured
+            }
+            NotifierError::Webconfig { .. }
+            | NotifierError::Auth { .. }
+            | NotifierError::Fetch { .. } => CheckOutcome::Failed,
+            NotifierError::Store { .. } | NotifierError::Io(_) => CheckOutcome::Skipped,
+            NotifierError::Dispatch { .. } => CheckOutcome::Ok,
+        }
+    }
+
+    /// Get the exit code of the CLI failing with the error (see the [module documentation](self))
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            NotifierError::Config(_) => 2,
+            NotifierError::Webconfig { source, .. } if !source.is_transient() => 2,
+            NotifierError::Auth { .. } => 3,
+            NotifierError::Webconfig { .. } | NotifierError::Fetch { .. } => 4,
+            NotifierError::Dispatch { .. } => 5,
+            NotifierError::Store { .. } | NotifierError::Io(_) => 1,
+        }
+    }
+}
+
+impl From<GlobalConfigLoadError> for NotifierError {
+    fn from(e: GlobalConfigLoadError) -> Self {
+        NotifierError::Config(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn maps_the_failures_to_exit_codes() {
+        let webconfig = |status| NotifierError::Webconfig {
+            instance: "kfet".to_string(),
+            source: WebConfigError::HttpStatus(status),
+        };
+        assert_eq!(webconfig(StatusCode::NOT_FOUND).exit_code(), 2);
+        assert_eq!(webconfig(StatusCode::BAD_GATEWAY).exit_code(), 4);
+        let auth = NotifierError::Auth {
+            instance: "kfet".to_string(),
+            source: LoginError {
+                attempts: Vec::new(),
+            },
+        };
+        assert_eq!(auth.exit_code(), 3);
+        let dispatch = NotifierError::Dispatch {
+            instance: "kfet".to_string(),
+            undelivered: 2,
+        };
+        assert_eq!(dispatch.exit_code(), 5);
+        assert_eq!(dispatch.outcome(), CheckOutcome::Ok);
+    }
+}
@@ -1,6 +1,16 @@
+use std::collections::BTreeSet;
+
+use openbar_api::models::Item;
+use serde::{Deserialize, Serialize};
+
+use crate::correction::Correction;
+use crate::prices::PriceComparison;
+
 /// ItemEvent types for OpenBar Notifier
 ///
 /// Represents the different types of events that can occur for an item.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum ItemEvent {
     /// The item has been added to the store
     Added,
@@ -10,4 +20,146 @@ pub enum ItemEvent {
     BecomeUnbuyable,
     /// The item is out of stock
     OutOfStock,
+    /// The item is low on stock (see [crate::lifecycle])
+    LowStock,
+    /// The item is no longer listed by the API
+    Removed,
+    /// The component items of a menu have changed
+    MenuChanged,
+    /// A component of a menu became unbuyable (or out of stock), making the menu unavailable
+    MenuUnavailable {
+        /// Names of the unavailable components
+        components: Vec<String>,
+    },
+    /// A promotion started on the item (or its discount changed)
+    PromotionStarted {
+        /// Discount, in hundredths of a percent (e.g. `3000` for -30%)
+        discount: i64,
+    },
+    /// The promotion of the item ended
+    PromotionEnded,
+    /// The item is projected to run out of stock soon, at its current sales rate
+    PredictedStockOut {
+        /// Projected time until the stock runs out, in hours
+        hours_left: i64,
+    },
+    /// The stock of the item dropped sharply since the previous poll (see
+    /// [crate::diff::StockDropConfig])
+    StockDropped {
+        /// Number of units gone since the previous poll
+        dropped: i64,
+    },
+    /// The stock of the item changed since the previous poll (e.g. for the watched items, see
+    /// [crate::watchlist])
+    StockChanged {
+        /// Change of the stock since the previous poll (negative for a decrease)
+        change: i64,
+    },
+}
+
+impl ItemEvent {
+    /// Names of all the event types (see [ItemEvent::kind])
+    pub const KINDS: &'static [&'static str] = &[
+        "added",
+        "become_buyable",
+        "become_unbuyable",
+        "out_of_stock",
+        "low_stock",
+        "removed",
+        "menu_changed",
+        "menu_unavailable",
+        "promotion_started",
+        "promotion_ended",
+        "predicted_stock_out",
+        "stock_dropped",
+        "stock_changed",
+    ];
+
+    /// Get the (stable) name of the event type, as used in serialization
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ItemEvent::Added => "added",
+            ItemEvent::BecomeBuyable => "become_buyable",
+            ItemEvent::BecomeUnbuyable => "become_unbuyable",
+            ItemEvent::OutOfStock => "out_of_stock",
+            ItemEvent::LowStock => "low_stock",
+            ItemEvent::Removed => "removed",
+            ItemEvent::MenuChanged => "menu_changed",
+            ItemEvent::MenuUnavailable { .. } => "menu_unavailable",
+            ItemEvent::PromotionStarted { .. } => "promotion_started",
+            ItemEvent::PromotionEnded => "promotion_ended",
+            ItemEvent::PredictedStockOut { .. } => "predicted_stock_out",
+            ItemEvent::StockDropped { .. } => "stock_dropped",
+            ItemEvent::StockChanged { .. } => "stock_changed",
+        }
+    }
+}
+
+/// Severity of an item event, to route the events by importance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Informative event (e.g. an item added)
+    #[default]
+    Info,
+    /// Event that may need an action (e.g. an item running out of stock)
+    Warning,
+    /// Event that needs an action
+    Critical,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!(
+                "unknown severity '{}' (expected info, warning or critical)",
+                s
+            )),
+        }
+    }
+}
+
+/// An item event, along with the context needed to route and render it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventContext {
+    /// The event
+    pub event: ItemEvent,
+    /// The item, in its state after the event
+    pub item: Item,
+    /// Name of the item category
+    pub category_name: String,
+    /// Tags of the item
+    pub tags: BTreeSet<String>,
+    /// Whether the item belongs to a restricted category (only notified to the targets allowing it)
+    #[serde(default)]
+    pub restricted: bool,
+    /// Estimated sales rate of the item, in units per day (see [crate::sales])
+    #[serde(default)]
+    pub sales_rate: Option<f64>,
+    /// Comparison of the new price of the item to its history, for the promotions (see
+    /// [crate::prices])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<PriceComparison>,
+    /// Severity of the event
+    #[serde(default)]
+    pub severity: Severity,
+    /// Earlier notification reverted by the event, which is notified as its correction (see
+    /// [crate::correction])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correction: Option<Correction>,
 }
@@ -0,0 +1,167 @@
+//! Inventory export of OpenBar Notifier
+//!
+//! The items tracked by the stores can be dumped (`store export`) for importing into
+//! spreadsheets or BI tools: one row per item, with its name, category, member price, stock,
+//! lifecycle state (see [crate::lifecycle]) and when it was last listed by the API.
+
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::lifecycle::Lifecycle;
+use crate::store::ItemStore;
+
+/// Format of an inventory export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    /// Comma-separated values, with a header row
+    #[default]
+    Csv,
+    /// JSON array of rows
+    Json,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!(
+                "unknown export format '{}' (expected csv or json)",
+                s
+            )),
+        }
+    }
+}
+
+/// An exported item
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InventoryRow {
+    /// Name of the instance tracking the item
+    pub instance: String,
+    /// ID of the item
+    pub id: Uuid,
+    /// Name of the item
+    pub name: String,
+    /// Name of the category of the item, if known
+    pub category: Option<String>,
+    /// Member (`ceten`) price of the item, in currency units
+    pub price: f64,
+    /// Stock left
+    pub stock: i64,
+    /// Lifecycle state of the item
+    pub state: Lifecycle,
+    /// When the item was last listed by the API
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// Get the rows of the items tracked by the store of an instance
+pub fn inventory(instance: &str, store: &ItemStore) -> Vec<InventoryRow> {
+    store
+        .iter()
+        .map(|item| {
+            let meta = store.meta(item.id);
+            let settled = meta.and_then(|meta| meta.lifecycle.notified);
+            InventoryRow {
+                instance: instance.to_string(),
+                id: item.id,
+                name: item.name.clone(),
+                category: meta.and_then(|meta| meta.category.clone()),
+                price: item.prices.ceten as f64 / 100.0,
+                stock: item.amount_left,
+                state: settled.unwrap_or_else(|| Lifecycle::of(Some(item), None, 0.0)),
+                last_seen: meta.and_then(|meta| meta.last_seen),
+            }
+        })
+        .collect()
+}
+
+/// Write the rows in the given format
+pub fn write(
+    format: ExportFormat,
+    rows: &[InventoryRow],
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, rows)?;
+            writeln!(output)
+        }
+        ExportFormat::Csv => {
+            writeln!(
+                output,
+                "instance,id,name,category,price,stock,state,last_seen"
+            )?;
+            for row in rows {
+                writeln!(
+                    output,
+                    "{},{},{},{},{:.2},{},{},{}",
+                    csv_field(&row.instance),
+                    row.id,
+                    csv_field(&row.name),
+                    csv_field(row.category.as_deref().unwrap_or_default()),
+                    row.price,
+                    row.stock,
+                    row.state.name(),
+                    row.last_seen.map(|at| at.to_rfc3339()).unwrap_or_default(),
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Quote a CSV field if needed (RFC 4180)
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{Item, ItemPrices, ItemState};
+
+    use super::*;
+
+    #[test]
+    fn exports_csv() {
+        let mut store = ItemStore::new();
+        store.append(Item::new(
+            Uuid::from_u128(1),
+            ItemPrices::new(100, 100, 100, 100, 150, 150),
+            3,
+            10,
+            Uuid::from_u128(0),
+            "Chips \"BBQ\", large".to_string(),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        ));
+        store.update_meta(Uuid::from_u128(1), |meta| {
+            meta.category = Some("Snacks".to_string())
+        });
+        let rows = inventory("kfet", &store);
+        let mut output = Vec::new();
+        write(ExportFormat::Csv, &rows, &mut output).unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "instance,id,name,category,price,stock,state,last_seen"
+        );
+        assert_eq!(
+            lines[1],
+            format!(
+                "kfet,{},\"Chips \"\"BBQ\"\", large\",Snacks,1.50,3,available,",
+                Uuid::from_u128(1)
+            )
+        );
+    }
+}
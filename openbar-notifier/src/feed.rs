@@ -0,0 +1,288 @@
+//! Catalog change feeds for OpenBar Notifier
+//!
+//! Item events are also published as RSS 2.0 feeds, written at predictable paths
+//! under the feed directory:
+//! - `<dir>/<instance>/all.xml`: all the catalog changes of the instance,
+//! - `<dir>/<instance>/categories/<category>.xml`: the catalog changes of a single category
+//!   (e.g. `craft-beers.xml` for the "Craft beers" category).
+//!
+//! Feed entries are kept in the item store, so that the feeds can be regenerated with the
+//! same (stable) entry IDs on each run.
+//!
+//! The feed targets (`atom` and `rss` formats, see [crate::payload]) are files too, given by
+//! their `file://` URL: the entries notified to them are kept beside the feed, in a
+//! `<file>.entries.json` file, and the feed is rewritten with the most recent ones on each
+//! notification. In daemon mode, the web dashboard also serves them (see [crate::web]).
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::event::EventContext;
+use crate::payload::PayloadFormat;
+use crate::render::{Formatting, render_line};
+use crate::theme::{Theme, Variables};
+
+/// Maximum number of entries of the feed targets
+pub const TARGET_MAX_ENTRIES: usize = 50;
+
+/// An entry of a catalog change feed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedEntry {
+    /// Stable ID of the entry (used as the RSS `guid`)
+    pub id: String,
+    /// Time of the event
+    pub at: DateTime<Utc>,
+    /// ID of the item category
+    pub category_id: Uuid,
+    /// Name of the item category
+    pub category_name: String,
+    /// Title of the entry
+    pub title: String,
+}
+
+impl FeedEntry {
+    /// Create the feed entry of an item event that occurred at `at`
+    pub fn new(ctx: &EventContext, at: DateTime<Utc>, formatting: &Formatting) -> Self {
+        FeedEntry {
+            id: format!(
+                "urn:openbar-notifier:{}:{}:{}",
+                ctx.item.id,
+                ctx.event.kind(),
+                at.timestamp()
+            ),
+            at,
+            category_id: ctx.item.category_id,
+            category_name: ctx.category_name.clone(),
+            title: render_line(ctx, formatting, Theme::Default, &Variables::new())
+                .trim_start_matches("- ")
+                .trim_end()
+                .to_string(),
+        }
+    }
+
+    /// Create the feed entry of a standalone message (e.g. a report) sent at `at`
+    pub fn message(content: &str, at: DateTime<Utc>) -> Self {
+        FeedEntry {
+            id: format!("urn:openbar-notifier:message:{}", at.timestamp_micros()),
+            at,
+            category_id: Uuid::nil(),
+            category_name: String::new(),
+            title: content.trim().to_string(),
+        }
+    }
+}
+
+/// Get the file name (without extension) of the feed of a category
+///
+/// The name is the lowercase category name, with any sequence of non-alphanumeric
+/// characters replaced by a single `-` (e.g. "Craft beers" gives `craft-beers`).
+pub fn category_slug(category_name: &str) -> String {
+    let mut slug = String::new();
+    for c in category_name.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "other".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Render feed entries as an RSS 2.0 document (most recent entries first)
+pub fn render_rss(title: &str, entries: &[&FeedEntry]) -> String {
+    let mut rss = String::new();
+    rss.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    rss.push_str("<rss version=\"2.0\">\n<channel>\n");
+    rss.push_str(&format!("<title>{}</title>\n", xml_escape(title)));
+    rss.push_str(&format!(
+        "<description>{}</description>\n",
+        xml_escape(title)
+    ));
+    for entry in entries.iter().rev() {
+        rss.push_str("<item>\n");
+        rss.push_str(&format!("<title>{}</title>\n", xml_escape(&entry.title)));
+        rss.push_str(&format!(
+            "<category>{}</category>\n",
+            xml_escape(&entry.category_name)
+        ));
+        rss.push_str(&format!(
+            "<guid isPermaLink=\"false\">{}</guid>\n",
+            xml_escape(&entry.id)
+        ));
+        rss.push_str(&format!("<pubDate>{}</pubDate>\n", entry.at.to_rfc2822()));
+        rss.push_str("</item>\n");
+    }
+    rss.push_str("</channel>\n</rss>\n");
+    rss
+}
+
+/// Render feed entries as an Atom document (most recent entries first)
+pub fn render_atom(title: &str, id: &str, entries: &[&FeedEntry]) -> String {
+    let updated = entries
+        .iter()
+        .map(|entry| entry.at)
+        .max()
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let mut atom = String::new();
+    atom.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    atom.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    atom.push_str(&format!("<title>{}</title>\n", xml_escape(title)));
+    atom.push_str(&format!("<id>{}</id>\n", xml_escape(id)));
+    atom.push_str(&format!("<updated>{}</updated>\n", updated.to_rfc3339()));
+    atom.push_str("<author><name>OpenBar Notifier</name></author>\n");
+    for entry in entries.iter().rev() {
+        atom.push_str("<entry>\n");
+        atom.push_str(&format!("<title>{}</title>\n", xml_escape(&entry.title)));
+        atom.push_str(&format!("<id>{}</id>\n", xml_escape(&entry.id)));
+        atom.push_str(&format!("<updated>{}</updated>\n", entry.at.to_rfc3339()));
+        if !entry.category_name.is_empty() {
+            atom.push_str(&format!(
+                "<category term=\"{}\"/>\n",
+                xml_escape(&entry.category_name)
+            ));
+        }
+        atom.push_str("</entry>\n");
+    }
+    atom.push_str("</feed>\n");
+    atom
+}
+
+/// Get the file keeping the entries of a feed target, beside the feed
+pub fn entries_file(path: &Path) -> PathBuf {
+    let mut file = path.as_os_str().to_owned();
+    file.push(".entries.json");
+    PathBuf::from(file)
+}
+
+/// Add entries to the feed target written at `path`, and rewrite it with the most recent ones
+///
+/// The entries already in the feed (same ID) are not added again.
+pub fn publish(
+    path: &Path,
+    format: PayloadFormat,
+    title: &str,
+    id: &str,
+    entries: &[FeedEntry],
+) -> std::io::Result<()> {
+    let entries_file = entries_file(path);
+    let mut kept: Vec<FeedEntry> = match std::fs::read(&entries_file) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    for entry in entries {
+        if !kept.iter().any(|kept| kept.id == entry.id) {
+            kept.push(entry.clone());
+        }
+    }
+    kept.sort_by_key(|entry| entry.at);
+    kept.drain(..kept.len().saturating_sub(TARGET_MAX_ENTRIES));
+
+    let recent: Vec<&FeedEntry> = kept.iter().collect();
+    let document = match format {
+        PayloadFormat::Rss => render_rss(title, &recent),
+        _ => render_atom(title, id, &recent),
+    };
+    write_atomically(&entries_file, serde_json::to_string(&kept)?.as_bytes())?;
+    write_atomically(path, document.as_bytes())
+}
+
+/// Write a file through a temporary file, so that its readers never see it half-written
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path)
+}
+
+/// Write the global and per-category feeds of an instance under `dir`
+///
+/// The global feed contains at most `max_entries` entries (the most recent ones).
+pub fn write_feeds(
+    dir: &Path,
+    instance: &str,
+    entries: &[FeedEntry],
+    max_entries: usize,
+) -> std::io::Result<()> {
+    let dir = dir.join(instance);
+    let categories_dir = dir.join("categories");
+    std::fs::create_dir_all(&categories_dir)?;
+
+    let all: Vec<&FeedEntry> = entries.iter().collect();
+    let all = &all[all.len().saturating_sub(max_entries)..];
+    std::fs::write(
+        dir.join("all.xml"),
+        render_rss(&format!("{}: catalog changes", instance), all),
+    )?;
+
+    let mut by_category: BTreeMap<String, Vec<&FeedEntry>> = BTreeMap::new();
+    for entry in entries {
+        by_category
+            .entry(category_slug(&entry.category_name))
+            .or_default()
+            .push(entry);
+    }
+    for (slug, entries) in by_category {
+        let title = format!("{}: {} catalog changes", instance, entries[0].category_name);
+        std::fs::write(
+            categories_dir.join(format!("{}.xml", slug)),
+            render_rss(&title, &entries),
+        )?;
+    }
+    Ok(())
+}
+
+/// Escape the XML special characters of a text
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(n: i64) -> FeedEntry {
+        FeedEntry::message(
+            &format!("Message <{}>", n),
+            DateTime::UNIX_EPOCH + chrono::Duration::minutes(n),
+        )
+    }
+
+    #[test]
+    fn feed_targets_keep_the_recent_entries() {
+        let path =
+            std::env::temp_dir().join(format!("openbar-notifier-feed-{}.xml", std::process::id()));
+        let first: Vec<FeedEntry> = (0..TARGET_MAX_ENTRIES as i64).map(entry).collect();
+        publish(&path, PayloadFormat::Atom, "Bar", "urn:bar", &first).unwrap();
+        // Already published entries are not duplicated
+        let next = [entry(0), entry(TARGET_MAX_ENTRIES as i64)];
+        publish(&path, PayloadFormat::Atom, "Bar", "urn:bar", &next).unwrap();
+
+        let atom = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(atom.matches("<entry>").count(), TARGET_MAX_ENTRIES);
+        assert!(atom.contains("<title>Message &lt;50&gt;</title>"));
+        assert!(!atom.contains("Message &lt;0&gt;"));
+        // Most recent first
+        assert!(atom.find("&lt;50&gt;") < atom.find("&lt;49&gt;"));
+
+        publish(&path, PayloadFormat::Rss, "Bar", "urn:bar", &[]).unwrap();
+        let rss = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(rss.matches("<item>").count(), TARGET_MAX_ENTRIES);
+
+        std::fs::remove_file(entries_file(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
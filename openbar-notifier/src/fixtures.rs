@@ -0,0 +1,234 @@
+//! Test fixtures for OpenBar Notifier
+//!
+//! This module fabricates realistic category/item datasets, as a sequence of catalog
+//! snapshots (one per poll). They provide consistent inputs for simulations, benchmarks
+//! and the mock OpenBar server, without requiring access to a real instance.
+
+use openbar_api::models::{Category, Item, ItemPrices, ItemState};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Category names used by the generator
+const CATEGORY_NAMES: &[&str] = &[
+    "Bières",
+    "Softs",
+    "Snacks",
+    "Sandwichs",
+    "Boissons chaudes",
+    "Confiseries",
+    "Menus",
+    "Goodies",
+];
+
+/// Item names used by the generator
+const ITEM_NAMES: &[&str] = &[
+    "Chouffe",
+    "Kwak",
+    "Delirium",
+    "Coca-Cola",
+    "Ice Tea",
+    "Oasis",
+    "Chips",
+    "Kinder Bueno",
+    "Twix",
+    "Jambon-beurre",
+    "Poulet-crudités",
+    "Café",
+    "Thé",
+    "Chocolat chaud",
+    "Haribo",
+    "Sweat",
+    "Mug",
+];
+
+/// How the catalog evolves from one snapshot to the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangePattern {
+    /// Random stock decreases, restocks and state changes
+    #[default]
+    Random,
+    /// Stocks only decrease, until items run out
+    SellOut,
+    /// Items start out of stock and are progressively restocked
+    Restock,
+    /// Items keep switching between buyable and unbuyable
+    Flapping,
+}
+
+impl std::str::FromStr for ChangePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(ChangePattern::Random),
+            "sell_out" => Ok(ChangePattern::SellOut),
+            "restock" => Ok(ChangePattern::Restock),
+            "flapping" => Ok(ChangePattern::Flapping),
+            _ => Err(format!(
+                "unknown change pattern '{}' (expected random, sell_out, restock or flapping)",
+                s
+            )),
+        }
+    }
+}
+
+/// Options of the fixture generator
+#[derive(Debug, Clone)]
+pub struct FixtureOptions {
+    /// Number of categories
+    pub categories: usize,
+    /// Number of items per category
+    pub items_per_category: usize,
+    /// Number of snapshots to generate (at least one)
+    pub snapshots: usize,
+    /// Probability of an item changing between two snapshots
+    pub change_rate: f64,
+    /// How the items change between two snapshots
+    pub pattern: ChangePattern,
+    /// Seed of the random generator, the same seed always gives the same fixtures
+    pub seed: u64,
+}
+
+impl Default for FixtureOptions {
+    fn default() -> Self {
+        FixtureOptions {
+            categories: 4,
+            items_per_category: 10,
+            snapshots: 10,
+            change_rate: 0.2,
+            pattern: ChangePattern::Random,
+            seed: 0,
+        }
+    }
+}
+
+/// A generated dataset: the categories and the successive snapshots of their items
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FixtureSet {
+    /// Categories of the catalog
+    pub categories: Vec<Category>,
+    /// Successive snapshots of all the items of the catalog
+    pub snapshots: Vec<Vec<Item>>,
+}
+
+impl FixtureSet {
+    /// Get the items of a category in the given snapshot
+    pub fn category_items(&self, snapshot: usize, category_id: Uuid) -> Vec<Item> {
+        self.snapshots
+            .get(snapshot)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|item| item.category_id == category_id)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Generate a fixture set according to the given options
+pub fn generate(options: &FixtureOptions) -> FixtureSet {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let categories: Vec<Category> = (0..options.categories)
+        .map(|i| {
+            let base = CATEGORY_NAMES[i % CATEGORY_NAMES.len()];
+            let name = match i / CATEGORY_NAMES.len() {
+                0 => base.to_string(),
+                n => format!("{} {}", base, n + 1),
+            };
+            Category::new(
+                random_uuid(&mut rng),
+                name,
+                String::new(),
+                i as i64,
+                false,
+                false,
+            )
+        })
+        .collect();
+
+    let mut items = Vec::with_capacity(options.categories * options.items_per_category);
+    for category in &categories {
+        for _ in 0..options.items_per_category {
+            let base = ITEM_NAMES[rng.random_range(0..ITEM_NAMES.len())];
+            let name = format!("{} #{}", base, items.len() + 1);
+            let price = rng.random_range(50..400);
+            let prices = ItemPrices::new(price, price, price, price, price, price + 50);
+            let optimal_amount = rng.random_range(10..50);
+            let (amount_left, state) = match options.pattern {
+                ChangePattern::Restock => (0, ItemState::ItemNotBuyable),
+                _ => (rng.random_range(1..=optimal_amount), ItemState::ItemBuyable),
+            };
+            items.push(Item::new(
+                random_uuid(&mut rng),
+                prices,
+                amount_left,
+                optimal_amount,
+                category.id,
+                name,
+                String::new(),
+                false,
+                state,
+            ));
+        }
+    }
+
+    let mut snapshots = vec![items.clone()];
+    for _ in 1..options.snapshots.max(1) {
+        for item in items.iter_mut() {
+            if rng.random_bool(options.change_rate.clamp(0.0, 1.0)) {
+                apply_change(&mut rng, item, options.pattern);
+            }
+        }
+        snapshots.push(items.clone());
+    }
+
+    FixtureSet {
+        categories,
+        snapshots,
+    }
+}
+
+/// Apply a single change to an item, following the given pattern
+fn apply_change(rng: &mut StdRng, item: &mut Item, pattern: ChangePattern) {
+    match pattern {
+        ChangePattern::Random => match rng.random_range(0..3) {
+            0 => sell(rng, item),
+            1 => restock(rng, item),
+            _ => toggle_state(item),
+        },
+        ChangePattern::SellOut => sell(rng, item),
+        ChangePattern::Restock => restock(rng, item),
+        ChangePattern::Flapping => toggle_state(item),
+    }
+}
+
+/// Decrease the stock of an item
+fn sell(rng: &mut StdRng, item: &mut Item) {
+    let sold = rng.random_range(1..=item.amount_left.max(1));
+    item.amount_left = (item.amount_left - sold).max(0);
+}
+
+/// Restock an item, making it buyable
+fn restock(rng: &mut StdRng, item: &mut Item) {
+    item.amount_left += rng.random_range(1..=item.optimal_amount.max(1));
+    item.state = ItemState::ItemBuyable;
+}
+
+/// Switch an item between buyable and unbuyable
+fn toggle_state(item: &mut Item) {
+    item.state = match item.state {
+        ItemState::ItemBuyable => ItemState::ItemNotBuyable,
+        ItemState::ItemNotBuyable => ItemState::ItemBuyable,
+    };
+}
+
+/// Generate a random (but seeded) UUID
+fn random_uuid(rng: &mut StdRng) -> Uuid {
+    uuid::Builder::from_random_bytes(rng.random()).into_uuid()
+}
@@ -0,0 +1,158 @@
+//! Message localization of OpenBar Notifier
+//!
+//! The messages are translated gettext-style: each message is identified by its English text
+//! (its `msgid`), and the catalog of each language (a `.po` file of the `locales` directory,
+//! embedded in the binary) maps it to its translation (`msgstr`). Messages missing from a
+//! catalog, and the languages without a catalog, fall back to English.
+//!
+//! The language of the messages is the one of the locale (see [crate::render::Formatting]),
+//! e.g. `NOTIFY_LOCALE=fr`. Translations keep the placeholders of the English messages (e.g.
+//! `{name}`, see [crate::theme]).
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Message catalogs, by language
+const CATALOGS: &[(&str, &str)] = &[("fr", include_str!("../locales/fr.po"))];
+
+/// Translations, by language and message
+type Translations = HashMap<&'static str, HashMap<String, String>>;
+
+/// Translate a message into the given language (e.g. `fr`), falling back to English
+pub fn translate(language: &str, msgid: &'static str) -> &'static str {
+    static TRANSLATIONS: OnceLock<Translations> = OnceLock::new();
+    let translations = TRANSLATIONS.get_or_init(|| {
+        CATALOGS
+            .iter()
+            .map(|(language, data)| {
+                let catalog = parse_po(data).unwrap_or_else(|e| {
+                    panic!("Invalid message catalog of language {}: {}", language, e)
+                });
+                (*language, catalog)
+            })
+            .collect()
+    });
+    translations
+        .get(language)
+        .and_then(|catalog| catalog.get(msgid))
+        .map(String::as_str)
+        .unwrap_or(msgid)
+}
+
+/// Parse a gettext `.po` catalog into the translations of its messages
+///
+/// Only the `msgid`/`msgstr` pairs are supported (no context nor plural forms). The header
+/// entry (empty `msgid`) and the untranslated messages (empty `msgstr`) are skipped.
+pub fn parse_po(data: &str) -> Result<HashMap<String, String>, String> {
+    let mut catalog = HashMap::new();
+    let mut msgid: Option<String> = None;
+    let mut msgstr: Option<String> = None;
+    let mut insert = |msgid: Option<String>, msgstr: Option<String>| match (msgid, msgstr) {
+        (Some(msgid), Some(msgstr)) if !msgid.is_empty() && !msgstr.is_empty() => {
+            catalog.insert(msgid, msgstr);
+            Ok(())
+        }
+        (Some(_), Some(_)) | (None, None) => Ok(()),
+        (Some(msgid), None) => Err(format!("missing msgstr of \"{}\"", msgid)),
+        (None, Some(_)) => Err("msgstr without msgid".to_string()),
+    };
+    for (number, line) in data.lines().enumerate() {
+        let line = line.trim();
+        let error = |e: String| format!("line {}: {}", number + 1, e);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("msgid ") {
+            insert(msgid.take(), msgstr.take()).map_err(error)?;
+            msgid = Some(unquote(rest).map_err(error)?);
+        } else if let Some(rest) = line.strip_prefix("msgstr ") {
+            if msgid.is_none() || msgstr.is_some() {
+                return Err(error("msgstr without msgid".to_string()));
+            }
+            msgstr = Some(unquote(rest).map_err(error)?);
+        } else if line.starts_with('"') {
+            // Continuation of the last string
+            let target = msgstr.as_mut().or(msgid.as_mut());
+            let target = target.ok_or_else(|| error("string outside of an entry".to_string()))?;
+            target.push_str(&unquote(line).map_err(error)?);
+        } else {
+            return Err(error(format!("unexpected line '{}'", line)));
+        }
+    }
+    insert(msgid, msgstr)?;
+    Ok(catalog)
+}
+
+/// Unquote and unescape a `.po` string (e.g. `"Hello\n"`)
+fn unquote(s: &str) -> Result<String, String> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("invalid string {}", s))?;
+    let mut output = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some('"') => output.push('"'),
+            Some('\\') => output.push('\\'),
+            other => return Err(format!("invalid escape \\{}", other.unwrap_or(' '))),
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the placeholders of a message, sorted
+    fn placeholders(message: &str) -> Vec<&str> {
+        let mut placeholders: Vec<&str> = message
+            .split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(key, _)| key))
+            .collect();
+        placeholders.sort();
+        placeholders
+    }
+
+    #[test]
+    fn parses_po_catalogs() {
+        let data = "# Comment\nmsgid \"\"\nmsgstr \"Language: fr\\n\"\n\n\
+            msgid \"The item \\\"{name}\\\" is out of stock.\\n\"\n\
+            msgstr \"\"\n\"L'article « {name} » est \"\n\"en rupture de stock.\\n\"\n\n\
+            msgid \"Untranslated\"\nmsgstr \"\"\n";
+        let catalog = parse_po(data).unwrap();
+        assert_eq!(catalog.len(), 1);
+        assert_eq!(
+            catalog["The item \"{name}\" is out of stock.\n"],
+            "L'article « {name} » est en rupture de stock.\n"
+        );
+        assert!(parse_po("msgid \"a\"\n").is_err());
+        assert!(parse_po("msgstr \"a\"\n").is_err());
+        assert!(parse_po("msgid \"a\\q\"\nmsgstr \"b\"\n").is_err());
+    }
+
+    #[test]
+    fn catalogs_keep_the_placeholders() {
+        for (language, data) in CATALOGS {
+            let catalog = parse_po(data).unwrap();
+            assert!(!catalog.is_empty(), "{}", language);
+            for (msgid, msgstr) in &catalog {
+                assert_eq!(placeholders(msgid), placeholders(msgstr), "{}", msgid);
+            }
+        }
+        assert_eq!(
+            translate("fr", "- {name} ({id}) removed.\n"),
+            "- {name} ({id}) retiré.\n"
+        );
+        assert_eq!(translate("de", "Other"), "Other");
+        assert_eq!(translate("fr", "Not in the catalog"), "Not in the catalog");
+    }
+}
@@ -0,0 +1,342 @@
+//! Persistent event journal of OpenBar Notifier
+//!
+//! Every emitted item event is appended to a local journal (a JSON Lines file), whether its
+//! notification succeeds or not: each entry holds the time of the event, the instance, the
+//! event along with its context (see [EventContext], the item in its new state) and the item
+//! in its previous state. The journal is the history of the catalog, from which the past
+//! events can be replayed or analyzed.
+//!
+//! The journal is only ever appended to, the malformed lines (e.g. of an interrupted write)
+//! being skipped when reading it. When the stores are encrypted (see [crate::crypto]), so are
+//! the entries of the journal, each line being then an entry encrypted with the store cipher and
+//! encoded in base64. The plaintext lines of a journal are still read.
+//!
+//! The past events can be sent again to a target (e.g. after a day of notifications lost to
+//! a misconfigured webhook) with `openbar-notifier events replay --since <date> --target <target>`,
+//! the events of each instance being replayed as a batch (see [replay_batches]).
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, Utc};
+use log::warn;
+use openbar_api::models::Item;
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{self, StoreCipher};
+use crate::event::EventContext;
+use crate::notifier::Batch;
+use crate::render::Formatting;
+
+/// An entry of the event journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Time of the event
+    pub at: DateTime<Utc>,
+    /// Name of the instance
+    pub instance: String,
+    /// The event, along with its context (and the item in its new state)
+    #[serde(flatten)]
+    pub context: EventContext,
+    /// The item in its previous state, if it was known
+    #[serde(default)]
+    pub previous: Option<Item>,
+}
+
+/// Append entries to the journal, creating it if needed, encrypted with the given cipher (if
+/// any)
+pub fn append(
+    path: &Path,
+    entries: &[JournalEntry],
+    cipher: Option<&dyn StoreCipher>,
+) -> std::io::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut buf = Vec::new();
+    for entry in entries {
+        let line = serde_json::to_vec(entry)?;
+        match cipher {
+            Some(cipher) => {
+                let data = cipher.encrypt(&line).map_err(std::io::Error::other)?;
+                buf.extend_from_slice(
+                    base64::engine::general_purpose::STANDARD
+                        .encode(data)
+                        .as_bytes(),
+                );
+            }
+            None => buf.extend_from_slice(&line),
+        }
+        buf.push(b'\n');
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&buf)
+}
+
+/// Read the entries of the journal since the given time (all of them if `None`), oldest first
+///
+/// A missing journal has no entries. The encrypted entries are decrypted with the given cipher,
+/// reading them without one is an error.
+pub fn read(
+    path: &Path,
+    since: Option<DateTime<Utc>>,
+    cipher: Option<&dyn StoreCipher>,
+) -> std::io::Result<Vec<JournalEntry>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line = match decrypt_line(&line, cipher) {
+            Ok(line) => line,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => return Err(e),
+            Err(e) => {
+                warn!(
+                    "Skipping malformed line {} of the journal {:?}: {}",
+                    index + 1,
+                    path,
+                    e
+                );
+                continue;
+            }
+        };
+        match serde_json::from_slice::<JournalEntry>(&line) {
+            Ok(entry) if since.is_none_or(|since| entry.at >= since) => entries.push(entry),
+            Ok(_) => {}
+            Err(e) => warn!(
+                "Skipping malformed line {} of the journal {:?}: {}",
+                index + 1,
+                path,
+                e
+            ),
+        }
+    }
+    Ok(entries)
+}
+
+/// Get the JSON of a line of the journal, decrypting it with the given cipher if it is encrypted
+///
+/// Reading an encrypted line without a cipher fails with [std::io::ErrorKind::PermissionDenied].
+fn decrypt_line(line: &str, cipher: Option<&dyn StoreCipher>) -> std::io::Result<Vec<u8>> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        return Ok(line.as_bytes().to_vec());
+    }
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(line)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if !crypto::is_encrypted(&data) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "neither JSON nor an encrypted entry",
+        ));
+    }
+    let cipher = cipher.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "the journal is encrypted, but no encryption key is configured",
+        )
+    })?;
+    cipher
+        .decrypt(&data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Parse the start of a replay, given as a date (`YYYY-MM-DD`, at midnight UTC) or as an
+/// RFC 3339 timestamp
+pub fn parse_since(s: &str) -> Result<DateTime<Utc>, String> {
+    let s = s.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_time(chrono::NaiveTime::MIN).and_utc());
+    }
+    DateTime::parse_from_rfc3339(s)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|_| {
+            format!(
+                "invalid date '{}' (expected YYYY-MM-DD or an RFC 3339 timestamp)",
+                s
+            )
+        })
+}
+
+/// Group journal entries into the batches replaying them, one per instance (in the order of
+/// their first event)
+///
+/// The messages are tagged as replayed with their header, and never mention anyone.
+pub fn replay_batches(entries: Vec<JournalEntry>, formatting: &Formatting) -> Vec<Batch> {
+    let mut batches: Vec<Batch> = Vec::new();
+    for entry in entries {
+        let index = match batches.iter().position(|b| b.instance == entry.instance) {
+            Some(index) => index,
+            None => {
+                batches.push(Batch {
+                    header: format!("[{}, replayed]\n", entry.instance),
+                    instance: entry.instance.clone(),
+                    formatting: formatting.clone(),
+                    ..Batch::default()
+                });
+                batches.len() - 1
+            }
+        };
+        batches[index].events.push(entry.context);
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::crypto::AesGcmCipher;
+    use crate::event::ItemEvent;
+
+    fn item(amount_left: i64) -> Item {
+        Item::new(
+            Uuid::from_u128(1),
+            ItemPrices::new(100, 100, 100, 100, 150, 150),
+            amount_left,
+            10,
+            Uuid::from_u128(0),
+            "Chips".to_string(),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        )
+    }
+
+    fn entry(at: &str, event: ItemEvent) -> JournalEntry {
+        JournalEntry {
+            at: at.parse().unwrap(),
+            instance: "kfet".to_string(),
+            context: EventContext {
+                event,
+                item: item(0),
+                category_name: "Snacks".to_string(),
+                tags: Default::default(),
+                restricted: false,
+                sales_rate: None,
+                price: None,
+                severity: Default::default(),
+                correction: None,
+            },
+            previous: Some(item(4)),
+        }
+    }
+
+    #[test]
+    fn journal_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "openbar-notifier-journal-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        assert!(read(&path, None, None).unwrap().is_empty());
+
+        append(
+            &path,
+            &[
+                entry(
+                    "2024-01-01T12:00:00Z",
+                    ItemEvent::StockDropped { dropped: 4 },
+                ),
+                entry("2024-01-02T12:00:00Z", ItemEvent::OutOfStock),
+            ],
+            None,
+        )
+        .unwrap();
+        // An interrupted write is skipped
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"at\":\n")
+            .unwrap();
+
+        let entries = read(&path, None, None).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].context.event,
+            ItemEvent::StockDropped { dropped: 4 }
+        );
+        assert_eq!(entries[0].previous.as_ref().unwrap().amount_left, 4);
+        let since = read(&path, Some("2024-01-02T00:00:00Z".parse().unwrap()), None).unwrap();
+        assert_eq!(since.len(), 1);
+        assert_eq!(since[0].context.event, ItemEvent::OutOfStock);
+
+        let batches = replay_batches(entries, &Formatting::default());
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].header, "[kfet, replayed]\n");
+        assert_eq!(batches[0].events.len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn encrypted_journal_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "openbar-notifier-journal-encrypted-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let cipher = AesGcmCipher::new(&[7u8; 32]).unwrap();
+
+        // A plaintext entry, written before the encryption was enabled
+        append(
+            &path,
+            &[entry("2024-01-01T12:00:00Z", ItemEvent::OutOfStock)],
+            None,
+        )
+        .unwrap();
+        append(
+            &path,
+            &[entry(
+                "2024-01-02T12:00:00Z",
+                ItemEvent::StockDropped { dropped: 4 },
+            )],
+            Some(&cipher),
+        )
+        .unwrap();
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(raw.matches("kfet").count(), 1);
+
+        let entries = read(&path, None, Some(&cipher)).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].context.event, ItemEvent::OutOfStock);
+        assert_eq!(
+            entries[1].context.event,
+            ItemEvent::StockDropped { dropped: 4 }
+        );
+        assert_eq!(entries[1].previous.as_ref().unwrap().amount_left, 4);
+        assert_eq!(
+            read(&path, None, None).unwrap_err().kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn parses_the_start_of_the_replays() {
+        assert_eq!(
+            parse_since("2024-01-01").unwrap(),
+            "2024-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(
+            parse_since("2024-01-01T12:00:00+02:00").unwrap(),
+            "2024-01-01T10:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(parse_since("yesterday").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Message layout of OpenBar Notifier
+//!
+//! The events of a message are listed by priority by default (see [crate::priority]), but they
+//! can also be sorted by item name, by category (as ordered in the bar UI), by stock or by price
+//! change ([EventSort]). The events of the same item can also be grouped into a single line
+//! (e.g. `Chouffe is buyable again and was restocked to 24`, see
+//! [crate::render::render_group]).
+
+use serde::Deserialize;
+
+use crate::event::{EventContext, ItemEvent};
+use crate::priority;
+
+/// Order of the events in the messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSort {
+    /// Most urgent first (see [crate::priority])
+    #[default]
+    Priority,
+    /// By item name (case-insensitive)
+    Name,
+    /// By category, as ordered in the bar UI
+    Category,
+    /// Lowest stock first
+    Stock,
+    /// Biggest price drop (promotion) first
+    PriceDelta,
+}
+
+impl std::str::FromStr for EventSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "priority" => Ok(EventSort::Priority),
+            "name" => Ok(EventSort::Name),
+            "category" => Ok(EventSort::Category),
+            "stock" => Ok(EventSort::Stock),
+            "price_delta" => Ok(EventSort::PriceDelta),
+            _ => Err(format!(
+                "unknown event sort '{}' (expected priority, name, category, stock or price_delta)",
+                s
+            )),
+        }
+    }
+}
+
+/// Get the change of the (member) price of an item by an event, in cents
+fn price_delta(ctx: &EventContext) -> i64 {
+    match ctx.event {
+        ItemEvent::PromotionStarted { discount } => -ctx.item.prices.ceten * discount / 10_000,
+        _ => 0,
+    }
+}
+
+/// Sort the events of a message, given in catalog order (see
+/// [crate::store::ItemStore::sort_by_category])
+///
+/// The sort is stable: the events of the same rank keep the catalog order.
+pub fn sort(mut events: Vec<EventContext>, sort: EventSort) -> Vec<EventContext> {
+    match sort {
+        EventSort::Priority => return priority::by_priority(events),
+        EventSort::Name => events.sort_by_key(|ctx| ctx.item.name.to_lowercase()),
+        EventSort::Category => {}
+        EventSort::Stock => events.sort_by_key(|ctx| ctx.item.amount_left),
+        EventSort::PriceDelta => events.sort_by_key(price_delta),
+    }
+    events
+}
+
+/// Group the events of the same item, in the order of their first event
+///
+/// The corrections (see [crate::correction]) are never grouped, they stay on their own line.
+pub fn group_by_item(events: &[EventContext]) -> Vec<Vec<&EventContext>> {
+    let mut groups: Vec<Vec<&EventContext>> = Vec::new();
+    for ctx in events {
+        let group = groups.iter_mut().find(|group| {
+            ctx.correction.is_none()
+                && group[0].correction.is_none()
+                && group[0].item.id == ctx.item.id
+        });
+        match group {
+            Some(group) => group.push(ctx),
+            None => groups.push(vec![ctx]),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{Item, ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn context(n: u128, name: &str, amount_left: i64, event: ItemEvent) -> EventContext {
+        EventContext {
+            event,
+            item: Item::new(
+                Uuid::from_u128(n),
+                ItemPrices::new(200, 200, 200, 200, 200, 300),
+                amount_left,
+                10,
+                Uuid::from_u128(0),
+                name.to_string(),
+                String::new(),
+                false,
+                ItemState::ItemBuyable,
+            ),
+            category_name: "Beers".to_string(),
+            tags: Default::default(),
+            restricted: false,
+            sales_rate: None,
+            price: None,
+            severity: Default::default(),
+            correction: None,
+        }
+    }
+
+    #[test]
+    fn sorts_and_groups_the_events() {
+        let events = vec![
+            context(1, "kwak", 24, ItemEvent::BecomeBuyable),
+            context(
+                2,
+                "Chouffe",
+                3,
+                ItemEvent::PromotionStarted { discount: 1000 },
+            ),
+            context(
+                3,
+                "Leffe",
+                0,
+                ItemEvent::PromotionStarted { discount: 5000 },
+            ),
+            context(1, "kwak", 24, ItemEvent::StockChanged { change: 24 }),
+        ];
+        let names = |events: &[EventContext]| -> Vec<String> {
+            events.iter().map(|ctx| ctx.item.name.clone()).collect()
+        };
+        assert_eq!(
+            names(&sort(events.clone(), EventSort::Name)),
+            ["Chouffe", "kwak", "kwak", "Leffe"]
+        );
+        assert_eq!(
+            names(&sort(events.clone(), EventSort::Stock)),
+            ["Leffe", "Chouffe", "kwak", "kwak"]
+        );
+        assert_eq!(
+            names(&sort(events.clone(), EventSort::PriceDelta)),
+            ["Leffe", "Chouffe", "kwak", "kwak"]
+        );
+        assert_eq!(
+            names(&sort(events.clone(), EventSort::Category)),
+            names(&events)
+        );
+
+        let groups = group_by_item(&events);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].len(), 2);
+        assert_eq!(groups[0][1].event, ItemEvent::StockChanged { change: 24 });
+        assert_eq!("price-delta".parse(), Ok(EventSort::PriceDelta));
+        assert!("price".parse::<EventSort>().is_err());
+    }
+}
@@ -1,4 +1,58 @@
+pub mod analytics;
+pub mod chaos;
+pub mod clock;
 pub mod config;
+pub mod correction;
+pub mod crypto;
+pub mod debounce;
+pub mod delivery;
+pub mod diff;
+pub mod dns;
+pub mod error;
 pub mod event;
+pub mod export;
+pub mod feed;
+pub mod fixtures;
+pub mod i18n;
+pub mod journal;
+pub mod layout;
+pub mod lifecycle;
+pub mod lock;
+pub mod maintenance;
+pub mod mention;
+#[cfg(feature = "test-util")]
+pub mod mock;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod mute;
+pub mod notifier;
 pub mod openbar;
+pub mod pattern;
+pub mod payload;
+pub mod permissions;
+pub mod preview;
+pub mod prices;
+pub mod priority;
+pub mod probe;
+pub mod query;
+pub mod ratelimit;
+pub mod render;
+pub mod report;
+pub mod routing;
+pub mod runner;
+pub mod sales;
+pub mod schedule;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sink;
+pub mod status;
 pub mod store;
+pub mod summary;
+pub mod tags;
+pub mod theme;
+pub mod transactions;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod watchlist;
+#[cfg(feature = "web")]
+pub mod web;
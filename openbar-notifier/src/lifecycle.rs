@@ -0,0 +1,244 @@
+//! Item lifecycle of OpenBar Notifier
+//!
+//! Each item goes through an explicit state machine, whose transitions generate the
+//! availability events:
+//!
+//! | From | To | Event |
+//! |------|----|-------|
+//! | (new item) | any | `added` |
+//! | `Removed` | any | `added` |
+//! | any | `Removed` | `removed` |
+//! | `Unlisted` | `Available`, `LowStock`, `OutOfStock` | `become_buyable` |
+//! | `Available`, `LowStock`, `OutOfStock` | `Unlisted` | `become_unbuyable` |
+//! | `Available`, `LowStock` | `OutOfStock` | `out_of_stock` |
+//! | `Available` | `LowStock` | `low_stock` |
+//!
+//! The other transitions (e.g. a restock) generate no event.
+//!
+//! An item is [Lifecycle::Unlisted] while it is not buyable, whatever its stock, and
+//! [Lifecycle::Removed] once the API no longer lists it. The low stock threshold is a ratio of
+//! the optimal amount of the item, with hysteresis: an item stays low on stock until it is
+//! restocked above twice the threshold. Every transition goes through the debouncing of
+//! [crate::debounce] before being notified.
+
+use chrono::{DateTime, Utc};
+use openbar_api::models::{Item, ItemState};
+use serde::{Deserialize, Serialize};
+
+use crate::debounce::{DebounceConfig, StateTracking};
+use crate::event::ItemEvent;
+
+/// Lifecycle state of an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lifecycle {
+    /// Buyable and in stock
+    Available,
+    /// Buyable, but low on stock
+    LowStock,
+    /// Buyable, but out of stock
+    OutOfStock,
+    /// Not buyable
+    Unlisted,
+    /// No longer listed by the API
+    Removed,
+}
+
+impl Lifecycle {
+    /// Get the (stable) name of the state, as used in serialization
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lifecycle::Available => "available",
+            Lifecycle::LowStock => "low_stock",
+            Lifecycle::OutOfStock => "out_of_stock",
+            Lifecycle::Unlisted => "unlisted",
+            Lifecycle::Removed => "removed",
+        }
+    }
+
+    /// Get the state of an item (`None` if the API no longer lists it)
+    ///
+    /// `settled` is the current (notified) state of the item, for the low stock hysteresis.
+    /// Items are low on stock once their stock is at most `low_stock_ratio` times their optimal
+    /// amount (a zero ratio disables the [Lifecycle::LowStock] state).
+    pub fn of(item: Option<&Item>, settled: Option<Lifecycle>, low_stock_ratio: f64) -> Lifecycle {
+        let Some(item) = item else {
+            return Lifecycle::Removed;
+        };
+        if item.state == ItemState::ItemNotBuyable {
+            return Lifecycle::Unlisted;
+        }
+        if item.amount_left <= 0 {
+            return Lifecycle::OutOfStock;
+        }
+        let mut threshold = low_stock_ratio * item.optimal_amount as f64;
+        if settled == Some(Lifecycle::LowStock) {
+            threshold *= 2.0;
+        }
+        if low_stock_ratio > 0.0 && item.amount_left as f64 <= threshold {
+            Lifecycle::LowStock
+        } else {
+            Lifecycle::Available
+        }
+    }
+}
+
+/// Get the events of the transition of an item between two states
+pub fn transition_events(from: Lifecycle, to: Lifecycle) -> Vec<ItemEvent> {
+    use Lifecycle::*;
+    match (from, to) {
+        (from, to) if from == to => Vec::new(),
+        (Removed, _) => vec![ItemEvent::Added],
+        (_, Removed) => vec![ItemEvent::Removed],
+        (Unlisted, _) => vec![ItemEvent::BecomeBuyable],
+        (_, Unlisted) => vec![ItemEvent::BecomeUnbuyable],
+        (_, OutOfStock) => vec![ItemEvent::OutOfStock],
+        (Available, LowStock) => vec![ItemEvent::LowStock],
+        _ => Vec::new(),
+    }
+}
+
+/// Observe an item, and get the events of its transition once settled
+///
+/// `previous` is the item known to the store (`None` if new, which is [ItemEvent::Added]
+/// right away), and `fresh` the item returned by the API (`None` if no longer listed).
+/// An item selling out and becoming unbuyable at once goes through
+/// [Lifecycle::OutOfStock], both events being generated.
+pub fn observe(
+    tracking: &mut StateTracking<Lifecycle>,
+    previous: Option<&Item>,
+    fresh: Option<&Item>,
+    now: DateTime<Utc>,
+    low_stock_ratio: f64,
+    debounce: &DebounceConfig,
+) -> Vec<ItemEvent> {
+    let current = Lifecycle::of(fresh, tracking.notified, low_stock_ratio);
+    let Some(previous) = previous else {
+        *tracking = StateTracking::settled(current);
+        return vec![ItemEvent::Added];
+    };
+    let initial = Lifecycle::of(Some(previous), tracking.notified, low_stock_ratio);
+    let Some((from, to)) = tracking.observe(initial, current, now, debounce) else {
+        return Vec::new();
+    };
+    let sold_out = fresh.is_some_and(|item| item.amount_left <= 0);
+    if to == Lifecycle::Unlisted
+        && sold_out
+        && matches!(from, Lifecycle::Available | Lifecycle::LowStock)
+    {
+        let mut events = transition_events(from, Lifecycle::OutOfStock);
+        events.extend(transition_events(Lifecycle::OutOfStock, to));
+        return events;
+    }
+    transition_events(from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use openbar_api::models::ItemPrices;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn item(amount_left: i64, state: ItemState) -> Item {
+        Item::new(
+            Uuid::from_u128(1),
+            ItemPrices::new(100, 100, 100, 100, 100, 150),
+            amount_left,
+            10,
+            Uuid::from_u128(0),
+            "Beer".to_string(),
+            String::new(),
+            false,
+            state,
+        )
+    }
+
+    fn kinds(events: &[ItemEvent]) -> Vec<&'static str> {
+        events.iter().map(ItemEvent::kind).collect()
+    }
+
+    #[test]
+    fn low_stock_has_hysteresis() {
+        let ratio = 0.2;
+        let buyable = |amount| item(amount, ItemState::ItemBuyable);
+        assert_eq!(
+            Lifecycle::of(Some(&buyable(3)), None, ratio),
+            Lifecycle::Available
+        );
+        assert_eq!(
+            Lifecycle::of(Some(&buyable(2)), None, ratio),
+            Lifecycle::LowStock
+        );
+        // Restocked a little: still low on stock
+        assert_eq!(
+            Lifecycle::of(Some(&buyable(4)), Some(Lifecycle::LowStock), ratio),
+            Lifecycle::LowStock
+        );
+        assert_eq!(
+            Lifecycle::of(Some(&buyable(5)), Some(Lifecycle::LowStock), ratio),
+            Lifecycle::Available
+        );
+        assert_eq!(
+            Lifecycle::of(Some(&buyable(1)), None, 0.0),
+            Lifecycle::Available
+        );
+    }
+
+    #[test]
+    fn transitions_generate_events() {
+        use Lifecycle::*;
+        assert_eq!(
+            kinds(&transition_events(Available, LowStock)),
+            ["low_stock"]
+        );
+        assert_eq!(
+            kinds(&transition_events(LowStock, OutOfStock)),
+            ["out_of_stock"]
+        );
+        assert!(transition_events(OutOfStock, LowStock).is_empty());
+        assert!(transition_events(LowStock, Available).is_empty());
+        assert_eq!(
+            kinds(&transition_events(OutOfStock, Unlisted)),
+            ["become_unbuyable"]
+        );
+        assert_eq!(
+            kinds(&transition_events(Unlisted, OutOfStock)),
+            ["become_buyable"]
+        );
+        assert_eq!(kinds(&transition_events(Unlisted, Removed)), ["removed"]);
+        assert_eq!(kinds(&transition_events(Removed, Available)), ["added"]);
+    }
+
+    #[test]
+    fn transitions_are_debounced() {
+        let debounce = DebounceConfig { polls: 2, secs: 0 };
+        let now = DateTime::UNIX_EPOCH;
+        let mut tracking = StateTracking::default();
+        let available = item(5, ItemState::ItemBuyable);
+        let events = observe(&mut tracking, None, Some(&available), now, 0.0, &debounce);
+        assert_eq!(kinds(&events), ["added"]);
+
+        // Delisted at one poll only: not notified
+        let events = observe(&mut tracking, Some(&available), None, now, 0.0, &debounce);
+        assert!(events.is_empty());
+        let events = observe(
+            &mut tracking,
+            Some(&available),
+            Some(&available),
+            now,
+            0.0,
+            &debounce,
+        );
+        assert!(events.is_empty());
+
+        // Delisted for two polls: notified once
+        let later = now + Duration::minutes(5);
+        assert!(observe(&mut tracking, Some(&available), None, later, 0.0, &debounce).is_empty());
+        let events = observe(&mut tracking, Some(&available), None, later, 0.0, &debounce);
+        assert_eq!(kinds(&events), ["removed"]);
+        assert!(observe(&mut tracking, Some(&available), None, later, 0.0, &debounce).is_empty());
+        assert_eq!(tracking.notified, Some(Lifecycle::Removed));
+    }
+}
@@ -0,0 +1,91 @@
+//! Store file locking of OpenBar Notifier
+//!
+//! Two notifier processes using the same store file (e.g. overlapping cron runs) would
+//! silently overwrite each other's changes. Each store is thus guarded by an advisory lock on
+//! a sidecar `<store file>.lock` file, held for as long as the store is loaded in memory: a
+//! process finding the lock held does not load the store at all.
+//!
+//! The store file itself cannot be locked, since it is replaced (renamed over) on each save.
+//! The lock is released by the operating system when the process exits, even if it crashed,
+//! and the lock file is left in place.
+
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+
+/// Errors while locking a store file
+#[derive(Debug, thiserror::Error)]
+pub enum StoreLockError {
+    /// The store file is locked by another process
+    #[error("the store file {0:?} is locked by another notifier process (lock file {1:?})")]
+    Held(PathBuf, PathBuf),
+    /// The lock file could not be opened or locked
+    #[error("cannot lock the store file {0:?}: {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+}
+
+impl StoreLockError {
+    /// Check if the lock file could not be created, the filesystem being read-only
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, StoreLockError::Io(_, e) if e.kind() == std::io::ErrorKind::ReadOnlyFilesystem)
+    }
+}
+
+/// Exclusive lock of a store file, released when dropped
+#[derive(Debug)]
+pub struct StoreLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl StoreLock {
+    /// Lock a store file, failing right away if it is locked by another process
+    pub fn acquire(store_file: &Path) -> Result<StoreLock, StoreLockError> {
+        let path = lock_path(store_file);
+        let io_error = |e| StoreLockError::Io(store_file.to_path_buf(), e);
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(io_error)?;
+        match file.try_lock() {
+            Ok(()) => Ok(StoreLock { _file: file, path }),
+            Err(TryLockError::WouldBlock) => {
+                Err(StoreLockError::Held(store_file.to_path_buf(), path))
+            }
+            Err(TryLockError::Error(e)) => Err(io_error(e)),
+        }
+    }
+
+    /// Get the path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Get the path of the lock file of a store file
+pub fn lock_path(store_file: &Path) -> PathBuf {
+    let mut path = store_file.as_os_str().to_owned();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_is_exclusive() {
+        let store_file =
+            std::env::temp_dir().join(format!("openbar-notifier-lock-{}.json", std::process::id()));
+        let lock = StoreLock::acquire(&store_file).unwrap();
+        assert_eq!(lock.path(), lock_path(&store_file));
+        assert!(matches!(
+            StoreLock::acquire(&store_file),
+            Err(StoreLockError::Held(..))
+        ));
+        drop(lock);
+        let lock = StoreLock::acquire(&store_file).unwrap();
+        std::fs::remove_file(lock.path()).unwrap();
+    }
+}
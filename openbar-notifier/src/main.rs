@@ -1,245 +1,928 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::sync::Arc;
+use std::time::Duration;
 
+#[cfg(feature = "analytics")]
+use chrono::NaiveDate;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use log::{debug, error, info, warn};
-use openbar_api::models::ItemState;
-use openbar_notifier::config::GlobalConfig;
-use openbar_notifier::event::ItemEvent;
-use openbar_notifier::openbar::{OpenBarClient, webconfig::get_config_with_client};
-use serde_json::json;
-use std::io::Write;
-use uuid::Uuid;
+use log::{error, info, warn};
+#[cfg(feature = "analytics")]
+use openbar_notifier::analytics::AnalyticsDb;
+use openbar_notifier::clock;
+use openbar_notifier::config::{GlobalConfig, InstanceConfig, TargetConfig};
+use openbar_notifier::error::NotifierError;
+use openbar_notifier::export::{self, ExportFormat};
+use openbar_notifier::fixtures::{self, ChangePattern, FixtureOptions};
+use openbar_notifier::journal;
+use openbar_notifier::maintenance::{self, MaintenanceWindow};
+use openbar_notifier::mute::{self, MuteRequest};
+use openbar_notifier::notifier::{Batch, Notifier};
+use openbar_notifier::preview;
+use openbar_notifier::query::{self, OutputFormat};
+use openbar_notifier::render::Formatting;
+#[cfg(feature = "analytics")]
+use openbar_notifier::report;
+use openbar_notifier::runner::{
+    Connection, Runner, connect, create_http_client, create_http_client_with_cookies, disconnect,
+    load_item_store_from_file,
+};
+#[cfg(feature = "server")]
+use openbar_notifier::server::{self, ApiState};
+#[cfg(feature = "tui")]
+use openbar_notifier::tui::{self, DashboardSource};
+#[cfg(feature = "web")]
+use openbar_notifier::web::{self, WebState};
+use reqwest::cookie::Jar;
+use tokio::sync::watch;
+
+/// OpenBar-Notifier informs you about any interesting changes in the stocks of an OpenBar instance.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Never save the stores (e.g. on a read-only filesystem, otherwise detected automatically)
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Diff the stores even when they were created against another instance URL, or have an
+    /// incompatible format (otherwise, they are reset)
+    #[arg(long, global = true)]
+    force: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check the instances once and notify the item events (default)
+    Run,
+    /// Keep checking the instances at a regular interval, and serve the HTTP API (if enabled)
+    Daemon,
+    /// Mute the notifications during a maintenance window (e.g. while restructuring the catalog)
+    Maintenance {
+        #[command(subcommand)]
+        command: MaintenanceCommand,
+    },
+    /// Mute an item for a while (use the HTTP API instead while the daemon is running)
+    Mute {
+        /// The item, by ID or name (case-insensitive)
+        item: String,
+        /// Duration of the mute (e.g. `24h`, `30m`)
+        #[arg(long = "for", value_parser = maintenance::parse_duration)]
+        duration: Duration,
+        /// Only mute the item of the instance with this name (all the instances by default)
+        #[arg(long)]
+        instance: Option<String>,
+    },
+    /// Unmute an item (use the HTTP API instead while the daemon is running)
+    Unmute {
+        /// The item, by ID or name (case-insensitive)
+        item: String,
+        /// Only unmute the item of the instance with this name (all the instances by default)
+        #[arg(long)]
+        instance: Option<String>,
+    },
+    /// Render the payload a target would receive for an event of an item, without sending it
+    Render {
+        /// Type of the event (e.g. `out-of-stock`, `low_stock`)
+        #[arg(long)]
+        event: String,
+        /// The item, by ID or name (case-insensitive)
+        #[arg(long)]
+        item: String,
+        /// Use the store and the targets of the instance with this name (the first configured
+        /// instance by default)
+        #[arg(long)]
+        instance: Option<String>,
+        /// The target, by the URL of a configured target or as a target shorthand (all the
+        /// targets of the instance by default)
+        #[arg(long)]
+        target: Option<String>,
+    },
+    /// Print (or send) the monthly consumption report of the instances, from the analytics database
+    #[cfg(feature = "analytics")]
+    Report {
+        /// Month of the report (`YYYY-MM`, the previous month by default)
+        #[arg(long, value_parser = report::parse_month)]
+        month: Option<NaiveDate>,
+        /// Send the report to the report targets instead of printing it
+        #[arg(long)]
+        send: bool,
+    },
+    /// Use the event journal
+    Events {
+        #[command(subcommand)]
+        command: EventsCommand,
+    },
+    /// Inspect the item stores
+    Store {
+        #[command(subcommand)]
+        command: StoreCommand,
+    },
+    /// Show the live dashboard of the daemon (or of an in-process one) in the terminal
+    #[cfg(feature = "tui")]
+    Tui {
+        /// Base URL of the HTTP API of a running daemon (e.g. `http://127.0.0.1:8080`), the
+        /// instances being checked in-process otherwise
+        #[arg(long)]
+        url: Option<url::Url>,
+    },
+    /// Query the OpenBar API of an instance, logged in with the configured credentials
+    Api {
+        #[command(subcommand)]
+        command: ApiCommand,
+        /// Query the instance with this name (the first configured instance by default)
+        #[arg(long, global = true)]
+        instance: Option<String>,
+        /// Output format (table or json)
+        #[arg(long, global = true, default_value = "table")]
+        format: OutputFormat,
+    },
+    /// Development tools for test fixtures
+    Fixtures {
+        #[command(subcommand)]
+        command: FixturesCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum MaintenanceCommand {
+    /// Mute all the notifications for a while
+    Start {
+        /// Duration of the window (e.g. `2h`, `30m`, `1h30m`)
+        #[arg(value_parser = maintenance::parse_duration)]
+        duration: Duration,
+        /// Why the notifications are muted
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// End the maintenance window now
+    Stop,
+    /// Show the active maintenance window
+    Status,
+}
+
+#[derive(Subcommand)]
+enum EventsCommand {
+    /// Send the past events of the journal again to a target (e.g. after a webhook outage)
+    Replay {
+        /// Start of the replay (`YYYY-MM-DD` or RFC 3339 timestamp)
+        #[arg(long, value_parser = journal::parse_since)]
+        since: DateTime<Utc>,
+        /// The target, by the URL of a configured target or as a target shorthand
+        #[arg(long)]
+        target: String,
+        /// Only replay the events of the instance with this name (all the instances by default)
+        #[arg(long)]
+        instance: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreCommand {
+    /// Export the tracked inventory (name, category, price, stock, state, last seen)
+    Export {
+        /// Export format (csv or json)
+        #[arg(long, default_value = "csv")]
+        format: ExportFormat,
+        /// Only export the items of the instance with this name (all the instances by default)
+        #[arg(long)]
+        instance: Option<String>,
+        /// Output file (stdout if not specified)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ApiCommand {
+    /// List the categories of the instance
+    Categories,
+    /// List the items of a category
+    Items {
+        /// The category, by ID or name (case-insensitive)
+        category: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum FixturesCommand {
+    /// Generate a fixture dataset (categories and successive item snapshots) as JSON
+    Generate {
+        /// Number of categories
+        #[arg(long, default_value_t = 4)]
+        categories: usize,
+        /// Number of items per category
+        #[arg(long, default_value_t = 10)]
+        items: usize,
+        /// Number of successive snapshots
+        #[arg(long, default_value_t = 10)]
+        snapshots: usize,
+        /// Probability of an item changing between two snapshots
+        #[arg(long, default_value_t = 0.2)]
+        change_rate: f64,
+        /// Change pattern (random, sell_out, restock or flapping)
+        #[arg(long, default_value = "random")]
+        pattern: ChangePattern,
+        /// Seed of the random generator
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Output file (stdout if not specified)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     dotenv().ok();
     env_logger::init();
-    info!("Hello, world!");
+    let cli = Cli::parse();
 
-    // Get the configuration from environment variables
-    let config = match GlobalConfig::load_env() {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            error!("Error loading configuration: {:?}", e);
-            return;
+    let result = match cli.command {
+        None | Some(Command::Run) => run(cli.read_only, cli.force).await,
+        Some(Command::Daemon) => daemon(cli.read_only, cli.force).await,
+        Some(Command::Maintenance { command }) => maintenance(command),
+        Some(Command::Mute {
+            item,
+            duration,
+            instance,
+        }) => mute(MuteRequest {
+            instance,
+            item,
+            until: Some(clock::after(Utc::now(), duration)),
+        }),
+        Some(Command::Unmute { item, instance }) => mute(MuteRequest {
+            instance,
+            item,
+            until: None,
+        }),
+        #[cfg(feature = "analytics")]
+        Some(Command::Report { month, send }) => report(month, send).await,
+        Some(Command::Events {
+            command:
+                EventsCommand::Replay {
+                    since,
+                    target,
+                    instance,
+                },
+        }) => replay(since, &target, instance).await,
+        Some(Command::Render {
+            event,
+            item,
+            instance,
+            target,
+        }) => render(&event, &item, instance, target),
+        Some(Command::Store {
+            command:
+                StoreCommand::Export {
+                    format,
+                    instance,
+                    output,
+                },
+        }) => export(format, instance, output),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui { url }) => tui(url, cli.read_only, cli.force).await,
+        Some(Command::Api {
+            command,
+            instance,
+            format,
+        }) => api(command, instance, format).await,
+        Some(Command::Fixtures {
+            command:
+                FixturesCommand::Generate {
+                    categories,
+                    items,
+                    snapshots,
+                    change_rate,
+                    pattern,
+                    seed,
+                    output,
+                },
+        }) => {
+            let options = FixtureOptions {
+                categories,
+                items_per_category: items,
+                snapshots,
+                change_rate,
+                pattern,
+                seed,
+            };
+            let fixtures = fixtures::generate(&options);
+            let data =
+                serde_json::to_string_pretty(&fixtures).expect("Failed to serialize fixtures");
+            match output {
+                Some(path) => std::fs::write(&path, data).map_err(|e| {
+                    NotifierError::Io(format!("error writing fixtures to {:?}: {}", path, e))
+                }),
+                None => {
+                    println!("{}", data);
+                    Ok(())
+                }
+            }
         }
     };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-    // Create a Reqwest client with TLS Keylog enabled
-    let http = create_http_client();
+/// Create a runner for the configuration, never saving the stores if `read_only`, and diffing
+/// the mismatched stores if `force`
+fn create_runner(
+    config: GlobalConfig,
+    read_only: bool,
+    force: bool,
+) -> Result<Runner, NotifierError> {
+    let mut runner = Runner::new(config)
+        .map_err(|e| NotifierError::Config(format!("error creating the runner: {}", e)))?;
+    if read_only {
+        runner.set_read_only();
+    }
+    if force {
+        runner.set_force();
+    }
+    Ok(runner)
+}
 
-    // Get the Instance webconfig
-    let webconfig = match get_config_with_client(&http, &config.openbar.instance_url).await {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("Error retrieving webconfig: {}", e);
-            return;
+/// Find an instance of the configuration by its name, the first one by default
+fn find_instance<'a>(
+    config: &'a GlobalConfig,
+    name: Option<&str>,
+) -> Result<&'a InstanceConfig, NotifierError> {
+    match name {
+        Some(name) => config
+            .instances
+            .iter()
+            .find(|i| i.name == name)
+            .ok_or_else(|| NotifierError::Config(format!("no instance named {}", name))),
+        None => config
+            .instances
+            .first()
+            .ok_or_else(|| NotifierError::Config("no instance is configured".to_string())),
+    }
+}
+
+/// Create an HTTP client for the configuration
+fn http_client(config: &GlobalConfig) -> Result<reqwest::Client, NotifierError> {
+    create_http_client(&config.http)
+        .map_err(|e| NotifierError::Config(format!("error creating the HTTP client: {}", e)))
+}
+
+/// Check all the configured instances once
+async fn run(read_only: bool, force: bool) -> Result<(), NotifierError> {
+    info!("Hello, world!");
+
+    // Get the configuration from the config file or environment variables
+    let config = GlobalConfig::load()?;
+
+    let mut runner = create_runner(config, read_only, force)?;
+    runner.run_once().await
+}
+
+/// Manage the maintenance window
+fn maintenance(command: MaintenanceCommand) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+    let path = &config.maintenance.file;
+    let result = match command {
+        MaintenanceCommand::Start { duration, reason } => {
+            let window = MaintenanceWindow::starting_now(duration, reason);
+            maintenance::save(path, &window).map(|()| println!("Notifications muted {}.", window))
+        }
+        MaintenanceCommand::Stop => {
+            maintenance::clear(path).map(|()| println!("Notifications unmuted."))
+        }
+        MaintenanceCommand::Status => {
+            match maintenance::active(path, Utc::now()) {
+                Some(window) => println!("Notifications muted {}.", window),
+                None => println!("No maintenance in progress."),
+            }
+            Ok(())
         }
     };
+    result.map_err(|e| {
+        NotifierError::Io(format!(
+            "error updating the maintenance window {:?}: {}",
+            path, e
+        ))
+    })
+}
 
-    debug!("WebConfig: {:?}", webconfig);
-
-    // Connect to OpenBar API
-    let mut client = OpenBarClient::with_client(&webconfig.api, http.clone());
-    client.set_local_token(&webconfig.local_token);
+/// Mute (or unmute) an item in the stores
+fn mute(request: MuteRequest) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+    let mut runner = create_runner(config, false, false)?;
+    let results = runner.apply_mute(&request);
+    if results.is_empty() {
+        return Err(NotifierError::Config(
+            "no instance matching the request".to_string(),
+        ));
+    }
+    for (instance, result) in results {
+        match (result, request.until) {
+            (Ok(id), Some(until)) => println!("[{}] Item {} muted until {}.", instance, id, until),
+            (Ok(id), None) => println!("[{}] Item {} unmuted.", instance, id),
+            (Err(e), _) => println!("[{}] {}.", instance, e),
+        }
+    }
+    runner.save_stores();
+    Ok(())
+}
 
-    // Login
-    match client
-        .login_by_card(&config.openbar.card_id, &config.openbar.pin)
-        .await
+/// Export the inventory tracked by the stores
+///
+/// The stores are read as they were last saved, without locking them.
+fn export(
+    format: ExportFormat,
+    instance: Option<String>,
+    output: Option<PathBuf>,
+) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+    let cipher = config.store.cipher().map_err(|e| {
+        NotifierError::Config(format!("error loading the store encryption key: {}", e))
+    })?;
+    if let Some(name) = &instance
+        && !config.instances.iter().any(|i| &i.name == name)
     {
-        Ok(_resp) => info!("Logged in successfully"),
-        Err(e) => {
-            error!("Error during login: {:?}", e);
-            return;
+        return Err(NotifierError::Config(format!("no instance named {}", name)));
+    }
+    let mut rows = Vec::new();
+    for instance in config
+        .instances
+        .iter()
+        .filter(|i| instance.as_ref().is_none_or(|name| &i.name == name))
+    {
+        match load_item_store_from_file(&instance.store_file, cipher.as_deref()) {
+            Ok(store) => rows.extend(export::inventory(&instance.name, &store)),
+            Err(e) => {
+                return Err(NotifierError::Store {
+                    instance: instance.name.clone(),
+                    message: e.to_string(),
+                });
+            }
         }
     }
+    let result = match &output {
+        Some(path) => std::fs::File::create(path)
+            .and_then(|file| export::write(format, &rows, std::io::BufWriter::new(file))),
+        None => export::write(format, &rows, std::io::stdout().lock()),
+    };
+    result.map_err(|e| NotifierError::Io(format!("error writing the export: {}", e)))
+}
 
-    // Load the item store from the file
-    let mut item_store = match load_item_store_from_file(&config.store_file) {
-        Ok(store) => store,
-        Err(e) => {
-            error!("Error loading item store: {}", e);
-            return;
-        }
+/// Send the events of the journal since the given time again to a target
+async fn replay(
+    since: DateTime<Utc>,
+    target: &str,
+    instance: Option<String>,
+) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+    let Some(path) = &config.journal.file else {
+        return Err(NotifierError::Config(
+            "the event journal is not configured (JOURNAL_FILE)".to_string(),
+        ));
     };
+    // A configured target keeps its options (format, theme, filters...)
+    let configured = config
+        .instances
+        .iter()
+        .flat_map(|i| &i.targets)
+        .find(|t| t.url == target)
+        .cloned();
+    let target = configured
+        .map(Ok)
+        .unwrap_or_else(|| TargetConfig::parse(target))
+        .map_err(|e| NotifierError::Config(format!("invalid target: {}", e)))?;
+    let cipher = config.store.cipher().map_err(|e| {
+        NotifierError::Config(format!("error loading the store encryption key: {}", e))
+    })?;
+    let entries = journal::read(path, Some(since), cipher.as_deref())
+        .map_err(|e| NotifierError::Io(format!("error reading the journal {:?}: {}", path, e)))?;
+    let entries: Vec<_> = entries
+        .into_iter()
+        .filter(|e| instance.as_ref().is_none_or(|name| &e.instance == name))
+        .collect();
+    if entries.is_empty() {
+        println!("No events to replay since {}.", since);
+        return Ok(());
+    }
+    let notifier = Notifier::new(http_client(&config)?);
+    let formatting = Formatting::new(config.notify.locale.clone(), config.notify.currency.clone());
+    let count = entries.len();
+    let (mut sent, mut undelivered) = (0, 0);
+    // Instance of the last undelivered message, if any
+    let mut failed = None;
+    for batch in journal::replay_batches(entries, &formatting) {
+        let report = notifier.notify(std::slice::from_ref(&target), &batch).await;
+        sent += report.sent;
+        undelivered += report.undelivered;
+        if report.undelivered > 0 {
+            failed = Some(batch.instance.clone());
+        }
+    }
+    if let Some(instance) = failed {
+        error!(
+            "{} events replayed to {}: {} messages sent, {} undelivered.",
+            count,
+            target.redacted_url(),
+            sent,
+            undelivered
+        );
+        return Err(NotifierError::Dispatch {
+            instance,
+            undelivered,
+        });
+    }
+    println!(
+        "{} events replayed to {} ({} messages).",
+        count,
+        target.redacted_url(),
+        sent
+    );
+    Ok(())
+}
 
-    // Store the item events to process later
-    let mut item_events: Vec<(Uuid, ItemEvent)> = Vec::new();
-
-    // Get all products
-    match client.get_categories().await {
-        Ok(categories) => {
-            info!("Got {} categories:", categories.len());
-            // - For each category, get items
-            for category in categories {
-                let category_id = category.id;
-                match client.get_category_items(&category_id.to_string()).await {
+/// Query the OpenBar API of an instance, and print the response
+async fn api(
+    command: ApiCommand,
+    instance: Option<String>,
+    format: OutputFormat,
+) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+    let instance = find_instance(&config, instance.as_deref())?;
+    let cookies = Arc::new(Jar::default());
+    let http = create_http_client_with_cookies(&config.http, cookies.clone())
+        .map_err(|e| NotifierError::Config(format!("error creating the HTTP client: {}", e)))?;
+    let Connection {
+        client, webconfig, ..
+    } = connect(&http, &config, instance, None, Some(cookies)).await?;
+    // The explicit configuration takes precedence over the instance locale/currency
+    let formatting = Formatting::new(
+        config.notify.locale.clone().or(webconfig.locale),
+        config.notify.currency.clone().or(webconfig.currency),
+    );
+    let fetch_error = |e: &dyn std::fmt::Display| NotifierError::Fetch {
+        instance: instance.name.clone(),
+        message: e.to_string(),
+    };
+    let categories = client.get_categories().await.map_err(|e| fetch_error(&e));
+    let result = match (command, categories) {
+        (_, Err(e)) => Err(e),
+        (ApiCommand::Categories, Ok(categories)) => {
+            query::write_categories(format, &categories, std::io::stdout().lock())
+                .map_err(|e| NotifierError::Io(format!("error writing the categories: {}", e)))
+        }
+        (ApiCommand::Items { category }, Ok(categories)) => {
+            match query::find_category(&categories, &category) {
+                Some(category) => match client
+                    .get_all_category_items(&category.id.to_string())
+                    .await
+                {
                     Ok(items) => {
-                        info!("{} items in category {}:", items.len(), category.name);
-                        for item in items {
-                            // Check if the item is already in the store
-                            if let Some(existing) = item_store.find_mut(item.id) {
-                                // Compare states to determine events
-                                if existing.state != item.state {
-                                    match item.state {
-                                        ItemState::ItemBuyable => {
-                                            item_events.push((item.id, ItemEvent::BecomeBuyable))
-                                        }
-                                        ItemState::ItemNotBuyable => {
-                                            item_events.push((item.id, ItemEvent::BecomeUnbuyable))
-                                        }
-                                    }
-                                }
-                                if existing.amount_left > 0 && item.amount_left == 0 {
-                                    item_events.push((item.id, ItemEvent::OutOfStock))
-                                }
-                                // Update existing item
-                                *existing = item.clone();
-                            } else {
-                                // New item, add to store
-                                item_store.append(item.clone());
-                                item_events.push((item.id, ItemEvent::Added));
-                                info!("New item added: {} (ID: {})", item.name, item.id);
-                            }
-                        }
+                        query::write_items(format, &items, &formatting, std::io::stdout().lock())
+                            .map_err(|e| {
+                                NotifierError::Io(format!("error writing the items: {}", e))
+                            })
                     }
-                    Err(e) => error!(
-                        "Error retrieving items for category {}: {:?}",
-                        category.name, e
-                    ),
-                }
+                    Err(e) => Err(fetch_error(&e)),
+                },
+                None => Err(NotifierError::Config(format!(
+                    "no category {} in instance {}",
+                    category, instance.name
+                ))),
             }
         }
-        Err(e) => error!("Error retrieving categories: {:?}", e),
-    }
-
-    // Logout
-    match client.logout().await {
-        Ok(_) => info!("Logged out successfully"),
-        Err(e) => error!("Error during logout: {}", e),
-    }
-
-    // Process item events (notifications, etc.)
-    let mut buf = Vec::new();
-    let mut writer = std::io::Cursor::new(&mut buf);
-    for (item_id, event) in item_events {
-        let item = match item_store.find(item_id) {
-            Some(i) => i,
-            None => {
-                warn!(
-                    "Item ID {} not found in store for event processing.",
-                    item_id
-                );
+    };
+    disconnect(&config, instance, &client).await;
+    result
+}
+
+/// Print the payloads the targets would receive for an event of an item
+///
+/// The store is read as it was last saved, without locking it.
+fn render(
+    event: &str,
+    item: &str,
+    instance: Option<String>,
+    target: Option<String>,
+) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+    let instance = find_instance(&config, instance.as_deref())?;
+    let targets = match target {
+        // A configured target keeps its options (format, theme, filters...)
+        Some(target) => vec![
+            instance
+                .targets
+                .iter()
+                .find(|t| t.url == target)
+                .cloned()
+                .map(Ok)
+                .unwrap_or_else(|| TargetConfig::parse(&target))
+                .map_err(|e| NotifierError::Config(format!("invalid target: {}", e)))?,
+        ],
+        None => instance.targets.clone(),
+    };
+    let cipher = config.store.cipher().map_err(|e| {
+        NotifierError::Config(format!("error loading the store encryption key: {}", e))
+    })?;
+    let store =
+        load_item_store_from_file(&instance.store_file, cipher.as_deref()).map_err(|e| {
+            NotifierError::Store {
+                instance: instance.name.clone(),
+                message: e.to_string(),
+            }
+        })?;
+    let item = mute::find_item(&store, item)
+        .and_then(|id| {
+            store
+                .find(id)
+                .ok_or_else(|| format!("no item with ID {}", id))
+        })
+        .map_err(|e| {
+            NotifierError::Config(format!("{} in the store of instance {}", e, instance.name))
+        })?;
+    let event = preview::sample_event(event, item).map_err(NotifierError::Config)?;
+    let batch = Batch {
+        instance: instance.name.clone(),
+        header: if config.instances.len() > 1 {
+            format!("[{}]\n", instance.name)
+        } else {
+            String::new()
+        },
+        events: vec![preview::context(&config, &store, item, event, Utc::now())],
+        digest: config.notify.digest.is_some(),
+        sort: config.notify.sort,
+        group_by_item: config.notify.group_by_item,
+        formatting: Formatting::new(config.notify.locale.clone(), config.notify.currency.clone()),
+        mentions: config.notify.mentions.clone(),
+        base_url: Some(instance.openbar.instance_url.clone()),
+        ..Batch::default()
+    };
+    let notifier = Notifier::new(http_client(&config)?);
+    let previews = notifier.preview(&targets, &batch);
+    if previews.is_empty() {
+        println!("The event is not routed to any target (see their filters).");
+    }
+    // The targets are named on stderr, so that the payloads can be piped as they are
+    for (target, payloads) in previews {
+        eprintln!(
+            "--- {} ({} messages)",
+            target.redacted_url(),
+            payloads.len()
+        );
+        for payload in payloads {
+            println!("{}", payload.body());
+        }
+    }
+    Ok(())
+}
+
+/// Print (or send) the monthly consumption report of the instances
+#[cfg(feature = "analytics")]
+async fn report(month: Option<NaiveDate>, send: bool) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+    let Some(path) = &config.analytics.database else {
+        return Err(NotifierError::Config(
+            "the analytics database is not configured (ANALYTICS_DATABASE)".to_string(),
+        ));
+    };
+    let db = AnalyticsDb::open(path).map_err(|e| {
+        NotifierError::Io(format!(
+            "error opening the analytics database {:?}: {}",
+            path, e
+        ))
+    })?;
+    if send && config.analytics.report_targets.is_empty() {
+        return Err(NotifierError::Config(
+            "no report target is configured (ANALYTICS_REPORT_TARGETS)".to_string(),
+        ));
+    }
+    let notifier = Notifier::new(http_client(&config)?);
+    let month = month.unwrap_or_else(|| report::previous_month(Utc::now().date_naive()));
+    let formatting = Formatting::new(config.notify.locale.clone(), config.notify.currency.clone());
+    let mut result = Ok(());
+    for instance in &config.instances {
+        let sales = match report::monthly_sales(&db, &instance.name, month) {
+            Ok(sales) => sales,
+            Err(e) => {
+                error!("Error computing the report of {}: {}", instance.name, e);
+                result = Err(NotifierError::Io(format!(
+                    "error computing the report of {}: {}",
+                    instance.name, e
+                )));
                 continue;
             }
         };
-        match event {
-            ItemEvent::Added if config.notify.item_added => writer
-                .write_all(format!("- {} ({}) added.\n", &item.name, item_id).as_bytes())
-                .unwrap(),
-            ItemEvent::BecomeBuyable if config.notify.become_buyable => writer
-                .write_all(
-                    format!(
-                        "- {} ({}) became buyable (stock: {}).\n",
-                        &item.name, item_id, item.amount_left
-                    )
-                    .as_bytes(),
-                )
-                .unwrap(),
-            ItemEvent::BecomeUnbuyable if config.notify.become_unbuyable => writer
-                .write_all(format!("- {} ({}) became unbuyable.\n", &item.name, item_id).as_bytes())
-                .unwrap(),
-            ItemEvent::OutOfStock if config.notify.on_out_of_stock => writer
-                .write_all(format!("- {} ({}) is out of stock.\n", &item.name, item_id).as_bytes())
-                .unwrap(),
-            _ => { /* Notification for this event type is disabled */ }
+        let messages = report::render_report(&instance.name, month, &sales, &formatting);
+        if !send {
+            println!("{}", messages.concat());
+        } else if !report::send_report(&notifier, &config.analytics.report_targets, &messages).await
+        {
+            error!(
+                "The report of {} could not be delivered to all the targets.",
+                instance.name
+            );
+            result = Err(NotifierError::Dispatch {
+                instance: instance.name.clone(),
+                undelivered: 1,
+            });
         }
     }
+    result
+}
+
+/// Check all the configured instances at a regular interval, serving the HTTP API meanwhile
+async fn daemon(read_only: bool, force: bool) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+
+    let mut runner = create_runner(config, read_only, force)?;
+    #[cfg(feature = "server")]
+    let state = ApiState {
+        deliveries: runner.deliveries(),
+        maintenance_file: runner.config().maintenance.file.clone(),
+        mutes: runner.mutes(),
+        poll: runner.poll_trigger(),
+        sales: runner.sales_rates(),
+        status: runner.status(),
+    };
+    #[cfg(feature = "web")]
+    let web_state = WebState {
+        deliveries: runner.deliveries(),
+        status: runner.status(),
+        feeds: web::feeds(runner.config()),
+    };
 
-    if !buf.is_empty() {
-        // If buf > 2000 bytes, truncate and add notice
-        if buf.len() > 2000 {
-            buf.truncate(1800);
-            let notice = b"\n... (truncated)";
-            buf.extend_from_slice(notice);
+    if runner.config().daemon.probe_targets {
+        let failing = runner.probe_targets().await;
+        if failing > 0 {
+            warn!(
+                "{} target(s) failed their probe, check their URLs.",
+                failing
+            );
         }
+    }
 
-        for target in &config.targets {
-            info!("Notifying target {}...", target);
-            let json_body = json!({ "content": String::from_utf8_lossy(&buf) });
-            let res = http.post(target).json(&json_body).send().await;
-            match res {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        info!("Notification sent successfully to {}", target);
-                    } else {
-                        error!(
-                            "Failed to send notification to {}: HTTP {}",
-                            target,
-                            resp.status()
-                        );
-                        dbg!(resp.text().await.unwrap_or_default());
-                    }
+    // Notify the shutdown requests (SIGINT/SIGTERM), so that the in-flight cycle can finish
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutdown requested, finishing the current cycle...");
+        let _ = shutdown_tx.send(true);
+    });
+
+    // Reload the configuration on SIGHUP
+    #[cfg(unix)]
+    {
+        let reload = runner.reload_trigger();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(signal) => signal,
+                Err(e) => {
+                    error!("Error listening for SIGHUP: {}", e);
+                    return;
                 }
+            };
+            while hangup.recv().await.is_some() {
+                info!("Reload requested, reloading the configuration after the current cycle...");
+                reload.notify_one();
+            }
+        });
+    }
+
+    // Poll all the instances right away on SIGUSR1
+    #[cfg(unix)]
+    {
+        let poll = runner.poll_trigger();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{SignalKind, signal};
+            let mut user_defined = match signal(SignalKind::user_defined1()) {
+                Ok(signal) => signal,
                 Err(e) => {
-                    error!("Error sending notification to {}: {}", target, e);
+                    error!("Error listening for SIGUSR1: {}", e);
+                    return;
                 }
+            };
+            while user_defined.recv().await.is_some() {
+                info!("Poll requested, checking all the instances after the current cycle...");
+                poll.notify_one();
             }
-        }
-    } else {
-        info!("No item events to notify.");
-    }
-
-    // Save the item store back to the file
-    if let Err(e) = save_item_store_to_file(&item_store, &config.store_file) {
-        error!("Error saving item store: {}", e);
-    }
-}
-
-/// Create a Reqwest HTTP client with TLS Keylog support (easier to debug).
-fn create_http_client() -> reqwest::Client {
-    let root_store =
-        rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-    let mut tls_client = rustls::ClientConfig::builder_with_provider(Arc::new(
-        rustls::crypto::aws_lc_rs::default_provider(),
-    ))
-    .with_safe_default_protocol_versions()
-    .expect("Failed to set protocol versions")
-    .with_root_certificates(root_store)
-    .with_no_client_auth();
-    tls_client.key_log = std::sync::Arc::new(rustls::KeyLogFile::new());
-    reqwest::ClientBuilder::new()
-        .use_preconfigured_tls(tls_client)
-        .cookie_store(true)
-        .build()
-        .expect("Failed to create Reqwest client")
-}
-
-/// Load from file, the item store
-fn load_item_store_from_file(
-    path: &std::path::Path,
-) -> Result<openbar_notifier::store::ItemStore, Box<dyn std::error::Error>> {
-    // Check if the file exists
-    if !path.exists() {
-        // If not, return an empty store
-        warn!(
-            "Store file does not exist at {:?}, starting with an empty store.",
-            path
-        );
-        return Ok(openbar_notifier::store::ItemStore::new());
+        });
     }
-    let data = std::fs::read_to_string(path)?;
-    let store: openbar_notifier::store::ItemStore = serde_json::from_str(&data)?;
-    Ok(store)
-}
 
-/// Save the item store to a file
-fn save_item_store_to_file(
-    store: &openbar_notifier::store::ItemStore,
-    path: &std::path::Path,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let data = serde_json::to_string_pretty(store)?;
-    std::fs::write(path, data)?;
+    // Serve the HTTP API in the background
+    #[cfg(feature = "server")]
+    let server = runner.config().daemon.http_bind.map(|bind| {
+        let mut shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(async move {
+            info!("Serving the HTTP API on {}", bind);
+            let shutdown = async move {
+                let _ = shutdown_rx.wait_for(|shutdown| *shutdown).await;
+            };
+            if let Err(e) = server::serve(bind, state, shutdown).await {
+                error!("Error serving the HTTP API: {}", e);
+            }
+        })
+    });
+
+    // Serve the web dashboard in the background
+    #[cfg(feature = "web")]
+    let dashboard = runner.config().daemon.web_bind.map(|bind| {
+        let mut shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(async move {
+            info!("Serving the web dashboard on http://{}", bind);
+            let shutdown = async move {
+                let _ = shutdown_rx.wait_for(|shutdown| *shutdown).await;
+            };
+            if let Err(e) = web::serve(bind, web_state, shutdown).await {
+                error!("Error serving the web dashboard: {}", e);
+            }
+        })
+    });
+
+    let mut shutdown_rx = shutdown_rx.clone();
+    runner
+        .run_forever(async move {
+            let _ = shutdown_rx.wait_for(|shutdown| *shutdown).await;
+        })
+        .await;
+
+    #[cfg(feature = "server")]
+    if let Some(server) = server {
+        let _ = server.await;
+    }
+    #[cfg(feature = "web")]
+    if let Some(dashboard) = dashboard {
+        let _ = dashboard.await;
+    }
+    info!("Goodbye!");
     Ok(())
 }
+
+/// Show the live dashboard, of a running daemon or of an in-process one
+#[cfg(feature = "tui")]
+async fn tui(url: Option<url::Url>, read_only: bool, force: bool) -> Result<(), NotifierError> {
+    let config = GlobalConfig::load()?;
+
+    if let Some(url) = url {
+        let http = http_client(&config)?;
+        return tui::run(DashboardSource::Remote { http, url })
+            .await
+            .map_err(|e| NotifierError::Io(format!("error showing the dashboard: {}", e)));
+    }
+
+    // Check the instances in-process, until the dashboard is quit
+    let mut runner = create_runner(config, read_only, force)?;
+    let source = DashboardSource::Local {
+        status: runner.status(),
+        deliveries: runner.deliveries(),
+    };
+    let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+    let dashboard = async move {
+        let result = tui::run(source).await;
+        let _ = shutdown_tx.send(true);
+        result
+    };
+    let (result, ()) = tokio::join!(
+        dashboard,
+        runner.run_forever(async move {
+            let _ = shutdown_rx.wait_for(|shutdown| *shutdown).await;
+        })
+    );
+    result.map_err(|e| NotifierError::Io(format!("error showing the dashboard: {}", e)))
+}
+
+/// Wait for a shutdown signal (SIGINT, or SIGTERM on Unix)
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Error listening for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!("Error listening for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
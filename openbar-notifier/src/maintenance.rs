@@ -0,0 +1,132 @@
+//! Maintenance windows of OpenBar Notifier
+//!
+//! While the admins restructure the catalog, the notifications can be muted for a while
+//! (e.g. "mute all notifications for 2 hours"). The maintenance window is persisted in a
+//! small JSON file, so that it survives restarts and can be set from another process
+//! (the `maintenance` subcommand, or the HTTP API of the daemon).
+//!
+//! The instances are still checked during a maintenance window, so that the stores are
+//! up-to-date once it is over: the item events are journaled and published in the feeds, but
+//! not notified (nor held for later). The held events are released once it is over.
+
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+
+/// A maintenance window, during which the notifications are muted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    /// When the window started
+    pub since: DateTime<Utc>,
+    /// When the window ends
+    pub until: DateTime<Utc>,
+    /// Why the notifications are muted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl MaintenanceWindow {
+    /// Create a window starting now and lasting for `duration`
+    pub fn starting_now(duration: Duration, reason: Option<String>) -> Self {
+        let since = Utc::now();
+        MaintenanceWindow {
+            since,
+            until: clock::after(since, duration),
+            reason,
+        }
+    }
+
+    /// Check if the window is active at the given time
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        now < self.until
+    }
+}
+
+impl std::fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let until = self.until.with_timezone(&chrono::Local);
+        write!(f, "until {}", until.format("%Y-%m-%d %H:%M"))?;
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Load the maintenance window from its file (None if there is none)
+pub fn load(path: &Path) -> std::io::Result<Option<MaintenanceWindow>> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(std::io::Error::from),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Save the maintenance window to its file
+pub fn save(path: &Path, window: &MaintenanceWindow) -> std::io::Result<()> {
+    let data = serde_json::to_vec_pretty(window)?;
+    std::fs::write(path, data)
+}
+
+/// Remove the maintenance window file, ending the window
+pub fn clear(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Get the maintenance window active at the given time, if any
+///
+/// An unreadable window file is reported, and considered as no maintenance.
+pub fn active(path: &Path, now: DateTime<Utc>) -> Option<MaintenanceWindow> {
+    match load(path) {
+        Ok(window) => window.filter(|w| w.is_active(now)),
+        Err(e) => {
+            warn!("Error reading the maintenance window {:?}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Parse a duration such as `2h`, `30m`, `1h30m` or `90s` (plain numbers are seconds)
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_string());
+    }
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(secs));
+    }
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86400,
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("invalid duration '{}' (unknown unit '{}')", s, c)),
+        };
+        let value: u64 = number
+            .parse()
+            .map_err(|_| format!("invalid duration '{}'", s))?;
+        total = total.saturating_add(value.saturating_mul(unit));
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(format!("invalid duration '{}' (missing unit)", s));
+    }
+    Ok(Duration::from_secs(total))
+}
@@ -0,0 +1,297 @@
+//! Discord mentions of OpenBar Notifier
+//!
+//! Mention rules attach mentions (e.g. `@here`, a role or a user) to some event types of some
+//! items, e.g. to mention the barmen when the main keg runs out:
+//!
+//! ```toml
+//! [[notify.mentions]]
+//! mention = "role:123456789012345678"
+//! events = ["out_of_stock"]
+//! items = ["Main keg"]
+//! ```
+//!
+//! The mentions of the notified events are prepended to the Discord messages. The Discord
+//! payloads always restrict their `allowed_mentions` to these mentions, so that nothing else
+//! pings anyone (e.g. an item named `@everyone`).
+
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::event::{EventContext, ItemEvent};
+use crate::pattern::ItemPattern;
+
+/// A Discord mention
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Mention {
+    /// `@everyone`: all the members of the channel
+    Everyone,
+    /// `@here`: the online members of the channel
+    Here,
+    /// A role, by ID
+    Role(u64),
+    /// A user, by ID
+    User(u64),
+}
+
+impl std::str::FromStr for Mention {
+    type Err = String;
+
+    /// Parse a mention: `@everyone`, `@here`, `role:<id>` (or `<@&id>`), `user:<id>` (or `<@id>`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let id = |id: &str| {
+            id.parse()
+                .map_err(|_| format!("invalid mention '{}' (invalid ID)", s))
+        };
+        if s == "@everyone" {
+            Ok(Mention::Everyone)
+        } else if s == "@here" {
+            Ok(Mention::Here)
+        } else if let Some(role) = s
+            .strip_prefix("role:")
+            .or_else(|| s.strip_prefix("<@&").and_then(|s| s.strip_suffix('>')))
+        {
+            Ok(Mention::Role(id(role)?))
+        } else if let Some(user) = s
+            .strip_prefix("user:")
+            .or_else(|| s.strip_prefix("<@").and_then(|s| s.strip_suffix('>')))
+        {
+            Ok(Mention::User(id(user.trim_start_matches('!'))?))
+        } else {
+            Err(format!(
+                "invalid mention '{}' (expected @everyone, @here, role:<id> or user:<id>)",
+                s
+            ))
+        }
+    }
+}
+
+impl TryFrom<String> for Mention {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl std::fmt::Display for Mention {
+    /// Format the mention as written in a Discord message
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mention::Everyone => write!(f, "@everyone"),
+            Mention::Here => write!(f, "@here"),
+            Mention::Role(id) => write!(f, "<@&{}>", id),
+            Mention::User(id) => write!(f, "<@{}>", id),
+        }
+    }
+}
+
+/// A mention rule, attaching a mention to some event types of some items
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MentionRule {
+    /// The mention
+    pub mention: Mention,
+    /// Types of the events to mention (e.g. `out_of_stock`), all if empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Items whose events are mentioned, all if empty
+    #[serde(default)]
+    pub items: Vec<ItemPattern>,
+}
+
+impl MentionRule {
+    /// Parse a mention rule from its textual representation
+    ///
+    /// The format is the mention, optionally followed by whitespace-separated options:
+    /// - `events=a+b`: only mention the events of type `a` or `b` (e.g. `out_of_stock`)
+    /// - `items=a+b`: only mention the events of the items matching the patterns `a` or `b`
+    ///   (see [ItemPattern])
+    ///
+    /// For instance: `role:123456789012345678 events=out_of_stock items=Kegs`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut parts = s.split_whitespace();
+        let mention = parts
+            .next()
+            .ok_or_else(|| "empty mention rule".to_string())?
+            .parse()?;
+        let mut rule = MentionRule {
+            mention,
+            events: Vec::new(),
+            items: Vec::new(),
+        };
+        for option in parts {
+            let list = |list: &str| -> Vec<String> {
+                list.split('+')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            };
+            match option.split_once('=') {
+                Some(("events", events)) => {
+                    rule.events = list(events).into_iter().map(|e| e.to_lowercase()).collect()
+                }
+                Some(("items", patterns)) => {
+                    rule.items = list(patterns)
+                        .iter()
+                        .map(|p| ItemPattern::parse(p))
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| format!("invalid pattern: {}", e))?
+                }
+                _ => return Err(format!("unknown mention rule option '{}'", option)),
+            }
+        }
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Check that the event types of the rule exist
+    pub fn validate(&self) -> Result<(), String> {
+        match self
+            .events
+            .iter()
+            .find(|e| !ItemEvent::KINDS.contains(&e.as_str()))
+        {
+            Some(event) => Err(format!(
+                "unknown event type '{}' (expected one of {})",
+                event,
+                ItemEvent::KINDS.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Check if the rule applies to an event
+    pub fn applies(&self, ctx: &EventContext) -> bool {
+        (self.events.is_empty() || self.events.iter().any(|e| e == ctx.event.kind()))
+            && (self.items.is_empty()
+                || self
+                    .items
+                    .iter()
+                    .any(|p| p.matches(&ctx.item, &ctx.category_name)))
+    }
+}
+
+/// Get the mentions of some events, by the given rules (without duplicates)
+pub fn mentions<'a>(
+    rules: &[MentionRule],
+    events: impl IntoIterator<Item = &'a EventContext>,
+) -> BTreeSet<Mention> {
+    let mut mentions = BTreeSet::new();
+    for ctx in events {
+        mentions.extend(
+            rules
+                .iter()
+                .filter(|rule| rule.applies(ctx))
+                .map(|rule| rule.mention),
+        );
+    }
+    mentions
+}
+
+/// Render the line of the mentions prepended to a message (empty without mentions)
+pub fn mention_line(mentions: &BTreeSet<Mention>) -> String {
+    if mentions.is_empty() {
+        return String::new();
+    }
+    let mentions: Vec<String> = mentions.iter().map(Mention::to_string).collect();
+    format!("{}\n", mentions.join(" "))
+}
+
+/// Build the Discord `allowed_mentions` object, only allowing the given mentions
+pub fn allowed_mentions(mentions: &BTreeSet<Mention>) -> Value {
+    let mut parse = Vec::new();
+    let mut roles = Vec::new();
+    let mut users = Vec::new();
+    for mention in mentions {
+        match mention {
+            // Discord allows both `@everyone` and `@here` with `everyone`
+            Mention::Everyone | Mention::Here => {
+                if parse.is_empty() {
+                    parse.push("everyone");
+                }
+            }
+            Mention::Role(id) => roles.push(id.to_string()),
+            Mention::User(id) => users.push(id.to_string()),
+        }
+    }
+    json!({ "parse": parse, "roles": roles, "users": users })
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{Item, ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::event::Severity;
+
+    fn context(name: &str, event: ItemEvent) -> EventContext {
+        EventContext {
+            event,
+            item: Item::new(
+                Uuid::from_u128(1),
+                ItemPrices::new(100, 100, 100, 100, 100, 150),
+                0,
+                10,
+                Uuid::from_u128(0),
+                name.to_string(),
+                String::new(),
+                false,
+                ItemState::ItemBuyable,
+            ),
+            category_name: "Beers".to_string(),
+            tags: BTreeSet::new(),
+            restricted: false,
+            sales_rate: None,
+            price: None,
+            severity: Severity::Info,
+            correction: None,
+        }
+    }
+
+    #[test]
+    fn parses_mentions() {
+        assert_eq!("@here".parse(), Ok(Mention::Here));
+        assert_eq!("role:42".parse(), Ok(Mention::Role(42)));
+        assert_eq!("<@&42>".parse(), Ok(Mention::Role(42)));
+        assert_eq!("<@!7>".parse(), Ok(Mention::User(7)));
+        assert!("role:abc".parse::<Mention>().is_err());
+        assert!("barman".parse::<Mention>().is_err());
+        assert_eq!(Mention::Role(42).to_string(), "<@&42>");
+        assert!(MentionRule::parse("@here events=sold_out").is_err());
+    }
+
+    #[test]
+    fn mentions_the_matching_events() {
+        let rules = vec![
+            MentionRule::parse("role:42 events=out_of_stock items=Main*").unwrap(),
+            MentionRule::parse("@here events=out_of_stock+removed").unwrap(),
+        ];
+        let keg = context("Main keg", ItemEvent::OutOfStock);
+        let mentioned = mentions(&rules, [&keg]);
+        assert_eq!(
+            mention_line(&mentioned),
+            "@here <@&42>\n",
+            "{:?}",
+            mentioned
+        );
+        assert_eq!(
+            allowed_mentions(&mentioned),
+            json!({ "parse": ["everyone"], "roles": ["42"], "users": [] })
+        );
+
+        let chips = context("Chips", ItemEvent::OutOfStock);
+        assert_eq!(mentions(&rules, [&chips]), BTreeSet::from([Mention::Here]));
+        let added = context("Main keg", ItemEvent::Added);
+        assert!(mentions(&rules, [&added]).is_empty());
+        assert_eq!(
+            allowed_mentions(&BTreeSet::new()),
+            json!({ "parse": [], "roles": [], "users": [] })
+        );
+    }
+}
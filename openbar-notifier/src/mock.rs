@@ -0,0 +1,256 @@
+//! Mock OpenBar instance for integration tests (requires the `test-util` feature)
+//!
+//! The [MockOpenBar] server serves the WebUI `config.json` and the few API endpoints used
+//! by the notifier (card login, session account, logout, categories and their items, account
+//! transactions and refills) from a [FixtureSet].
+//! The served snapshot can be advanced between two checks, to simulate the evolution of
+//! the catalog. The server also hosts a webhook, recording the notifications it receives,
+//! so that the full fetch-diff-notify pipeline can be tested without a real instance.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use openbar_api::models::Transaction;
+use serde_json::{Value, json};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::fixtures::FixtureSet;
+
+/// Local token served in the mock `config.json`
+pub const MOCK_LOCAL_TOKEN: &str = "mock-local-token";
+
+/// Session cookie set by the mock card login
+pub const MOCK_SESSION: &str = "BAR_SESS=mock-session";
+
+/// Shared state of the mock server
+#[derive(Debug)]
+struct MockState {
+    base_url: String,
+    fixtures: FixtureSet,
+    snapshot: AtomicUsize,
+    messages: Mutex<Vec<String>>,
+    received: AtomicUsize,
+    card_logins: AtomicUsize,
+    /// Category whose items cannot be fetched (HTTP 500), if any
+    failing_category: Mutex<Option<Uuid>>,
+    /// Transactions of the account, most recent first
+    transactions: Mutex<Vec<Transaction>>,
+}
+
+/// A mock OpenBar instance, serving fixtures over HTTP on a local port
+///
+/// The server is stopped when the mock is dropped.
+#[derive(Debug)]
+pub struct MockOpenBar {
+    addr: SocketAddr,
+    state: Arc<MockState>,
+    handle: JoinHandle<()>,
+}
+
+impl MockOpenBar {
+    /// Start a mock instance serving the given fixtures, starting at the first snapshot
+    pub async fn start(fixtures: FixtureSet) -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(MockState {
+            base_url: format!("http://{}", addr),
+            fixtures,
+            snapshot: AtomicUsize::new(0),
+            messages: Mutex::new(Vec::new()),
+            received: AtomicUsize::new(0),
+            card_logins: AtomicUsize::new(0),
+            failing_category: Mutex::new(None),
+            transactions: Mutex::new(Vec::new()),
+        });
+        let router = Router::new()
+            .route("/config.json", get(get_config))
+            .route("/api/auth/card", post(connect_card))
+            .route("/api/account", get(get_account))
+            .route("/api/logout", get(logout))
+            .route("/api/categories", get(get_categories))
+            .route("/api/categories/{id}/items", get(get_category_items))
+            .route("/api/account/transactions", get(get_transactions))
+            .route("/api/account/refills", get(get_refills))
+            .route("/webhook", post(post_webhook))
+            .with_state(state.clone());
+        let handle = tokio::spawn(async move {
+            let _ = axum::serve(listener, router).await;
+        });
+        Ok(MockOpenBar {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// Get the URL of the instance (to be used as the instance URL of the configuration)
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Get the URL of the webhook recording the notifications
+    pub fn webhook_url(&self) -> String {
+        format!("http://{}/webhook", self.addr)
+    }
+
+    /// Get the index of the served snapshot
+    pub fn snapshot(&self) -> usize {
+        self.state.snapshot.load(Ordering::SeqCst)
+    }
+
+    /// Serve the given snapshot (clamped to the last one)
+    pub fn set_snapshot(&self, snapshot: usize) {
+        let last = self.state.fixtures.snapshots.len().saturating_sub(1);
+        self.state
+            .snapshot
+            .store(snapshot.min(last), Ordering::SeqCst);
+    }
+
+    /// Serve the next snapshot, returns `false` if the last one is already served
+    pub fn advance(&self) -> bool {
+        let snapshot = self.snapshot();
+        self.set_snapshot(snapshot + 1);
+        self.snapshot() != snapshot
+    }
+
+    /// Fail the requests of the items of a category (with an HTTP 500 error), or none
+    pub fn set_failing_category(&self, category: Option<Uuid>) {
+        *self.state.failing_category.lock().unwrap() = category;
+    }
+
+    /// Record a transaction of the account, as its most recent one
+    pub fn push_transaction(&self, transaction: Transaction) {
+        self.state
+            .transactions
+            .lock()
+            .unwrap()
+            .insert(0, transaction);
+    }
+
+    /// Get the number of card logins so far
+    pub fn card_logins(&self) -> usize {
+        self.state.card_logins.load(Ordering::SeqCst)
+    }
+
+    /// Take the notifications received by the webhook so far
+    pub fn take_messages(&self) -> Vec<String> {
+        std::mem::take(&mut *self.state.messages.lock().unwrap())
+    }
+}
+
+impl Drop for MockOpenBar {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// `GET /config.json`
+async fn get_config(State(state): State<Arc<MockState>>) -> Json<Value> {
+    Json(json!({
+        "api": format!("{}/api", state.base_url),
+        "local_token": MOCK_LOCAL_TOKEN,
+    }))
+}
+
+/// `POST /api/auth/card`
+async fn connect_card(State(state): State<Arc<MockState>>) -> impl IntoResponse {
+    state.card_logins.fetch_add(1, Ordering::SeqCst);
+    (
+        [(header::SET_COOKIE, format!("{}; Path=/", MOCK_SESSION))],
+        Json(json!({ "account": account() })),
+    )
+}
+
+/// `GET /api/account`, for the sessions of the card logins
+async fn get_account(headers: HeaderMap) -> Result<Json<Value>, StatusCode> {
+    let logged_in = headers
+        .get(header::COOKIE)
+        .and_then(|cookies| cookies.to_str().ok())
+        .is_some_and(|cookies| cookies.split("; ").any(|c| c == MOCK_SESSION));
+    if !logged_in {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(Json(json!({ "account": account() })))
+}
+
+/// Account of the card logins
+fn account() -> Value {
+    json!({
+        "id": Uuid::nil(),
+        "first_name": "Mock",
+        "last_name": "Account",
+        "email_address": "mock@example.org",
+        "balance": 0,
+        "points": 0,
+        "role": "member",
+        "price_role": "ceten",
+        "state": "account_ok",
+        "wants_to_staff": false,
+    })
+}
+
+/// `GET /api/logout`
+async fn logout() -> StatusCode {
+    StatusCode::OK
+}
+
+/// `GET /api/categories`
+async fn get_categories(State(state): State<Arc<MockState>>) -> Json<Value> {
+    Json(json!(state.fixtures.categories))
+}
+
+/// `GET /api/categories/{id}/items`
+async fn get_category_items(
+    State(state): State<Arc<MockState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Value>, StatusCode> {
+    if *state.failing_category.lock().unwrap() == Some(id) {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    let items = state
+        .fixtures
+        .category_items(state.snapshot.load(Ordering::SeqCst), id);
+    Ok(Json(json!({
+        "items": items,
+        "page": 0,
+        "limit": items.len(),
+        "max_page": 0,
+    })))
+}
+
+/// `GET /api/account/transactions`
+async fn get_transactions(State(state): State<Arc<MockState>>) -> Json<Value> {
+    let transactions = state.transactions.lock().unwrap();
+    Json(json!({
+        "transactions": *transactions,
+        "page": 0,
+        "limit": transactions.len(),
+        "max_page": 0,
+    }))
+}
+
+/// `GET /api/account/refills`, the account never being refilled
+async fn get_refills() -> Json<Value> {
+    Json(json!({
+        "refills": [],
+        "page": 0,
+        "limit": 0,
+        "max_page": 0,
+    }))
+}
+
+/// `POST /webhook`
+async fn post_webhook(State(state): State<Arc<MockState>>, Json(body): Json<Value>) -> Json<Value> {
+    let content = body["content"].as_str().unwrap_or_default().to_string();
+    state.messages.lock().unwrap().push(content.clone());
+    // As a Discord webhook waited for, the messages being numbered
+    let id = state.received.fetch_add(1, Ordering::SeqCst) + 1;
+    Json(json!({ "id": id.to_string(), "content": content }))
+}
@@ -0,0 +1,84 @@
+//! Per-item manual mutes of OpenBar Notifier
+//!
+//! An operator can silence a (flapping) item for a while, without editing the configuration
+//! and redeploying. The mute is written into the item metadata of the store (see
+//! [crate::store::ItemMeta::muted_until]), and the events of a muted item are dropped.
+//!
+//! Since the daemon keeps the stores in memory, the mute requests received by its HTTP API
+//! are queued in a [MuteQueue], and applied by the [crate::runner::Runner] at its next cycle.
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::store::ItemStore;
+
+/// A request to mute (or unmute) an item
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MuteRequest {
+    /// Only apply to the instance with this name (all the instances if not set)
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// The item, by ID or name (case-insensitive)
+    pub item: String,
+    /// Mute the item until then (unmute it if not set)
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl MuteRequest {
+    /// Check if the request applies to the instance with the given name
+    pub fn applies_to(&self, instance: &str) -> bool {
+        self.instance.as_deref().is_none_or(|name| name == instance)
+    }
+
+    /// Apply the request to a store, returning the ID of the (un)muted item
+    pub fn apply(&self, store: &mut ItemStore) -> Result<Uuid, String> {
+        let id = find_item(store, &self.item)?;
+        store.update_meta(id, |meta| meta.muted_until = self.until);
+        Ok(id)
+    }
+}
+
+/// Queue of the mute requests, waiting to be applied by the runner
+#[derive(Debug, Default)]
+pub struct MuteQueue {
+    requests: Mutex<Vec<MuteRequest>>,
+}
+
+impl MuteQueue {
+    /// Queue a mute request
+    pub fn push(&self, request: MuteRequest) {
+        self.requests.lock().unwrap().push(request);
+    }
+
+    /// Take all the queued requests (oldest first)
+    pub fn take(&self) -> Vec<MuteRequest> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
+
+/// Find an item of a store, by ID or by name (case-insensitive)
+pub fn find_item(store: &ItemStore, query: &str) -> Result<Uuid, String> {
+    if let Ok(id) = query.trim().parse::<Uuid>() {
+        return store
+            .find(id)
+            .map(|item| item.id)
+            .ok_or_else(|| format!("no item with ID {}", id));
+    }
+    let matches: Vec<Uuid> = store
+        .iter()
+        .filter(|item| item.name.trim().eq_ignore_ascii_case(query.trim()))
+        .map(|item| item.id)
+        .collect();
+    match matches[..] {
+        [id] => Ok(id),
+        [] => Err(format!("no item named '{}'", query)),
+        _ => Err(format!(
+            "{} items named '{}', use the item ID instead",
+            matches.len(),
+            query
+        )),
+    }
+}
@@ -0,0 +1,998 @@
+//! Notification orchestrator for OpenBar Notifier
+//!
+//! The [Notifier] routes the item events to the targets, renders the messages and
+//! delivers them. When the crate is used as a library, host applications can register
+//! hooks on it to veto, augment or mirror the notifications without forking the
+//! delivery code:
+//! - pre-render hooks are called for each (target, event) pair before rendering, they
+//!   may modify the event and return `false` to drop it for this target;
+//! - post-delivery observers are called after each delivery attempt.
+//!
+//! The messages of all the targets are rendered first, then the targets are notified
+//! concurrently, so that a slow webhook does not delay the others of the same priority: the
+//! targets are dispatched in tiers of priority (see [crate::priority]), the most urgent ones
+//! being notified before the next tier is, and the messages of each target are delivered in
+//! order, within its timeout (see [Notifier::set_target_timeout]).
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::Write;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::chaos::Chaos;
+use crate::config::TargetConfig;
+use crate::event::EventContext;
+use crate::feed::{self, FeedEntry};
+use crate::journal::JournalEntry;
+use crate::layout::{self, EventSort};
+use crate::mention::{self, MentionRule};
+use crate::payload::{self, Payload, PayloadFormat, SIGNATURE_HEADER, events_payload, sign};
+use crate::priority::{DeliveryQueue, Priority};
+use crate::ratelimit::{RateLimiter, parse_retry_after};
+use crate::render::{Formatting, render_digest, render_group, render_line, split_message};
+use crate::routing;
+use crate::sink;
+use crate::theme::{fill, try_fill};
+
+/// Maximum length of a message (in characters), as accepted by Discord webhooks
+pub const MAX_MESSAGE_LEN: usize = 2000;
+
+/// Maximum number of retries of a message rejected with `429 Too Many Requests`
+pub const MAX_RATE_LIMITED_RETRIES: usize = 3;
+
+/// Hook called before rendering an event for a target, returns `false` to veto it
+pub type PreRenderHook = Box<dyn Fn(&TargetConfig, &mut EventContext) -> bool + Send + Sync>;
+
+/// Hook called after each delivery attempt
+pub type PostDeliveryHook = Box<dyn Fn(&Delivery) + Send + Sync>;
+
+/// Messages rendered for a target: the target, its events, and its messages (or structured
+/// payload body) along with their payloads and the events rendered in them
+type RenderedMessages<'a> = (
+    &'a TargetConfig,
+    Vec<EventContext>,
+    Vec<(String, Payload, Vec<EventContext>)>,
+);
+
+/// Spans (in characters) of the event lines of a message body, along with the indexes of
+/// the events they render
+type EventSpans = Vec<(Range<usize>, Vec<usize>)>;
+
+/// Status of a delivery attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "detail", rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// The target accepted the notification
+    Accepted,
+    /// The notification was rendered for a dry-run target, but not sent
+    DryRun,
+    /// The target kept rate-limiting the notification, even after the retries
+    RateLimited,
+    /// The target rejected the notification with this HTTP status code (e.g. a revoked
+    /// webhook), retrying it is pointless
+    Rejected(u16),
+    /// The target failed with this HTTP status code (e.g. a server error), it may recover
+    HttpError(u16),
+    /// The notification could not be sent (network error, timeout, ...)
+    Failed(String),
+}
+
+impl DeliveryStatus {
+    /// Get the status of a response with this (unsuccessful) HTTP status code
+    pub fn from_http(status: reqwest::StatusCode) -> Self {
+        match status {
+            reqwest::StatusCode::TOO_MANY_REQUESTS => DeliveryStatus::RateLimited,
+            reqwest::StatusCode::REQUEST_TIMEOUT => DeliveryStatus::HttpError(status.as_u16()),
+            status if status.is_client_error() => DeliveryStatus::Rejected(status.as_u16()),
+            status => DeliveryStatus::HttpError(status.as_u16()),
+        }
+    }
+
+    /// Check if the delivery succeeded (a dry run being as good as a delivery)
+    pub fn is_success(&self) -> bool {
+        matches!(self, DeliveryStatus::Accepted | DeliveryStatus::DryRun)
+    }
+
+    /// Check if the delivery failed for good, the target rejecting the notification
+    pub fn is_permanent_failure(&self) -> bool {
+        matches!(self, DeliveryStatus::Rejected(_))
+    }
+}
+
+impl fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeliveryStatus::Accepted => write!(f, "accepted"),
+            DeliveryStatus::DryRun => write!(f, "dry run"),
+            DeliveryStatus::RateLimited => write!(f, "rate-limited"),
+            DeliveryStatus::Rejected(status) => write!(f, "rejected (HTTP {})", status),
+            DeliveryStatus::HttpError(status) => write!(f, "failed (HTTP {})", status),
+            DeliveryStatus::Failed(error) => write!(f, "failed ({})", error),
+        }
+    }
+}
+
+/// Result of a delivery attempt, as reported by every payload format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeliveryResult {
+    /// Status of the delivery
+    #[serde(flatten)]
+    pub status: DeliveryStatus,
+    /// Number of retries of the notification (e.g. after being rate-limited)
+    pub retries: usize,
+    /// Identifier of the message given by the provider (e.g. the Twilio message SID), if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_id: Option<String>,
+}
+
+impl DeliveryResult {
+    /// Check if the delivery succeeded (a dry run being as good as a delivery)
+    pub fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+}
+
+impl From<DeliveryStatus> for DeliveryResult {
+    fn from(status: DeliveryStatus) -> Self {
+        DeliveryResult {
+            status,
+            retries: 0,
+            message_id: None,
+        }
+    }
+}
+
+/// A delivery attempt of a notification to a target
+#[derive(Debug, Clone)]
+pub struct Delivery<'a> {
+    /// The target
+    pub target: &'a TargetConfig,
+    /// The rendered message (part), or the body of a structured payload
+    pub content: &'a str,
+    /// Index of the message part (long messages are split in several parts)
+    pub part: usize,
+    /// Number of message parts
+    pub parts: usize,
+    /// The events included in the message
+    pub events: &'a [EventContext],
+    /// Result of the delivery
+    pub result: DeliveryResult,
+}
+
+/// A message accepted by a target, as identified by its provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentMessage {
+    /// Key of the target (see [TargetConfig::key])
+    pub target: String,
+    /// Identifier of the message given by the provider
+    pub message_id: String,
+    /// Events included in the message, by item ID and event type
+    pub events: Vec<(Uuid, &'static str)>,
+}
+
+/// Aggregate outcome of the notification of a batch of events
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DispatchReport {
+    /// Targets which accepted all their messages
+    pub succeeded: usize,
+    /// Targets which rejected (or timed out on) at least one of their messages
+    pub failed: usize,
+    /// Messages delivered (or dry-run)
+    pub sent: usize,
+    /// Messages not delivered
+    pub undelivered: usize,
+    /// Messages rejected for good by their target, among the undelivered ones
+    pub rejected: usize,
+    /// Retries of the messages (e.g. after being rate-limited)
+    pub retries: usize,
+    /// Messages identified by their provider (see [DeliveryResult::message_id])
+    pub messages: Vec<SentMessage>,
+}
+
+impl DispatchReport {
+    /// Check if all the targets accepted all their messages
+    pub fn is_success(&self) -> bool {
+        self.failed == 0
+    }
+
+    /// Account for the result of the delivery of a message
+    pub fn record(&mut self, result: &DeliveryResult) {
+        if result.is_success() {
+            self.sent += 1;
+        } else {
+            self.undelivered += 1;
+        }
+        if result.status.is_permanent_failure() {
+            self.rejected += 1;
+        }
+        self.retries += result.retries;
+    }
+
+    /// Merge the report of a target into this one
+    fn merge(&mut self, target: DispatchReport) {
+        if target.undelivered == 0 {
+            self.succeeded += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.sent += target.sent;
+        self.undelivered += target.undelivered;
+        self.rejected += target.rejected;
+        self.retries += target.retries;
+        self.messages.extend(target.messages);
+    }
+}
+
+/// A batch of events to notify
+#[derive(Debug, Clone, Default)]
+pub struct Batch {
+    /// Name of the instance
+    pub instance: String,
+    /// Header prepended to every message (e.g. the instance name)
+    pub header: String,
+    /// Events previously held (e.g. during quiet hours)
+    pub held: Vec<EventContext>,
+    /// New events
+    pub events: Vec<EventContext>,
+    /// Render the events as a digest instead of one line per event
+    pub digest: bool,
+    /// Order of the events in the messages
+    pub sort: EventSort,
+    /// Group the events of the same item into a single line
+    pub group_by_item: bool,
+    /// Locale-dependent formatting of the messages
+    pub formatting: Formatting,
+    /// Mention rules of the events, for the Discord targets (see [crate::mention])
+    pub mentions: Vec<MentionRule>,
+    /// Base URL of the instance, to resolve the pictures of the items against (no pictures if
+    /// unset)
+    pub base_url: Option<String>,
+}
+
+impl Batch {
+    /// Check if the batch contains no event
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty() && self.events.is_empty()
+    }
+}
+
+/// Notification orchestrator
+pub struct Notifier {
+    http: reqwest::Client,
+    rate_limiter: Arc<RateLimiter>,
+    chaos: Option<Arc<Chaos>>,
+    target_timeout: Option<Duration>,
+    pre_render: Vec<PreRenderHook>,
+    post_delivery: Vec<PostDeliveryHook>,
+}
+
+impl Notifier {
+    /// Create a new notifier, delivering the notifications with the given HTTP client
+    pub fn new(http: reqwest::Client) -> Self {
+        Notifier {
+            http,
+            rate_limiter: Arc::new(RateLimiter::default()),
+            chaos: None,
+            target_timeout: None,
+            pre_render: Vec::new(),
+            post_delivery: Vec::new(),
+        }
+    }
+
+    /// Set the rate limiter of the messages
+    ///
+    /// The rate limiter can be shared between several notifiers, e.g. to rate-limit
+    /// a target notified for several instances as a whole.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>) -> &mut Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    /// Set the failure injector of the webhook requests (see [crate::chaos])
+    pub fn set_chaos(&mut self, chaos: Option<Arc<Chaos>>) -> &mut Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Set the timeout of the delivery of all the messages of a target (none by default)
+    ///
+    /// Once exceeded, the pending messages of the target are reported as failed.
+    pub fn set_target_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.target_timeout = timeout;
+        self
+    }
+
+    /// Register a hook called before rendering each event for each target
+    ///
+    /// The hook may modify the event, and returns `false` to drop it for this target.
+    pub fn on_pre_render(
+        &mut self,
+        hook: impl Fn(&TargetConfig, &mut EventContext) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.pre_render.push(Box::new(hook));
+        self
+    }
+
+    /// Register an observer called after each delivery attempt
+    pub fn on_post_delivery(
+        &mut self,
+        hook: impl Fn(&Delivery) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.post_delivery.push(Box::new(hook));
+        self
+    }
+
+    /// Route the batch of events to the targets, and notify them concurrently
+    ///
+    /// The events of each target are ordered by priority, and the targets are dispatched in
+    /// tiers by the priority of their most urgent event: the targets of a tier are notified
+    /// concurrently, once the previous tier was. The messages of a target are delivered in
+    /// order.
+    pub async fn notify(&self, targets: &[TargetConfig], batch: &Batch) -> DispatchReport {
+        if batch.is_empty() {
+            info!("No item events to notify.");
+            return DispatchReport::default();
+        }
+        let rendered = self.render(targets, batch);
+        let mut queue = DeliveryQueue::default();
+        for (index, (_, all, _)) in rendered.iter().enumerate() {
+            let priority = all.iter().map(Priority::of).max().unwrap_or_default();
+            queue.push(priority, index);
+        }
+
+        let mut report = DispatchReport::default();
+        while !queue.is_empty() {
+            let tier = queue.pop_tier();
+            let results =
+                join_all(tier.into_iter().map(|index| self.deliver(&rendered[index]))).await;
+            for target in results {
+                report.merge(target);
+            }
+        }
+        if report.is_success() {
+            info!(
+                "Notified {} targets ({} messages).",
+                report.succeeded, report.sent
+            );
+        } else {
+            warn!(
+                "Notified {} of {} targets: {} messages sent, {} undelivered ({} rejected).",
+                report.succeeded,
+                report.succeeded + report.failed,
+                report.sent,
+                report.undelivered,
+                report.rejected
+            );
+        }
+        report
+    }
+
+    /// Render the messages (or structured payload) each target would receive for the batch,
+    /// without delivering them (e.g. to preview the templates of the targets)
+    ///
+    /// The targets to which no event of the batch is routed are omitted.
+    pub fn preview<'a>(
+        &self,
+        targets: &'a [TargetConfig],
+        batch: &'a Batch,
+    ) -> Vec<(&'a TargetConfig, Vec<Payload>)> {
+        self.render(targets, batch)
+            .into_iter()
+            .map(|(target, _, payloads)| {
+                (target, payloads.into_iter().map(|(_, p, _)| p).collect())
+            })
+            .collect()
+    }
+
+    /// Route the batch of events to the targets, and render their messages
+    fn render<'a>(
+        &self,
+        targets: &'a [TargetConfig],
+        batch: &'a Batch,
+    ) -> Vec<RenderedMessages<'a>> {
+        let routed_held = routing::route(targets, &batch.held);
+        let routed = routing::route(targets, &batch.events);
+        let mut rendered: Vec<RenderedMessages> = Vec::new();
+        for ((target, held), (_, events)) in routed_held.into_iter().zip(routed) {
+            let held = layout::sort(self.apply_pre_render(target, held), batch.sort);
+            let events = layout::sort(self.apply_pre_render(target, events), batch.sort);
+            if held.is_empty() && events.is_empty() {
+                debug!(
+                    "No item events to notify for target {}.",
+                    target.redacted_url()
+                );
+                continue;
+            }
+            let all: Vec<EventContext> = held.iter().chain(&events).cloned().collect();
+            // The messages (or structured payload body) along with their payloads and events
+            let payloads: Vec<(String, Payload, Vec<EventContext>)> =
+                if target.format.is_structured() {
+                    let payload = events_payload(&batch.instance, &all);
+                    vec![(payload.body(), payload, all.clone())]
+                } else if target.format.is_feed() {
+                    let now = chrono::Utc::now();
+                    let payload = Payload::Entries(
+                        all.iter()
+                            .map(|ctx| FeedEntry::new(ctx, now, &batch.formatting))
+                            .collect(),
+                    );
+                    vec![(payload.body(), payload, all.clone())]
+                } else if target.format == PayloadFormat::Jsonl {
+                    let now = chrono::Utc::now();
+                    let payload = Payload::Lines(
+                        all.iter()
+                            .map(|ctx| {
+                                let entry = JournalEntry {
+                                    at: now,
+                                    instance: batch.instance.clone(),
+                                    context: ctx.clone(),
+                                    previous: None,
+                                };
+                                serde_json::to_string(&entry).unwrap_or_default()
+                            })
+                            .collect(),
+                    );
+                    vec![(payload.body(), payload, all.clone())]
+                } else {
+                    let mentions = match target.format {
+                        PayloadFormat::Discord => mention::mentions(&batch.mentions, &all),
+                        _ => BTreeSet::new(),
+                    };
+                    // The pictures are shown along with the first message
+                    let pictures = match &batch.base_url {
+                        Some(base_url) if target.images => payload::pictures(base_url, &all),
+                        _ => Vec::new(),
+                    };
+                    let mention_line = mention::mention_line(&mentions);
+                    let (body, spans) = render_body(batch, target, &held, &events);
+                    let body = mention_line.clone() + &body;
+                    let offset = mention_line.chars().count();
+                    let max_len = target
+                        .format
+                        .max_message_len()
+                        .saturating_sub(batch.header.chars().count());
+                    let mut start = 0;
+                    split_message(&body, max_len)
+                        .iter()
+                        .enumerate()
+                        .map(|(part, chunk)| {
+                            // The events whose lines are (even partly) in the chunk
+                            let end = start + chunk.chars().count();
+                            let indexes: BTreeSet<usize> = spans
+                                .iter()
+                                .filter(|(span, _)| {
+                                    span.start + offset < end && start < span.end + offset
+                                })
+                                .flat_map(|(_, indexes)| indexes.iter().copied())
+                                .collect();
+                            let events = indexes
+                                .into_iter()
+                                .map(|index| all[index].clone())
+                                .collect();
+                            start = end;
+                            let content = format!("{}{}", batch.header, chunk);
+                            let pictures = if part == 0 {
+                                &pictures[..]
+                            } else {
+                                &pictures[..0]
+                            };
+                            let payload =
+                                target
+                                    .format
+                                    .message(&content, &mentions, pictures, &target.sms);
+                            (content, payload, events)
+                        })
+                        .collect()
+                };
+            rendered.push((target, all, payloads));
+        }
+        rendered
+    }
+
+    /// Deliver the messages rendered for a target in order, within the target timeout
+    async fn deliver(&self, (target, _, payloads): &RenderedMessages<'_>) -> DispatchReport {
+        let deadline = self
+            .target_timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut report = DispatchReport::default();
+        let mut timed_out = false;
+        for (part, (content, payload, events)) in payloads.iter().enumerate() {
+            let result = match deadline {
+                Some(_) if timed_out => {
+                    DeliveryStatus::Failed("target timed out".to_string()).into()
+                }
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.send(target, payload)).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            error!(
+                                "Timed out notifying target {}, {} messages left undelivered",
+                                target.redacted_url(),
+                                payloads.len() - part
+                            );
+                            timed_out = true;
+                            DeliveryStatus::Failed("target timed out".to_string()).into()
+                        }
+                    }
+                }
+                None => self.send(target, payload).await,
+            };
+            report.record(&result);
+            if let Some(message_id) = &result.message_id {
+                report.messages.push(SentMessage {
+                    target: target.key(),
+                    message_id: message_id.clone(),
+                    events: events
+                        .iter()
+                        .map(|ctx| (ctx.item.id, ctx.event.kind()))
+                        .collect(),
+                });
+            }
+            let delivery = Delivery {
+                target,
+                content,
+                part,
+                parts: payloads.len(),
+                events,
+                result,
+            };
+            for hook in &self.post_delivery {
+                hook(&delivery);
+            }
+        }
+        report
+    }
+
+    /// Send a standalone message (e.g. a report) to a target, as a part of `parts`
+    ///
+    /// The message is not split, it must fit in [MAX_MESSAGE_LEN] characters.
+    pub async fn send_message(
+        &self,
+        target: &TargetConfig,
+        content: &str,
+        part: usize,
+        parts: usize,
+    ) -> DeliveryResult {
+        let result = self
+            .send(
+                target,
+                &target
+                    .format
+                    .message(content, &BTreeSet::new(), &[], &target.sms),
+            )
+            .await;
+        let delivery = Delivery {
+            target,
+            content,
+            part,
+            parts,
+            events: &[],
+            result: result.clone(),
+        };
+        for hook in &self.post_delivery {
+            hook(&delivery);
+        }
+        result
+    }
+
+    /// Apply the pre-render hooks to the events routed to a target
+    ///
+    /// The corrections of the events are restricted to the target beforehand.
+    fn apply_pre_render(
+        &self,
+        target: &TargetConfig,
+        events: Vec<&EventContext>,
+    ) -> Vec<EventContext> {
+        events
+            .into_iter()
+            .filter_map(|ctx| {
+                let mut ctx = ctx.clone();
+                // Only correct the messages the target got
+                ctx.correction = ctx
+                    .correction
+                    .take()
+                    .and_then(|correction| correction.for_target(&target.key()));
+                self.pre_render
+                    .iter()
+                    .all(|hook| hook(target, &mut ctx))
+                    .then_some(ctx)
+            })
+            .collect()
+    }
+
+    /// Send a payload to a target, honoring its rate limits
+    ///
+    /// The payloads of the dry-run targets are only logged (and written to their file), the
+    /// feeds and JSON Lines are written to their file, and the standard streams print them.
+    async fn send(&self, target: &TargetConfig, payload: &Payload) -> DeliveryResult {
+        if target.dry_run {
+            return dry_run(target, payload).into();
+        }
+        if let Some(stream) = target.stream() {
+            return match stream.print(&payload.body()) {
+                Ok(()) => DeliveryStatus::Accepted,
+                Err(e) => {
+                    error!("Error printing to {}: {}", target.redacted_url(), e);
+                    DeliveryStatus::Failed(e.to_string())
+                }
+            }
+            .into();
+        }
+        if let Payload::Entries(entries) = payload
+            && target.format.is_feed()
+        {
+            return publish_feed(target, entries).into();
+        }
+        if let Payload::Lines(lines) = payload
+            && target.format == PayloadFormat::Jsonl
+        {
+            return append_lines(target, lines).into();
+        }
+        let mut retries = 0;
+        let (status, message_id) = loop {
+            self.rate_limiter.acquire(&target.url).await;
+            info!("Notifying target {}...", target.redacted_url());
+            if let Some(chaos) = &self.chaos
+                && chaos.webhook_failure(&target.url)
+            {
+                break (DeliveryStatus::HttpError(500), None);
+            }
+            let request = match payload {
+                Payload::Json(_) if target.format.is_structured() && target.secret.is_some() => {
+                    let body = payload.body();
+                    let secret = target.secret.as_deref().unwrap_or_default();
+                    self.http
+                        .post(&target.url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .header(SIGNATURE_HEADER, sign(secret, body.as_bytes()))
+                        .body(body)
+                }
+                // Wait for the Discord messages to be created, to get their ID
+                Payload::Json(value) if target.format == PayloadFormat::Discord => self
+                    .http
+                    .post(&target.url)
+                    .query(&[("wait", "true")])
+                    .json(value),
+                Payload::Json(value) => self.http.post(&target.url).json(value),
+                Payload::Text(text) => self
+                    .http
+                    .post(&target.url)
+                    .header(reqwest::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                    .body(text.clone()),
+                Payload::Form(fields) => self.http.post(&target.url).form(fields),
+                Payload::Query(fields) => self.http.get(&target.url).query(fields),
+                Payload::Entries(entries) => self.http.post(&target.url).json(entries),
+                Payload::Lines(_) => self
+                    .http
+                    .post(&target.url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/x-ndjson")
+                    .body(payload.body()),
+            };
+            let resp = match request.send().await {
+                Ok(resp) => resp,
+                Err(e) => break (DeliveryStatus::Failed(e.to_string()), None),
+            };
+            let status = resp.status();
+            if status.is_success() {
+                let body = resp.text().await.unwrap_or_default();
+                break (DeliveryStatus::Accepted, target.format.message_id(&body));
+            }
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                && retries < MAX_RATE_LIMITED_RETRIES
+            {
+                let retry_after = resp
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(parse_retry_after)
+                    .unwrap_or(std::time::Duration::from_secs(1));
+                warn!(
+                    "Rate-limited by {}, retrying in {:?}",
+                    target.redacted_url(),
+                    retry_after
+                );
+                self.rate_limiter.defer(&target.url, retry_after);
+                retries += 1;
+                continue;
+            }
+            debug!("Response: {}", resp.text().await.unwrap_or_default());
+            break (DeliveryStatus::from_http(status), None);
+        };
+        if status.is_success() {
+            info!(
+                "Notification sent successfully to {}",
+                target.redacted_url()
+            );
+        } else {
+            error!(
+                "Failed to send notification to {}: {}",
+                target.redacted_url(),
+                status
+            );
+        }
+        DeliveryResult {
+            status,
+            retries,
+            message_id,
+        }
+    }
+}
+
+/// Log the payload of a dry-run target, appending it to its file (if any)
+fn dry_run(target: &TargetConfig, payload: &Payload) -> DeliveryStatus {
+    let body = payload.body();
+    info!("Dry run of target {}: {}", target.redacted_url(), body);
+    let Some(path) = &target.dry_run_file else {
+        return DeliveryStatus::DryRun;
+    };
+    let record = serde_json::json!({
+        "at": chrono::Utc::now(),
+        "target": target.redacted_url(),
+        "body": body,
+    });
+    let written = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", record));
+    match written {
+        Ok(()) => DeliveryStatus::DryRun,
+        Err(e) => {
+            error!(
+                "Error writing the dry run of target {} to {:?}: {}",
+                target.redacted_url(),
+                path,
+                e
+            );
+            DeliveryStatus::Failed(e.to_string())
+        }
+    }
+}
+
+/// Add entries to the feed file of a target
+///
+/// The feed is titled after the `bar` variable of the target, if any.
+fn publish_feed(target: &TargetConfig, entries: &[FeedEntry]) -> DeliveryStatus {
+    let Some(path) = target.file_path() else {
+        return DeliveryStatus::Failed("the feed is not a file:// URL".to_string());
+    };
+    info!(
+        "Writing {} entries to the feed {:?}...",
+        entries.len(),
+        path
+    );
+    let title = target
+        .variables
+        .get("bar")
+        .map_or("OpenBar Notifier".to_string(), |bar| {
+            format!("{}: OpenBar Notifier", bar)
+        });
+    let id = format!("urn:openbar-notifier:feed:{}", target.key());
+    match feed::publish(&path, target.format, &title, &id, entries) {
+        Ok(()) => DeliveryStatus::Accepted,
+        Err(e) => {
+            error!("Error writing the feed {:?}: {}", path, e);
+            DeliveryStatus::Failed(e.to_string())
+        }
+    }
+}
+
+/// Append lines to the JSON Lines file of a target
+fn append_lines(target: &TargetConfig, lines: &[String]) -> DeliveryStatus {
+    let Some(path) = target.file_path() else {
+        return DeliveryStatus::Failed("the file is not a file:// URL".to_string());
+    };
+    info!("Appending {} lines to {:?}...", lines.len(), path);
+    match sink::append_lines(&path, lines, target.rotation()) {
+        Ok(()) => DeliveryStatus::Accepted,
+        Err(e) => {
+            error!("Error appending to {:?}: {}", path, e);
+            DeliveryStatus::Failed(e.to_string())
+        }
+    }
+}
+
+/// Render events one line per event, or per item if they are grouped (see [crate::layout])
+///
+/// Each line comes with the indexes of the events it renders.
+fn render_lines(
+    batch: &Batch,
+    events: &[EventContext],
+    target: &TargetConfig,
+) -> Vec<(String, Vec<usize>)> {
+    let (formatting, theme, variables) = (&batch.formatting, target.theme, &target.variables);
+    if batch.group_by_item {
+        layout::group_by_item(events)
+            .iter()
+            .map(|group| {
+                let indexes = group
+                    .iter()
+                    .filter_map(|ctx| events.iter().position(|e| std::ptr::eq(e, *ctx)))
+                    .collect();
+                (render_group(group, formatting, theme, variables), indexes)
+            })
+            .collect()
+    } else {
+        events
+            .iter()
+            .enumerate()
+            .map(|(index, ctx)| (render_line(ctx, formatting, theme, variables), vec![index]))
+            .collect()
+    }
+}
+
+/// Render the message body (without the instance header) for a target
+///
+/// The theme header and footer are included if the target defines their variables. The body
+/// comes with the spans (in characters) of the event lines, along with the indexes of their
+/// events in the held then new events.
+fn render_body(
+    batch: &Batch,
+    target: &TargetConfig,
+    held: &[EventContext],
+    events: &[EventContext],
+) -> (String, EventSpans) {
+    let (theme, variables) = (target.theme, &target.variables);
+    let templates = theme.templates().localized(&batch.formatting.language());
+    let mut body = String::new();
+    let mut spans = Vec::new();
+    let mut push = |body: &mut String, text: &str, indexes: Vec<usize>| {
+        let start = body.chars().count();
+        body.push_str(text);
+        if !indexes.is_empty() {
+            spans.push((start..body.chars().count(), indexes));
+        }
+    };
+    if let Some(header) = try_fill(templates.header, &[], variables) {
+        push(&mut body, &header, Vec::new());
+    }
+    if batch.digest {
+        let all: Vec<&EventContext> = held.iter().chain(events).collect();
+        let digest = render_digest(&all, &batch.formatting);
+        push(&mut body, &digest, (0..all.len()).collect());
+    } else {
+        if !held.is_empty() {
+            push(
+                &mut body,
+                &fill(templates.held_heading, &[], variables),
+                Vec::new(),
+            );
+            for (line, indexes) in render_lines(batch, held, target) {
+                push(&mut body, &line, indexes);
+            }
+            if !events.is_empty() {
+                push(
+                    &mut body,
+                    &fill(templates.new_heading, &[], variables),
+                    Vec::new(),
+                );
+            }
+        }
+        for (line, indexes) in render_lines(batch, events, target) {
+            let indexes = indexes
+                .into_iter()
+                .map(|index| held.len() + index)
+                .collect();
+            push(&mut body, &line, indexes);
+        }
+    }
+    if let Some(footer) = try_fill(templates.footer, &[], variables) {
+        push(&mut body, &footer, Vec::new());
+    }
+    (body, spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{Item, ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::event::ItemEvent;
+
+    fn context(n: u128) -> EventContext {
+        tagged(n, ItemEvent::BecomeBuyable, "beer")
+    }
+
+    fn tagged(n: u128, event: ItemEvent, tag: &str) -> EventContext {
+        EventContext {
+            event,
+            item: Item::new(
+                Uuid::from_u128(n),
+                ItemPrices::new(200, 200, 200, 200, 200, 300),
+                10,
+                10,
+                Uuid::from_u128(0),
+                format!("Beer #{:03}", n),
+                String::new(),
+                false,
+                ItemState::ItemBuyable,
+            ),
+            category_name: "Beers".to_string(),
+            tags: BTreeSet::from([tag.to_string()]),
+            restricted: false,
+            sales_rate: None,
+            price: None,
+            severity: Default::default(),
+            correction: None,
+        }
+    }
+
+    #[test]
+    fn records_the_events_of_each_message_part() {
+        let notifier = Notifier::new(reqwest::Client::new());
+        let targets = vec![TargetConfig::parse("https://hook/1 format=text").unwrap()];
+        let batch = Batch {
+            instance: "kfet".to_string(),
+            header: "[kfet] ".to_string(),
+            held: (0..20).map(context).collect(),
+            events: (20..200).map(context).collect(),
+            digest: false,
+            sort: Default::default(),
+            group_by_item: false,
+            formatting: Default::default(),
+            mentions: Vec::new(),
+            base_url: None,
+        };
+        let rendered = notifier.render(&targets, &batch);
+        let (_, all, payloads) = &rendered[0];
+        assert!(payloads.len() > 1);
+        for (content, _, events) in payloads {
+            let rendered: Vec<Uuid> = all
+                .iter()
+                .filter(|ctx| content.contains(&ctx.item.name))
+                .map(|ctx| ctx.item.id)
+                .collect();
+            let ids: Vec<Uuid> = events.iter().map(|ctx| ctx.item.id).collect();
+            assert_eq!(ids, rendered, "{}", content);
+        }
+        let recorded: usize = payloads.iter().map(|(_, _, events)| events.len()).sum();
+        assert_eq!(recorded, all.len());
+    }
+
+    #[tokio::test]
+    async fn dispatches_the_urgent_targets_first() {
+        // The urgent target accepts the connections, but never responds
+        let slow = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let slow_url = format!("http://{}/hook", slow.local_addr().unwrap());
+        let targets = vec![
+            TargetConfig::parse("https://hook/churn tags=beer dry_run=true").unwrap(),
+            TargetConfig::parse(&format!("{} tags=keg", slow_url)).unwrap(),
+        ];
+        let mut notifier = Notifier::new(reqwest::Client::new());
+        notifier.set_target_timeout(Some(Duration::from_millis(300)));
+        let order = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = order.clone();
+        notifier.on_post_delivery(move |delivery| {
+            recorded.lock().unwrap().push(delivery.target.url.clone());
+        });
+        let batch = Batch {
+            instance: "kfet".to_string(),
+            header: String::new(),
+            held: Vec::new(),
+            events: vec![context(1), tagged(2, ItemEvent::OutOfStock, "keg")],
+            digest: false,
+            sort: Default::default(),
+            group_by_item: false,
+            formatting: Default::default(),
+            mentions: Vec::new(),
+            base_url: None,
+        };
+
+        // The churn is only delivered once the urgent target timed out
+        let report = notifier.notify(&targets, &batch).await;
+        assert_eq!(report.undelivered, 1);
+        assert_eq!(
+            *order.lock().unwrap(),
+            [slow_url, "https://hook/churn".to_string()]
+        );
+        drop(slow);
+    }
+}
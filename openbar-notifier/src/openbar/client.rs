@@ -1,11 +1,84 @@
 use std::sync::Arc;
 
 use openbar_api::apis::Error as ApiError;
+use openbar_api::apis::accounts_api::{AccountsApi, AccountsApiClient};
 use openbar_api::apis::auth_api::{AuthApi, AuthApiClient, ConnectCardError, LogoutError};
 use openbar_api::apis::categories_api::{CategoriesApi, CategoriesApiClient, GetCategoriesError};
 use openbar_api::apis::configuration::Configuration as BarConfiguration;
 use openbar_api::apis::items_api::{GetCategoryItemsError, ItemsApi, ItemsApiClient};
-use openbar_api::models::{Account, Category, ConnectCardRequest, Item};
+use openbar_api::apis::refills_api::{GetSelfRefillsError, RefillsApi, RefillsApiClient};
+use openbar_api::apis::transactions_api::{
+    GetCurrentAccountTransactionsError, TransactionsApi, TransactionsApiClient,
+};
+use openbar_api::models::{
+    Account, Category, ConnectCardRequest, GetCategoryItems200Response, Item, Refill, Transaction,
+};
+use reqwest::cookie::{CookieStore, Jar};
+use uuid::Uuid;
+
+use super::{ItemLookupError, LoginError};
+use crate::chaos::Chaos;
+
+/// Number of items requested per page by [OpenBarClient::get_category_items]
+pub const ITEMS_PAGE_LIMIT: i64 = 100;
+
+/// A page of the items of a category, along with its pagination metadata
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItemsPage {
+    /// Items of the page
+    pub items: Vec<Item>,
+    /// Index of the page (starting from 0)
+    pub page: i64,
+    /// Maximal number of items per page
+    pub limit: i64,
+    /// Index of the last page
+    pub max_page: i64,
+}
+
+impl ItemsPage {
+    /// Check if more items are available after this page
+    pub fn is_truncated(&self) -> bool {
+        self.page < self.max_page
+    }
+}
+
+impl From<GetCategoryItems200Response> for ItemsPage {
+    fn from(response: GetCategoryItems200Response) -> Self {
+        ItemsPage {
+            items: response.items,
+            page: response.page,
+            limit: response.limit,
+            max_page: response.max_page,
+        }
+    }
+}
+
+/// A way of logging in to an OpenBar instance, tried in order by [OpenBarClient::login]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoginStrategy {
+    /// Resume the session persisted by a previous login (see [OpenBarClient::session])
+    Session(String),
+    /// Log in with a card ID and its PIN
+    Card { card_id: String, pin: String },
+}
+
+impl std::fmt::Display for LoginStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoginStrategy::Session(_) => write!(f, "persisted session"),
+            LoginStrategy::Card { .. } => write!(f, "card and PIN"),
+        }
+    }
+}
+
+/// Successful login to an OpenBar instance
+#[derive(Debug, Clone)]
+pub struct Login {
+    /// Strategy which logged in (e.g. `persisted session`)
+    pub strategy: String,
+    /// Logged-in account, if returned by the API
+    pub account: Option<Account>,
+}
 
 /// `OpenBarClient` provides a convenient wrapper for interacting with the OpenBar API.
 /// It manages API configuration, authentication tokens, and exposes API clients.
@@ -21,13 +94,15 @@ use openbar_api::models::{Account, Category, ConnectCardRequest, Item};
 #[derive(Default)]
 pub struct OpenBarClient {
     bar_config: Arc<BarConfiguration>,
+    chaos: Option<Arc<Chaos>>,
+    cookies: Option<Arc<Jar>>,
 }
 
 impl OpenBarClient {
     /// Create a new OpenBarClient with the specified API base URL.
     /// This uses a default reqwest client.
     pub fn new(api_base: &str) -> Self {
-        return OpenBarClient::with_client(api_base, reqwest::Client::new());
+        OpenBarClient::with_client(api_base, reqwest::Client::new())
     }
 
     /// Create a new OpenBarClient with the specified API base URL and a custom reqwest client.
@@ -40,7 +115,8 @@ impl OpenBarClient {
         };
         OpenBarClient {
             bar_config: Arc::new(bar_config),
-            ..Default::default()
+            chaos: None,
+            cookies: None,
         }
     }
 
@@ -51,7 +127,35 @@ impl OpenBarClient {
     pub fn with_configuration(configuration: BarConfiguration) -> Self {
         OpenBarClient {
             bar_config: Arc::new(configuration),
-            ..Default::default()
+            chaos: None,
+            cookies: None,
+        }
+    }
+
+    /// Set the failure injector of the requests (see [crate::chaos])
+    pub fn set_chaos(&mut self, chaos: Option<Arc<Chaos>>) {
+        self.chaos = chaos;
+    }
+
+    /// Set the cookie jar of the HTTP client, holding the session cookies
+    ///
+    /// The sessions can only be persisted and resumed with the jar of the client.
+    pub fn set_cookie_jar(&mut self, cookies: Option<Arc<Jar>>) {
+        self.cookies = cookies;
+    }
+
+    /// Get the current session (the cookies sent to the API), to persist it
+    pub fn session(&self) -> Option<String> {
+        let url = url::Url::parse(&self.bar_config.base_path).ok()?;
+        let cookies = self.cookies.as_ref()?.cookies(&url)?;
+        cookies.to_str().ok().map(str::to_string)
+    }
+
+    /// Inject a delay and/or a failure before a request, if enabled
+    async fn inject<T>(&self, request: &str) -> Result<(), ApiError<T>> {
+        match &self.chaos {
+            Some(chaos) => chaos.api_request(request).await.map_err(ApiError::Io),
+            None => Ok(()),
         }
     }
 
@@ -71,6 +175,11 @@ impl OpenBarClient {
         AuthApiClient::new(self.bar_config.clone())
     }
 
+    /// Get an instance of the AccountsApiClient using the current configuration.
+    fn as_accounts(&self) -> AccountsApiClient {
+        AccountsApiClient::new(self.bar_config.clone())
+    }
+
     /// Get an instance of the CategoriesApiClient using the current configuration.
     fn as_categories(&self) -> CategoriesApiClient {
         CategoriesApiClient::new(self.bar_config.clone())
@@ -81,6 +190,16 @@ impl OpenBarClient {
         ItemsApiClient::new(self.bar_config.clone())
     }
 
+    /// Get an instance of the TransactionsApiClient using the current configuration.
+    fn as_transactions(&self) -> TransactionsApiClient {
+        TransactionsApiClient::new(self.bar_config.clone())
+    }
+
+    /// Get an instance of the RefillsApiClient using the current configuration.
+    fn as_refills(&self) -> RefillsApiClient {
+        RefillsApiClient::new(self.bar_config.clone())
+    }
+
     /// Log in using a card ID and PIN, returning the associated Account if successful.
     /// This is a convenience method that wraps the `connect_card` method of the AuthApiClient.
     ///
@@ -90,6 +209,7 @@ impl OpenBarClient {
         card_id: &str,
         pin: &str,
     ) -> Result<Option<Account>, ApiError<ConnectCardError>> {
+        self.inject("login").await?;
         let auth_api = self.as_auth();
         let auth_req = ConnectCardRequest::new(card_id.to_owned(), pin.to_owned());
         match auth_api.connect_card(Some(auth_req)).await {
@@ -104,10 +224,59 @@ impl OpenBarClient {
         }
     }
 
+    /// Log in with the first strategy that succeeds, trying them in order
+    ///
+    /// A persisted session is resumed if the API still knows its account. The error lists
+    /// what every strategy attempted.
+    pub async fn login(&self, strategies: &[LoginStrategy]) -> Result<Login, LoginError> {
+        let mut attempts = Vec::new();
+        for strategy in strategies {
+            let result = match strategy {
+                LoginStrategy::Session(session) => self.resume_session(session).await,
+                LoginStrategy::Card { card_id, pin } => self
+                    .login_by_card(card_id, pin)
+                    .await
+                    .map_err(|e| e.to_string()),
+            };
+            match result {
+                Ok(account) => {
+                    return Ok(Login {
+                        strategy: strategy.to_string(),
+                        account,
+                    });
+                }
+                Err(e) => attempts.push((strategy.to_string(), e)),
+            }
+        }
+        Err(LoginError { attempts })
+    }
+
+    /// Resume a persisted session, returning its account
+    async fn resume_session(&self, session: &str) -> Result<Option<Account>, String> {
+        let Some(cookies) = &self.cookies else {
+            return Err("the HTTP client has no cookie jar".to_string());
+        };
+        let url = url::Url::parse(&self.bar_config.base_path).map_err(|e| e.to_string())?;
+        for cookie in session.split(';').map(str::trim).filter(|c| !c.is_empty()) {
+            cookies.add_cookie_str(cookie, &url);
+        }
+        self.inject::<()>("login")
+            .await
+            .map_err(|e| e.to_string())?;
+        match self.as_accounts().get_account().await {
+            Ok(resp) => match resp.account {
+                Some(account) => Ok(Some(*account)),
+                None => Err("expired session".to_string()),
+            },
+            Err(e) => Err(format!("expired session: {}", e)),
+        }
+    }
+
     /// Log out the current user by calling the `logout` method of the AuthApiClient.
     ///
     /// Note: this method will modify the internal state of the client by clearing the auth token/cookies.
     pub async fn logout(&self) -> Result<(), ApiError<LogoutError>> {
+        self.inject("logout").await?;
         let auth_api = self.as_auth();
         match auth_api.logout().await {
             Ok(_) => Ok(()),
@@ -117,6 +286,7 @@ impl OpenBarClient {
 
     /// Get all categories available in the OpenBar instance.
     pub async fn get_categories(&self) -> Result<Vec<Category>, ApiError<GetCategoriesError>> {
+        self.inject("categories").await?;
         let categories_api = self.as_categories();
         match categories_api.get_categories(None).await {
             Ok(categories) => Ok(categories),
@@ -125,17 +295,120 @@ impl OpenBarClient {
     }
 
     /// Get items for a specific category by its ID.
+    ///
+    /// Only the first page of [ITEMS_PAGE_LIMIT] items is returned, see
+    /// [OpenBarClient::get_category_items_page] to detect truncation or get the other pages.
     pub async fn get_category_items(
         &self,
         category_id: &str,
     ) -> Result<Vec<Item>, ApiError<GetCategoryItemsError>> {
+        self.get_category_items_page(category_id, 0, ITEMS_PAGE_LIMIT)
+            .await
+            .map(|page| page.items)
+    }
+
+    /// Get a page of the items of a specific category by its ID, along with the pagination
+    /// metadata (pages are indexed from 0).
+    pub async fn get_category_items_page(
+        &self,
+        category_id: &str,
+        page: i64,
+        limit: i64,
+    ) -> Result<ItemsPage, ApiError<GetCategoryItemsError>> {
+        self.inject("category items").await?;
         let items_api = self.as_items();
         match items_api
-            .get_category_items(category_id, Some(0), Some(100), None)
+            .get_category_items(category_id, Some(page), Some(limit), None)
+            .await
+        {
+            Ok(items) => Ok(items.into()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get all the items of a specific category by its ID, fetching all its pages.
+    pub async fn get_all_category_items(
+        &self,
+        category_id: &str,
+    ) -> Result<Vec<Item>, ApiError<GetCategoryItemsError>> {
+        let mut items = Vec::new();
+        for page in 0.. {
+            let page = self
+                .get_category_items_page(category_id, page, ITEMS_PAGE_LIMIT)
+                .await?;
+            let truncated = page.is_truncated();
+            items.extend(page.items);
+            if !truncated {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Get the latest transactions (purchases) of the logged-in account, most recent first.
+    pub async fn get_transactions(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<Transaction>, ApiError<GetCurrentAccountTransactionsError>> {
+        self.inject("transactions").await?;
+        let transactions_api = self.as_transactions();
+        match transactions_api
+            .get_current_account_transactions(Some(0), Some(limit), None)
             .await
         {
-            Ok(items) => Ok(items.items),
+            Ok(resp) => Ok(resp.transactions),
             Err(e) => Err(e),
         }
     }
+
+    /// Get the latest refills of the logged-in account, most recent first.
+    pub async fn get_refills(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<Refill>, ApiError<GetSelfRefillsError>> {
+        self.inject("refills").await?;
+        let refills_api = self.as_refills();
+        match refills_api
+            .get_self_refills(Some(0), Some(limit), None, None)
+            .await
+        {
+            Ok(resp) => Ok(resp.refills),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get an item by its ID, if it exists.
+    ///
+    /// The API has no endpoint for a single item, so the categories are looked through,
+    /// stopping at the one containing the item.
+    pub async fn get_item(&self, item_id: Uuid) -> Result<Option<Item>, ItemLookupError> {
+        for category in self.get_categories().await? {
+            let items = self
+                .get_all_category_items(&category.id.to_string())
+                .await?;
+            if let Some(item) = items.into_iter().find(|item| item.id == item_id) {
+                return Ok(Some(item));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Search the items whose name contains `query` (case-insensitive), in all the categories.
+    ///
+    /// The API has no search endpoint, so all the categories are looked through.
+    pub async fn search_items(&self, query: &str) -> Result<Vec<Item>, ItemLookupError> {
+        let query = query.to_lowercase();
+        let mut found = Vec::new();
+        for category in self.get_categories().await? {
+            let items = self
+                .get_all_category_items(&category.id.to_string())
+                .await?;
+            found.extend(
+                items
+                    .into_iter()
+                    .filter(|item| item.name.to_lowercase().contains(&query)),
+            );
+        }
+        Ok(found)
+    }
 }
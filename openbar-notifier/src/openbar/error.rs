@@ -0,0 +1,38 @@
+use openbar_api::apis::Error as ApiError;
+use openbar_api::apis::categories_api::GetCategoriesError;
+use openbar_api::apis::items_api::GetCategoryItemsError;
+
+/// Errors while looking up items across the categories
+/// (see [crate::openbar::OpenBarClient::get_item] and [crate::openbar::OpenBarClient::search_items])
+#[derive(Debug, thiserror::Error)]
+pub enum ItemLookupError {
+    /// The categories could not be retrieved
+    #[error("error retrieving the categories: {0}")]
+    Categories(#[from] ApiError<GetCategoriesError>),
+    /// The items of a category could not be retrieved
+    #[error("error retrieving the items of a category: {0}")]
+    Items(#[from] ApiError<GetCategoryItemsError>),
+}
+
+/// Errors of all the strategies tried to log in (see [crate::openbar::OpenBarClient::login])
+#[derive(Debug)]
+pub struct LoginError {
+    /// Attempted strategies, along with their error
+    pub attempts: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.attempts.is_empty() {
+            return write!(f, "login failed, no login strategy is configured");
+        }
+        let attempts: Vec<String> = self
+            .attempts
+            .iter()
+            .map(|(strategy, error)| format!("{}: {}", strategy, error))
+            .collect();
+        write!(f, "login failed, tried {}", attempts.join("; "))
+    }
+}
+
+impl std::error::Error for LoginError {}
@@ -1,4 +1,6 @@
 mod client;
+mod error;
 pub mod webconfig;
 
 pub use client::*;
+pub use error::*;
@@ -4,13 +4,25 @@
 //! It contains the API endpoint and a small token that is used to authentificate a "local" order terminal.
 //! This token is not really private, but it allows us to connect using a NFC card and not a Google account,
 //! which is a lot easier to use for this project.
+//!
+//! The configuration rarely changes, so it can be cached on disk (see [get_config_cached]):
+//! the cached copy is used as-is for a while, then revalidated with a conditional request,
+//! and it is still used when the WebUI is temporarily unreachable.
+
+use std::path::Path;
 
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Response, StatusCode};
 use serde::{Deserialize, Serialize};
 
+use crate::clock;
+
 /// The configuration as served by the OpenBar WebUI
 ///
 /// Actually only the interesting fields are specified here.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebConfig {
     /// The API endpoint, usually something like `https://openbar.com/api/`
     pub api: String,
@@ -18,6 +30,56 @@ pub struct WebConfig {
     ///
     /// It is then specified for some API operations using the `X-Local-Token` header.
     pub local_token: String,
+    /// Locale of the instance (e.g. `fr-FR`), if exposed
+    #[serde(default, alias = "lang", alias = "language")]
+    pub locale: Option<String>,
+    /// Currency of the instance prices (ISO 4217 code, e.g. `EUR`), if exposed
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+/// Errors while retrieving the configuration of an OpenBar instance
+#[derive(Debug, thiserror::Error)]
+pub enum WebConfigError {
+    /// The WebUI could not be reached (DNS, connection, TLS, timeout...)
+    #[error("network error: {0}")]
+    Network(#[source] reqwest::Error),
+    /// The WebUI answered with an error status
+    #[error("HTTP status {0}")]
+    HttpStatus(StatusCode),
+    /// The configuration is not valid JSON (or a field has an invalid type)
+    #[error("malformed configuration: {0}")]
+    Json(#[source] serde_json::Error),
+    /// A required field is missing from the configuration
+    #[error("missing field '{0}' in the configuration")]
+    MissingField(&'static str),
+}
+
+impl WebConfigError {
+    /// Check if the error is transient (the instance is down, it may recover by itself),
+    /// rather than a malformed configuration (e.g. a wrong instance URL)
+    pub fn is_transient(&self) -> bool {
+        match self {
+            WebConfigError::Network(_) => true,
+            WebConfigError::HttpStatus(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            WebConfigError::Json(_) | WebConfigError::MissingField(_) => false,
+        }
+    }
+}
+
+/// Required fields of the configuration
+const REQUIRED_FIELDS: &[&str] = &["api", "local_token"];
+
+/// Parse the configuration from a (successful) response
+async fn parse_response(resp: Response) -> Result<WebConfig, WebConfigError> {
+    let data = resp.bytes().await.map_err(WebConfigError::Network)?;
+    let value: serde_json::Value = serde_json::from_slice(&data).map_err(WebConfigError::Json)?;
+    if let Some(field) = REQUIRED_FIELDS.iter().find(|f| value.get(**f).is_none()) {
+        return Err(WebConfigError::MissingField(field));
+    }
+    serde_json::from_value(value).map_err(WebConfigError::Json)
 }
 
 /// Retrieve the configuration from the OpenBar instance at `base_url/config.json`.
@@ -28,15 +90,121 @@ pub struct WebConfig {
 pub async fn get_config_with_client(
     client: &reqwest::Client,
     base_url: &str,
-) -> Result<WebConfig, Box<dyn std::error::Error>> {
+) -> Result<WebConfig, WebConfigError> {
     let url = format!("{}/config.json", base_url);
-    let resp = client.get(&url).send().await?;
-    let config = resp.json::<WebConfig>().await?;
-    Ok(config)
+    let resp = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(WebConfigError::Network)?;
+    if !resp.status().is_success() {
+        return Err(WebConfigError::HttpStatus(resp.status()));
+    }
+    parse_response(resp).await
+}
+
+/// A configuration cached on disk, along with its validators
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedWebConfig {
+    /// When the configuration was last fetched (or revalidated)
+    fetched_at: DateTime<Utc>,
+    /// `ETag` header of the response
+    etag: Option<String>,
+    /// `Last-Modified` header of the response
+    last_modified: Option<String>,
+    /// The configuration
+    config: WebConfig,
+}
+
+/// Retrieve the configuration from the OpenBar instance at `base_url/config.json`,
+/// through a cache file at `cache_path`.
+///
+/// The cached configuration is used as-is if it is younger than `ttl`, otherwise it is
+/// revalidated with a conditional request (`If-None-Match`/`If-Modified-Since`).
+/// If the WebUI cannot be reached, the cached configuration is used whatever its age.
+pub async fn get_config_cached(
+    client: &reqwest::Client,
+    base_url: &str,
+    cache_path: &Path,
+    ttl: chrono::Duration,
+) -> Result<WebConfig, WebConfigError> {
+    let cached: Option<CachedWebConfig> = std::fs::read(cache_path)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok());
+    let now = Utc::now();
+    if let Some(cached) = &cached
+        && clock::elapsed(cached.fetched_at, now) < ttl
+    {
+        debug!("Using the cached webconfig from {:?}", cache_path);
+        return Ok(cached.config.clone());
+    }
+
+    let url = format!("{}/config.json", base_url);
+    let mut request = client.get(&url);
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let fresh = match request.send().await {
+        Ok(resp) if resp.status() == StatusCode::NOT_MODIFIED && cached.is_some() => {
+            debug!("The cached webconfig is still valid");
+            None
+        }
+        Ok(resp) if resp.status().is_success() => {
+            let header = |name| {
+                resp.headers()
+                    .get(name)
+                    .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                    .map(str::to_string)
+            };
+            let etag = header(ETAG);
+            let last_modified = header(LAST_MODIFIED);
+            Some(
+                parse_response(resp)
+                    .await
+                    .map(|config| (config, etag, last_modified)),
+            )
+        }
+        Ok(resp) => Some(Err(WebConfigError::HttpStatus(resp.status()))),
+        Err(e) => Some(Err(WebConfigError::Network(e))),
+    };
+
+    let entry = match (fresh, cached) {
+        (None, Some(cached)) => CachedWebConfig {
+            fetched_at: now,
+            ..cached
+        },
+        (Some(Ok((config, etag, last_modified))), _) => CachedWebConfig {
+            fetched_at: now,
+            etag,
+            last_modified,
+            config,
+        },
+        (Some(Err(e)), Some(cached)) => {
+            warn!(
+                "Error retrieving webconfig ({}), using the cached copy from {}",
+                e, cached.fetched_at
+            );
+            return Ok(cached.config);
+        }
+        (Some(Err(e)), None) => return Err(e),
+        (None, None) => unreachable!("304 responses are only accepted with a cached copy"),
+    };
+    let written = serde_json::to_vec_pretty(&entry)
+        .map_err(std::io::Error::from)
+        .and_then(|data| std::fs::write(cache_path, data));
+    if let Err(e) = written {
+        warn!("Error writing the webconfig cache {:?}: {}", cache_path, e);
+    }
+    Ok(entry.config)
 }
 
 /// Retrieve the configuration from the OpenBar instance at `base_url/config.json`.
-pub async fn get_config(base_url: &str) -> Result<WebConfig, Box<dyn std::error::Error>> {
+pub async fn get_config(base_url: &str) -> Result<WebConfig, WebConfigError> {
     let client = reqwest::Client::new();
     get_config_with_client(&client, base_url).await
 }
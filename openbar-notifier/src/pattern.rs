@@ -0,0 +1,118 @@
+//! Item patterns for OpenBar Notifier
+//!
+//! Patterns are used by the configuration to designate a set of items, either
+//! by their (or their category) UUID, or by a glob/regex on their (or their category) name.
+
+use openbar_api::models::Item;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use uuid::Uuid;
+
+/// A pattern matching some items
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum ItemPattern {
+    /// Match an item, or all the items of a category, by its UUID
+    Id(Uuid),
+    /// Match items whose name or category name matches the glob (case-insensitive)
+    ///
+    /// The glob supports `*` (any sequence of characters) and `?` (any single character).
+    Glob(String),
+    /// Match items whose name or category name matches the regex (case-insensitive)
+    Regex(Regex),
+}
+
+impl PartialEq for ItemPattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ItemPattern::Id(a), ItemPattern::Id(b)) => a == b,
+            (ItemPattern::Glob(a), ItemPattern::Glob(b)) => a == b,
+            (ItemPattern::Regex(a), ItemPattern::Regex(b)) => a.as_str() == b.as_str(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ItemPattern {}
+
+impl ItemPattern {
+    /// Parse a pattern from its textual representation
+    ///
+    /// Anything prefixed by `re:` is a regex pattern, anything that looks like a UUID
+    /// is an ID pattern, everything else is a glob.
+    pub fn parse(s: &str) -> Result<Self, regex::Error> {
+        let s = s.trim();
+        if let Some(re) = s.strip_prefix("re:") {
+            let re = RegexBuilder::new(re).case_insensitive(true).build()?;
+            return Ok(ItemPattern::Regex(re));
+        }
+        match Uuid::parse_str(s) {
+            Ok(id) => Ok(ItemPattern::Id(id)),
+            Err(_) => Ok(ItemPattern::Glob(s.to_lowercase())),
+        }
+    }
+
+    /// Check if the pattern matches the given item (and its category name)
+    pub fn matches(&self, item: &Item, category_name: &str) -> bool {
+        match self {
+            ItemPattern::Id(id) => item.id == *id || item.category_id == *id,
+            ItemPattern::Glob(glob) => {
+                glob_match(glob, &item.name.to_lowercase())
+                    || glob_match(glob, &category_name.to_lowercase())
+            }
+            ItemPattern::Regex(re) => re.is_match(&item.name) || re.is_match(category_name),
+        }
+    }
+
+    /// Check if the pattern matches the given category
+    pub fn matches_category(&self, category_id: Uuid, category_name: &str) -> bool {
+        match self {
+            ItemPattern::Id(id) => category_id == *id,
+            ItemPattern::Glob(glob) => glob_match(glob, &category_name.to_lowercase()),
+            ItemPattern::Regex(re) => re.is_match(category_name),
+        }
+    }
+}
+
+impl TryFrom<String> for ItemPattern {
+    type Error = regex::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        ItemPattern::parse(&s)
+    }
+}
+
+/// Parse a comma-separated list of patterns
+pub fn parse_patterns(s: &str) -> Result<Vec<ItemPattern>, regex::Error> {
+    s.split(',')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(ItemPattern::parse)
+        .collect()
+}
+
+/// Simple glob matching supporting `*` and `?`
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // Position of the last `*` in the glob, and the text position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if g < glob.len() && (glob[g] == '?' || glob[g] == text[t]) {
+            g += 1;
+            t += 1;
+        } else if g < glob.len() && glob[g] == '*' {
+            backtrack = Some((g, t));
+            g += 1;
+        } else if let Some((star_g, star_t)) = backtrack {
+            // Let the last `*` absorb one more character
+            g = star_g + 1;
+            t = star_t + 1;
+            backtrack = Some((star_g, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    glob[g..].iter().all(|c| *c == '*')
+}
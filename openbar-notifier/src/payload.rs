@@ -0,0 +1,377 @@
+//! Webhook payload formats of OpenBar Notifier
+//!
+//! Each target chooses the format of the payloads it receives (e.g. `format=slack`):
+//! - `discord` (default): `{"content": "<message>", "allowed_mentions": {...}}`, only allowing
+//!   the configured mentions (see [crate::mention]);
+//! - `slack`: `{"text": "<message>", "blocks": [...]}`, with a single `mrkdwn` section;
+//! - `json`: `{"instance": "<name>", "events": [...]}`, the events being machine-readable
+//!   (see [crate::event::EventContext]), for downstream automation;
+//! - `text`: the message as plain text;
+//! - `twilio`: an SMS through a Twilio-compatible API, as a form (`To`, `From`, `Body`), the
+//!   account SID and token being given in the URL (`https://<sid>:<token>@api.twilio.com/...`);
+//! - `ovh`: an SMS through the OVH `http2sms` gateway, as query parameters (`to`, `from`,
+//!   `message`) added to the URL holding the account, login and password;
+//! - `atom` (or `feed`) and `rss`: a feed file, given by its `file://` URL, one entry per event
+//!   (see [crate::feed]);
+//! - `jsonl`: a JSON Lines file, given by its `file://` URL, one line per event (see
+//!   [crate::sink]).
+//!
+//! The targets enabling the images (`images=true`) also show the pictures of the items, resolved
+//! against the instance URL: as embed thumbnails on Discord, as image blocks on Slack. They are
+//! hotlinked by the receivers, hence disabled by default.
+//!
+//! The SMS formats are sent to the recipient of the target (`sms_to=...`, and the optional
+//! sender `sms_from=...`), and only carry the critical events (see [crate::routing]).
+//!
+//! The `json` payloads can be signed with a secret shared with the receiver (`secret=...`):
+//! the `X-Signature` header then holds `sha256=<hex HMAC-SHA256 of the body>`, so that receivers
+//! exposed to the internet can verify that the notification really came from this notifier.
+
+use ring::hmac;
+use serde_json::{Value, json};
+
+use std::collections::BTreeSet;
+
+use crate::event::EventContext;
+use crate::feed::FeedEntry;
+use crate::mention::{self, Mention};
+use crate::notifier::MAX_MESSAGE_LEN;
+
+/// Maximum length of an SMS message (in characters), a few concatenated segments
+pub const MAX_SMS_LEN: usize = 640;
+
+/// Maximum number of pictures shown in a message (embeds of a Discord message)
+pub const MAX_PICTURES: usize = 10;
+
+/// Payload format of a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadFormat {
+    /// Discord webhook message
+    #[default]
+    Discord,
+    /// Slack incoming webhook message, with blocks
+    Slack,
+    /// Machine-readable events, as JSON
+    Json,
+    /// Plain text message
+    Text,
+    /// SMS through a Twilio-compatible API
+    Twilio,
+    /// SMS through the OVH `http2sms` gateway
+    Ovh,
+    /// Atom feed file
+    Atom,
+    /// RSS 2.0 feed file
+    Rss,
+    /// JSON Lines file
+    Jsonl,
+}
+
+impl std::str::FromStr for PayloadFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "discord" => Ok(PayloadFormat::Discord),
+            "slack" => Ok(PayloadFormat::Slack),
+            "json" => Ok(PayloadFormat::Json),
+            "text" => Ok(PayloadFormat::Text),
+            "twilio" => Ok(PayloadFormat::Twilio),
+            "ovh" => Ok(PayloadFormat::Ovh),
+            "atom" | "feed" => Ok(PayloadFormat::Atom),
+            "rss" => Ok(PayloadFormat::Rss),
+            "jsonl" => Ok(PayloadFormat::Jsonl),
+            _ => Err(format!(
+                "unknown payload format '{}' (expected discord, slack, json, text, twilio, ovh, \
+                 atom, rss or jsonl)",
+                s
+            )),
+        }
+    }
+}
+
+/// A payload, ready to be sent to a target
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payload {
+    /// JSON body
+    Json(Value),
+    /// Plain text body
+    Text(String),
+    /// Form body (`application/x-www-form-urlencoded`)
+    Form(Vec<(String, String)>),
+    /// Query parameters of a `GET` request, without body
+    Query(Vec<(String, String)>),
+    /// Entries added to a feed
+    Entries(Vec<FeedEntry>),
+    /// Lines appended to a file
+    Lines(Vec<String>),
+}
+
+impl Payload {
+    /// Get the body of the payload, as sent
+    pub fn body(&self) -> String {
+        match self {
+            Payload::Json(value) => value.to_string(),
+            Payload::Text(text) => text.clone(),
+            Payload::Form(fields) | Payload::Query(fields) => {
+                url::form_urlencoded::Serializer::new(String::new())
+                    .extend_pairs(fields)
+                    .finish()
+            }
+            Payload::Entries(entries) => serde_json::to_string(entries).unwrap_or_default(),
+            Payload::Lines(lines) => lines.join("\n"),
+        }
+    }
+}
+
+/// Picture of an item, shown along with a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Picture {
+    /// Name of the item
+    pub title: String,
+    /// Full URL of the picture
+    pub url: String,
+}
+
+/// Get the pictures of the items of events (once per item, at most [MAX_PICTURES]), resolving
+/// their URI against the base URL of the instance
+pub fn pictures(base: &str, events: &[EventContext]) -> Vec<Picture> {
+    let Ok(base) = url::Url::parse(base) else {
+        return Vec::new();
+    };
+    let mut pictures: Vec<Picture> = Vec::new();
+    for ctx in events {
+        if ctx.item.picture_uri.trim().is_empty() {
+            continue;
+        }
+        let Ok(url) = base.join(ctx.item.picture_uri.trim()) else {
+            continue;
+        };
+        let url = url.to_string();
+        if !pictures.iter().any(|p| p.url == url) {
+            pictures.push(Picture {
+                title: ctx.item.name.clone(),
+                url,
+            });
+        }
+    }
+    pictures.truncate(MAX_PICTURES);
+    pictures
+}
+
+/// Recipient of the SMS of a target
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmsRecipient {
+    /// Phone number of the recipient (e.g. `+33612345678`), empty if unset
+    pub to: String,
+    /// Sender (phone number or alphanumeric sender ID), the gateway default if unset
+    pub from: Option<String>,
+}
+
+impl PayloadFormat {
+    /// Check if the format carries the events themselves rather than a rendered message
+    pub fn is_structured(&self) -> bool {
+        *self == PayloadFormat::Json
+    }
+
+    /// Check if the format writes a feed file rather than calling a webhook
+    pub fn is_feed(&self) -> bool {
+        matches!(self, PayloadFormat::Atom | PayloadFormat::Rss)
+    }
+
+    /// Check if the format writes a file (given by a `file://` URL) rather than calling a webhook
+    pub fn is_file(&self) -> bool {
+        self.is_feed() || *self == PayloadFormat::Jsonl
+    }
+
+    /// Check if the format sends SMS, restricted to the critical events
+    pub fn is_sms(&self) -> bool {
+        matches!(self, PayloadFormat::Twilio | PayloadFormat::Ovh)
+    }
+
+    /// Maximum length of a message (part), in characters
+    pub fn max_message_len(&self) -> usize {
+        if self.is_sms() {
+            MAX_SMS_LEN
+        } else {
+            MAX_MESSAGE_LEN
+        }
+    }
+
+    /// Build the payload of a rendered message (part), allowed to ping the given mentions and
+    /// showing the given pictures
+    ///
+    /// Structured formats do not carry messages, the message is then sent as plain text. The
+    /// feeds get the message as an entry of its own, and the JSON Lines files as a line of its own.
+    /// Only the Discord messages support the mentions, only the Discord and Slack ones show the
+    /// pictures, and only the SMS use the recipient.
+    pub fn message(
+        &self,
+        content: &str,
+        mentions: &BTreeSet<Mention>,
+        pictures: &[Picture],
+        recipient: &SmsRecipient,
+    ) -> Payload {
+        let fields = |to: &str, from: &str, body: &str| {
+            let mut fields = vec![(to.to_string(), recipient.to.clone())];
+            if let Some(sender) = &recipient.from {
+                fields.push((from.to_string(), sender.clone()));
+            }
+            fields.push((body.to_string(), content.to_string()));
+            fields
+        };
+        match self {
+            PayloadFormat::Discord => {
+                let mut message = json!({
+                    "content": content,
+                    "allowed_mentions": mention::allowed_mentions(mentions),
+                });
+                if !pictures.is_empty() {
+                    message["embeds"] = pictures
+                        .iter()
+                        .map(|p| json!({ "title": p.title, "thumbnail": { "url": p.url } }))
+                        .collect();
+                }
+                Payload::Json(message)
+            }
+            PayloadFormat::Slack => {
+                // Slack uses single asterisks for bold text
+                let text = content.replace("**", "*");
+                let mut blocks = vec![json!({
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": text },
+                })];
+                blocks.extend(
+                    pictures.iter().map(
+                        |p| json!({ "type": "image", "image_url": p.url, "alt_text": p.title }),
+                    ),
+                );
+                Payload::Json(json!({ "text": text, "blocks": blocks }))
+            }
+            PayloadFormat::Json | PayloadFormat::Text => Payload::Text(content.to_string()),
+            PayloadFormat::Twilio => Payload::Form(fields("To", "From", "Body")),
+            PayloadFormat::Ovh => Payload::Query(fields("to", "from", "message")),
+            PayloadFormat::Atom | PayloadFormat::Rss => {
+                Payload::Entries(vec![FeedEntry::message(content, chrono::Utc::now())])
+            }
+            PayloadFormat::Jsonl => Payload::Lines(vec![
+                json!({ "at": chrono::Utc::now(), "message": content }).to_string(),
+            ]),
+        }
+    }
+
+    /// Get the identifier of the message from the response of the provider, if it gives one
+    ///
+    /// The Discord webhooks return the message when waited for (`?wait=true`, as notified), and
+    /// the Twilio API returns the SID of the SMS.
+    pub fn message_id(&self, response: &str) -> Option<String> {
+        let key = match self {
+            PayloadFormat::Discord => "id",
+            PayloadFormat::Twilio => "sid",
+            _ => return None,
+        };
+        let value: Value = serde_json::from_str(response).ok()?;
+        match value.get(key)? {
+            Value::String(id) => Some(id.clone()),
+            Value::Number(id) => Some(id.to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the header holding the signature of the payload
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// Sign a payload body with a shared secret, as sent in the [SIGNATURE_HEADER] header
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, body);
+    let hex: String = tag
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", hex)
+}
+
+/// Build the machine-readable payload of the events of an instance
+pub fn events_payload(instance: &str, events: &[EventContext]) -> Payload {
+    Payload::Json(json!({
+        "instance": instance,
+        "events": events,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{Item, ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::event::ItemEvent;
+
+    fn event(name: &str, picture_uri: &str) -> EventContext {
+        let item = Item::new(
+            Uuid::nil(),
+            ItemPrices::new(100, 100, 100, 100, 150, 150),
+            5,
+            10,
+            Uuid::nil(),
+            name.to_string(),
+            picture_uri.to_string(),
+            false,
+            ItemState::ItemBuyable,
+        );
+        EventContext {
+            event: ItemEvent::BecomeBuyable,
+            item,
+            category_name: String::new(),
+            tags: BTreeSet::new(),
+            restricted: false,
+            sales_rate: None,
+            price: None,
+            severity: Default::default(),
+            correction: None,
+        }
+    }
+
+    #[test]
+    fn messages_show_the_item_pictures() {
+        let events = [
+            event("Chips", "/api/pictures/chips.webp"),
+            event("Chips", "/api/pictures/chips.webp"),
+            event("Kwak", "https://cdn.example.org/kwak.png"),
+            event("Leffe", ""),
+        ];
+        let pictures = pictures("https://bar.example.org/app/", &events);
+        assert_eq!(
+            pictures.iter().map(|p| p.url.as_str()).collect::<Vec<_>>(),
+            vec![
+                "https://bar.example.org/api/pictures/chips.webp",
+                "https://cdn.example.org/kwak.png"
+            ]
+        );
+
+        let recipient = SmsRecipient::default();
+        let Payload::Json(discord) =
+            PayloadFormat::Discord.message("Hi", &BTreeSet::new(), &pictures, &recipient)
+        else {
+            panic!("Discord messages are JSON");
+        };
+        assert_eq!(discord["embeds"][1]["title"], "Kwak");
+        assert_eq!(
+            discord["embeds"][0]["thumbnail"]["url"],
+            "https://bar.example.org/api/pictures/chips.webp"
+        );
+        let Payload::Json(slack) =
+            PayloadFormat::Slack.message("Hi", &BTreeSet::new(), &pictures, &recipient)
+        else {
+            panic!("Slack messages are JSON");
+        };
+        assert_eq!(slack["blocks"].as_array().unwrap().len(), 3);
+        assert_eq!(
+            PayloadFormat::Text.message("Hi", &BTreeSet::new(), &pictures, &recipient),
+            Payload::Text("Hi".to_string())
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Account permissions of OpenBar Notifier
+//!
+//! Not every feature works with any OpenBar account: the account transactions need an
+//! onboarded (non-ghost) account, the admin endpoints an admin one, and ordering an account
+//! neither blocked nor restricted remotely. After each login, the [Permissions] of the account
+//! are derived from its role, state and restrictions, and the features it cannot use are
+//! disabled with a warning (on startup, then whenever they change), instead of failing with
+//! opaque `403 Forbidden` errors mid-run.
+
+use openbar_api::models::{Account, AccountRestrictions, AccountRole, AccountState};
+
+/// Features available to an OpenBar account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Permissions {
+    /// The account can read its transactions
+    pub transactions: bool,
+    /// The account can use the admin endpoints
+    pub admin: bool,
+    /// The account can order items
+    pub ordering: bool,
+}
+
+impl Permissions {
+    /// Get the permissions of an account, from its role, state and restrictions
+    pub fn of(account: &Account) -> Self {
+        let onboarded =
+            account.state == AccountState::AccountOK && account.role != AccountRole::AccountGhost;
+        let restricted = account
+            .restrictions
+            .clone()
+            .flatten()
+            .unwrap_or_default()
+            .iter()
+            .any(|r| {
+                matches!(
+                    r,
+                    AccountRestrictions::AccountBlocked
+                        | AccountRestrictions::AccountRemoteRestricted
+                )
+            });
+        Permissions {
+            transactions: onboarded,
+            admin: matches!(
+                account.role,
+                AccountRole::AccountAdmin | AccountRole::AccountSuperAdmin
+            ),
+            ordering: onboarded && !restricted,
+        }
+    }
+
+    /// Get the names of the features the account cannot use
+    pub fn disabled_features(&self) -> Vec<&'static str> {
+        [
+            (self.transactions, "account transactions"),
+            (self.admin, "admin endpoints"),
+            (self.ordering, "ordering"),
+        ]
+        .into_iter()
+        .filter(|(allowed, _)| !allowed)
+        .map(|(_, feature)| feature)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::AccountPriceRole;
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn account(role: AccountRole, restrictions: Vec<AccountRestrictions>) -> Account {
+        let mut account = Account::new(
+            Uuid::nil(),
+            "Jane".to_string(),
+            "Doe".to_string(),
+            "jane@example.org".to_string(),
+            0,
+            0,
+            role,
+            AccountPriceRole::AccountPriceCeten,
+            AccountState::AccountOK,
+            false,
+        );
+        account.restrictions = Some(Some(restrictions));
+        account
+    }
+
+    #[test]
+    fn derives_the_permissions_of_the_accounts() {
+        let member = Permissions::of(&account(AccountRole::AccountMember, vec![]));
+        assert_eq!(member.disabled_features(), vec!["admin endpoints"]);
+
+        let admin = Permissions::of(&account(AccountRole::AccountAdmin, vec![]));
+        assert!(admin.disabled_features().is_empty());
+
+        let blocked = Permissions::of(&account(
+            AccountRole::AccountMember,
+            vec![AccountRestrictions::AccountBlocked],
+        ));
+        assert!(blocked.transactions && !blocked.ordering);
+
+        let ghost = Permissions::of(&account(AccountRole::AccountGhost, vec![]));
+        assert_eq!(
+            ghost.disabled_features(),
+            vec!["account transactions", "admin endpoints", "ordering"]
+        );
+    }
+}
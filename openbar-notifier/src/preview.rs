@@ -0,0 +1,131 @@
+//! Payload previews of OpenBar Notifier (`openbar-notifier render`)
+//!
+//! For the authors of templates and themes, the payload a target would receive for an event of
+//! a tracked item can be rendered without waiting for the event (nor delivering anything). The
+//! event is made up for the item, in its last known state, with sample values for its details
+//! (e.g. the units gone for `stock_dropped`).
+
+use chrono::{DateTime, Utc};
+use openbar_api::models::Item;
+
+use crate::config::GlobalConfig;
+use crate::event::{EventContext, ItemEvent};
+use crate::prices;
+use crate::store::ItemStore;
+
+/// Discount of the sample promotions, in hundredths of a percent
+const SAMPLE_DISCOUNT: i64 = 2000;
+/// Projected time until the stock runs out of the sample predictions, in hours
+const SAMPLE_HOURS_LEFT: i64 = 12;
+
+/// Make up an event of an item by its type (e.g. `out_of_stock` or `out-of-stock`)
+///
+/// The details of the event are sampled from the item when possible (e.g. its promotion, or
+/// its stock for `stock_dropped`), with arbitrary values otherwise.
+pub fn sample_event(kind: &str, item: &Item) -> Result<ItemEvent, String> {
+    let kind = kind.trim().to_lowercase().replace('-', "_");
+    let event = match kind.as_str() {
+        "added" => ItemEvent::Added,
+        "become_buyable" => ItemEvent::BecomeBuyable,
+        "become_unbuyable" => ItemEvent::BecomeUnbuyable,
+        "out_of_stock" => ItemEvent::OutOfStock,
+        "low_stock" => ItemEvent::LowStock,
+        "removed" => ItemEvent::Removed,
+        "menu_changed" => ItemEvent::MenuChanged,
+        "menu_unavailable" => ItemEvent::MenuUnavailable {
+            components: vec![item.name.clone()],
+        },
+        "promotion_started" => ItemEvent::PromotionStarted {
+            discount: item
+                .promotion
+                .filter(|discount| *discount > 0)
+                .unwrap_or(SAMPLE_DISCOUNT),
+        },
+        "promotion_ended" => ItemEvent::PromotionEnded,
+        "predicted_stock_out" => ItemEvent::PredictedStockOut {
+            hours_left: SAMPLE_HOURS_LEFT,
+        },
+        "stock_dropped" => ItemEvent::StockDropped {
+            dropped: item.amount_left.max(1),
+        },
+        "stock_changed" => ItemEvent::StockChanged { change: -1 },
+        _ => {
+            return Err(format!(
+                "unknown event type '{}' (expected one of {})",
+                kind,
+                ItemEvent::KINDS.join(", ")
+            ));
+        }
+    };
+    Ok(event)
+}
+
+/// Get the context of an event of a tracked item, as a check would notify it
+pub fn context(
+    config: &GlobalConfig,
+    store: &ItemStore,
+    item: &Item,
+    event: ItemEvent,
+    now: DateTime<Utc>,
+) -> EventContext {
+    let meta = store.meta(item.id);
+    let category_name = meta
+        .and_then(|meta| meta.category.clone())
+        .unwrap_or_default();
+    EventContext {
+        item: item.clone(),
+        restricted: config.notify.is_restricted(item, &category_name),
+        severity: config.notify.severity(&event, item, &category_name),
+        category_name,
+        tags: store.tags(item.id),
+        sales_rate: meta.and_then(|meta| meta.sales.rate_per_day(now)),
+        price: match event {
+            ItemEvent::PromotionStarted { .. } => {
+                meta.and_then(|meta| meta.prices.compare(prices::effective_price(item), now))
+            }
+            _ => None,
+        },
+        correction: None,
+        event,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn samples_the_events_by_type() {
+        let mut item = Item::new(
+            Uuid::from_u128(1),
+            ItemPrices::new(100, 100, 100, 100, 150, 150),
+            4,
+            10,
+            Uuid::from_u128(0),
+            "Chips".to_string(),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        );
+        assert_eq!(
+            sample_event("out-of-stock", &item).unwrap(),
+            ItemEvent::OutOfStock
+        );
+        assert_eq!(
+            sample_event("stock_dropped", &item).unwrap(),
+            ItemEvent::StockDropped { dropped: 4 }
+        );
+        item.promotion = Some(3000);
+        assert_eq!(
+            sample_event("Promotion-Started", &item).unwrap(),
+            ItemEvent::PromotionStarted { discount: 3000 }
+        );
+        for kind in ItemEvent::KINDS {
+            assert_eq!(sample_event(kind, &item).unwrap().kind(), *kind);
+        }
+        assert!(sample_event("sold_out", &item).is_err());
+    }
+}
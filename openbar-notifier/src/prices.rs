@@ -0,0 +1,174 @@
+//! Price history of OpenBar Notifier
+//!
+//! Each item keeps the (member) prices it was sold at during the last month, promotions
+//! included ([PriceHistory]), along with the lowest one ever seen. When a promotion starts,
+//! its notification tells whether the new price is the lowest ever seen, and how it compares to
+//! the average price of the last 30 days (e.g. `, lowest price ever, 25% below the 30-day
+//! average`), turning the notifier into a small price tracker.
+
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Duration, Utc};
+use openbar_api::models::Item;
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+use crate::i18n;
+use crate::theme::{Variables, fill};
+
+/// Period over which the average price is computed
+pub const WINDOW: Duration = Duration::days(30);
+
+/// Minimal observed period to compute an average price
+pub const MIN_OBSERVATION: Duration = Duration::days(1);
+
+/// Get the price an item is sold at to the members, its promotion included (in cents)
+pub fn effective_price(item: &Item) -> i64 {
+    let discount = item.promotion.unwrap_or_default().clamp(0, 10_000);
+    item.prices.ceten - item.prices.ceten * discount / 10_000
+}
+
+/// Prices of an item over the last month
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PriceHistory {
+    /// Price samples (time, price in cents), oldest first, only recorded when the price changes
+    samples: VecDeque<(DateTime<Utc>, i64)>,
+    /// Lowest price ever seen
+    lowest: Option<i64>,
+}
+
+/// Comparison of a new price of an item to its history
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PriceComparison {
+    /// Whether the price is lower than any price seen before
+    pub lowest_ever: bool,
+    /// Difference to the average price of the last 30 days, in percent (negative when below),
+    /// if the item was observed long enough
+    pub average_delta: Option<f64>,
+}
+
+impl PriceHistory {
+    /// Observe the price of the item at the given time
+    pub fn observe(&mut self, at: DateTime<Utc>, price: i64) {
+        if self.samples.back().is_none_or(|(_, last)| *last != price) {
+            self.samples.push_back((at, price));
+        }
+        self.lowest = Some(self.lowest.map_or(price, |lowest| lowest.min(price)));
+        // Keep a single sample before the window, as the price at its start
+        let start = at - WINDOW;
+        while self.samples.len() > 1 && self.samples[1].0 <= start {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Clamp the timestamps to `now` if they are in the future (the clock went backwards)
+    pub fn clamp_to_now(&mut self, now: DateTime<Utc>) {
+        for (at, _) in &mut self.samples {
+            *at = clock::clamp_to_now(*at, now);
+        }
+    }
+
+    /// Get the lowest price ever seen
+    pub fn lowest(&self) -> Option<i64> {
+        self.lowest
+    }
+
+    /// Get the time-weighted average price over the window, if the item was observed long
+    /// enough
+    pub fn average(&self, now: DateTime<Utc>) -> Option<f64> {
+        let (first, _) = self.samples.front()?;
+        let start = (*first).max(now - WINDOW);
+        let observed = clock::elapsed(start, now);
+        if observed < MIN_OBSERVATION {
+            return None;
+        }
+        let ends = self.samples.iter().skip(1).map(|(at, _)| *at).chain([now]);
+        let weighted: f64 = self
+            .samples
+            .iter()
+            .zip(ends)
+            .map(|((from, price), to)| {
+                let seconds = clock::elapsed((*from).max(start), to.max(start)).num_seconds();
+                *price as f64 * seconds as f64
+            })
+            .sum();
+        Some(weighted / observed.num_seconds() as f64)
+    }
+
+    /// Compare a new price of the item to its history (before observing it), if known
+    pub fn compare(&self, price: i64, now: DateTime<Utc>) -> Option<PriceComparison> {
+        let lowest = self.lowest?;
+        Some(PriceComparison {
+            lowest_ever: price < lowest,
+            average_delta: self
+                .average(now)
+                .filter(|average| *average > 0.0)
+                .map(|average| (price as f64 - average) / average * 100.0),
+        })
+    }
+}
+
+impl PriceComparison {
+    /// Format the comparison in a language, as a list continuing the previous one (e.g.
+    /// `, lowest price ever, 25% below the 30-day average`), empty if there is nothing to tell
+    pub fn format(&self, language: &str) -> String {
+        let tr = |msgid| i18n::translate(language, msgid);
+        let mut parts = Vec::new();
+        if self.lowest_ever {
+            parts.push(tr("lowest price ever").to_string());
+        }
+        if let Some(delta) = self.average_delta {
+            let percent = format!("{:.0}", delta.abs());
+            if percent != "0" {
+                let msgid = if delta < 0.0 {
+                    "{percent}% below the 30-day average"
+                } else {
+                    "{percent}% above the 30-day average"
+                };
+                parts.push(fill(tr(msgid), &[("percent", &percent)], &Variables::new()));
+            }
+        }
+        parts.iter().map(|part| format!(", {}", part)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hours: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + Duration::hours(hours)
+    }
+
+    #[test]
+    fn compares_the_prices_to_the_history() {
+        let mut history = PriceHistory::default();
+        assert_eq!(history.compare(150, at(0)), None);
+        history.observe(at(0), 200);
+        // Not observed long enough for an average
+        assert_eq!(
+            history.compare(150, at(12)),
+            Some(PriceComparison {
+                lowest_ever: true,
+                average_delta: None,
+            })
+        );
+        history.observe(at(24), 100);
+        history.observe(at(48), 200);
+        // 200 during 2 days, 100 during 1 day
+        let average = history.average(at(72)).unwrap();
+        assert!((average - 500.0 / 3.0).abs() < 1e-6);
+        let comparison = history.compare(125, at(72)).unwrap();
+        assert!(!comparison.lowest_ever);
+        assert_eq!(comparison.format("en"), ", 25% below the 30-day average");
+        assert_eq!(
+            history.compare(90, at(72)).unwrap().format("en"),
+            ", lowest price ever, 46% below the 30-day average"
+        );
+
+        // The prices before the window only count from its start
+        history.observe(at(24 * 40), 200);
+        assert_eq!(history.average(at(24 * 40)), Some(200.0));
+        assert_eq!(history.lowest(), Some(100));
+    }
+}
@@ -0,0 +1,199 @@
+//! Delivery priorities of OpenBar Notifier
+//!
+//! When many events (and message parts) are pending, the urgent ones are delivered first: the
+//! events are ordered by [Priority] (their severity, then the urgency of their type, e.g. an
+//! out-of-stock before a menu change) rather than by catalog order, and the targets are dispatched
+//! through a [DeliveryQueue], tier by tier, so that the targets of the low-priority churn are only
+//! notified once the urgent ones were.
+//!
+//! Events (and messages) of the same priority keep their original order.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use crate::event::{EventContext, ItemEvent, Severity};
+
+/// Delivery priority of an event, the greatest being delivered first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Priority {
+    /// Severity of the event
+    pub severity: Severity,
+    /// Urgency of the event type (see [urgency])
+    pub urgency: u8,
+}
+
+impl Priority {
+    /// Get the priority of an event
+    pub fn of(ctx: &EventContext) -> Self {
+        Priority {
+            severity: ctx.severity,
+            urgency: urgency(&ctx.event),
+        }
+    }
+}
+
+/// Get the urgency of an event type, from 0 (catalog churn) to 3 (the item can no longer be
+/// bought)
+pub fn urgency(event: &ItemEvent) -> u8 {
+    match event {
+        ItemEvent::OutOfStock | ItemEvent::BecomeUnbuyable | ItemEvent::MenuUnavailable { .. } => 3,
+        ItemEvent::LowStock
+        | ItemEvent::PredictedStockOut { .. }
+        | ItemEvent::StockDropped { .. } => 2,
+        ItemEvent::BecomeBuyable
+        | ItemEvent::Added
+        | ItemEvent::Removed
+        | ItemEvent::StockChanged { .. } => 1,
+        ItemEvent::MenuChanged | ItemEvent::PromotionStarted { .. } | ItemEvent::PromotionEnded => {
+            0
+        }
+    }
+}
+
+/// Queue of pending deliveries, popped by decreasing priority, then in insertion order
+#[derive(Debug)]
+pub struct DeliveryQueue<T> {
+    heap: BinaryHeap<Entry<T>>,
+    sequence: usize,
+}
+
+/// Entry of a [DeliveryQueue], only ordered by its priority and sequence number
+#[derive(Debug)]
+struct Entry<T> {
+    priority: Priority,
+    sequence: Reverse<usize>,
+    value: T,
+}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.sequence).cmp(&(other.priority, other.sequence))
+    }
+}
+
+impl<T> Default for DeliveryQueue<T> {
+    fn default() -> Self {
+        DeliveryQueue {
+            heap: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+}
+
+impl<T> DeliveryQueue<T> {
+    /// Queue a value with the given priority
+    pub fn push(&mut self, priority: Priority, value: T) {
+        self.heap.push(Entry {
+            priority,
+            sequence: Reverse(self.sequence),
+            value,
+        });
+        self.sequence += 1;
+    }
+
+    /// Take the value of highest priority (the first queued among equals)
+    pub fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(|entry| entry.value)
+    }
+
+    /// Take all the values of the highest priority, in insertion order (none if the queue is
+    /// empty)
+    pub fn pop_tier(&mut self) -> Vec<T> {
+        let Some(first) = self.heap.pop() else {
+            return Vec::new();
+        };
+        let mut tier = vec![first.value];
+        while let Some(entry) = self.heap.peek()
+            && entry.priority == first.priority
+        {
+            tier.extend(self.heap.pop().map(|entry| entry.value));
+        }
+        tier
+    }
+
+    /// Get the number of queued values
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Check if the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+/// Order events by decreasing priority, keeping the order of the events of the same priority
+pub fn by_priority(events: Vec<EventContext>) -> Vec<EventContext> {
+    let mut queue = DeliveryQueue::default();
+    for ctx in events {
+        queue.push(Priority::of(&ctx), ctx);
+    }
+    std::iter::from_fn(|| queue.pop()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_by_priority_then_in_order() {
+        let low = Priority::default();
+        let urgent = Priority {
+            severity: Severity::Warning,
+            urgency: 3,
+        };
+        let mut queue = DeliveryQueue::default();
+        queue.push(low, "menu");
+        queue.push(urgent, "keg");
+        queue.push(low, "promotion");
+        queue.push(urgent, "chips");
+        queue.push(
+            Priority {
+                severity: Severity::Warning,
+                urgency: 0,
+            },
+            "warning",
+        );
+        assert_eq!(queue.len(), 5);
+        let order: Vec<&str> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(order, ["keg", "chips", "warning", "menu", "promotion"]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn pops_the_tiers_of_priority() {
+        let urgent = Priority {
+            severity: Severity::Warning,
+            urgency: 3,
+        };
+        let mut queue = DeliveryQueue::default();
+        queue.push(Priority::default(), "menu");
+        queue.push(urgent, "keg");
+        queue.push(Priority::default(), "promotion");
+        queue.push(urgent, "chips");
+        assert_eq!(queue.pop_tier(), ["keg", "chips"]);
+        assert_eq!(queue.pop_tier(), ["menu", "promotion"]);
+        assert!(queue.pop_tier().is_empty());
+    }
+
+    #[test]
+    fn urgency_ranks_event_types() {
+        assert!(urgency(&ItemEvent::OutOfStock) > urgency(&ItemEvent::LowStock));
+        assert!(urgency(&ItemEvent::LowStock) > urgency(&ItemEvent::Added));
+        assert!(urgency(&ItemEvent::Added) > urgency(&ItemEvent::MenuChanged));
+    }
+}
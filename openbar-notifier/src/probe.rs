@@ -0,0 +1,78 @@
+//! Health probes of the notification targets
+//!
+//! At daemon startup, each target can be probed with a lightweight request, so that
+//! invalid or revoked webhooks are reported immediately rather than on the first real event.
+//! Discord webhooks are validated with a `GET` on their URL (which returns the webhook
+//! details without posting anything), the other targets with a `HEAD` request. The directory
+//! of the file targets (e.g. the feeds) must exist, and the standard streams are always ok.
+
+use reqwest::StatusCode;
+
+use crate::config::TargetConfig;
+
+/// Outcome of the probe of a target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeStatus {
+    /// The target is reachable (and valid, for providers supporting validation)
+    Ok,
+    /// The target rejected the probe as invalid, unauthorized or gone (with this status code)
+    Invalid(u16),
+    /// The target could not be reached (network error, ...)
+    Unreachable(String),
+    /// The target answered with an unexpected status code, it may still accept notifications
+    Inconclusive(u16),
+}
+
+impl std::fmt::Display for ProbeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeStatus::Ok => write!(f, "ok"),
+            ProbeStatus::Invalid(status) => write!(f, "invalid or revoked (HTTP {})", status),
+            ProbeStatus::Unreachable(e) => write!(f, "unreachable ({})", e),
+            ProbeStatus::Inconclusive(status) => write!(f, "inconclusive (HTTP {})", status),
+        }
+    }
+}
+
+/// Check if a target is a Discord webhook
+fn is_discord_webhook(target: &TargetConfig) -> bool {
+    url::Url::parse(&target.url).is_ok_and(|url| {
+        matches!(
+            url.host_str(),
+            Some("discord.com" | "discordapp.com" | "ptb.discord.com" | "canary.discord.com")
+        ) && url.path().starts_with("/api/webhooks/")
+    })
+}
+
+/// Probe a target
+pub async fn probe(http: &reqwest::Client, target: &TargetConfig) -> ProbeStatus {
+    if target.stream().is_some() {
+        return ProbeStatus::Ok;
+    }
+    if let Some(path) = target.file_path() {
+        return match path.parent().map(|dir| dir.is_dir()) {
+            Some(false) => ProbeStatus::Unreachable("no such directory".to_string()),
+            _ => ProbeStatus::Ok,
+        };
+    }
+    let discord = is_discord_webhook(target);
+    let request = if discord {
+        http.get(&target.url)
+    } else {
+        http.head(&target.url)
+    };
+    let status = match request.send().await {
+        Ok(resp) => resp.status(),
+        Err(e) => return ProbeStatus::Unreachable(e.to_string()),
+    };
+    match status {
+        s if s.is_success() => ProbeStatus::Ok,
+        StatusCode::UNAUTHORIZED
+        | StatusCode::FORBIDDEN
+        | StatusCode::NOT_FOUND
+        | StatusCode::GONE => ProbeStatus::Invalid(status.as_u16()),
+        // Generic webhooks usually only accept POST requests
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::BAD_REQUEST if !discord => ProbeStatus::Ok,
+        _ => ProbeStatus::Inconclusive(status.as_u16()),
+    }
+}
@@ -0,0 +1,186 @@
+//! Queries of the OpenBar API (`openbar-notifier api`)
+//!
+//! For debugging the configuration (or the filters of the targets), the categories and the items
+//! of an instance can be listed as the API returns them, logged in with the configured
+//! credentials: as a table for humans, or as JSON for scripts.
+
+use std::io::Write;
+
+use openbar_api::models::{Category, Item};
+
+use crate::render::Formatting;
+
+/// Format of the query output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Aligned columns, with a header row
+    #[default]
+    Table,
+    /// JSON array of the API models, as returned by the API
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!(
+                "unknown output format '{}' (expected table or json)",
+                s
+            )),
+        }
+    }
+}
+
+/// Find a category by its ID, or by its name (case-insensitive)
+pub fn find_category<'a>(categories: &'a [Category], query: &str) -> Option<&'a Category> {
+    categories
+        .iter()
+        .find(|c| c.id.to_string() == query)
+        .or_else(|| {
+            categories
+                .iter()
+                .find(|c| c.name.to_lowercase() == query.to_lowercase())
+        })
+}
+
+/// Write the categories of an instance (ID, name, position, hidden)
+pub fn write_categories(
+    format: OutputFormat,
+    categories: &[Category],
+    writer: impl Write,
+) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Table => {
+            let rows = categories
+                .iter()
+                .map(|c| {
+                    vec![
+                        c.id.to_string(),
+                        c.name.clone(),
+                        c.position.to_string(),
+                        if c.hidden { "yes" } else { "no" }.to_string(),
+                    ]
+                })
+                .collect();
+            write_table(&["ID", "Name", "Position", "Hidden"], rows, writer)
+        }
+        OutputFormat::Json => write_json(categories, writer),
+    }
+}
+
+/// Write the items of a category (ID, name, stock, state, member price)
+pub fn write_items(
+    format: OutputFormat,
+    items: &[Item],
+    formatting: &Formatting,
+    writer: impl Write,
+) -> std::io::Result<()> {
+    match format {
+        OutputFormat::Table => {
+            let rows = items
+                .iter()
+                .map(|item| {
+                    vec![
+                        item.id.to_string(),
+                        item.name.clone(),
+                        item.amount_left.to_string(),
+                        item.state.to_string(),
+                        formatting.format_price(item.prices.ceten),
+                    ]
+                })
+                .collect();
+            write_table(&["ID", "Name", "Stock", "State", "Price"], rows, writer)
+        }
+        OutputFormat::Json => write_json(items, writer),
+    }
+}
+
+fn write_json<T: serde::Serialize + ?Sized>(
+    value: &T,
+    mut writer: impl Write,
+) -> std::io::Result<()> {
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writeln!(writer)
+}
+
+/// Write rows as aligned columns, separated by two spaces
+fn write_table(
+    header: &[&str],
+    rows: Vec<Vec<String>>,
+    mut writer: impl Write,
+) -> std::io::Result<()> {
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        writeln!(writer, "{}", line.join("  ").trim_end())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn writes_the_items_as_a_table() {
+        let item = |id: u128, name: &str, amount_left: i64| {
+            Item::new(
+                Uuid::from_u128(id),
+                ItemPrices::new(100, 120, 100, 100, 100, 150),
+                amount_left,
+                10,
+                Uuid::from_u128(0),
+                name.to_string(),
+                String::new(),
+                false,
+                ItemState::ItemBuyable,
+            )
+        };
+        let items = vec![item(1, "Chouffe", 12), item(2, "Kwak", 3)];
+        let mut output = Vec::new();
+        write_items(
+            OutputFormat::Table,
+            &items,
+            &Formatting::default(),
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].starts_with("ID                                    Name     Stock  State")
+        );
+        assert!(lines[1].contains("  Chouffe  12     buyable  "));
+        assert!(lines[2].contains("  Kwak     3      buyable  "));
+
+        let categories = vec![Category::new(
+            Uuid::from_u128(7),
+            "Bières".to_string(),
+            String::new(),
+            0,
+            false,
+            false,
+        )];
+        assert!(find_category(&categories, "bières").is_some());
+        assert!(find_category(&categories, &Uuid::from_u128(7).to_string()).is_some());
+        assert!(find_category(&categories, "Softs").is_none());
+    }
+}
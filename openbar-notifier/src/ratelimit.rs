@@ -0,0 +1,147 @@
+//! Rate limiting of the outgoing notifications
+//!
+//! Webhook providers (e.g. Discord) rate-limit their webhooks, so a burst of events
+//! posted back-to-back can get rejected with `429 Too Many Requests`. Each target has
+//! its own token bucket: up to `burst` messages can be sent at once, then the bucket
+//! refills at `per_minute` messages per minute. When a target still answers with a 429,
+//! its `Retry-After` delay is honored before sending anything else to it.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+/// Longest `Retry-After` delay honored, longer ones are capped to it
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(600);
+
+/// Rate limiting configuration
+///
+/// A zero `per_minute` disables rate limiting (`Retry-After` delays are still honored).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Number of messages that can be sent at once to a target
+    pub burst: u32,
+    /// Number of messages that can be sent to a target per minute, in the long run
+    pub per_minute: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            burst: 5,
+            per_minute: 30,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Check if rate limiting is disabled
+    pub fn is_disabled(&self) -> bool {
+        self.per_minute == 0
+    }
+}
+
+/// Token bucket of a target
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last: Instant,
+    /// Nothing can be sent before this instant (from a `Retry-After` delay)
+    blocked_until: Option<Instant>,
+}
+
+impl TokenBucket {
+    /// Take a token, or return how long to wait before retrying
+    fn try_acquire(&mut self, now: Instant, config: &RateLimitConfig) -> Option<Duration> {
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return Some(until - now);
+            }
+            self.blocked_until = None;
+        }
+        if config.is_disabled() {
+            return None;
+        }
+        let rate = config.per_minute as f64 / 60.0;
+        let burst = config.burst.max(1) as f64;
+        self.tokens = (self.tokens + (now - self.last).as_secs_f64() * rate).min(burst);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+/// Per-target rate limiter
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter with the given configuration
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until a message can be sent to the given target
+    pub async fn acquire(&self, target: &str) {
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut buckets = self.buckets.lock().unwrap();
+                let bucket = buckets
+                    .entry(target.to_string())
+                    .or_insert_with(|| TokenBucket {
+                        tokens: self.config.burst.max(1) as f64,
+                        last: now,
+                        blocked_until: None,
+                    });
+                bucket.try_acquire(now, &self.config)
+            };
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+
+    /// Block the given target for `delay` (e.g. from a `Retry-After` header)
+    pub fn defer(&self, target: &str, delay: Duration) {
+        let now = Instant::now();
+        let until = now + delay.min(MAX_RETRY_AFTER);
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets
+            .entry(target.to_string())
+            .or_insert_with(|| TokenBucket {
+                tokens: 0.0,
+                last: now,
+                blocked_until: None,
+            });
+        bucket.blocked_until = Some(bucket.blocked_until.map_or(until, |b| b.max(until)));
+    }
+}
+
+/// Parse the value of a `Retry-After` header
+///
+/// The value is either a (possibly fractional) number of seconds, or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return (secs.is_finite() && secs >= 0.0).then(|| Duration::from_secs_f64(secs));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
@@ -0,0 +1,504 @@
+//! Notification rendering for OpenBar Notifier
+//!
+//! This module turns item events into human-readable notification messages.
+
+use std::collections::BTreeMap;
+
+use openbar_api::models::Item;
+
+use crate::correction;
+use crate::event::{EventContext, ItemEvent};
+use crate::i18n;
+use crate::sales::{format_hours_left, format_rate};
+use crate::theme::{Theme, Variables, fill};
+
+/// Default locale of the messages
+pub const DEFAULT_LOCALE: &str = "en";
+/// Default currency of the prices
+pub const DEFAULT_CURRENCY: &str = "EUR";
+
+/// Languages using a decimal point (the others use a decimal comma)
+const DECIMAL_POINT_LANGUAGES: &[&str] = &["en", "ja", "ko", "zh", "he", "th", "hi"];
+
+/// Locale-dependent formatting of the messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Formatting {
+    /// Locale of the messages (e.g. `fr-FR`)
+    pub locale: String,
+    /// Currency of the prices (ISO 4217 code, e.g. `EUR`)
+    pub currency: String,
+}
+
+impl Default for Formatting {
+    fn default() -> Self {
+        Formatting {
+            locale: DEFAULT_LOCALE.to_string(),
+            currency: DEFAULT_CURRENCY.to_string(),
+        }
+    }
+}
+
+impl Formatting {
+    /// Create the formatting for the given locale and currency, falling back to the defaults
+    pub fn new(locale: Option<String>, currency: Option<String>) -> Self {
+        Formatting {
+            locale: locale.unwrap_or_else(|| DEFAULT_LOCALE.to_string()),
+            currency: currency
+                .map(|c| c.to_uppercase())
+                .unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+        }
+    }
+
+    /// Get the language of the locale (e.g. `fr` for `fr-FR`)
+    pub fn language(&self) -> String {
+        self.locale
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+    }
+
+    /// Format a price, given in cents (e.g. `1,50 €` in French, `€1.50` in English)
+    pub fn format_price(&self, cents: i64) -> String {
+        let language = self.language();
+        let separator = if DECIMAL_POINT_LANGUAGES.contains(&language.as_str()) {
+            '.'
+        } else {
+            ','
+        };
+        let sign = if cents < 0 { "-" } else { "" };
+        let amount = format!("{}{}{:02}", cents.abs() / 100, separator, cents.abs() % 100);
+        let symbol = match self.currency.as_str() {
+            "EUR" => "€",
+            "USD" => "$",
+            "GBP" => "£",
+            "JPY" => "¥",
+            other => other,
+        };
+        if language == "en" && symbol.chars().count() == 1 {
+            format!("{}{}{}", sign, symbol, amount)
+        } else {
+            format!("{}{} {}", sign, amount, symbol)
+        }
+    }
+}
+
+/// Render an event as a single message line (including the trailing newline), in the given theme
+/// and with the given target variables
+///
+/// Prices are the member (`ceten`) prices of the items.
+pub fn render_line(
+    ctx: &EventContext,
+    formatting: &Formatting,
+    theme: Theme,
+    variables: &Variables,
+) -> String {
+    let language = formatting.language();
+    if let Some(correction) = &ctx.correction {
+        return correction::render(ctx, correction, &language);
+    }
+    let templates = theme.templates().localized(&language);
+    let item = &ctx.item;
+    let (template, components) = match &ctx.event {
+        ItemEvent::Added => (templates.added, String::new()),
+        ItemEvent::BecomeBuyable => (templates.become_buyable, String::new()),
+        ItemEvent::BecomeUnbuyable => (templates.become_unbuyable, String::new()),
+        ItemEvent::OutOfStock => (templates.out_of_stock, String::new()),
+        ItemEvent::LowStock => (templates.low_stock, String::new()),
+        ItemEvent::Removed => (templates.removed, String::new()),
+        ItemEvent::MenuChanged => (templates.menu_changed, menu_component_names(item)),
+        ItemEvent::MenuUnavailable { components } => {
+            (templates.menu_unavailable, components.join(", "))
+        }
+        ItemEvent::PromotionStarted { .. } => (templates.promotion_started, String::new()),
+        ItemEvent::PromotionEnded => (templates.promotion_ended, String::new()),
+        ItemEvent::PredictedStockOut { .. } => (templates.predicted_stock_out, String::new()),
+        ItemEvent::StockDropped { .. } => (templates.stock_dropped, String::new()),
+        ItemEvent::StockChanged { .. } => (templates.stock_changed, String::new()),
+    };
+    let values = event_values(ctx, formatting, &language, &components);
+    let values: Vec<(&str, &str)> = values.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    fill(template, &values, variables)
+}
+
+/// Render several events of the same item as a single message line (e.g. `- Chouffe (...) is
+/// buyable again and was restocked to 24.`), in the given theme and with the given target
+/// variables
+///
+/// A single event (or a correction) is rendered as its own line (see [render_line]). The
+/// severity of the line is the highest one of the events.
+pub fn render_group(
+    group: &[&EventContext],
+    formatting: &Formatting,
+    theme: Theme,
+    variables: &Variables,
+) -> String {
+    let [first, ..] = group else {
+        return String::new();
+    };
+    if group.len() == 1 || group.iter().any(|ctx| ctx.correction.is_some()) {
+        return group
+            .iter()
+            .map(|ctx| render_line(ctx, formatting, theme, variables))
+            .collect();
+    }
+    let language = formatting.language();
+    let tr = |msgid| i18n::translate(&language, msgid);
+    let phrases: Vec<String> = group
+        .iter()
+        .map(|ctx| {
+            let components = match &ctx.event {
+                ItemEvent::MenuChanged => menu_component_names(&ctx.item),
+                ItemEvent::MenuUnavailable { components } => components.join(", "),
+                _ => String::new(),
+            };
+            let values = event_values(ctx, formatting, &language, &components);
+            let values: Vec<(&str, &str)> = values.iter().map(|(k, v)| (*k, v.as_str())).collect();
+            fill(tr(event_phrase(&ctx.event)), &values, &Variables::new())
+        })
+        .collect();
+    let changes = match phrases.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, others)) => fill(
+            tr("{first} and {last}"),
+            &[("first", &others.join(", ")), ("last", last)],
+            &Variables::new(),
+        ),
+        None => String::new(),
+    };
+    let severity = group
+        .iter()
+        .map(|ctx| ctx.severity)
+        .max()
+        .unwrap_or_default();
+    let templates = theme.templates().localized(&language);
+    let mut values = event_values(first, formatting, &language, "");
+    values.retain(|(key, _)| *key != "severity");
+    values.push(("severity", severity.to_string()));
+    values.push(("changes", changes));
+    let values: Vec<(&str, &str)> = values.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    fill(templates.grouped, &values, variables)
+}
+
+/// Get the values of the placeholders of an event (see [crate::theme])
+fn event_values(
+    ctx: &EventContext,
+    formatting: &Formatting,
+    language: &str,
+    components: &str,
+) -> Vec<(&'static str, String)> {
+    let item = &ctx.item;
+    let eta = match &ctx.event {
+        ItemEvent::PredictedStockOut { hours_left } => format_hours_left(*hours_left, language),
+        _ => String::new(),
+    };
+    let dropped = match &ctx.event {
+        ItemEvent::StockDropped { dropped } => dropped.to_string(),
+        _ => String::new(),
+    };
+    let change = match &ctx.event {
+        ItemEvent::StockChanged { change } => format!("{:+}", change),
+        _ => String::new(),
+    };
+    let discount = match &ctx.event {
+        ItemEvent::PromotionStarted { discount } => format_discount(*discount),
+        _ => String::new(),
+    };
+    let price_history = ctx
+        .price
+        .map(|comparison| comparison.format(language))
+        .unwrap_or_default();
+    let bundle = item
+        .amount_per_bundle
+        .map(|n| n.to_string())
+        .unwrap_or_default();
+    let buy_limit = item.buy_limit.map(|n| n.to_string()).unwrap_or_default();
+    vec![
+        ("name", item.name.clone()),
+        ("id", item.id.to_string()),
+        ("category", ctx.category_name.clone()),
+        ("stock", item.amount_left.to_string()),
+        ("price", formatting.format_price(item.prices.ceten)),
+        ("bundle", bundle),
+        ("buy_limit", buy_limit),
+        ("details", format_details(item, language)),
+        ("components", components.to_string()),
+        ("discount", discount),
+        ("price_history", price_history),
+        ("eta", eta),
+        ("dropped", dropped),
+        ("change", change),
+        ("severity", ctx.severity.to_string()),
+    ]
+}
+
+/// Render events as a digest, grouping them by category and event type
+///
+/// For instance: `**Beers**: 3 became buyable (Chouffe, Kwak, Delirium), 1 out of stock (Leffe)`.
+/// The item names are followed by their sales rate when known (e.g. `Chouffe ~12/day`). The
+/// categories are listed in the order of their first event (see [crate::store::ItemStore::sort_by_category]).
+pub fn render_digest(events: &[&EventContext], formatting: &Formatting) -> String {
+    let language = formatting.language();
+    // (Category name, event label -> item names)
+    let mut groups: Vec<(&str, BTreeMap<&str, Vec<String>>)> = Vec::new();
+    for ctx in events {
+        let category = ctx.category_name.as_str();
+        let index = match groups.iter().position(|(name, _)| *name == category) {
+            Some(index) => index,
+            None => {
+                groups.push((category, BTreeMap::new()));
+                groups.len() - 1
+            }
+        };
+        groups[index]
+            .1
+            .entry(i18n::translate(&language, event_label(&ctx.event)))
+            .or_default()
+            .push(match ctx.sales_rate {
+                Some(rate) => format!("{} {}", ctx.item.name, format_rate(rate, &language)),
+                None => ctx.item.name.clone(),
+            });
+    }
+    let mut digest = String::new();
+    for (category, labels) in groups {
+        let category = if category.is_empty() {
+            i18n::translate(&language, "Other")
+        } else {
+            category
+        };
+        let parts: Vec<String> = labels
+            .into_iter()
+            .map(|(label, names)| format!("{} {} ({})", names.len(), label, names.join(", ")))
+            .collect();
+        digest.push_str(&format!("**{}**: {}\n", category, parts.join(", ")));
+    }
+    digest
+}
+
+/// Split a message into chunks of at most `max_chars` characters
+///
+/// Messages are split on line boundaries, lines longer than `max_chars` are split
+/// on character boundaries.
+pub fn split_message(message: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+    for line in message.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if chunk_len + line_len > max_chars && !chunk.is_empty() {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_len = 0;
+        }
+        if line_len > max_chars {
+            // Split the (too) long line, the remainder stays in the current chunk
+            let chars: Vec<char> = line.chars().collect();
+            let mut parts = chars.chunks(max_chars).peekable();
+            while let Some(part) = parts.next() {
+                if parts.peek().is_some() {
+                    chunks.push(part.iter().collect());
+                } else {
+                    chunk = part.iter().collect();
+                    chunk_len = part.len();
+                }
+            }
+        } else {
+            chunk.push_str(line);
+            chunk_len += line_len;
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Short label of an event type, used in digests (in English)
+fn event_label(event: &ItemEvent) -> &'static str {
+    match event {
+        ItemEvent::Added => "added",
+        ItemEvent::BecomeBuyable => "became buyable",
+        ItemEvent::BecomeUnbuyable => "became unbuyable",
+        ItemEvent::OutOfStock => "out of stock",
+        ItemEvent::LowStock => "low on stock",
+        ItemEvent::Removed => "removed",
+        ItemEvent::MenuChanged => "menu changed",
+        ItemEvent::MenuUnavailable { .. } => "menu unavailable",
+        ItemEvent::PromotionStarted { .. } => "on promotion",
+        ItemEvent::PromotionEnded => "promotion ended",
+        ItemEvent::PredictedStockOut { .. } => "running out soon",
+        ItemEvent::StockDropped { .. } => "stock dropped",
+        ItemEvent::StockChanged { .. } => "stock changed",
+    }
+}
+
+/// Phrase of an event of an item, in a line grouping several of them (in English)
+fn event_phrase(event: &ItemEvent) -> &'static str {
+    match event {
+        ItemEvent::Added => "was added ({price})",
+        ItemEvent::BecomeBuyable => "is buyable again",
+        ItemEvent::BecomeUnbuyable => "can no longer be bought",
+        ItemEvent::OutOfStock => "is out of stock",
+        ItemEvent::LowStock => "is low on stock",
+        ItemEvent::Removed => "was removed",
+        ItemEvent::MenuChanged => "now comes with {components}",
+        ItemEvent::MenuUnavailable { .. } => "is unavailable (missing: {components})",
+        ItemEvent::PromotionStarted { .. } => "is {discount} off{price_history}",
+        ItemEvent::PromotionEnded => "is back to its regular price",
+        ItemEvent::PredictedStockOut { .. } => "will run out of stock in {eta}",
+        ItemEvent::StockDropped { .. } => "lost {dropped} units",
+        ItemEvent::StockChanged { change } if *change > 0 => "was restocked to {stock}",
+        ItemEvent::StockChanged { .. } => "went down to {stock}",
+    }
+}
+
+/// Format a discount given in hundredths of a percent (e.g. `30%` for 3000, `12.5%` for 1250)
+pub fn format_discount(discount: i64) -> String {
+    let percent = format!("{}.{:02}", discount / 100, (discount % 100).abs());
+    format!("{}%", percent.trim_end_matches('0').trim_end_matches('.'))
+}
+
+/// Format the bundle size and purchase limit of an item as a list continuing a previous one
+/// (e.g. `, 24 per bundle, max 2 per purchase`), empty if the API exposes neither
+///
+/// Bundles of a single unit and non-positive limits are left out, being meaningless.
+pub fn format_details(item: &Item, language: &str) -> String {
+    let tr = |msgid| i18n::translate(language, msgid);
+    let mut details = String::new();
+    if let Some(bundle) = item.amount_per_bundle.filter(|n| *n > 1) {
+        details.push_str(", ");
+        details.push_str(&fill(
+            tr("{bundle} per bundle"),
+            &[("bundle", &bundle.to_string())],
+            &Variables::new(),
+        ));
+    }
+    if let Some(buy_limit) = item.buy_limit.filter(|n| *n > 0) {
+        details.push_str(", ");
+        details.push_str(&fill(
+            tr("max {buy_limit} per purchase"),
+            &[("buy_limit", &buy_limit.to_string())],
+            &Variables::new(),
+        ));
+    }
+    details
+}
+
+/// Get the human-readable list of components of a menu
+fn menu_component_names(item: &Item) -> String {
+    item.menu_items
+        .iter()
+        .flatten()
+        .map(|c| format!("{}x {}", c.amount, c.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::correction::Correction;
+
+    fn context(amount_per_bundle: Option<i64>, buy_limit: Option<i64>) -> EventContext {
+        let mut item = Item::new(
+            Uuid::nil(),
+            ItemPrices::new(150, 150, 150, 150, 150, 200),
+            48,
+            96,
+            Uuid::nil(),
+            "Kwak".to_string(),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        );
+        item.amount_per_bundle = amount_per_bundle;
+        item.buy_limit = buy_limit;
+        EventContext {
+            event: ItemEvent::BecomeBuyable,
+            item,
+            category_name: "Beers".to_string(),
+            tags: Default::default(),
+            restricted: false,
+            sales_rate: None,
+            price: None,
+            severity: Default::default(),
+            correction: None,
+        }
+    }
+
+    #[test]
+    fn lines_mention_the_bundles_and_buy_limits() {
+        let line = |ctx: &EventContext, locale: &str| {
+            let formatting = Formatting::new(Some(locale.to_string()), None);
+            render_line(ctx, &formatting, Theme::Default, &Variables::new())
+        };
+        assert_eq!(
+            line(&context(Some(24), Some(2)), "en"),
+            "- Kwak (00000000-0000-0000-0000-000000000000) became buyable \
+             (stock: 48, price: €1.50, 24 per bundle, max 2 per purchase).\n"
+        );
+        assert_eq!(
+            line(&context(Some(24), None), "fr"),
+            "- Kwak (00000000-0000-0000-0000-000000000000) de nouveau disponible \
+             (stock : 48, prix : 1,50 €, 24 par lot).\n"
+        );
+        assert_eq!(
+            line(&context(Some(1), Some(0)), "en"),
+            "- Kwak (00000000-0000-0000-0000-000000000000) became buyable \
+             (stock: 48, price: €1.50).\n"
+        );
+    }
+
+    #[test]
+    fn grouped_events_share_a_line() {
+        let buyable = context(None, None);
+        let mut restocked = context(None, None);
+        restocked.event = ItemEvent::StockChanged { change: 24 };
+        let mut promoted = context(None, None);
+        promoted.event = ItemEvent::PromotionStarted { discount: 2000 };
+        let formatting = Formatting::default();
+        assert_eq!(
+            render_group(
+                &[&buyable, &restocked],
+                &formatting,
+                Theme::Minimal,
+                &Variables::new()
+            ),
+            "* Kwak: is buyable again and was restocked to 48\n"
+        );
+        let formatting = Formatting::new(Some("fr".to_string()), None);
+        assert_eq!(
+            render_group(
+                &[&buyable, &restocked, &promoted],
+                &formatting,
+                Theme::Formal,
+                &Variables::new()
+            ),
+            "L'article « Kwak » est de nouveau disponible, a été réapprovisionné à 48 et est à \
+             -20%.\n"
+        );
+        assert_eq!(
+            render_group(&[&buyable], &formatting, Theme::Default, &Variables::new()),
+            render_line(&buyable, &formatting, Theme::Default, &Variables::new())
+        );
+    }
+
+    #[test]
+    fn corrections_are_compact() {
+        let mut ctx = context(None, None);
+        ctx.correction = Some(Correction {
+            event: "out_of_stock".to_string(),
+            after_secs: 720,
+            message_id: Some("1".to_string()),
+            messages: Default::default(),
+        });
+        let formatting = Formatting::new(Some("fr".to_string()), None);
+        assert_eq!(
+            render_line(&ctx, &formatting, Theme::Emoji, &Variables::new()),
+            "- Rectificatif : Kwak est de nouveau disponible (12 min plus tard).\n"
+        );
+    }
+}
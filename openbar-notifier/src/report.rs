@@ -0,0 +1,364 @@
+//! Monthly consumption reports of OpenBar Notifier
+//!
+//! Built on the [crate::analytics] database, the units sold during a month are inferred from
+//! the stock decreases between two samples of each item (restocks are ignored), then summed by
+//! category. The menus are left out, their sales already decreasing the stock of their
+//! components. The revenue is estimated with the (member) price of the item at the time.
+//!
+//! The report of the previous month is sent once to the report targets (e.g. the treasurer
+//! channel), on the first check of each month (in UTC). Querying the database requires the
+//! `analytics` feature, the rendering and sending of the reports being also used by the run
+//! summaries (see [crate::summary]).
+
+#[cfg(feature = "analytics")]
+use chrono::{DateTime, Utc};
+use chrono::{Datelike, NaiveDate};
+#[cfg(feature = "analytics")]
+use log::{error, info};
+#[cfg(feature = "analytics")]
+use rusqlite::{OptionalExtension, params};
+
+#[cfg(feature = "analytics")]
+use crate::analytics::AnalyticsDb;
+use crate::config::TargetConfig;
+use crate::notifier::{MAX_MESSAGE_LEN, Notifier};
+use crate::render::{Formatting, split_message};
+
+/// Sales of a category during a month
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategorySales {
+    /// Name of the category
+    pub category: String,
+    /// Units sold
+    pub units: i64,
+    /// Estimated revenue (in cents)
+    pub revenue: i64,
+}
+
+/// Get the first day of the month of a date
+pub fn month_of(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("the first day of a month is valid")
+}
+
+/// Get the first day of the month preceding the month of a date
+pub fn previous_month(date: NaiveDate) -> NaiveDate {
+    month_of(
+        month_of(date)
+            .pred_opt()
+            .expect("dates before year -262143 are not supported"),
+    )
+}
+
+/// Get the first day of the month following the month of a date
+pub fn next_month(date: NaiveDate) -> NaiveDate {
+    let month = month_of(date);
+    month
+        .checked_add_months(chrono::Months::new(1))
+        .unwrap_or(month)
+}
+
+/// Parse a month given as `YYYY-MM`, returning its first day
+pub fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", s.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("invalid month '{}' (expected YYYY-MM)", s))
+}
+
+/// Get the sales by category of an instance during the month starting at `month`
+///
+/// The categories are sorted by decreasing units sold, those without sales are left out.
+#[cfg(feature = "analytics")]
+pub fn monthly_sales(
+    db: &AnalyticsDb,
+    instance: &str,
+    month: NaiveDate,
+) -> rusqlite::Result<Vec<CategorySales>> {
+    let bound = |date: NaiveDate| date.and_time(chrono::NaiveTime::MIN).and_utc().to_rfc3339();
+    let mut statement = db.connection().prepare_cached(
+        "WITH sales AS (
+             SELECT instance, item_id, at, price,
+                    MAX(LAG(amount_left) OVER w - amount_left, 0) AS sold
+             FROM samples WHERE instance = ?1
+             WINDOW w AS (PARTITION BY item_id ORDER BY at)
+         )
+         SELECT COALESCE(c.name, i.category_id), SUM(s.sold), SUM(s.sold * s.price)
+         FROM sales s
+         JOIN items i ON i.instance = s.instance AND i.id = s.item_id
+         LEFT JOIN categories c ON c.instance = i.instance AND c.id = i.category_id
+         WHERE s.at >= ?2 AND s.at < ?3 AND NOT i.is_menu
+         GROUP BY 1 HAVING SUM(s.sold) > 0
+         ORDER BY 2 DESC, 1",
+    )?;
+    statement
+        .query_map(
+            params![instance, bound(month), bound(next_month(month))],
+            |row| {
+                Ok(CategorySales {
+                    category: row.get(0)?,
+                    units: row.get(1)?,
+                    revenue: row.get(2)?,
+                })
+            },
+        )?
+        .collect()
+}
+
+/// Render the sales as a table, one line per category followed by the total
+pub fn render_table(sales: &[CategorySales], formatting: &Formatting) -> String {
+    let total = CategorySales {
+        category: "Total".to_string(),
+        units: sales.iter().map(|s| s.units).sum(),
+        revenue: sales.iter().map(|s| s.revenue).sum(),
+    };
+    let rows: Vec<(&str, String, String)> = sales
+        .iter()
+        .chain(std::iter::once(&total))
+        .map(|s| {
+            (
+                s.category.as_str(),
+                s.units.to_string(),
+                formatting.format_price(s.revenue),
+            )
+        })
+        .collect();
+    let width = |column: fn(&(&str, String, String)) -> usize, title: &str| {
+        rows.iter()
+            .map(column)
+            .max()
+            .unwrap_or_default()
+            .max(title.chars().count())
+    };
+    let (category_width, units_width, revenue_width) = (
+        width(|r| r.0.chars().count(), "Category"),
+        width(|r| r.1.chars().count(), "Units"),
+        width(|r| r.2.chars().count(), "Revenue"),
+    );
+    let line = |category: &str, units: &str, revenue: &str| {
+        format!(
+            "{:<category_width$}  {:>units_width$}  {:>revenue_width$}\n",
+            category, units, revenue
+        )
+    };
+    let mut table = line("Category", "Units", "Revenue");
+    for (i, (category, units, revenue)) in rows.iter().enumerate() {
+        if i == rows.len() - 1 {
+            table.push_str(&"-".repeat(category_width + units_width + revenue_width + 4));
+            table.push('\n');
+        }
+        table.push_str(&line(category, units, revenue));
+    }
+    table
+}
+
+/// Render the report of an instance for a month, as messages of at most [MAX_MESSAGE_LEN]
+/// characters (the table being split in several code blocks if needed)
+pub fn render_report(
+    instance: &str,
+    month: NaiveDate,
+    sales: &[CategorySales],
+    formatting: &Formatting,
+) -> Vec<String> {
+    let title = format!(
+        "**Consumption report of {}, {}** (estimated from the stock decreases)\n",
+        instance,
+        month.format("%B %Y")
+    );
+    if sales.is_empty() {
+        return vec![format!("{}No sales recorded.\n", title)];
+    }
+    const FENCE: &str = "```\n";
+    let max_len = MAX_MESSAGE_LEN.saturating_sub(title.chars().count() + 2 * FENCE.len());
+    split_message(&render_table(sales, formatting), max_len)
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let title = if i == 0 { title.as_str() } else { "" };
+            format!("{}{}{}{}", title, FENCE, chunk, FENCE)
+        })
+        .collect()
+}
+
+/// Check if the report of an instance for a month has already been sent
+#[cfg(feature = "analytics")]
+fn is_report_sent(db: &AnalyticsDb, instance: &str, month: NaiveDate) -> rusqlite::Result<bool> {
+    db.connection()
+        .query_row(
+            "SELECT 1 FROM reports WHERE instance = ?1 AND month = ?2",
+            params![instance, month.format("%Y-%m").to_string()],
+            |_| Ok(()),
+        )
+        .optional()
+        .map(|sent| sent.is_some())
+}
+
+/// Record that the report of an instance for a month has been sent
+#[cfg(feature = "analytics")]
+fn record_report_sent(
+    db: &AnalyticsDb,
+    instance: &str,
+    month: NaiveDate,
+    at: DateTime<Utc>,
+) -> rusqlite::Result<()> {
+    db.connection()
+        .execute(
+            "INSERT OR REPLACE INTO reports (instance, month, sent_at) VALUES (?1, ?2, ?3)",
+            params![instance, month.format("%Y-%m").to_string(), at.to_rfc3339()],
+        )
+        .map(|_| ())
+}
+
+/// Send a report to the targets, returning whether all the messages were sent
+pub async fn send_report(
+    notifier: &Notifier,
+    targets: &[TargetConfig],
+    messages: &[String],
+) -> bool {
+    let mut sent = true;
+    for target in targets {
+        for (part, message) in messages.iter().enumerate() {
+            let result = notifier
+                .send_message(target, message, part, messages.len())
+                .await;
+            sent &= result.is_success();
+        }
+    }
+    sent
+}
+
+/// Send the report of the previous month to the targets, if not sent yet
+///
+/// The report is only sent once, even if some deliveries failed (use the `report` command
+/// to send it again).
+#[cfg(feature = "analytics")]
+pub async fn send_due_report(
+    db: &AnalyticsDb,
+    instance: &str,
+    now: DateTime<Utc>,
+    targets: &[TargetConfig],
+    notifier: &Notifier,
+    formatting: &Formatting,
+) {
+    let month = previous_month(now.date_naive());
+    let sales = match is_report_sent(db, instance, month) {
+        Ok(true) => return,
+        Ok(false) => monthly_sales(db, instance, month),
+        Err(e) => Err(e),
+    };
+    let sales = match sales {
+        Ok(sales) => sales,
+        Err(e) => {
+            error!("Error computing the monthly report: {}", e);
+            return;
+        }
+    };
+    info!(
+        "Sending the consumption report of {} for {}...",
+        instance,
+        month.format("%Y-%m")
+    );
+    let messages = render_report(instance, month, &sales, formatting);
+    if !send_report(notifier, targets, &messages).await {
+        error!("The monthly report could not be delivered to all the report targets.");
+    }
+    if let Err(e) = record_report_sent(db, instance, month, now) {
+        error!("Error recording the monthly report: {}", e);
+    }
+}
+
+#[cfg(all(test, feature = "analytics"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use openbar_api::models::{Item, ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::analytics::Observations;
+
+    fn item(n: u128, category: u128, amount_left: i64, price: i64) -> Item {
+        Item::new(
+            Uuid::from_u128(n),
+            ItemPrices::new(price, price, price, price, price, price),
+            amount_left,
+            10,
+            Uuid::from_u128(category),
+            format!("Item {}", n),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        )
+    }
+
+    fn record(db: &mut AnalyticsDb, at: &str, items: Vec<Item>) {
+        let observations = Observations {
+            categories: HashMap::from([
+                (Uuid::from_u128(100), "Beers".to_string()),
+                (Uuid::from_u128(200), "Snacks".to_string()),
+            ]),
+            items: items.into_iter().map(|item| (item, true)).collect(),
+            events: Vec::new(),
+        };
+        let at = DateTime::parse_from_rfc3339(at).unwrap().to_utc();
+        db.record("bar", at, &observations).unwrap();
+    }
+
+    #[test]
+    fn months() {
+        let date = NaiveDate::from_ymd_opt(2026, 1, 15).unwrap();
+        assert_eq!(
+            previous_month(date),
+            NaiveDate::from_ymd_opt(2025, 12, 1).unwrap()
+        );
+        assert_eq!(
+            next_month(date),
+            NaiveDate::from_ymd_opt(2026, 2, 1).unwrap()
+        );
+        assert_eq!(parse_month("2026-01"), Ok(month_of(date)));
+        assert!(parse_month("2026-13").is_err());
+    }
+
+    #[test]
+    fn sales_are_inferred_from_stock_decreases() {
+        let mut db = AnalyticsDb::open_in_memory().unwrap();
+        record(
+            &mut db,
+            "2026-08-31T20:00:00Z",
+            vec![item(1, 100, 10, 150), item(2, 200, 5, 80)],
+        );
+        // Sold during September: 4 + 3 (then restocked) beers, 5 snacks
+        record(
+            &mut db,
+            "2026-09-01T10:00:00Z",
+            vec![item(1, 100, 6, 150), item(2, 200, 5, 80)],
+        );
+        record(
+            &mut db,
+            "2026-09-10T10:00:00Z",
+            vec![item(1, 100, 3, 150), item(2, 200, 0, 80)],
+        );
+        record(&mut db, "2026-09-11T10:00:00Z", vec![item(1, 100, 24, 150)]);
+        // Sold during October
+        record(&mut db, "2026-10-01T10:00:00Z", vec![item(1, 100, 20, 150)]);
+
+        let september = NaiveDate::from_ymd_opt(2026, 9, 1).unwrap();
+        let sales = monthly_sales(&db, "bar", september).unwrap();
+        assert_eq!(
+            sales,
+            [
+                CategorySales {
+                    category: "Beers".to_string(),
+                    units: 7,
+                    revenue: 1050,
+                },
+                CategorySales {
+                    category: "Snacks".to_string(),
+                    units: 5,
+                    revenue: 400,
+                },
+            ]
+        );
+
+        let table = render_table(&sales, &Formatting::default());
+        assert_eq!(table.lines().last(), Some("Total        12   €14.50"));
+        assert!(monthly_sales(&db, "other", september).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+//! Notification routing for OpenBar Notifier
+//!
+//! This module sits between event generation and notification dispatch: it decides
+//! which targets should receive the notification of each item event.
+
+use crate::config::TargetConfig;
+use crate::event::EventContext;
+
+/// Check if a target accepts the given event
+pub fn accepts(target: &TargetConfig, ctx: &EventContext) -> bool {
+    if ctx.restricted && !target.allow_restricted {
+        return false;
+    }
+    if ctx.severity < target.effective_min_severity() {
+        return false;
+    }
+    if target.exclude_tags.iter().any(|t| ctx.tags.contains(t)) {
+        return false;
+    }
+    if !target.tags.is_empty() && !target.tags.iter().any(|t| ctx.tags.contains(t)) {
+        return false;
+    }
+    target.categories.is_empty()
+        || target
+            .categories
+            .iter()
+            .any(|p| p.matches_category(ctx.item.category_id, &ctx.category_name))
+}
+
+/// Route the events to the targets
+///
+/// Returns, for each target (in order), the events it accepts. Targets accepting
+/// no event are still returned, with an empty list.
+pub fn route<'a>(
+    targets: &'a [TargetConfig],
+    events: &'a [EventContext],
+) -> Vec<(&'a TargetConfig, Vec<&'a EventContext>)> {
+    targets
+        .iter()
+        .map(|target| {
+            let accepted = events.iter().filter(|ctx| accepts(target, ctx)).collect();
+            (target, accepted)
+        })
+        .collect()
+}
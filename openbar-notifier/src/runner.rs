@@ -0,0 +1,1849 @@
+//! Orchestration of the checks for OpenBar Notifier
+//!
+//! The [Runner] ties everything together: it fetches the items of the configured
+//! instances, detects the item events, notifies them, and persists the stores.
+//! It can check the instances once ([Runner::run_once]), or keep checking them at a
+//! regular interval ([Runner::run_forever]), so that the notifier can be embedded
+//! in other binaries (e.g. a Discord bot).
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info, warn};
+use openbar_api::models::Item;
+use reqwest::cookie::Jar;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::pem::PemObject;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+#[cfg(feature = "analytics")]
+use crate::analytics::AnalyticsDb;
+use crate::analytics::{self, Observations};
+use crate::chaos::Chaos;
+use crate::clock::{self, Clock, SystemClock};
+use crate::config::{
+    GlobalConfig, HttpConfig, InstanceConfig, LoginMethod, TargetConfig, TlsProvider,
+};
+use crate::correction;
+use crate::crypto::{self, StoreCipher};
+use crate::delivery::DeliveryLog;
+use crate::diff;
+use crate::dns::DohResolver;
+use crate::error::NotifierError;
+use crate::event::{EventContext, ItemEvent};
+use crate::feed::{self, FeedEntry};
+use crate::journal::{self, JournalEntry};
+use crate::lifecycle;
+use crate::lock::StoreLock;
+use crate::maintenance;
+#[cfg(feature = "mqtt")]
+use crate::mqtt::HomeAssistant;
+use crate::mute::{MuteQueue, MuteRequest};
+use crate::notifier::{Batch, Delivery, Notifier};
+use crate::openbar::webconfig::{WebConfig, get_config_cached};
+use crate::openbar::{ITEMS_PAGE_LIMIT, Login, LoginStrategy, OpenBarClient};
+use crate::permissions::Permissions;
+use crate::prices::{self, PriceComparison};
+use crate::probe::{self, ProbeStatus};
+use crate::ratelimit::RateLimiter;
+use crate::render::Formatting;
+use crate::report;
+use crate::sales::{self, ItemSalesRate, SalesBoard};
+use crate::schedule::{self, Backoff, SpecialEvent};
+use crate::status::StatusBoard;
+use crate::store::{ItemStore, MessageRef, StoreFormat};
+use crate::summary::{RunStats, RunSummary};
+use crate::tags::item_tags;
+use crate::transactions::{self, AccountActivity};
+
+/// Observer of the delivery attempts, shared by the notifiers of all the checks
+type SharedPostDeliveryHook = Arc<dyn Fn(&str, &Delivery) + Send + Sync>;
+
+/// Runner state of an instance
+struct InstanceState {
+    /// Backoff of the polling interval
+    backoff: Backoff,
+    /// Time of the next check
+    next_check: Instant,
+    /// Item store, kept in memory between the checks (loaded on the first one)
+    store: Option<ItemStore>,
+    /// Lock of the store file, held while the store is loaded (see [crate::lock])
+    lock: Option<StoreLock>,
+    /// Whether the store is read-only (neither locked nor saved)
+    read_only: bool,
+    /// Time of the last save of the store
+    last_save: Instant,
+    /// Permissions of the account, known after its first login
+    permissions: Option<Permissions>,
+}
+
+/// Orchestrator of the instance checks
+pub struct Runner {
+    config: GlobalConfig,
+    http: reqwest::Client,
+    cipher: Option<Box<dyn StoreCipher>>,
+    rate_limiter: Arc<RateLimiter>,
+    deliveries: Arc<DeliveryLog>,
+    sales: Arc<SalesBoard>,
+    status: Arc<StatusBoard>,
+    #[cfg(feature = "mqtt")]
+    home_assistant: HomeAssistant,
+    mutes: Arc<MuteQueue>,
+    #[cfg(feature = "analytics")]
+    analytics: Option<AnalyticsDb>,
+    chaos: Option<Arc<Chaos>>,
+    clock: Arc<dyn Clock>,
+    read_only: bool,
+    force: bool,
+    reload: Arc<Notify>,
+    poll: Arc<Notify>,
+    post_delivery: Vec<SharedPostDeliveryHook>,
+    states: Vec<InstanceState>,
+    cookies: Option<Arc<Jar>>,
+}
+
+impl Runner {
+    /// Create a runner for the given configuration, with an HTTP client configured by it
+    pub fn new(config: GlobalConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let cookies = Arc::new(Jar::default());
+        let http = create_http_client_with_cookies(&config.http, cookies.clone())?;
+        let mut runner = Runner::with_http_client(config, http)?;
+        runner.cookies = Some(cookies);
+        Ok(runner)
+    }
+
+    /// Create a runner for the given configuration, with a custom HTTP client
+    ///
+    /// The login sessions cannot be persisted with a custom client (see [crate::config::LoginConfig]).
+    pub fn with_http_client(
+        config: GlobalConfig,
+        http: reqwest::Client,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let cipher = config.store.cipher()?;
+        #[cfg(feature = "analytics")]
+        let analytics = config
+            .analytics
+            .database
+            .as_deref()
+            .map(AnalyticsDb::open)
+            .transpose()?;
+        // Rate-limit the targets as a whole, even when notified for several instances
+        let rate_limiter = Arc::new(RateLimiter::new(config.notify.rate_limit));
+        let chaos = Chaos::new(&config.chaos);
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let backoff = config.daemon.backoff();
+        let states = config
+            .instances
+            .iter()
+            .map(|_| InstanceState {
+                backoff,
+                next_check: clock.instant(),
+                store: None,
+                lock: None,
+                read_only: config.store.read_only,
+                last_save: clock.instant(),
+                permissions: None,
+            })
+            .collect();
+        let status = Arc::new(StatusBoard::default());
+        status.set_targets(&config);
+        Ok(Runner {
+            config,
+            http,
+            cipher,
+            rate_limiter,
+            deliveries: Arc::new(DeliveryLog::default()),
+            sales: Arc::new(SalesBoard::default()),
+            status,
+            #[cfg(feature = "mqtt")]
+            home_assistant: HomeAssistant::default(),
+            mutes: Arc::new(MuteQueue::default()),
+            #[cfg(feature = "analytics")]
+            analytics,
+            chaos,
+            clock,
+            read_only: false,
+            force: false,
+            reload: Arc::new(Notify::new()),
+            poll: Arc::new(Notify::new()),
+            post_delivery: Vec::new(),
+            states,
+            cookies: None,
+        })
+    }
+
+    /// Never save (nor lock) the stores, whatever the configuration (e.g. `--read-only`)
+    pub fn set_read_only(&mut self) -> &mut Self {
+        self.read_only = true;
+        for state in &mut self.states {
+            state.read_only = true;
+            state.lock = None;
+        }
+        self
+    }
+
+    /// Diff the stores even when they do not match their instance (e.g. `--force`), instead of
+    /// resetting them (see [ItemStore::mismatch])
+    pub fn set_force(&mut self) -> &mut Self {
+        self.force = true;
+        self
+    }
+
+    /// Read the current time from the given clock (e.g. a [crate::clock::TestClock]) instead of
+    /// the system one
+    ///
+    /// The instances are due to be checked right away.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        let now = clock.instant();
+        for state in &mut self.states {
+            state.next_check = now;
+            state.last_save = now;
+        }
+        self.clock = clock;
+        self
+    }
+
+    /// Get the configuration of the runner
+    pub fn config(&self) -> &GlobalConfig {
+        &self.config
+    }
+
+    /// Get the audit log of the delivery attempts
+    pub fn deliveries(&self) -> Arc<DeliveryLog> {
+        self.deliveries.clone()
+    }
+
+    /// Get the latest sales rates of the items (see [crate::sales])
+    pub fn sales_rates(&self) -> Arc<SalesBoard> {
+        self.sales.clone()
+    }
+
+    /// Get the live status of the instances (see [crate::status])
+    pub fn status(&self) -> Arc<StatusBoard> {
+        self.status.clone()
+    }
+
+    /// Get the queue of the mute requests, applied at the beginning of each cycle
+    pub fn mutes(&self) -> Arc<MuteQueue> {
+        self.mutes.clone()
+    }
+
+    /// Get the trigger of the configuration reloads, applied between two checks in
+    /// [Runner::run_forever] (e.g. on SIGHUP)
+    pub fn reload_trigger(&self) -> Arc<Notify> {
+        self.reload.clone()
+    }
+
+    /// Get the trigger of the immediate polls, checking all the instances right away in
+    /// [Runner::run_forever] (e.g. on SIGUSR1), whatever their schedule or backoff
+    pub fn poll_trigger(&self) -> Arc<Notify> {
+        self.poll.clone()
+    }
+
+    /// Replace the configuration of the runner
+    ///
+    /// The new configuration is only applied if it is valid (store encryption key, analytics
+    /// database). The stores are saved first; those of the instances kept with the same store
+    /// file stay in memory. The backoffs are reset, and the new polling intervals apply from
+    /// the next check.
+    pub fn reconfigure(&mut self, config: GlobalConfig) -> Result<(), Box<dyn std::error::Error>> {
+        let cipher = config.store.cipher()?;
+        #[cfg(feature = "analytics")]
+        let reopen = config.analytics.database != self.config.analytics.database;
+        #[cfg(feature = "analytics")]
+        let analytics = match &config.analytics.database {
+            Some(path) if reopen => Some(AnalyticsDb::open(path)?),
+            _ => None,
+        };
+
+        self.save_stores();
+        let mut previous: HashMap<String, (PathBuf, InstanceState)> = self
+            .config
+            .instances
+            .iter()
+            .zip(self.states.drain(..))
+            .map(|(instance, state)| (instance.name.clone(), (instance.store_file.clone(), state)))
+            .collect();
+        let backoff = config.daemon.backoff();
+        let now = self.clock.instant();
+        self.states = config
+            .instances
+            .iter()
+            .map(|instance| match previous.remove(&instance.name) {
+                // Known instance, checked again within the new interval at most
+                Some((store_file, state)) => {
+                    let kept = store_file == instance.store_file;
+                    InstanceState {
+                        backoff,
+                        next_check: state.next_check.min(now + backoff.interval()),
+                        store: state.store.filter(|_| kept),
+                        lock: state.lock.filter(|_| kept),
+                        read_only: config.store.read_only
+                            || self.read_only
+                            || (kept && state.read_only),
+                        last_save: state.last_save,
+                        permissions: state.permissions,
+                    }
+                }
+                // New instance, checked straight away
+                None => InstanceState {
+                    backoff,
+                    next_check: now,
+                    store: None,
+                    lock: None,
+                    read_only: config.store.read_only || self.read_only,
+                    last_save: now,
+                    permissions: None,
+                },
+            })
+            .collect();
+        // Release the locks of the stores no longer used
+        drop(previous);
+
+        if config.notify.rate_limit != self.config.notify.rate_limit {
+            self.rate_limiter = Arc::new(RateLimiter::new(config.notify.rate_limit));
+        }
+        #[cfg(feature = "analytics")]
+        if reopen {
+            self.analytics = analytics;
+        }
+        if config.chaos != self.config.chaos {
+            self.chaos = Chaos::new(&config.chaos);
+        }
+        self.status.set_targets(&config);
+        self.cipher = cipher;
+        self.config = config;
+        Ok(())
+    }
+
+    /// Reload the configuration (see [GlobalConfig::load]), keeping the current one if invalid
+    fn reload_config(&mut self) {
+        let reloaded = GlobalConfig::load()
+            .map_err(Box::from)
+            .and_then(|config| self.reconfigure(config));
+        match reloaded {
+            Ok(()) => info!(
+                "Configuration reloaded: {} instance(s), polling every {}s.",
+                self.config.instances.len(),
+                self.config.daemon.poll_interval_secs
+            ),
+            Err(e) => error!(
+                "Error reloading the configuration, keeping the current one: {}",
+                e
+            ),
+        }
+    }
+
+    /// Make all the instances due to be checked right away
+    fn poll_now(&mut self) {
+        let now = self.clock.instant();
+        for state in &mut self.states {
+            state.next_check = now;
+        }
+    }
+
+    /// Get the item store of an instance, if it has been loaded
+    pub fn store(&self, instance: &str) -> Option<&ItemStore> {
+        self.config
+            .instances
+            .iter()
+            .position(|i| i.name == instance)
+            .and_then(|index| self.states[index].store.as_ref())
+    }
+
+    /// Get the permissions of the account of an instance, if it has logged in
+    pub fn permissions(&self, instance: &str) -> Option<Permissions> {
+        self.config
+            .instances
+            .iter()
+            .position(|i| i.name == instance)
+            .and_then(|index| self.states[index].permissions)
+    }
+
+    /// Register an observer called after each delivery attempt, with the instance name
+    pub fn on_post_delivery(
+        &mut self,
+        hook: impl Fn(&str, &Delivery) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.post_delivery.push(Arc::new(hook));
+        self
+    }
+
+    /// Probe the targets of all the instances (once per URL), and report the failing ones
+    ///
+    /// The dry-run targets are never contacted, they are not probed. Returns the number of
+    /// targets found invalid or unreachable.
+    pub async fn probe_targets(&self) -> usize {
+        let mut probed = BTreeSet::new();
+        let mut failing = 0;
+        for target in self.config.instances.iter().flat_map(|i| &i.targets) {
+            if target.dry_run || !probed.insert(target.url.as_str()) {
+                continue;
+            }
+            match probe::probe(&self.http, target).await {
+                ProbeStatus::Ok => info!("Target {} is ok.", target.redacted_url()),
+                status @ ProbeStatus::Inconclusive(_) => {
+                    warn!("Target {} probe is {}.", target.redacted_url(), status)
+                }
+                status => {
+                    error!("Target {} is {}.", target.redacted_url(), status);
+                    failing += 1;
+                }
+            }
+        }
+        failing
+    }
+
+    /// Mute (or unmute) an item in the stores of the instances the request applies to
+    ///
+    /// The stores are loaded if needed, but not saved. Returns the outcome for each instance
+    /// (the ID of the item, or why it could not be muted).
+    pub fn apply_mute(&mut self, request: &MuteRequest) -> Vec<(String, Result<Uuid, String>)> {
+        let mut results = Vec::new();
+        for index in 0..self.states.len() {
+            let name = self.config.instances[index].name.clone();
+            if !request.applies_to(&name) {
+                continue;
+            }
+            let result = match self.load_store(index) {
+                Ok(store) => request.apply(store),
+                Err(_) => Err("the store could not be loaded".to_string()),
+            };
+            results.push((name, result));
+        }
+        results
+    }
+
+    /// Save the unsaved changes of the loaded stores
+    pub fn save_stores(&mut self) {
+        for index in 0..self.states.len() {
+            if self.states[index]
+                .store
+                .as_ref()
+                .is_some_and(|store| store.is_dirty())
+            {
+                self.save(index, CheckOutcome::Ok, Duration::MAX);
+            }
+        }
+    }
+
+    /// Apply the queued mute requests
+    fn apply_queued_mutes(&mut self) {
+        for request in self.mutes.take() {
+            for (instance, result) in self.apply_mute(&request) {
+                match (result, request.until) {
+                    (Ok(id), Some(until)) => {
+                        info!("Item {} of instance {} muted until {}", id, instance, until)
+                    }
+                    (Ok(id), None) => info!("Item {} of instance {} unmuted", id, instance),
+                    (Err(e), _) => warn!(
+                        "Cannot mute item '{}' of instance {}: {}",
+                        request.item, instance, e
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Get the interval until the next check of an instance
+    ///
+    /// The instance is polled more often during a special event (unless its API is failing),
+    /// and checked as soon as the next one starts. A random jitter is added, if enabled.
+    fn poll_interval(&self, index: usize) -> Duration {
+        let backoff = &self.states[index].backoff;
+        let now = self.clock.now();
+        let mut interval = backoff.interval();
+        if backoff.failures() == 0
+            && let Some(poll_interval) = schedule::active_event(&self.config.calendar, now)
+                .and_then(SpecialEvent::poll_interval)
+        {
+            interval = interval.min(poll_interval);
+        }
+        if let Some(start) = schedule::next_event_start(&self.config.calendar, now) {
+            interval = interval.min((start - now).to_std().unwrap_or_default());
+        }
+        if let Some(jitter) = self.config.daemon.poll_jitter() {
+            interval += schedule::jitter(jitter);
+        }
+        interval
+    }
+
+    /// Check all the instances once, and save their stores
+    ///
+    /// Every instance is checked, even after another one failed. Returns the error of the
+    /// first failed check (in the configuration order), if any.
+    pub async fn run_once(&mut self) -> Result<(), NotifierError> {
+        self.apply_queued_mutes();
+        let mut first_error = None;
+        for index in 0..self.states.len() {
+            let result = self.check(index).await;
+            // This is the last check before exiting (as far as the runner knows)
+            self.save(index, CheckOutcome::of(&result), Duration::MAX);
+            if let Err(e) = result {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Keep checking the instances at a regular interval, until `shutdown` completes
+    ///
+    /// Each instance is checked on its own schedule, backing off while its API is failing.
+    /// The in-flight checks always run to completion, and the unsaved changes are saved
+    /// before returning.
+    pub async fn run_forever(&mut self, shutdown: impl Future<Output = ()>) {
+        let mut shutdown = std::pin::pin!(shutdown);
+        let reload = self.reload.clone();
+        let poll = self.poll.clone();
+        // Spread the first checks (e.g. of several notifiers started at the same time)
+        if let Some(jitter) = self.config.daemon.startup_jitter() {
+            for state in &mut self.states {
+                state.next_check += schedule::jitter(jitter);
+            }
+        }
+        loop {
+            self.apply_queued_mutes();
+            // Each instance check runs to completion (notifications sent, logged out, store updated)
+            for index in 0..self.states.len() {
+                if self.states[index].next_check > self.clock.instant() {
+                    continue;
+                }
+                let outcome = CheckOutcome::of(&self.check(index).await);
+                let name = &self.config.instances[index].name;
+                let backoff = &mut self.states[index].backoff;
+                match outcome {
+                    CheckOutcome::Ok => {
+                        if backoff.failures() > 0 {
+                            info!("Instance {} recovered, back to the normal interval.", name);
+                        }
+                        backoff.record_success();
+                    }
+                    CheckOutcome::Skipped => {}
+                    CheckOutcome::Failed => {
+                        backoff.record_failure();
+                        warn!(
+                            "Instance {} failed {} time(s) in a row, next check in {:?}.",
+                            name,
+                            backoff.failures(),
+                            backoff.interval()
+                        );
+                    }
+                    CheckOutcome::Misconfigured => {
+                        backoff.record_permanent_failure();
+                        warn!(
+                            "Instance {} is misconfigured, next check in {:?}.",
+                            name,
+                            backoff.interval()
+                        );
+                    }
+                }
+                let since_last_save = self
+                    .clock
+                    .instant()
+                    .saturating_duration_since(self.states[index].last_save);
+                self.save(index, outcome, since_last_save);
+                let interval = self.poll_interval(index);
+                self.states[index].next_check = self.clock.instant() + interval;
+                self.status.set_next_check(
+                    &self.config.instances[index].name,
+                    clock::after(self.clock.now(), interval),
+                );
+            }
+            let wake = self
+                .states
+                .iter()
+                .map(|state| state.next_check)
+                .min()
+                .unwrap_or_else(|| self.clock.instant() + self.config.daemon.backoff().interval());
+            let sleep = wake.saturating_duration_since(self.clock.instant());
+            tokio::select! {
+                _ = tokio::time::sleep(sleep) => {}
+                _ = reload.notified() => self.reload_config(),
+                _ = poll.notified() => self.poll_now(),
+                _ = &mut shutdown => break,
+            }
+        }
+
+        // Save the changes not saved yet
+        self.save_stores();
+    }
+
+    /// Check an instance, loading its store first if needed, then send its run summary
+    async fn check(&mut self, index: usize) -> Result<(), NotifierError> {
+        let started = self.clock.instant();
+        let mut stats = RunStats::default();
+        let result = self.check_instance(index, &mut stats).await;
+        let summary = RunSummary {
+            instance: self.config.instances[index].name.clone(),
+            outcome: CheckOutcome::of(&result),
+            stats,
+            duration: self.clock.instant().saturating_duration_since(started),
+        };
+        self.status.record_check(
+            &summary.instance,
+            self.clock.now(),
+            summary.outcome,
+            self.states[index].store.as_ref(),
+        );
+        #[cfg(feature = "mqtt")]
+        if let Some(url) = &self.config.mqtt.url
+            && let Some(store) = self.states[index].store.as_ref()
+            && let Err(e) = self
+                .home_assistant
+                .publish(
+                    &self.config.mqtt,
+                    &self.config.http,
+                    url,
+                    &summary.instance,
+                    store,
+                )
+                .await
+        {
+            error!("Error publishing the item states to MQTT: {}", e);
+        }
+        info!("Run summary of {}: {}", summary.instance, summary);
+        if !self.config.summary.targets.is_empty() {
+            let notifier = self.notifier(&summary.instance);
+            report::send_report(&notifier, &self.config.summary.targets, &[summary.render()]).await;
+        }
+        result
+    }
+
+    /// Check an instance, loading its store first if needed
+    async fn check_instance(
+        &mut self,
+        index: usize,
+        stats: &mut RunStats,
+    ) -> Result<(), NotifierError> {
+        let instance = &self.config.instances[index];
+        info!("Checking instance {}...", instance.name);
+        let notifier = self.notifier(&instance.name);
+        self.load_store(index)?;
+        let instance = &self.config.instances[index];
+        let state = &mut self.states[index];
+        let item_store = state.store.as_mut().expect("the store is loaded");
+        // Without a saved store, every check of a read-only one would notify all the items again
+        let mut baseline = state.read_only && item_store.last_run().is_none();
+        if baseline {
+            info!(
+                "The read-only store of instance {} is empty, its items are recorded without being notified.",
+                instance.name
+            );
+        }
+        // Diffing the store of another instance would notify bogus events for all its items
+        if let Some(mismatch) = item_store.mismatch(&instance.openbar.instance_url) {
+            if self.force {
+                warn!(
+                    "The store of instance {} does not match: {}. Diffing it anyway (--force).",
+                    instance.name, mismatch
+                );
+            } else {
+                warn!(
+                    "The store of instance {} does not match: {}. It is reset, its items are recorded without being notified.",
+                    instance.name, mismatch
+                );
+                item_store.reset();
+                baseline = true;
+                let message = format!(
+                    "**Store of {} reset**\n{}, so its items are recorded again without being notified (run with `--force` to diff it anyway).\n",
+                    instance.name, mismatch
+                );
+                report::send_report(&notifier, &self.config.summary.targets, &[message]).await;
+            }
+        }
+        let result = run_instance(
+            &self.http,
+            &self.config,
+            instance,
+            item_store,
+            notifier,
+            #[cfg(feature = "analytics")]
+            self.analytics.as_mut(),
+            &self.sales,
+            &self.status,
+            self.chaos.as_ref(),
+            self.clock.as_ref(),
+            self.cipher.as_deref(),
+            baseline,
+            self.cookies.clone(),
+            &mut state.permissions,
+            stats,
+        )
+        .await;
+        // Only bind the store once it holds the items of the instance, a check failing before
+        // that must leave it untouched (and unsaved)
+        if result
+            .as_ref()
+            .map_or_else(NotifierError::outcome, |_| CheckOutcome::Ok)
+            == CheckOutcome::Ok
+        {
+            item_store.bind(&instance.openbar.instance_url);
+        }
+        result
+    }
+
+    /// Get the store of an instance, locking and loading it first if needed
+    ///
+    /// The store is not loaded if its file is locked by another process. Read-only stores
+    /// (including the ones whose lock file cannot be created on a read-only filesystem) are
+    /// loaded without being locked.
+    fn load_store(&mut self, index: usize) -> Result<&mut ItemStore, NotifierError> {
+        let instance = &self.config.instances[index];
+        let state = &mut self.states[index];
+        if state.store.is_none() {
+            let mut lock = None;
+            if !state.read_only {
+                match StoreLock::acquire(&instance.store_file) {
+                    Ok(acquired) => lock = Some(acquired),
+                    Err(e) if e.is_read_only() => {
+                        warn!(
+                            "The store of instance {} is on a read-only filesystem, it is not saved.",
+                            instance.name
+                        );
+                        state.read_only = true;
+                    }
+                    Err(e) => {
+                        error!("Error loading item store: {}", e);
+                        return Err(NotifierError::Store {
+                            instance: instance.name.clone(),
+                            message: e.to_string(),
+                        });
+                    }
+                }
+            }
+            match load_item_store_from_file(&instance.store_file, self.cipher.as_deref()) {
+                Ok(store) => {
+                    state.store = Some(store);
+                    state.lock = lock;
+                }
+                Err(e) => {
+                    error!("Error loading item store: {}", e);
+                    return Err(NotifierError::Store {
+                        instance: instance.name.clone(),
+                        message: e.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(state.store.as_mut().expect("the store is loaded"))
+    }
+
+    /// Save the store of an instance after a check, if the save mode requires it
+    ///
+    /// Stores left untouched by a failed check, and read-only stores, are never saved. A store
+    /// found on a read-only filesystem becomes read-only (warned once).
+    fn save(&mut self, index: usize, outcome: CheckOutcome, since_last_save: Duration) {
+        let instance = &self.config.instances[index];
+        let state = &mut self.states[index];
+        let Some(item_store) = state.store.as_mut() else {
+            return;
+        };
+        if state.read_only || (outcome != CheckOutcome::Ok && !item_store.is_dirty()) {
+            return;
+        }
+        if self.config.store.should_save(item_store, since_last_save) {
+            match save_instance_store(
+                instance,
+                item_store,
+                self.config.store.format,
+                self.cipher.as_deref(),
+            ) {
+                Ok(()) => {}
+                Err(e) if is_read_only_error(e.as_ref()) => {
+                    warn!(
+                        "The store of instance {} is on a read-only filesystem, it is no longer saved.",
+                        instance.name
+                    );
+                    state.read_only = true;
+                }
+                Err(e) => error!("Error saving item store: {}", e),
+            }
+            state.last_save = self.clock.instant();
+        }
+    }
+
+    /// Create the notifier of an instance check
+    fn notifier(&self, instance: &str) -> Notifier {
+        let mut notifier = Notifier::new(self.http.clone());
+        notifier.set_rate_limiter(self.rate_limiter.clone());
+        notifier.set_chaos(self.chaos.clone());
+        notifier.set_target_timeout(self.config.notify.target_timeout());
+        // Record the delivery attempts in the audit log
+        let deliveries = self.deliveries.clone();
+        let name = instance.to_string();
+        notifier.on_post_delivery(move |delivery| deliveries.record(&name, delivery));
+        for hook in &self.post_delivery {
+            let hook = hook.clone();
+            let name = instance.to_string();
+            notifier.on_post_delivery(move |delivery| hook(&name, delivery));
+        }
+        notifier
+    }
+}
+
+/// Outcome of an instance check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckOutcome {
+    /// The check succeeded
+    Ok,
+    /// The check was skipped (e.g. the store could not be loaded)
+    Skipped,
+    /// The OpenBar API (or its WebUI) failed, it may recover by itself
+    Failed,
+    /// The instance is misconfigured (e.g. its webconfig is malformed), retrying soon is pointless
+    Misconfigured,
+}
+
+impl CheckOutcome {
+    /// Get the outcome of an instance check from its result (see [NotifierError::outcome])
+    pub fn of(result: &Result<(), NotifierError>) -> Self {
+        match result {
+            Ok(()) => CheckOutcome::Ok,
+            Err(e) => e.outcome(),
+        }
+    }
+}
+
+impl std::fmt::Display for CheckOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = match self {
+            CheckOutcome::Ok => "ok",
+            CheckOutcome::Skipped => "skipped",
+            CheckOutcome::Failed => "failed",
+            CheckOutcome::Misconfigured => "misconfigured",
+        };
+        write!(f, "{}", outcome)
+    }
+}
+
+/// An OpenBar instance, connected and logged in (see [connect])
+pub struct Connection {
+    /// Client of the API of the instance, logged in
+    pub client: OpenBarClient,
+    /// Webconfig of the instance
+    pub webconfig: WebConfig,
+    /// Login of the account
+    pub login: Login,
+}
+
+/// Connect to an OpenBar instance: get its webconfig, then log in with the configured strategies
+///
+/// The login sessions are persisted with the `cookies` jar of the HTTP client, if enabled.
+pub async fn connect(
+    http: &reqwest::Client,
+    config: &GlobalConfig,
+    instance: &InstanceConfig,
+    chaos: Option<&Arc<Chaos>>,
+    cookies: Option<Arc<Jar>>,
+) -> Result<Connection, NotifierError> {
+    // Get the Instance webconfig
+    let webconfig = match get_config_cached(
+        http,
+        &instance.openbar.instance_url,
+        &instance.webconfig_cache_file,
+        chrono::Duration::seconds(config.webconfig_cache.ttl_secs as i64),
+    )
+    .await
+    {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            if e.is_transient() {
+                error!("Error retrieving webconfig: {}", e);
+            } else {
+                error!(
+                    "Invalid webconfig for instance {} (is the instance URL correct?): {}",
+                    instance.name, e
+                );
+            }
+            return Err(NotifierError::Webconfig {
+                instance: instance.name.clone(),
+                source: e,
+            });
+        }
+    };
+
+    debug!("WebConfig: {:?}", webconfig);
+
+    // Connect to OpenBar API
+    let mut client = OpenBarClient::with_client(&webconfig.api, http.clone());
+    client.set_local_token(&webconfig.local_token);
+    client.set_chaos(chaos.cloned());
+    client.set_cookie_jar(cookies);
+
+    // Login, trying the strategies in order
+    let strategies: Vec<LoginStrategy> = config
+        .login
+        .strategies
+        .iter()
+        .filter_map(|method| match method {
+            LoginMethod::Session => std::fs::read_to_string(instance.session_file())
+                .ok()
+                .map(|session| session.trim().to_string())
+                .filter(|session| !session.is_empty())
+                .map(LoginStrategy::Session),
+            LoginMethod::Card => Some(LoginStrategy::Card {
+                card_id: instance.openbar.card_id.clone(),
+                pin: instance.openbar.pin.clone(),
+            }),
+        })
+        .collect();
+    match client.login(&strategies).await {
+        Ok(login) => {
+            info!("Logged in successfully ({})", login.strategy);
+            Ok(Connection {
+                client,
+                webconfig,
+                login,
+            })
+        }
+        Err(e) => {
+            error!(
+                "Error during the login of instance {}: {}",
+                instance.name, e
+            );
+            Err(NotifierError::Auth {
+                instance: instance.name.clone(),
+                source: e,
+            })
+        }
+    }
+}
+
+/// Logout from an OpenBar instance, unless the session is kept for the next check (then saved)
+pub async fn disconnect(config: &GlobalConfig, instance: &InstanceConfig, client: &OpenBarClient) {
+    if config.login.persists_session() {
+        if let Some(session) = client.session()
+            && let Err(e) = save_session(&instance.session_file(), &session)
+        {
+            error!("Error saving the login session: {}", e);
+        }
+    } else {
+        match client.logout().await {
+            Ok(_) => info!("Logged out successfully"),
+            Err(e) => error!("Error during logout: {}", e),
+        }
+    }
+}
+
+/// Check an OpenBar instance for item events, and notify its targets
+///
+/// In a `baseline` check, the items unknown to the store are recorded without any event. The
+/// login sessions are persisted with the `cookies` jar of the HTTP client, if enabled. The
+/// `permissions` of the account are updated on login, and the statistics of the check are
+/// gathered in `stats`.
+#[allow(clippy::too_many_arguments)]
+async fn run_instance(
+    http: &reqwest::Client,
+    config: &GlobalConfig,
+    instance: &InstanceConfig,
+    item_store: &mut ItemStore,
+    notifier: Notifier,
+    #[cfg(feature = "analytics")] mut analytics: Option<&mut AnalyticsDb>,
+    sales: &SalesBoard,
+    status: &StatusBoard,
+    chaos: Option<&Arc<Chaos>>,
+    clock: &dyn Clock,
+    cipher: Option<&dyn StoreCipher>,
+    baseline: bool,
+    cookies: Option<Arc<Jar>>,
+    permissions: &mut Option<Permissions>,
+    stats: &mut RunStats,
+) -> Result<(), NotifierError> {
+    let deadline = config
+        .run
+        .deadline()
+        .map(|deadline| clock.instant() + deadline);
+
+    let Connection {
+        client,
+        webconfig,
+        login,
+    } = connect(http, config, instance, chaos, cookies).await?;
+
+    // The explicit configuration takes precedence over the instance locale/currency
+    let formatting = Formatting::new(
+        config.notify.locale.clone().or(webconfig.locale.clone()),
+        config
+            .notify
+            .currency
+            .clone()
+            .or(webconfig.currency.clone()),
+    );
+
+    let account = login.account;
+    let granted = match &account {
+        Some(account) => Permissions::of(account),
+        None => Permissions::default(),
+    };
+    // Warn on startup, then whenever the account permissions change
+    if *permissions != Some(granted) {
+        if account.is_none() {
+            warn!(
+                "The login of instance {} returned no account, its permissions are unknown.",
+                instance.name
+            );
+        }
+        let disabled = granted.disabled_features();
+        if !disabled.is_empty() {
+            warn!(
+                "The account of instance {} cannot use the {}, these features are disabled.",
+                instance.name,
+                disabled.join(", ")
+            );
+        }
+        *permissions = Some(granted);
+    }
+
+    // Store the item events to process later
+    let mut item_events: Vec<(Uuid, ItemEvent)> = Vec::new();
+    // Category names, by category ID
+    let mut category_names: HashMap<Uuid, String> = HashMap::new();
+    // IDs of the items listed by the API
+    let mut seen: HashSet<Uuid> = HashSet::new();
+    // Whether the categories could be listed
+    let mut listed = false;
+    // Categories with more items than fetched
+    let mut truncated: BTreeSet<Uuid> = BTreeSet::new();
+    // Categories not fetched before the deadline
+    let mut unfetched: BTreeSet<Uuid> = BTreeSet::new();
+    // Categories whose items could not be fetched
+    let mut failed: BTreeSet<Uuid> = BTreeSet::new();
+    // New prices of the promoted items, compared to their history
+    let mut price_comparisons: HashMap<Uuid, PriceComparison> = HashMap::new();
+    // Previous state of the items with events, for the event journal
+    let mut previous_items: HashMap<Uuid, Item> = HashMap::new();
+    // Observations to record in the analytics database (all the items and events, unfiltered)
+    #[cfg(feature = "analytics")]
+    let mut observations = analytics.is_some().then(Observations::default);
+    #[cfg(not(feature = "analytics"))]
+    let mut observations: Option<Observations> = None;
+
+    // Get all products
+    let now = clock.now();
+    if item_store.record_run(now) {
+        warn!(
+            "The system clock went backwards since the last run, persisted timestamps were clamped to now."
+        );
+    }
+    // Watch the instance closely during a special event
+    let special_event = schedule::active_event(&config.calendar, now);
+    if let Some(event) = special_event {
+        info!("Special event {} until {}", event.name, event.end);
+    }
+    let low_stock_ratio = special_event
+        .and_then(|event| event.low_stock_ratio)
+        .unwrap_or(config.notify.low_stock_ratio);
+    match client.get_categories().await {
+        Ok(mut categories) => {
+            listed = true;
+            info!("Got {} categories:", categories.len());
+            stats.categories = categories.len();
+            item_store.set_categories(&categories);
+            // Fetch first the categories left unfetched by the previous check
+            let carried_over = item_store.unfetched_categories();
+            categories.sort_by_key(|category| !carried_over.contains(&category.id));
+            category_names.extend(categories.iter().map(|c| (c.id, c.name.clone())));
+            // - For each category, get items (until the deadline)
+            let mut categories = categories.into_iter();
+            for category in categories.by_ref() {
+                let category_id = category.id;
+                let category_key = category_id.to_string();
+                let fetch = client.get_category_items_page(&category_key, 0, ITEMS_PAGE_LIMIT);
+                let result = match deadline {
+                    Some(deadline) => match tokio::time::timeout_at(deadline.into(), fetch).await {
+                        Ok(result) => result,
+                        Err(_) => {
+                            unfetched.insert(category_id);
+                            break;
+                        }
+                    },
+                    None => fetch.await,
+                };
+                match result {
+                    Ok(page) => {
+                        if page.is_truncated() {
+                            warn!(
+                                "Category {} has more than {} items, only the first ones are checked.",
+                                category.name, page.limit
+                            );
+                            truncated.insert(category_id);
+                        }
+                        stats.items += page.items.len();
+                        // Skip the diff of the category if none of its items changed (unless a
+                        // transition of one of them is being debounced)
+                        let hash = diff::items_hash(&page.items, low_stock_ratio);
+                        if config.run.skip_unchanged_categories
+                            && !baseline
+                            && item_store.category_hash(category_id) == Some(hash.as_str())
+                            && !page
+                                .items
+                                .iter()
+                                .any(|item| item_store.is_settling(item.id))
+                        {
+                            debug!("Category {} unchanged, skipping its items.", category.name);
+                            stats.unchanged_categories += 1;
+                            for item in page.items {
+                                seen.insert(item.id);
+                                item_store.mark_seen(item.id, now);
+                                if let Some(observations) = &mut observations {
+                                    observations.items.push((item, false));
+                                }
+                            }
+                            continue;
+                        }
+                        item_store.set_category_hash(category_id, hash);
+                        info!("{} items in category {}:", page.items.len(), category.name);
+                        // Derive the events of the items, with the thresholds of their watch
+                        // rule (if any), before storing them
+                        let rules = |item: &Item| {
+                            let watch = config.notify.watch_rule(item, &category.name);
+                            diff::DiffRules {
+                                low_stock_ratio: watch
+                                    .and_then(|rule| rule.low_stock_ratio)
+                                    .unwrap_or(low_stock_ratio),
+                                debounce: config.notify.debounce,
+                                stock_drop: watch.map_or(config.notify.stock_drop, |rule| {
+                                    rule.stock_drop(&config.notify.stock_drop)
+                                }),
+                                prediction: config.notify.stock_out_prediction,
+                                // Only follow the stock changes when notified, every sale being
+                                // one
+                                stock_changes: config.notify.is_event_notified(
+                                    &ItemEvent::StockChanged { change: 0 },
+                                    item,
+                                    &category.name,
+                                ),
+                            }
+                        };
+                        let mut page_events: HashMap<Uuid, Vec<ItemEvent>> = HashMap::new();
+                        for (item_id, event) in
+                            diff::diff_items(item_store, &page.items, clock, rules)
+                        {
+                            page_events.entry(item_id).or_default().push(event);
+                        }
+                        for item in page.items {
+                            let tags = item_tags(&item, &category.name, &config.tags);
+                            let notified = config.notify.is_item_notified(&item, &category.name);
+                            seen.insert(item.id);
+                            item_store.mark_seen(item.id, now);
+                            // Check if the item is already in the store
+                            let previous = item_store.find(item.id).cloned();
+                            let mut events = page_events.remove(&item.id).unwrap_or_default();
+                            if let Some(observations) = &mut observations {
+                                let changed =
+                                    analytics::is_sample_changed(previous.as_ref(), &item);
+                                observations.items.push((item.clone(), changed));
+                            }
+                            if previous.is_some() {
+                                // Update existing item
+                                let _ = item_store.replace(item.clone());
+                            } else {
+                                // New item, add to store
+                                item_store.append(item.clone());
+                                info!("New item added: {} (ID: {})", item.name, item.id);
+                            }
+                            let promoted = events
+                                .iter()
+                                .any(|e| matches!(e, ItemEvent::PromotionStarted { .. }));
+                            let price = prices::effective_price(&item);
+                            item_store.update_meta(item.id, |meta| {
+                                meta.tags = tags;
+                                meta.category = Some(category.name.clone());
+                                // Compare the new price of a promotion before recording it
+                                if promoted
+                                    && let Some(comparison) = meta.prices.compare(price, now)
+                                {
+                                    price_comparisons.insert(item.id, comparison);
+                                }
+                                meta.prices.observe(now, price);
+                            });
+                            if baseline && previous.is_none() {
+                                // Baseline check: the items are not new, only unknown
+                                events.clear();
+                            }
+                            if let Some(observations) = &mut observations {
+                                observations
+                                    .events
+                                    .extend(events.iter().map(|e| (item.id, e.clone())));
+                            }
+                            if !events.is_empty()
+                                && config.journal.file.is_some()
+                                && let Some(previous) = &previous
+                            {
+                                previous_items.insert(item.id, previous.clone());
+                            }
+                            if !events.is_empty() && item_store.is_muted(item.id, now) {
+                                info!("Ignoring events of muted item {}", item.name);
+                            } else if notified {
+                                item_events.extend(events.into_iter().map(|e| (item.id, e)));
+                            } else if !events.is_empty() {
+                                debug!("Ignoring events of filtered item {}", item.name);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!(
+                            "Error retrieving items for category {}: {:?}",
+                            category.name, e
+                        );
+                        stats.api_errors += 1;
+                        failed.insert(category_id);
+                    }
+                }
+            }
+            unfetched.extend(categories.map(|category| category.id));
+            if !failed.is_empty() {
+                let names: Vec<&str> = failed
+                    .iter()
+                    .filter_map(|id| category_names.get(id).map(String::as_str))
+                    .collect();
+                let stale = item_store
+                    .iter()
+                    .filter(|item| failed.contains(&item.category_id))
+                    .count();
+                warn!(
+                    "The items of {} categories could not be fetched ({}), their {} stored items keep their last known state.",
+                    failed.len(),
+                    names.join(", "),
+                    stale
+                );
+            }
+            stats.failed_categories = failed.len();
+            if !unfetched.is_empty() {
+                warn!(
+                    "Deadline of the check exceeded, {} categories left for the next check.",
+                    unfetched.len()
+                );
+            }
+            stats.unfetched_categories = unfetched.len();
+            item_store.set_unfetched_categories(unfetched.clone());
+        }
+        Err(e) => {
+            error!("Error retrieving categories: {:?}", e);
+            stats.api_errors += 1;
+            // Name the categories as last listed
+            category_names.extend(
+                item_store
+                    .categories()
+                    .map(|(id, category)| (id, category.name.clone())),
+            );
+        }
+    }
+
+    // Follow the items no longer listed, except in the categories not (completely) fetched
+    if listed {
+        let incomplete: BTreeSet<Uuid> = failed
+            .iter()
+            .chain(&truncated)
+            .chain(&unfetched)
+            .copied()
+            .collect();
+        let unlisted: Vec<_> = item_store
+            .iter()
+            .filter(|item| !seen.contains(&item.id) && !incomplete.contains(&item.category_id))
+            .cloned()
+            .collect();
+        for item in unlisted {
+            let category_name = category_names
+                .get(&item.category_id)
+                .map(String::as_str)
+                .unwrap_or_default();
+            let item_low_stock_ratio = config
+                .notify
+                .watch_rule(&item, category_name)
+                .and_then(|rule| rule.low_stock_ratio)
+                .unwrap_or(low_stock_ratio);
+            let events = item_store.update_meta(item.id, |meta| {
+                lifecycle::observe(
+                    &mut meta.lifecycle,
+                    Some(&item),
+                    None,
+                    now,
+                    item_low_stock_ratio,
+                    &config.notify.debounce,
+                )
+            });
+            if events.is_empty() {
+                continue;
+            }
+            info!("Item no longer listed: {} (ID: {})", item.name, item.id);
+            if let Some(observations) = &mut observations {
+                observations
+                    .events
+                    .extend(events.iter().map(|e| (item.id, e.clone())));
+            }
+            if item_store.is_muted(item.id, now) {
+                info!("Ignoring events of muted item {}", item.name);
+            } else if config.notify.is_item_notified(&item, category_name) {
+                item_events.extend(events.into_iter().map(|e| (item.id, e)));
+            }
+        }
+
+        // Prune the items no longer listed for too long
+        if let Some(retention) = config.store.retention() {
+            let pruned = item_store.prune_older_than(retention, now, |item| {
+                incomplete.contains(&item.category_id)
+            });
+            for item in pruned {
+                info!(
+                    "Item pruned from the store: {} (ID: {})",
+                    item.name, item.id
+                );
+            }
+        }
+    }
+
+    // Check the availability of the menus, now that all their components are up-to-date
+    for (menu_id, event) in diff::diff_menus(item_store) {
+        if let Some(observations) = &mut observations {
+            observations.events.push((menu_id, event.clone()));
+        }
+        let Some(menu) = item_store.find(menu_id) else {
+            continue;
+        };
+        let category_name = category_names
+            .get(&menu.category_id)
+            .map(String::as_str)
+            .unwrap_or_default();
+        if config.notify.is_item_notified(menu, category_name) && !item_store.is_muted(menu_id, now)
+        {
+            item_events.push((menu_id, event));
+        }
+    }
+
+    // Publish the sales rates of the items
+    let rates = item_store
+        .iter()
+        .filter_map(|item| {
+            let rate_per_day = item_store.meta(item.id)?.sales.rate_per_day(now)?;
+            Some(ItemSalesRate {
+                item_id: item.id,
+                name: item.name.clone(),
+                category_name: category_names
+                    .get(&item.category_id)
+                    .cloned()
+                    .unwrap_or_default(),
+                rate_per_day,
+                display: sales::format_rate(rate_per_day, &formatting.language()),
+            })
+        })
+        .collect();
+    sales.update(&instance.name, rates);
+
+    // Record the observations in the analytics database
+    #[cfg(feature = "analytics")]
+    if let (Some(analytics), Some(mut observations)) = (analytics.as_deref_mut(), observations) {
+        observations.categories = category_names.clone();
+        if let Err(e) = analytics.record(&instance.name, now, &observations) {
+            error!("Error recording the analytics: {}", e);
+        }
+    }
+
+    // Fetch the new purchases and refills of the account
+    let (activity, latest_activity) = if config.transactions.enabled && granted.transactions {
+        fetch_account_activity(&client, config, instance, item_store, &formatting, stats).await
+    } else {
+        (Vec::new(), None)
+    };
+
+    disconnect(config, instance, &client).await;
+
+    // Gather the context of the enabled item events
+    let mut contexts: Vec<EventContext> = Vec::new();
+    for (item_id, event) in item_events {
+        let item = match item_store.find(item_id) {
+            Some(i) => i,
+            None => {
+                warn!(
+                    "Item ID {} not found in store for event processing.",
+                    item_id
+                );
+                continue;
+            }
+        };
+        let category_name = category_names
+            .get(&item.category_id)
+            .cloned()
+            .unwrap_or_default();
+        if !config
+            .notify
+            .is_event_notified(&event, item, &category_name)
+        {
+            // Notification for this event type is disabled (for this item)
+            continue;
+        }
+        contexts.push(EventContext {
+            item: item.clone(),
+            restricted: config.notify.is_restricted(item, &category_name),
+            severity: config.notify.severity(&event, item, &category_name),
+            category_name,
+            tags: item_store.tags(item_id),
+            sales_rate: item_store
+                .meta(item_id)
+                .and_then(|meta| meta.sales.rate_per_day(now)),
+            price: match event {
+                ItemEvent::PromotionStarted { .. } => price_comparisons.get(&item_id).copied(),
+                _ => None,
+            },
+            correction: config.notify.correction_window().and_then(|window| {
+                let meta = item_store.meta(item_id)?;
+                correction::find(&event, &meta.messages, now, window)
+            }),
+            event,
+        });
+    }
+
+    // Mute the item events during a maintenance window, they are still journaled and published
+    // in the feeds
+    let maintenance = maintenance::active(&config.maintenance.file, now);
+    if let Some(window) = &maintenance {
+        info!(
+            "Maintenance {}: muting {} item events.",
+            window,
+            contexts.len()
+        );
+    }
+
+    // Drop the events of the items already notified of the same type within the cooldown
+    if maintenance.is_none()
+        && let Some(cooldown) = config.notify.cooldown()
+    {
+        contexts.retain(|ctx| {
+            let kind = ctx.event.kind();
+            let notified =
+                item_store.update_meta(ctx.item.id, |meta| meta.try_notify(kind, now, cooldown));
+            if !notified {
+                debug!(
+                    "Ignoring the {} event of {} during its cooldown",
+                    kind, ctx.item.name
+                );
+            }
+            notified
+        });
+    }
+    stats.events = contexts.len();
+    status.record_events(&instance.name, now, &contexts);
+
+    // Append the item events to the journal, whatever becomes of their notification
+    if let Some(path) = &config.journal.file {
+        let entries: Vec<JournalEntry> = contexts
+            .iter()
+            .map(|ctx| JournalEntry {
+                at: now,
+                instance: instance.name.clone(),
+                context: ctx.clone(),
+                previous: previous_items.get(&ctx.item.id).cloned(),
+            })
+            .collect();
+        if let Err(e) = journal::append(path, &entries, cipher) {
+            error!("Error appending the item events to the journal: {}", e);
+        }
+    }
+
+    // Publish the item events in the catalog change feeds
+    if let Some(dir) = &config.feed.dir {
+        item_store.record_feed_entries(
+            contexts
+                .iter()
+                .map(|ctx| FeedEntry::new(ctx, now, &formatting)),
+            config.feed.max_entries,
+        );
+        if let Err(e) = feed::write_feeds(
+            dir,
+            &instance.name,
+            item_store.feed(),
+            config.feed.max_entries,
+        ) {
+            error!("Error writing the catalog change feeds: {}", e);
+        }
+    }
+
+    // Hold the events during quiet hours or until the next digest is due,
+    // and release the held ones once it is over (and no maintenance window is active)
+    let digest_due = maintenance.is_none()
+        && config
+            .notify
+            .digest
+            .is_none_or(|d| d.is_due(item_store.last_digest(), now));
+    let mut held = if maintenance.is_some() {
+        contexts.clear();
+        Vec::new()
+    } else if config.notify.quiet_hours.is_some_and(|q| q.is_active(now)) {
+        if !contexts.is_empty() {
+            info!("Quiet hours: holding {} item events.", contexts.len());
+        }
+        item_store.hold_events(contexts.drain(..));
+        Vec::new()
+    } else if !digest_due {
+        if !contexts.is_empty() {
+            info!(
+                "Holding {} item events until the next digest.",
+                contexts.len()
+            );
+        }
+        item_store.hold_events(contexts.drain(..));
+        Vec::new()
+    } else {
+        item_store.take_pending_events()
+    };
+    if config.notify.digest.is_some() && digest_due {
+        item_store.record_digest(now);
+    }
+    // List the events by category, as ordered in the bar UI
+    item_store.sort_by_category(&mut held);
+    item_store.sort_by_category(&mut contexts);
+
+    // Route the events to the targets, and notify them
+    if maintenance.is_none() {
+        let batch = Batch {
+            instance: instance.name.clone(),
+            // Tag the notifications with the instance name when watching several instances
+            header: if config.instances.len() > 1 {
+                format!("[{}]\n", instance.name)
+            } else {
+                String::new()
+            },
+            held,
+            events: contexts,
+            digest: config.notify.digest.is_some(),
+            sort: config.notify.sort,
+            group_by_item: config.notify.group_by_item,
+            formatting: formatting.clone(),
+            mentions: config.notify.mentions.clone(),
+            base_url: Some(instance.openbar.instance_url.clone()),
+        };
+        // The targets of the special event are notified too
+        let targets: Vec<TargetConfig> = instance
+            .targets
+            .iter()
+            .chain(special_event.into_iter().flat_map(|event| &event.targets))
+            .cloned()
+            .collect();
+        let dispatch = notifier.notify(&targets, &batch).await;
+        stats.undelivered_messages += dispatch.undelivered;
+        stats.rejected_messages += dispatch.rejected;
+
+        // Remember the messages notifying the items, as identified by their provider
+        for message in &dispatch.messages {
+            for (item_id, event) in &message.events {
+                if item_store.meta(*item_id).is_none() {
+                    continue;
+                }
+                item_store.update_meta(*item_id, |meta| {
+                    meta.record_message(MessageRef {
+                        target: message.target.clone(),
+                        event: event.to_string(),
+                        message_id: message.message_id.clone(),
+                        at: now,
+                    })
+                });
+            }
+        }
+    }
+
+    // Send the consumption report of the previous month, once
+    #[cfg(feature = "analytics")]
+    if let Some(analytics) = analytics
+        && !config.analytics.report_targets.is_empty()
+    {
+        report::send_due_report(
+            analytics,
+            &instance.name,
+            now,
+            &config.analytics.report_targets,
+            &notifier,
+            &formatting,
+        )
+        .await;
+    }
+
+    // Notify the new purchases and refills of the account
+    if !activity.is_empty() {
+        info!(
+            "Notifying {} new transactions of the account of {}...",
+            activity.len(),
+            instance.name
+        );
+        let targets = config.transactions.targets(instance);
+        if !report::send_report(&notifier, targets, &activity).await {
+            // Keep the previous activity, to notify them again at the next check
+            error!("The account transactions could not be delivered to all their targets.");
+            return check_result(instance, stats);
+        }
+    }
+    if let Some(latest) = latest_activity {
+        item_store.set_account_activity(latest);
+    }
+
+    check_result(instance, stats)
+}
+
+/// Fetch the latest transactions and refills of the account and render the new ones (none on
+/// the first fetch), along with the activity to record in the store once they are delivered
+async fn fetch_account_activity(
+    client: &OpenBarClient,
+    config: &GlobalConfig,
+    instance: &InstanceConfig,
+    item_store: &ItemStore,
+    formatting: &Formatting,
+    stats: &mut RunStats,
+) -> (Vec<String>, Option<AccountActivity>) {
+    let limit = config.transactions.limit;
+    let (transactions, refills) = match (
+        client.get_transactions(limit).await,
+        client.get_refills(limit).await,
+    ) {
+        (Ok(transactions), Ok(refills)) => (transactions, refills),
+        (Err(e), _) => {
+            error!("Error retrieving the account transactions: {:?}", e);
+            stats.api_errors += 1;
+            return (Vec::new(), None);
+        }
+        (_, Err(e)) => {
+            error!("Error retrieving the account refills: {:?}", e);
+            stats.api_errors += 1;
+            return (Vec::new(), None);
+        }
+    };
+    let latest = AccountActivity::of(&transactions, &refills);
+    let mut messages = Vec::new();
+    if let Some(previous) = item_store.account_activity() {
+        let (new_transactions, new_refills) = previous.new_since(&latest, &transactions, &refills);
+        if new_transactions.len() as i64 >= limit || new_refills.len() as i64 >= limit {
+            warn!(
+                "All the {} latest transactions (or refills) of the account of {} are new, some may have been missed: consider raising the limit.",
+                limit, instance.name
+            );
+        }
+        messages.extend(
+            new_transactions
+                .into_iter()
+                .map(|t| transactions::render_transaction(&instance.name, t, formatting)),
+        );
+        messages.extend(
+            new_refills
+                .into_iter()
+                .map(|r| transactions::render_refill(&instance.name, r, formatting)),
+        );
+    } else {
+        info!(
+            "Recorded the latest transactions of the account of {}, the next ones will be notified.",
+            instance.name
+        );
+    }
+    (messages, Some(latest))
+}
+
+/// Get the result of an instance check from its statistics: a failed API request fails the
+/// fetch, an undelivered message the dispatch
+fn check_result(instance: &InstanceConfig, stats: &RunStats) -> Result<(), NotifierError> {
+    if stats.api_errors > 0 {
+        Err(NotifierError::Fetch {
+            instance: instance.name.clone(),
+            message: format!("{} API request(s) failed", stats.api_errors),
+        })
+    } else if stats.undelivered_messages > 0 {
+        Err(NotifierError::Dispatch {
+            instance: instance.name.clone(),
+            undelivered: stats.undelivered_messages,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Save the login session of an instance to its file, readable by the owner only
+fn save_session(path: &std::path::Path, session: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(session.as_bytes())
+}
+
+/// Save the item store of an instance to its file, marking it saved on success
+fn save_instance_store(
+    instance: &InstanceConfig,
+    item_store: &mut ItemStore,
+    format: StoreFormat,
+    cipher: Option<&dyn StoreCipher>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    debug!(
+        "Saving the store of instance {} ({} items changed, generation {})",
+        instance.name,
+        item_store.dirty_items().len(),
+        item_store.generation()
+    );
+    save_item_store_to_file(item_store, &instance.store_file, format, cipher)?;
+    item_store.mark_saved();
+    Ok(())
+}
+
+/// Check if an error comes from a read-only filesystem
+fn is_read_only_error(e: &(dyn std::error::Error + 'static)) -> bool {
+    e.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::ReadOnlyFilesystem)
+}
+
+/// Errors while creating the HTTP client
+#[derive(Debug, thiserror::Error)]
+pub enum HttpClientError {
+    /// The custom CA certificate file could not be loaded
+    #[error("invalid CA certificate file {0:?}: {1}")]
+    CaFile(PathBuf, String),
+    /// The proxy URL is invalid
+    #[error("invalid proxy: {0}")]
+    Proxy(#[source] reqwest::Error),
+    /// The TLS provider is not built in
+    #[error("the {0} TLS provider is not built in")]
+    TlsProvider(&'static str),
+    /// The TLS provider does not support the protocol versions
+    #[error("unsupported TLS configuration: {0}")]
+    Tls(#[source] rustls::Error),
+    /// The client could not be built
+    #[error("cannot build the HTTP client: {0}")]
+    Build(#[source] reqwest::Error),
+}
+
+/// Create a Reqwest HTTP client
+///
+/// The server certificates are verified against the bundled Mozilla roots, plus the system
+/// ones and a custom CA (e.g. of a self-signed OpenBar instance) if configured. The TLS session
+/// secrets are only written to `SSLKEYLOGFILE` if the TLS keylog is enabled (for debugging).
+/// The host names are resolved over HTTPS if a DoH server is configured (see [crate::dns]).
+pub fn create_http_client(config: &HttpConfig) -> Result<reqwest::Client, HttpClientError> {
+    create_http_client_with_cookies(config, Arc::new(Jar::default()))
+}
+
+/// Create a Reqwest HTTP client (see [create_http_client]) keeping its cookies in a given jar
+pub fn create_http_client_with_cookies(
+    config: &HttpConfig,
+    cookies: Arc<Jar>,
+) -> Result<reqwest::Client, HttpClientError> {
+    let tls_client = tls_client_config(config)?;
+    // The DoH client shares the TLS configuration
+    let doh_tls_client = config.dns_over_https.as_ref().map(|_| tls_client.clone());
+    let mut builder = reqwest::ClientBuilder::new()
+        .use_preconfigured_tls(tls_client)
+        .cookie_provider(cookies)
+        .user_agent(&config.user_agent);
+    if let Some(timeout) = config.connect_timeout() {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = config.timeout() {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(HttpClientError::Proxy)?);
+    }
+    if let Some(url) = &config.dns_over_https
+        && let Some(doh_tls_client) = doh_tls_client
+    {
+        // The DoH server is queried with the system resolver
+        let mut doh = reqwest::ClientBuilder::new()
+            .use_preconfigured_tls(doh_tls_client)
+            .user_agent(&config.user_agent);
+        if let Some(timeout) = config.timeout() {
+            doh = doh.timeout(timeout);
+        }
+        let doh = doh.build().map_err(HttpClientError::Build)?;
+        builder = builder.dns_resolver(Arc::new(DohResolver::new(doh, url)));
+    }
+    builder.build().map_err(HttpClientError::Build)
+}
+
+/// Create the TLS configuration of the clients (see [create_http_client]), also used by the
+/// connections to the MQTT brokers
+pub fn tls_client_config(config: &HttpConfig) -> Result<rustls::ClientConfig, HttpClientError> {
+    let mut root_store =
+        rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    if config.native_roots {
+        let native = rustls_native_certs::load_native_certs();
+        for e in &native.errors {
+            warn!("Error loading the system root certificates: {}", e);
+        }
+        let (added, ignored) = root_store.add_parsable_certificates(native.certs);
+        debug!(
+            "Loaded {} system root certificates ({} ignored)",
+            added, ignored
+        );
+    }
+    if let Some(path) = &config.ca_file {
+        let ca_error =
+            |e: &dyn std::fmt::Display| HttpClientError::CaFile(path.clone(), e.to_string());
+        let certs = CertificateDer::pem_file_iter(path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| ca_error(&e))?;
+        if certs.is_empty() {
+            return Err(ca_error(&"no certificate found"));
+        }
+        for cert in certs {
+            root_store.add(cert).map_err(|e| ca_error(&e))?;
+        }
+    }
+    let provider = match config.tls_provider {
+        #[cfg(feature = "aws-lc")]
+        TlsProvider::AwsLc => rustls::crypto::aws_lc_rs::default_provider(),
+        #[cfg(not(feature = "aws-lc"))]
+        TlsProvider::AwsLc => return Err(HttpClientError::TlsProvider("aws_lc")),
+        TlsProvider::Ring => rustls::crypto::ring::default_provider(),
+    };
+    let mut tls_client = rustls::ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_safe_default_protocol_versions()
+        .map_err(HttpClientError::Tls)?
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    if config.tls_keylog {
+        warn!("TLS keylog enabled, the session secrets are written to SSLKEYLOGFILE (if set)");
+        tls_client.key_log = std::sync::Arc::new(rustls::KeyLogFile::new());
+    }
+    Ok(tls_client)
+}
+
+/// Load from file, the item store
+///
+/// Encrypted stores are decrypted with the given cipher, plaintext stores are always accepted.
+/// The format of the store is detected (see [StoreFormat::detect]).
+pub fn load_item_store_from_file(
+    path: &std::path::Path,
+    cipher: Option<&dyn StoreCipher>,
+) -> Result<ItemStore, Box<dyn std::error::Error>> {
+    // Check if the file exists
+    if !path.exists() {
+        // If not, return an empty store
+        warn!(
+            "Store file does not exist at {:?}, starting with an empty store.",
+            path
+        );
+        return Ok(ItemStore::new());
+    }
+    let mut data = std::fs::read(path)?;
+    if crypto::is_encrypted(&data) {
+        let cipher = cipher.ok_or("the store is encrypted, but no encryption key is configured")?;
+        data = cipher.decrypt(&data)?;
+    }
+    StoreFormat::decode(&data)
+}
+
+/// Save the item store to a file in the given format, encrypted with the given cipher (if any)
+fn save_item_store_to_file(
+    store: &ItemStore,
+    path: &std::path::Path,
+    format: StoreFormat,
+    cipher: Option<&dyn StoreCipher>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut data = format.encode(store)?;
+    if let Some(cipher) = cipher {
+        data = cipher.encrypt(&data)?;
+    }
+    // Write to a temporary file first, so that an interrupted write never corrupts the store
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    std::fs::write(&tmp_path, data)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
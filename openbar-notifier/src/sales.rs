@@ -0,0 +1,222 @@
+//! Sales rate estimation of OpenBar Notifier
+//!
+//! The OpenBar API does not expose the sales, but they can be inferred from the stock:
+//! each item keeps the stock samples of the last days ([SalesHistory]), and its sales rate
+//! is the sum of the stock decreases over the observed period. Restocks (stock increases)
+//! are not sales, they only move the baseline.
+//!
+//! The rates are shown in the digests and served by the HTTP API (`GET /sales`), e.g. `~12/day`,
+//! to help with the ordering decisions.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::clock;
+use crate::event::Severity;
+use crate::i18n;
+use crate::theme::{self, Variables};
+
+/// Period over which the sales rate is estimated
+pub const WINDOW: Duration = Duration::days(7);
+
+/// Minimal observed period to estimate a sales rate
+pub const MIN_OBSERVATION: Duration = Duration::hours(6);
+
+/// Stock samples of an item, to estimate its sales rate
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SalesHistory {
+    /// Stock samples (time, amount left), oldest first, only recorded when the stock changes
+    samples: VecDeque<(DateTime<Utc>, i64)>,
+    /// When the item was first observed
+    since: Option<DateTime<Utc>>,
+}
+
+impl SalesHistory {
+    /// Observe the stock of the item at the given time
+    pub fn observe(&mut self, at: DateTime<Utc>, amount_left: i64) {
+        if self
+            .samples
+            .back()
+            .is_none_or(|(_, last)| *last != amount_left)
+        {
+            self.samples.push_back((at, amount_left));
+        }
+        self.since.get_or_insert(at);
+        // Keep a single sample before the window, as the baseline of the first decrease
+        let start = at - WINDOW;
+        while self.samples.len() > 1 && self.samples[1].0 <= start {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Clamp the timestamps to `now` if they are in the future (the clock went backwards)
+    pub fn clamp_to_now(&mut self, now: DateTime<Utc>) {
+        for (at, _) in &mut self.samples {
+            *at = clock::clamp_to_now(*at, now);
+        }
+        self.since = self.since.map(|since| clock::clamp_to_now(since, now));
+    }
+
+    /// Get the units sold over the window
+    pub fn units_sold(&self) -> i64 {
+        self.samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|((_, before), (_, after))| (before - after).max(0))
+            .sum()
+    }
+
+    /// Estimate the sales rate (in units per day), if the item was observed long enough
+    pub fn rate_per_day(&self, now: DateTime<Utc>) -> Option<f64> {
+        let observed = clock::elapsed(self.since?.max(now - WINDOW), now);
+        if observed < MIN_OBSERVATION {
+            return None;
+        }
+        let days = observed.num_seconds() as f64 / 86_400.0;
+        Some(self.units_sold() as f64 / days)
+    }
+}
+
+/// Configuration of the predicted stock-out warnings
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PredictionConfig {
+    /// Warn when an item is projected to run out of stock within this many days
+    pub days: f64,
+    /// Severity of the warnings
+    pub severity: Severity,
+}
+
+impl Default for PredictionConfig {
+    fn default() -> Self {
+        PredictionConfig {
+            days: 2.0,
+            severity: Severity::Warning,
+        }
+    }
+}
+
+/// Project the time until an item runs out of stock at the given sales rate (in hours)
+pub fn hours_left(amount_left: i64, rate_per_day: f64) -> Option<i64> {
+    (rate_per_day > 0.0).then(|| (amount_left.max(0) as f64 / rate_per_day * 24.0).round() as i64)
+}
+
+/// Format the projected time until a stock-out (e.g. `~5 hours`, `~2 days`) in a language
+pub fn format_hours_left(hours_left: i64, language: &str) -> String {
+    let tr = |msgid| i18n::translate(language, msgid);
+    match hours_left {
+        0 => tr("less than an hour").to_string(),
+        1 => tr("~1 hour").to_string(),
+        hours if hours < 48 => fill(tr("~{hours} hours"), &[("hours", &hours.to_string())]),
+        hours => {
+            let days = (hours as f64 / 24.0).round().to_string();
+            fill(tr("~{days} days"), &[("days", &days)])
+        }
+    }
+}
+
+/// Format a sales rate in a language, per day (e.g. `~12/day`), or per week for the slow sellers
+pub fn format_rate(rate_per_day: f64, language: &str) -> String {
+    let (msgid, rate) = if rate_per_day >= 1.0 || rate_per_day == 0.0 {
+        ("~{rate}/day", rate_per_day)
+    } else {
+        ("~{rate}/week", (rate_per_day * 7.0).max(1.0))
+    };
+    let rate = format!("{:.0}", rate);
+    fill(i18n::translate(language, msgid), &[("rate", &rate)])
+}
+
+/// Fill the placeholders of a message
+fn fill(message: &str, values: &[(&str, &str)]) -> String {
+    theme::fill(message, values, &Variables::new())
+}
+
+/// Sales rate of an item
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ItemSalesRate {
+    /// ID of the item
+    pub item_id: Uuid,
+    /// Name of the item
+    pub name: String,
+    /// Name of the item category
+    pub category_name: String,
+    /// Estimated units sold per day
+    pub rate_per_day: f64,
+    /// Human-readable rate (e.g. `~12/day`)
+    pub display: String,
+}
+
+/// Latest sales rates of the items of each instance, shared with the HTTP API
+#[derive(Debug, Default)]
+pub struct SalesBoard {
+    rates: RwLock<BTreeMap<String, Vec<ItemSalesRate>>>,
+}
+
+impl SalesBoard {
+    /// Replace the sales rates of an instance
+    pub fn update(&self, instance: &str, mut rates: Vec<ItemSalesRate>) {
+        rates.sort_by(|a, b| b.rate_per_day.total_cmp(&a.rate_per_day));
+        self.rates
+            .write()
+            .unwrap()
+            .insert(instance.to_string(), rates);
+    }
+
+    /// Get the sales rates, by instance (fastest sellers first)
+    pub fn get(&self) -> BTreeMap<String, Vec<ItemSalesRate>> {
+        self.rates.read().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hours: i64) -> DateTime<Utc> {
+        DateTime::UNIX_EPOCH + Duration::hours(hours)
+    }
+
+    #[test]
+    fn not_enough_observation() {
+        let mut history = SalesHistory::default();
+        history.observe(at(0), 10);
+        history.observe(at(1), 5);
+        assert_eq!(history.rate_per_day(at(1)), None);
+    }
+
+    #[test]
+    fn restocks_are_not_sales() {
+        let mut history = SalesHistory::default();
+        history.observe(at(0), 10);
+        history.observe(at(6), 4);
+        history.observe(at(12), 30);
+        history.observe(at(18), 24);
+        history.observe(at(24), 24);
+        assert_eq!(history.units_sold(), 12);
+        assert_eq!(history.rate_per_day(at(24)), Some(12.0));
+        assert_eq!(format_rate(12.0, "en"), "~12/day");
+        assert_eq!(format_rate(12.0, "fr"), "~12/jour");
+    }
+
+    #[test]
+    fn old_samples_leave_the_window() {
+        let mut history = SalesHistory::default();
+        history.observe(at(0), 100);
+        history.observe(at(1), 50);
+        history.observe(at(24 * 8), 43);
+        // Only the decrease observed within the window is counted
+        assert_eq!(history.units_sold(), 7);
+        assert_eq!(history.rate_per_day(at(24 * 8)), Some(1.0));
+    }
+
+    #[test]
+    fn slow_sellers_are_shown_per_week() {
+        assert_eq!(format_rate(3.0 / 7.0, "en"), "~3/week");
+        assert_eq!(format_rate(0.01, "en"), "~1/week");
+        assert_eq!(format_rate(0.0, "en"), "~0/day");
+    }
+}
@@ -0,0 +1,279 @@
+//! Notification schedule for OpenBar Notifier
+//!
+//! This module defines the time windows during which notifications are held
+//! instead of being sent immediately, and the polling schedule of the daemon mode.
+//!
+//! The calendar of special events (parties, integration week...) is also applied by the
+//! schedule: during an event, the instances are polled more often, the low-stock threshold is
+//! tightened and the event targets are notified too, without swapping the configuration.
+//!
+//! When many notifiers poll the same OpenBar instance (e.g. started by the same cron minute),
+//! their checks can be spread with a random jitter, at startup and between two polls (see
+//! [jitter]).
+
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use serde::{Deserialize, Deserializer};
+
+use crate::clock;
+use crate::config::TargetConfig;
+
+/// Quiet hours, a daily time window (in local time) during which notifications are held
+///
+/// The window may wrap around midnight (e.g. 22:00-08:00).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct QuietHours {
+    /// Start of the window (inclusive)
+    pub start: NaiveTime,
+    /// End of the window (exclusive)
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Parse quiet hours from a `HH:MM-HH:MM` string
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("invalid quiet hours '{}' (expected HH:MM-HH:MM)", s))?;
+        let parse_time = |t: &str| {
+            NaiveTime::parse_from_str(t.trim(), "%H:%M")
+                .map_err(|e| format!("invalid time '{}' in quiet hours: {}", t, e))
+        };
+        Ok(QuietHours {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+
+    /// Check if the given time is within the quiet hours
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Check if the given time, in local time, is within the quiet hours
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.contains(now.with_timezone(&chrono::Local).time())
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        QuietHours::parse(&s)
+    }
+}
+
+/// Frequency of the digest notifications
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestFrequency {
+    /// A digest is sent at every run with events
+    Immediate,
+    /// Events are accumulated and a digest is sent at most once per hour
+    Hourly,
+    /// Events are accumulated and a digest is sent at most once per day
+    Daily,
+}
+
+impl DigestFrequency {
+    /// Minimal duration between two digests
+    pub fn period(&self) -> Duration {
+        match self {
+            DigestFrequency::Immediate => Duration::zero(),
+            DigestFrequency::Hourly => Duration::hours(1),
+            DigestFrequency::Daily => Duration::days(1),
+        }
+    }
+
+    /// Check if a digest is due, given the time of the last one
+    pub fn is_due(&self, last_digest: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        last_digest.is_none_or(|last| clock::elapsed(last, now) >= self.period())
+    }
+}
+
+impl std::str::FromStr for DigestFrequency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "immediate" => Ok(DigestFrequency::Immediate),
+            "hourly" => Ok(DigestFrequency::Hourly),
+            "daily" => Ok(DigestFrequency::Daily),
+            _ => Err(format!(
+                "unknown digest frequency '{}' (expected immediate, hourly or daily)",
+                s
+            )),
+        }
+    }
+}
+
+/// Exponential backoff of the polling interval, while an instance API is failing
+///
+/// After `n` consecutive failures, the interval is `base * 2^n`, up to `max`.
+/// A success brings it back to `base`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    base: std::time::Duration,
+    max: std::time::Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    /// Create a backoff with the given normal and maximal intervals
+    pub fn new(base: std::time::Duration, max: std::time::Duration) -> Self {
+        Backoff {
+            base,
+            max: max.max(base),
+            failures: 0,
+        }
+    }
+
+    /// Get the number of consecutive failures
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Record a successful poll
+    pub fn record_success(&mut self) {
+        self.failures = 0;
+    }
+
+    /// Record a failed poll
+    pub fn record_failure(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    /// Record a failed poll that is not expected to resolve by itself (e.g. a misconfiguration),
+    /// backing off to the maximal interval straight away
+    pub fn record_permanent_failure(&mut self) {
+        self.failures = self.failures.max(31);
+    }
+
+    /// Get the interval until the next poll
+    pub fn interval(&self) -> std::time::Duration {
+        let factor = 2u32.saturating_pow(self.failures.min(31));
+        self.base.saturating_mul(factor).min(self.max)
+    }
+}
+
+/// Draw a random jitter, up to `max` (included)
+pub fn jitter(max: std::time::Duration) -> std::time::Duration {
+    let max_ms = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    std::time::Duration::from_millis(rand::random_range(0..=max_ms))
+}
+
+/// Special event of the calendar (e.g. a party), during which the instances are watched closely
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpecialEvent {
+    /// Name of the event
+    pub name: String,
+    /// Start of the event (inclusive), as a date (midnight UTC) or an RFC 3339 timestamp
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: DateTime<Utc>,
+    /// End of the event (exclusive)
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: DateTime<Utc>,
+    /// Interval between two polls of the instances during the event (in seconds), if shorter
+    /// than the usual one
+    #[serde(default)]
+    pub poll_interval_secs: Option<u64>,
+    /// Ratio of the optimal amount under which an item is low on stock during the event
+    #[serde(default)]
+    pub low_stock_ratio: Option<f64>,
+    /// Targets notified of the events of all the instances during the event, besides their own
+    #[serde(default)]
+    pub targets: Vec<TargetConfig>,
+}
+
+impl SpecialEvent {
+    /// Check if the event is ongoing at the given time
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.start <= now && now < self.end
+    }
+
+    /// Get the polling interval of the event, if it has one
+    pub fn poll_interval(&self) -> Option<std::time::Duration> {
+        self.poll_interval_secs
+            .filter(|secs| *secs > 0)
+            .map(std::time::Duration::from_secs)
+    }
+}
+
+/// Get the special event ongoing at the given time (the first one listed, if they overlap)
+pub fn active_event(calendar: &[SpecialEvent], now: DateTime<Utc>) -> Option<&SpecialEvent> {
+    calendar.iter().find(|event| event.is_active(now))
+}
+
+/// Get the start of the next special event after the given time, if any
+pub fn next_event_start(calendar: &[SpecialEvent], now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    calendar
+        .iter()
+        .map(|event| event.start)
+        .filter(|start| *start > now)
+        .min()
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    crate::journal::parse_since(&s).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(name: &str, start: &str, end: &str) -> SpecialEvent {
+        SpecialEvent {
+            name: name.to_string(),
+            start: start.parse().unwrap(),
+            end: end.parse().unwrap(),
+            poll_interval_secs: Some(15),
+            low_stock_ratio: None,
+            targets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn jitter_stays_within_its_bound() {
+        let max = std::time::Duration::from_secs(5);
+        assert!((0..100).all(|_| jitter(max) <= max));
+        assert_eq!(jitter(std::time::Duration::ZERO), std::time::Duration::ZERO);
+    }
+
+    #[test]
+    fn applies_the_ongoing_special_event() {
+        let calendar = [
+            event("party", "2024-09-06T18:00:00Z", "2024-09-07T04:00:00Z"),
+            event(
+                "integration",
+                "2024-09-02T00:00:00Z",
+                "2024-09-09T00:00:00Z",
+            ),
+        ];
+        let at = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+
+        assert!(active_event(&calendar, at("2024-09-01T12:00:00Z")).is_none());
+        assert_eq!(
+            next_event_start(&calendar, at("2024-09-01T12:00:00Z")),
+            Some(at("2024-09-02T00:00:00Z"))
+        );
+        let ongoing = active_event(&calendar, at("2024-09-03T12:00:00Z")).unwrap();
+        assert_eq!(ongoing.name, "integration");
+        assert_eq!(
+            ongoing.poll_interval(),
+            Some(std::time::Duration::from_secs(15))
+        );
+        // The first event listed wins
+        let ongoing = active_event(&calendar, at("2024-09-06T20:00:00Z")).unwrap();
+        assert_eq!(ongoing.name, "party");
+        assert!(active_event(&calendar, at("2024-09-09T00:00:00Z")).is_none());
+    }
+}
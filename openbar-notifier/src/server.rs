@@ -0,0 +1,228 @@
+//! HTTP API of the daemon mode
+//!
+//! Endpoints:
+//! - `GET /deliveries?since=<RFC 3339 timestamp>`: recent delivery attempts (see [crate::delivery])
+//! - `GET /maintenance`: the active maintenance window, if any (see [crate::maintenance])
+//! - `PUT /maintenance` (`{"duration": "2h", "reason": "..."}`): mute the notifications for a while
+//! - `DELETE /maintenance`: end the maintenance window
+//! - `PUT /mutes/{item}` (`{"duration": "24h", "instance": "..."}`): mute an item (by ID or name),
+//!   applied at the next cycle (see [crate::mute])
+//! - `DELETE /mutes/{item}?instance=<name>`: unmute an item, applied at the next cycle
+//! - `POST /poll`: check all the instances right away, after the current cycle
+//! - `GET /sales?instance=<name>`: estimated sales rates of the items, by instance, fastest
+//!   sellers first (see [crate::sales])
+//! - `GET /status`: overview of the instances and of the health of the targets (see
+//!   [crate::status])
+//! - `GET /stores/{instance}`: the store of an instance, as of its last check
+//! - `GET /targets`: the configured targets (with their secrets redacted) and their instances
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use crate::clock;
+use crate::delivery::{DeliveryLog, DeliveryRecord};
+use crate::maintenance::{self, MaintenanceWindow};
+use crate::mute::{MuteQueue, MuteRequest};
+use crate::sales::{ItemSalesRate, SalesBoard};
+use crate::status::{Dashboard, StatusBoard, TargetInfo};
+use crate::store::ItemStore;
+
+/// Shared state of the HTTP API
+#[derive(Debug, Clone, Default)]
+pub struct ApiState {
+    /// Audit log of the delivery attempts
+    pub deliveries: Arc<DeliveryLog>,
+    /// File persisting the maintenance window
+    pub maintenance_file: PathBuf,
+    /// Queue of the mute requests, applied by the runner
+    pub mutes: Arc<MuteQueue>,
+    /// Trigger of the immediate polls (see [crate::runner::Runner::poll_trigger])
+    pub poll: Arc<Notify>,
+    /// Latest sales rates of the items, updated by the runner
+    pub sales: Arc<SalesBoard>,
+    /// Live status of the instances, updated by the runner
+    pub status: Arc<StatusBoard>,
+}
+
+/// Query parameters of `GET /deliveries`
+#[derive(Debug, Deserialize)]
+struct DeliveriesQuery {
+    since: Option<DateTime<Utc>>,
+}
+
+/// Body of `PUT /maintenance`
+#[derive(Debug, Deserialize)]
+struct MaintenanceRequest {
+    /// Duration of the window (e.g. `2h`, `30m`)
+    duration: String,
+    reason: Option<String>,
+}
+
+/// Body of `PUT /mutes/{item}`
+#[derive(Debug, Deserialize)]
+struct MuteBody {
+    /// Duration of the mute (e.g. `24h`)
+    duration: String,
+    instance: Option<String>,
+}
+
+/// Query parameters of `DELETE /mutes/{item}`
+#[derive(Debug, Deserialize)]
+struct UnmuteQuery {
+    instance: Option<String>,
+}
+
+/// Query parameters of `GET /sales`
+#[derive(Debug, Deserialize)]
+struct SalesQuery {
+    instance: Option<String>,
+}
+
+/// Error of an API request, with its status code
+type ApiError = (StatusCode, String);
+
+/// Build the router of the HTTP API
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/deliveries", get(get_deliveries))
+        .route(
+            "/maintenance",
+            get(get_maintenance)
+                .put(put_maintenance)
+                .delete(delete_maintenance),
+        )
+        .route("/mutes/{item}", put(put_mute).delete(delete_mute))
+        .route("/poll", post(post_poll))
+        .route("/sales", get(get_sales))
+        .route("/status", get(get_status))
+        .route("/stores/{instance}", get(get_store))
+        .route("/targets", get(get_targets))
+        .with_state(state)
+}
+
+/// Serve the HTTP API on the given address, until the `shutdown` future completes
+pub async fn serve(
+    bind: SocketAddr,
+    state: ApiState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+/// `GET /deliveries`
+async fn get_deliveries(
+    State(state): State<ApiState>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Json<Vec<DeliveryRecord>> {
+    Json(state.deliveries.since(query.since))
+}
+
+/// `GET /sales`
+async fn get_sales(
+    State(state): State<ApiState>,
+    Query(query): Query<SalesQuery>,
+) -> Json<BTreeMap<String, Vec<ItemSalesRate>>> {
+    let mut rates = state.sales.get();
+    if let Some(instance) = query.instance {
+        rates.retain(|name, _| *name == instance);
+    }
+    Json(rates)
+}
+
+/// `GET /status`
+async fn get_status(State(state): State<ApiState>) -> Json<Dashboard> {
+    Json(state.status.dashboard(&state.deliveries))
+}
+
+/// `GET /stores/{instance}`
+async fn get_store(
+    State(state): State<ApiState>,
+    Path(instance): Path<String>,
+) -> Result<Json<ItemStore>, ApiError> {
+    state.status.store(&instance).map(Json).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("no store loaded for the instance '{}'", instance),
+        )
+    })
+}
+
+/// `GET /targets`
+async fn get_targets(State(state): State<ApiState>) -> Json<Vec<TargetInfo>> {
+    Json(state.status.targets())
+}
+
+/// `POST /poll`
+async fn post_poll(State(state): State<ApiState>) -> StatusCode {
+    state.poll.notify_one();
+    StatusCode::ACCEPTED
+}
+
+/// `GET /maintenance`
+async fn get_maintenance(State(state): State<ApiState>) -> Json<Option<MaintenanceWindow>> {
+    Json(maintenance::active(&state.maintenance_file, Utc::now()))
+}
+
+/// `PUT /maintenance`
+async fn put_maintenance(
+    State(state): State<ApiState>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<Json<MaintenanceWindow>, ApiError> {
+    let duration =
+        maintenance::parse_duration(&request.duration).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let window = MaintenanceWindow::starting_now(duration, request.reason);
+    maintenance::save(&state.maintenance_file, &window)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(window))
+}
+
+/// `DELETE /maintenance`
+async fn delete_maintenance(State(state): State<ApiState>) -> Result<StatusCode, ApiError> {
+    maintenance::clear(&state.maintenance_file)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /mutes/{item}`
+async fn put_mute(
+    State(state): State<ApiState>,
+    Path(item): Path<String>,
+    Json(body): Json<MuteBody>,
+) -> Result<(StatusCode, Json<MuteRequest>), ApiError> {
+    let duration =
+        maintenance::parse_duration(&body.duration).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let request = MuteRequest {
+        instance: body.instance,
+        item,
+        until: Some(clock::after(Utc::now(), duration)),
+    };
+    state.mutes.push(request.clone());
+    Ok((StatusCode::ACCEPTED, Json(request)))
+}
+
+/// `DELETE /mutes/{item}`
+async fn delete_mute(
+    State(state): State<ApiState>,
+    Path(item): Path<String>,
+    Query(query): Query<UnmuteQuery>,
+) -> StatusCode {
+    state.mutes.push(MuteRequest {
+        instance: query.instance,
+        item,
+        until: None,
+    });
+    StatusCode::ACCEPTED
+}
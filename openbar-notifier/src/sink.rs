@@ -0,0 +1,141 @@
+//! File sinks of OpenBar Notifier
+//!
+//! The `jsonl` targets append each notified event as a line of JSON to a file, given by its
+//! `file://` URL, so that downstream scripts can simply tail it instead of running a webhook
+//! receiver. The lines are journal entries (see [crate::journal::JournalEntry]), and the
+//! standalone messages (e.g. the reports) are appended as `{"at": ..., "message": ...}`.
+//!
+//! The file can be rotated by size (`rotate_size=<bytes>`): once it reaches this size, it is
+//! renamed to `<file>.1` before the next lines are appended, the previous rotated files being
+//! shifted (`<file>.1` to `<file>.2`, ...) and only the `rotate_keep` most recent ones kept
+//! ([DEFAULT_ROTATE_KEEP] by default).
+//!
+//! The `stdout` and `stderr` targets print the notifications to the standard streams instead,
+//! making the binary composable in shell pipelines (e.g. `stdout format=json`, one line of
+//! JSON per batch, or `stdout format=jsonl`, one per event), and easy to test in CI. They get
+//! the plain text messages by default, and the logs never go to the standard output.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Number of rotated files kept by default
+pub const DEFAULT_ROTATE_KEEP: usize = 5;
+
+/// Standard stream of a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    /// Get the stream of a target by its URL (`stdout` or `stderr`), if it is one
+    pub fn of(url: &str) -> Option<Self> {
+        match url {
+            "stdout" => Some(Stream::Stdout),
+            "stderr" => Some(Stream::Stderr),
+            _ => None,
+        }
+    }
+
+    /// Print a text to the stream, followed by a newline
+    pub fn print(&self, text: &str) -> std::io::Result<()> {
+        match self {
+            Stream::Stdout => writeln!(std::io::stdout().lock(), "{}", text),
+            Stream::Stderr => writeln!(std::io::stderr().lock(), "{}", text),
+        }
+    }
+}
+
+/// Size-based rotation of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rotation {
+    /// Size from which the file is rotated, in bytes
+    pub max_size: u64,
+    /// Number of rotated files kept
+    pub keep: usize,
+}
+
+/// Get the path of a rotated file (`<file>.<n>`, `<file>.1` being the most recent)
+pub fn rotated_file(path: &Path, n: usize) -> PathBuf {
+    let mut file = path.as_os_str().to_owned();
+    file.push(format!(".{}", n));
+    PathBuf::from(file)
+}
+
+/// Append lines to a file, creating it if needed, and rotating it first if it is too large
+pub fn append_lines(
+    path: &Path,
+    lines: &[String],
+    rotation: Option<Rotation>,
+) -> std::io::Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    if let Some(rotation) = rotation
+        && std::fs::metadata(path).is_ok_and(|meta| meta.len() >= rotation.max_size)
+    {
+        rotate(path, rotation.keep)?;
+    }
+    let mut buf = Vec::new();
+    for line in lines {
+        buf.extend_from_slice(line.as_bytes());
+        buf.push(b'\n');
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&buf)
+}
+
+/// Rotate a file, keeping the `keep` most recent rotated files
+fn rotate(path: &Path, keep: usize) -> std::io::Result<()> {
+    if keep == 0 {
+        return std::fs::remove_file(path);
+    }
+    let oldest = rotated_file(path, keep);
+    if oldest.exists() {
+        std::fs::remove_file(oldest)?;
+    }
+    for n in (1..keep).rev() {
+        let file = rotated_file(path, n);
+        if file.exists() {
+            std::fs::rename(file, rotated_file(path, n + 1))?;
+        }
+    }
+    std::fs::rename(path, rotated_file(path, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_the_files_by_size() {
+        let path = std::env::temp_dir().join(format!(
+            "openbar-notifier-sink-{}.jsonl",
+            std::process::id()
+        ));
+        let rotation = Some(Rotation {
+            max_size: 10,
+            keep: 2,
+        });
+        for n in 0..4 {
+            append_lines(&path, &[format!("{{\"n\":{}}}", n)], rotation).unwrap();
+            // Below the limit, the lines are appended to the same file
+            append_lines(&path, &["{}".to_string()], rotation).unwrap();
+        }
+
+        let read = |path: &Path| std::fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "{\"n\":3}\n{}\n");
+        assert_eq!(read(&rotated_file(&path, 1)), "{\"n\":2}\n{}\n");
+        assert_eq!(read(&rotated_file(&path, 2)), "{\"n\":1}\n{}\n");
+        assert!(!rotated_file(&path, 3).exists());
+
+        std::fs::remove_file(&path).unwrap();
+        for n in 1..=2 {
+            std::fs::remove_file(rotated_file(&path, n)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,312 @@
+//! Live status of the daemon, for the dashboards
+//!
+//! The runner keeps a [StatusBoard] up to date: the items of each store after a check, the
+//! recent item events, and the time of the next check. Along with the health of the targets
+//! (their last delivery attempt, from the [DeliveryLog]), it makes the [Dashboard] served by
+//! `GET /status` and shown by `openbar-notifier tui`. The board also keeps a copy of the stores
+//! and the list of the configured targets, for the admin endpoints of the HTTP API.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+
+use chrono::{DateTime, Utc};
+use openbar_api::models::ItemState;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GlobalConfig;
+use crate::delivery::DeliveryLog;
+use crate::event::EventContext;
+use crate::runner::CheckOutcome;
+use crate::store::ItemStore;
+
+/// Number of recent item events kept for each instance
+pub const MAX_RECENT_EVENTS: usize = 50;
+
+/// An item of a store, as shown on the dashboard
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStatus {
+    pub name: String,
+    /// Name of the category of the item (empty if unknown)
+    pub category: String,
+    pub amount_left: i64,
+    pub buyable: bool,
+}
+
+/// A recent item event, as shown on the dashboard
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentEvent {
+    pub at: DateTime<Utc>,
+    /// Name of the item
+    pub item: String,
+    /// Type of the event (e.g. `out_of_stock`)
+    pub event: String,
+}
+
+/// Status of an instance
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstanceStatus {
+    /// Name of the instance
+    pub instance: String,
+    /// Time of the last check, if any
+    pub last_check: Option<DateTime<Utc>>,
+    /// Outcome of the last check (e.g. `ok`), if any
+    pub outcome: Option<String>,
+    /// Time of the next check, in daemon mode
+    pub next_check: Option<DateTime<Utc>>,
+    /// Items of the store, as of the last check
+    pub items: Vec<ItemStatus>,
+    /// Recent item events, newest first
+    pub events: VecDeque<RecentEvent>,
+}
+
+/// Health of a target, from its last delivery attempt
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetHealth {
+    /// The target (with its secrets redacted)
+    pub target: String,
+    /// Time of the last delivery attempt
+    pub at: DateTime<Utc>,
+    /// Status of the last delivery attempt (e.g. `accepted`)
+    pub status: String,
+    /// Whether the last delivery attempt succeeded
+    pub healthy: bool,
+}
+
+/// A configured target, as served by `GET /targets`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetInfo {
+    /// Stable identifier of the target (see [crate::config::TargetConfig::key])
+    pub key: String,
+    /// The target (with its secrets redacted)
+    pub target: String,
+    /// Minimal severity of the events notified to the target (e.g. `info`)
+    pub min_severity: String,
+    pub dry_run: bool,
+    /// Names of the instances notifying the target
+    pub instances: Vec<String>,
+}
+
+/// Overview of the daemon, as served by `GET /status`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Dashboard {
+    pub instances: Vec<InstanceStatus>,
+    pub targets: Vec<TargetHealth>,
+}
+
+/// Live status of the instances, updated by the runner and shared with the dashboards
+#[derive(Debug, Default)]
+pub struct StatusBoard {
+    instances: RwLock<BTreeMap<String, InstanceStatus>>,
+    /// Stores of the instances, as of their last check
+    stores: RwLock<BTreeMap<String, ItemStore>>,
+    targets: RwLock<Vec<TargetInfo>>,
+}
+
+impl StatusBoard {
+    /// Record the check of an instance, with the items of its store (if loaded)
+    pub fn record_check(
+        &self,
+        instance: &str,
+        at: DateTime<Utc>,
+        outcome: CheckOutcome,
+        store: Option<&ItemStore>,
+    ) {
+        self.update(instance, |status| {
+            status.last_check = Some(at);
+            status.outcome = Some(outcome.to_string());
+            if let Some(store) = store {
+                status.items = store
+                    .iter()
+                    .map(|item| ItemStatus {
+                        name: item.name.clone(),
+                        category: store
+                            .category(item.category_id)
+                            .map(|category| category.name.clone())
+                            .unwrap_or_default(),
+                        amount_left: item.amount_left,
+                        buyable: item.state == ItemState::ItemBuyable,
+                    })
+                    .collect();
+            }
+        });
+        if let Some(store) = store {
+            self.stores
+                .write()
+                .unwrap()
+                .insert(instance.to_string(), store.clone());
+        }
+    }
+
+    /// Record the item events emitted by a check of an instance
+    pub fn record_events(&self, instance: &str, at: DateTime<Utc>, contexts: &[EventContext]) {
+        self.update(instance, |status| {
+            for ctx in contexts {
+                status.events.push_front(RecentEvent {
+                    at,
+                    item: ctx.item.name.clone(),
+                    event: ctx.event.kind().to_string(),
+                });
+            }
+            status.events.truncate(MAX_RECENT_EVENTS);
+        });
+    }
+
+    /// Record the time of the next check of an instance
+    pub fn set_next_check(&self, instance: &str, at: DateTime<Utc>) {
+        self.update(instance, |status| status.next_check = Some(at));
+    }
+
+    /// Record the targets of the instances of a configuration (e.g. once reloaded)
+    pub fn set_targets(&self, config: &GlobalConfig) {
+        let mut targets: Vec<TargetInfo> = Vec::new();
+        for instance in &config.instances {
+            for target in &instance.targets {
+                let key = target.key();
+                match targets.iter_mut().find(|info| info.key == key) {
+                    Some(info) => info.instances.push(instance.name.clone()),
+                    None => targets.push(TargetInfo {
+                        key,
+                        target: target.redacted_url(),
+                        min_severity: target.effective_min_severity().to_string(),
+                        dry_run: target.dry_run,
+                        instances: vec![instance.name.clone()],
+                    }),
+                }
+            }
+        }
+        *self.targets.write().unwrap() = targets;
+    }
+
+    /// Get the configured targets, in the order of the configuration
+    pub fn targets(&self) -> Vec<TargetInfo> {
+        self.targets.read().unwrap().clone()
+    }
+
+    /// Get the store of an instance, as of its last check
+    pub fn store(&self, instance: &str) -> Option<ItemStore> {
+        self.stores.read().unwrap().get(instance).cloned()
+    }
+
+    /// Get the overview of the instances, and of the targets given the delivery attempts
+    pub fn dashboard(&self, deliveries: &DeliveryLog) -> Dashboard {
+        let mut targets: BTreeMap<String, TargetHealth> = BTreeMap::new();
+        for record in deliveries.since(None) {
+            targets.insert(
+                record.target.clone(),
+                TargetHealth {
+                    target: record.target,
+                    at: record.at,
+                    status: record.result.status.to_string(),
+                    healthy: record.result.is_success(),
+                },
+            );
+        }
+        Dashboard {
+            instances: self.instances.read().unwrap().values().cloned().collect(),
+            targets: targets.into_values().collect(),
+        }
+    }
+
+    fn update(&self, instance: &str, f: impl FnOnce(&mut InstanceStatus)) {
+        let mut instances = self.instances.write().unwrap();
+        let status = instances
+            .entry(instance.to_string())
+            .or_insert_with(|| InstanceStatus {
+                instance: instance.to_string(),
+                ..InstanceStatus::default()
+            });
+        f(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{Item, ItemPrices};
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::config::{InstanceConfig, TargetConfig};
+    use crate::event::ItemEvent;
+
+    fn context(name: &str, event: ItemEvent) -> EventContext {
+        EventContext {
+            event,
+            item: Item::new(
+                Uuid::from_u128(1),
+                ItemPrices::new(100, 100, 100, 100, 150, 150),
+                0,
+                10,
+                Uuid::from_u128(0),
+                name.to_string(),
+                String::new(),
+                false,
+                ItemState::ItemBuyable,
+            ),
+            category_name: "Snacks".to_string(),
+            tags: Default::default(),
+            restricted: false,
+            sales_rate: None,
+            price: None,
+            severity: Default::default(),
+            correction: None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_recent_events_of_the_instances() {
+        let board = StatusBoard::default();
+        let at = DateTime::UNIX_EPOCH;
+        board.record_check("kfet", at, CheckOutcome::Ok, None);
+        board.record_events("kfet", at, &[context("Chips", ItemEvent::OutOfStock)]);
+        let burst: Vec<_> = (0..MAX_RECENT_EVENTS)
+            .map(|_| context("Soda", ItemEvent::LowStock))
+            .collect();
+        board.record_events("kfet", at, &burst);
+
+        let dashboard = board.dashboard(&DeliveryLog::default());
+        assert_eq!(dashboard.instances.len(), 1);
+        let status = &dashboard.instances[0];
+        assert_eq!(status.outcome.as_deref(), Some("ok"));
+        assert_eq!(status.events.len(), MAX_RECENT_EVENTS);
+        assert!(status.events.iter().all(|event| event.item == "Soda"));
+        assert!(dashboard.targets.is_empty());
+    }
+
+    #[test]
+    fn lists_the_targets_of_the_instances() {
+        let target = |url: &str| TargetConfig {
+            url: url.to_string(),
+            ..TargetConfig::default()
+        };
+        let shared = target("https://discord.com/api/webhooks/1/secret");
+        let instance = |name: &str, targets: Vec<TargetConfig>| InstanceConfig {
+            name: name.to_string(),
+            targets,
+            ..InstanceConfig::default()
+        };
+        let config = GlobalConfig {
+            instances: vec![
+                instance("kfet", vec![shared.clone(), target("https://ntfy.sh/kfet")]),
+                instance("foyer", vec![shared.clone()]),
+            ],
+            ..GlobalConfig::default()
+        };
+        let board = StatusBoard::default();
+        board.set_targets(&config);
+        let targets = board.targets();
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].key, shared.key());
+        assert_eq!(targets[0].target, "https://discord.com/…");
+        assert_eq!(targets[0].instances, ["kfet", "foyer"]);
+        assert_eq!(targets[1].instances, ["kfet"]);
+
+        assert!(board.store("kfet").is_none());
+        board.record_check(
+            "kfet",
+            DateTime::UNIX_EPOCH,
+            CheckOutcome::Ok,
+            Some(&ItemStore::new()),
+        );
+        assert!(board.store("kfet").is_some());
+    }
+}
@@ -2,23 +2,357 @@
 //!
 //! This module contains the item store implementation, which enables
 //! tracking the state of items across multiple checks.
+//!
+//! A store remembers the instance it was created against, and the version of its format (see
+//! [STORE_SCHEMA]). Diffing a store of another instance (or of an incompatible format) would
+//! emit hundreds of bogus events, so such a store is reset instead (see [ItemStore::mismatch]).
+
+use std::collections::{BTreeMap, BTreeSet};
 
-use openbar_api::models::Item;
+use chrono::{DateTime, Duration, Utc};
+use openbar_api::models::{Category, Item};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::clock;
+use crate::debounce::StateTracking;
+use crate::diff;
+use crate::event::EventContext;
+use crate::feed::FeedEntry;
+use crate::lifecycle::Lifecycle;
+use crate::prices::PriceHistory;
+use crate::sales::SalesHistory;
+use crate::transactions::AccountActivity;
+
+/// Version of the store format, bumped on the changes the older stores cannot be diffed across
+///
+/// Every older format (including the unversioned stores, `0`) is still diffed, only the stores
+/// of a newer version are not.
+pub const STORE_SCHEMA: u32 = 1;
+
+/// When the stores are saved to their file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaveMode {
+    /// After every poll
+    #[default]
+    EveryPoll,
+    /// After the polls that changed the store
+    OnChange,
+    /// At most once per save interval, if the store changed (and when the daemon exits)
+    Periodic,
+}
+
+impl std::str::FromStr for SaveMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "every_poll" => Ok(SaveMode::EveryPoll),
+            "on_change" => Ok(SaveMode::OnChange),
+            "periodic" => Ok(SaveMode::Periodic),
+            _ => Err(format!(
+                "unknown store save mode '{}' (expected every_poll, on_change or periodic)",
+                s
+            )),
+        }
+    }
+}
+
+/// Serialization format of the stores
+///
+/// The format of a store is detected when it is loaded, so that it can be changed at any time
+/// (the store being converted on its next save).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreFormat {
+    /// Pretty-printed JSON, for reading (and fixing) the stores by hand
+    #[default]
+    Json,
+    /// JSON without whitespace
+    CompactJson,
+    /// CBOR (RFC 8949), compact binary format
+    Cbor,
+    /// MessagePack, compact binary format
+    #[serde(rename = "messagepack")]
+    MessagePack,
+}
+
+impl std::str::FromStr for StoreFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(StoreFormat::Json),
+            "compact_json" => Ok(StoreFormat::CompactJson),
+            "cbor" => Ok(StoreFormat::Cbor),
+            "messagepack" | "msgpack" => Ok(StoreFormat::MessagePack),
+            _ => Err(format!(
+                "unknown store format '{}' (expected json, compact_json, cbor or messagepack)",
+                s
+            )),
+        }
+    }
+}
+
+impl StoreFormat {
+    /// Detect the format of a serialized store, from its first byte
+    ///
+    /// A store is a map: an object in JSON, a map in CBOR (major type 5) or in MessagePack
+    /// (fixmap, map 16 or map 32). The JSON stores may be compact or not, they are read alike.
+    pub fn detect(data: &[u8]) -> Option<StoreFormat> {
+        match data.iter().find(|b| !b.is_ascii_whitespace())? {
+            b'{' => Some(StoreFormat::Json),
+            0xa0..=0xbf => Some(StoreFormat::Cbor),
+            0x80..=0x8f | 0xde | 0xdf => Some(StoreFormat::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Serialize a store in this format
+    pub fn encode(&self, store: &ItemStore) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(match self {
+            StoreFormat::Json => serde_json::to_vec_pretty(store)?,
+            StoreFormat::CompactJson => serde_json::to_vec(store)?,
+            #[cfg(feature = "store-formats")]
+            StoreFormat::Cbor => {
+                let mut data = Vec::new();
+                ciborium::into_writer(store, &mut data)?;
+                data
+            }
+            // The structures are serialized as maps, for their fields to be optional
+            #[cfg(feature = "store-formats")]
+            StoreFormat::MessagePack => rmp_serde::to_vec_named(store)?,
+            #[cfg(not(feature = "store-formats"))]
+            StoreFormat::Cbor | StoreFormat::MessagePack => return Err(self.unsupported().into()),
+        })
+    }
+
+    /// Deserialize a store, detecting its format
+    pub fn decode(data: &[u8]) -> Result<ItemStore, Box<dyn std::error::Error>> {
+        Ok(match StoreFormat::detect(data) {
+            Some(StoreFormat::Json | StoreFormat::CompactJson) => serde_json::from_slice(data)?,
+            #[cfg(feature = "store-formats")]
+            Some(StoreFormat::Cbor) => ciborium::from_reader(data)?,
+            #[cfg(feature = "store-formats")]
+            Some(StoreFormat::MessagePack) => rmp_serde::from_slice(data)?,
+            #[cfg(not(feature = "store-formats"))]
+            Some(format @ (StoreFormat::Cbor | StoreFormat::MessagePack)) => {
+                return Err(format.unsupported().into());
+            }
+            None => return Err("unknown store format".into()),
+        })
+    }
+
+    /// Error of the binary formats, when the binary is built without them
+    #[cfg(not(feature = "store-formats"))]
+    fn unsupported(&self) -> String {
+        format!(
+            "the {:?} store format requires the `store-formats` feature",
+            self
+        )
+    }
+}
+
 /// Item store to track item states
 ///
-/// Internally, the store is simply an ordered list of items.
+/// Internally, the store is simply an ordered list of items, plus some
+/// notifier-specific metadata for each item.
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct ItemStore {
     items: Vec<Item>,
+    #[serde(default)]
+    meta: BTreeMap<Uuid, ItemMeta>,
+    /// Events held for later notification (e.g. during quiet hours)
+    #[serde(default)]
+    pending: Vec<EventContext>,
+    /// Time of the last run using this store
+    #[serde(default)]
+    last_run: Option<DateTime<Utc>>,
+    /// Time of the last digest notification
+    #[serde(default)]
+    last_digest: Option<DateTime<Utc>>,
+    /// Entries of the catalog change feeds (oldest first)
+    #[serde(default)]
+    feed: Vec<FeedEntry>,
+    /// Categories left unfetched by the last run (deadline exceeded), fetched first by the next
+    #[serde(default)]
+    unfetched_categories: BTreeSet<Uuid>,
+    /// Categories of the items, as last listed by the API
+    #[serde(default)]
+    categories: BTreeMap<Uuid, CategoryMeta>,
+    /// Hashes of the items of the categories, as last diffed (see [diff::items_hash])
+    #[serde(default)]
+    category_hashes: BTreeMap<Uuid, String>,
+    /// Version of the format of the store (see [STORE_SCHEMA])
+    #[serde(default)]
+    schema: u32,
+    /// URL of the instance the store was created against, unknown for the older stores
+    #[serde(default)]
+    instance_url: Option<String>,
+    /// Latest transactions and refills of the account, as last fetched (see
+    /// [crate::transactions])
+    #[serde(default)]
+    account_activity: Option<AccountActivity>,
+    /// Change tracking, since the store was loaded
+    #[serde(skip)]
+    changes: ChangeTracking,
+}
+
+/// Change tracking of an item store
+///
+/// Every change of the store increments its generation. The generation of the last change
+/// of each item (data or metadata) is also kept, so that consumers remembering a generation
+/// can tell what changed since then.
+#[derive(Debug, Default, Clone)]
+struct ChangeTracking {
+    /// Current generation of the store
+    generation: u64,
+    /// Generation of the last save
+    saved_generation: u64,
+    /// Generation of the last change of each item (including removed ones)
+    items: BTreeMap<Uuid, u64>,
+}
+
+/// Notifier-specific metadata about an item
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemMeta {
+    /// Tags of the item (see [crate::tags])
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Whether the item is a menu made unavailable by one of its components
+    #[serde(default)]
+    pub menu_unavailable: bool,
+    /// Lifecycle state tracking, for debouncing the transitions (see [crate::lifecycle])
+    #[serde(default)]
+    pub lifecycle: StateTracking<Lifecycle>,
+    /// The events of the item are not notified until then (see [crate::mute])
+    #[serde(default)]
+    pub muted_until: Option<DateTime<Utc>>,
+    /// Stock samples, to estimate the sales rate (see [crate::sales])
+    #[serde(default)]
+    pub sales: SalesHistory,
+    /// Price samples, to compare the new prices to (see [crate::prices])
+    #[serde(default)]
+    pub prices: PriceHistory,
+    /// Whether the item was warned about running out of stock soon
+    #[serde(default)]
+    pub stock_out_predicted: bool,
+    /// When the item was last notified, by event type (for the cooldown)
+    #[serde(default)]
+    pub last_notified: BTreeMap<String, DateTime<Utc>>,
+    /// When the item was last listed by the API (see [ItemStore::mark_seen])
+    #[serde(default)]
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Name of the category of the item, when it was last listed
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Last messages notifying the item, by target and event type, as identified by their
+    /// provider (for later edits or deletions)
+    #[serde(default)]
+    pub messages: Vec<MessageRef>,
+}
+
+/// A message notifying an item, as identified by the provider of its target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRef {
+    /// Key of the target (see [crate::config::TargetConfig::key])
+    pub target: String,
+    /// Type of the notified event (see [crate::event::ItemEvent::kind])
+    pub event: String,
+    /// Identifier of the message given by the provider
+    pub message_id: String,
+    /// Time of the message
+    pub at: DateTime<Utc>,
+}
+
+/// Why a store cannot be diffed against an instance (see [ItemStore::mismatch])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreMismatch {
+    /// The store was created against another instance
+    Instance { stored: String, configured: String },
+    /// The store has a format newer than this version
+    Schema { stored: u32 },
+}
+
+impl std::fmt::Display for StoreMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreMismatch::Instance { stored, configured } => write!(
+                f,
+                "the store was created against {}, not {}",
+                stored, configured
+            ),
+            StoreMismatch::Schema { stored } => write!(
+                f,
+                "the store format {} is newer than the supported one ({})",
+                stored, STORE_SCHEMA
+            ),
+        }
+    }
+}
+
+/// Metadata about a category of items
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CategoryMeta {
+    /// Display name of the category
+    pub name: String,
+    /// Position of the category, as ordered in the bar UI
+    pub position: i64,
+}
+
+impl ItemMeta {
+    /// Check if an event of the given type can be notified at `now`, not having been notified
+    /// within the `cooldown`, and record it as notified if so
+    pub fn try_notify(&mut self, kind: &str, now: DateTime<Utc>, cooldown: Duration) -> bool {
+        if let Some(last) = self.last_notified.get(kind)
+            && clock::elapsed(*last, now) < cooldown
+        {
+            return false;
+        }
+        self.last_notified.insert(kind.to_string(), now);
+        true
+    }
+
+    /// Record a message notifying the item, replacing the previous one of the same target and
+    /// event type
+    pub fn record_message(&mut self, message: MessageRef) {
+        self.messages
+            .retain(|m| m.target != message.target || m.event != message.event);
+        self.messages.push(message);
+    }
+
+    /// Get the last message notifying the item of the given event type on a target, if any
+    pub fn last_message(&self, target: &str, event: &str) -> Option<&MessageRef> {
+        self.messages
+            .iter()
+            .find(|m| m.target == target && m.event == event)
+    }
+
+    /// Check if the item is muted at the given time
+    pub fn is_muted(&self, now: DateTime<Utc>) -> bool {
+        self.muted_until.is_some_and(|until| now < until)
+    }
 }
 
 impl ItemStore {
     /// Create a new, empty ItemStore
     pub fn new() -> Self {
-        ItemStore { items: Vec::new() }
+        ItemStore {
+            items: Vec::new(),
+            meta: BTreeMap::new(),
+            pending: Vec::new(),
+            last_run: None,
+            last_digest: None,
+            feed: Vec::new(),
+            unfetched_categories: BTreeSet::new(),
+            categories: BTreeMap::new(),
+            category_hashes: BTreeMap::new(),
+            schema: STORE_SCHEMA,
+            instance_url: None,
+            account_activity: None,
+            changes: ChangeTracking::default(),
+        }
     }
 
     /// Get a reference to the internal list of items
@@ -26,9 +360,420 @@ impl ItemStore {
         &self.items
     }
 
+    /// Get the number of items in the store
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Check if the store contains no item
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Iterate over the items of the store (ordered by ID)
+    pub fn iter(&self) -> std::slice::Iter<'_, Item> {
+        self.items.iter()
+    }
+
+    /// Remove an item (and its metadata) from the store by its ID
+    pub fn remove(&mut self, item_id: Uuid) -> Option<Item> {
+        let index = self
+            .items
+            .binary_search_by_key(&item_id, |item| item.id)
+            .ok()?;
+        self.touch_item(item_id);
+        self.meta.remove(&item_id);
+        Some(self.items.remove(index))
+    }
+
+    /// Only keep the items matching the predicate, removing the others (and their metadata)
+    pub fn retain(&mut self, mut f: impl FnMut(&Item) -> bool) {
+        let mut removed = Vec::new();
+        self.items.retain(|item| {
+            let keep = f(item);
+            if !keep {
+                removed.push(item.id);
+            }
+            keep
+        });
+        for id in removed {
+            self.touch_item(id);
+            self.meta.remove(&id);
+        }
+    }
+
     /// Clear the item store
     pub fn clear(&mut self) {
+        let ids: Vec<Uuid> = self.items.iter().map(|item| item.id).collect();
+        for id in ids {
+            self.touch_item(id);
+        }
         self.items.clear();
+        self.meta.clear();
+        self.pending.clear();
+        self.category_hashes.clear();
+        self.touch();
+    }
+
+    /// Reset the store to an empty one, e.g. to bootstrap it again after a mismatch
+    pub fn reset(&mut self) {
+        self.clear();
+        self.last_run = None;
+        self.last_digest = None;
+        self.feed.clear();
+        self.unfetched_categories.clear();
+        self.categories.clear();
+        self.instance_url = None;
+        self.account_activity = None;
+    }
+
+    /// Check if the store can be diffed against the instance at `instance_url`
+    ///
+    /// An empty store matches any instance, as does a store of an unknown instance (created
+    /// before the instances were recorded).
+    pub fn mismatch(&self, instance_url: &str) -> Option<StoreMismatch> {
+        if self.items.is_empty() {
+            return None;
+        }
+        if let Some(stored) = &self.instance_url
+            && stored.trim_end_matches('/') != instance_url.trim_end_matches('/')
+        {
+            return Some(StoreMismatch::Instance {
+                stored: stored.clone(),
+                configured: instance_url.to_string(),
+            });
+        }
+        if self.schema > STORE_SCHEMA {
+            return Some(StoreMismatch::Schema {
+                stored: self.schema,
+            });
+        }
+        None
+    }
+
+    /// Record the instance the store is diffed against, in the current format
+    pub fn bind(&mut self, instance_url: &str) {
+        if self.instance_url.as_deref() != Some(instance_url) || self.schema != STORE_SCHEMA {
+            self.instance_url = Some(instance_url.to_string());
+            self.schema = STORE_SCHEMA;
+            self.touch();
+        }
+    }
+
+    /// Get the current generation of the store, incremented by every change
+    ///
+    /// Bookkeeping of the runs (see [ItemStore::record_run]) is not a change.
+    pub fn generation(&self) -> u64 {
+        self.changes.generation
+    }
+
+    /// Check if the store changed since it was loaded or last saved
+    pub fn is_dirty(&self) -> bool {
+        self.changes.generation != self.changes.saved_generation
+    }
+
+    /// Mark the store as saved (not dirty)
+    pub fn mark_saved(&mut self) {
+        self.changes.saved_generation = self.changes.generation;
+    }
+
+    /// Get the IDs of the items that changed (data or metadata) after the given generation
+    ///
+    /// Removed items are included, they are no longer found in the store.
+    pub fn changed_since(&self, generation: u64) -> BTreeSet<Uuid> {
+        self.changes
+            .items
+            .iter()
+            .filter(|(_, changed)| **changed > generation)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Get the IDs of the items that changed since the store was loaded or last saved
+    pub fn dirty_items(&self) -> BTreeSet<Uuid> {
+        self.changed_since(self.changes.saved_generation)
+    }
+
+    /// Record a change of the store
+    fn touch(&mut self) {
+        self.changes.generation += 1;
+    }
+
+    /// Record a change of an item
+    fn touch_item(&mut self, item_id: Uuid) {
+        self.touch();
+        self.changes.items.insert(item_id, self.changes.generation);
+    }
+
+    /// Get the time of the last run using this store
+    pub fn last_run(&self) -> Option<DateTime<Utc>> {
+        self.last_run
+    }
+
+    /// Record a new run at `now`
+    ///
+    /// If the clock went backwards since the last run, the persisted timestamps are
+    /// brought back to `now` (so that no negative duration is computed from them),
+    /// and `true` is returned.
+    pub fn record_run(&mut self, now: DateTime<Utc>) -> bool {
+        let skewed = self.last_run.is_some_and(|last_run| last_run > now);
+        if skewed {
+            for meta in self.meta.values_mut() {
+                meta.lifecycle.clamp_to_now(now);
+                meta.sales.clamp_to_now(now);
+                meta.prices.clamp_to_now(now);
+                for last in meta.last_notified.values_mut() {
+                    *last = clock::clamp_to_now(*last, now);
+                }
+                meta.last_seen = meta.last_seen.map(|last| clock::clamp_to_now(last, now));
+            }
+            self.touch();
+        }
+        self.last_run = Some(now);
+        skewed
+    }
+
+    /// Record that an item is listed by the API at `now`
+    ///
+    /// Like [ItemStore::record_run], this is bookkeeping: the item is only marked changed once
+    /// a day, so that an unchanged store is not saved after every poll just for it. The
+    /// persisted time may thus lag by a day.
+    pub fn mark_seen(&mut self, item_id: Uuid, now: DateTime<Utc>) {
+        let meta = self.meta.entry(item_id).or_default();
+        let stale = meta
+            .last_seen
+            .is_none_or(|last| clock::elapsed(last, now) >= Duration::days(1));
+        meta.last_seen = Some(now);
+        if stale {
+            self.touch_item(item_id);
+        }
+    }
+
+    /// Prune the items (and their metadata) not listed by the API for more than `age`, except
+    /// the `spared` ones (e.g. of a category which could not be fetched)
+    ///
+    /// Items never seen since their last seen time is tracked are considered seen at `now`,
+    /// so they are pruned after `age` too. The pruned items are returned.
+    pub fn prune_older_than(
+        &mut self,
+        age: Duration,
+        now: DateTime<Utc>,
+        spared: impl Fn(&Item) -> bool,
+    ) -> Vec<Item> {
+        let mut unknown = Vec::new();
+        for item in &self.items {
+            let meta = self.meta.entry(item.id).or_default();
+            if meta.last_seen.is_none() {
+                meta.last_seen = Some(now);
+                unknown.push(item.id);
+            }
+        }
+        for id in unknown {
+            self.touch_item(id);
+        }
+        let mut pruned = Vec::new();
+        let meta = &self.meta;
+        self.items.retain(|item| {
+            let keep = spared(item)
+                || meta
+                    .get(&item.id)
+                    .and_then(|meta| meta.last_seen)
+                    .is_none_or(|last| clock::elapsed(last, now) <= age);
+            if !keep {
+                pruned.push(item.clone());
+            }
+            keep
+        });
+        for item in &pruned {
+            self.touch_item(item.id);
+            self.meta.remove(&item.id);
+        }
+        pruned
+    }
+
+    /// Get the time of the last digest notification
+    pub fn last_digest(&self) -> Option<DateTime<Utc>> {
+        self.last_digest
+    }
+
+    /// Record a digest notification at `now`
+    pub fn record_digest(&mut self, now: DateTime<Utc>) {
+        self.last_digest = Some(now);
+        self.touch();
+    }
+
+    /// Hold events for later notification
+    pub fn hold_events(&mut self, events: impl IntoIterator<Item = EventContext>) {
+        let len = self.pending.len();
+        self.pending.extend(events);
+        if self.pending.len() != len {
+            self.touch();
+        }
+    }
+
+    /// Take all the held events, leaving none in the store
+    pub fn take_pending_events(&mut self) -> Vec<EventContext> {
+        if !self.pending.is_empty() {
+            self.touch();
+        }
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Get the entries of the catalog change feeds (oldest first)
+    pub fn feed(&self) -> &[FeedEntry] {
+        &self.feed
+    }
+
+    /// Record new feed entries, keeping at most `max_per_category` entries in each category
+    pub fn record_feed_entries(
+        &mut self,
+        entries: impl IntoIterator<Item = FeedEntry>,
+        max_per_category: usize,
+    ) {
+        let len = self.feed.len();
+        self.feed.extend(entries);
+        if self.feed.len() == len {
+            return;
+        }
+        self.touch();
+        // Count the entries of each category from the most recent one, dropping the oldest ones
+        let mut counts: BTreeMap<Uuid, usize> = BTreeMap::new();
+        let mut keep: Vec<bool> = self
+            .feed
+            .iter()
+            .rev()
+            .map(|entry| {
+                let count = counts.entry(entry.category_id).or_default();
+                *count += 1;
+                *count <= max_per_category
+            })
+            .collect();
+        keep.reverse();
+        let mut keep = keep.into_iter();
+        self.feed.retain(|_| keep.next().unwrap_or(true));
+    }
+
+    /// Get the categories left unfetched by the last run, to fetch first
+    pub fn unfetched_categories(&self) -> &BTreeSet<Uuid> {
+        &self.unfetched_categories
+    }
+
+    /// Record the categories left unfetched by a run (none if it fetched them all)
+    pub fn set_unfetched_categories(&mut self, categories: BTreeSet<Uuid>) {
+        if self.unfetched_categories != categories {
+            self.unfetched_categories = categories;
+            self.touch();
+        }
+    }
+
+    /// Record the categories listed by the API, replacing the previous ones
+    pub fn set_categories(&mut self, categories: &[Category]) {
+        let categories: BTreeMap<Uuid, CategoryMeta> = categories
+            .iter()
+            .map(|c| {
+                (
+                    c.id,
+                    CategoryMeta {
+                        name: c.name.clone(),
+                        position: c.position,
+                    },
+                )
+            })
+            .collect();
+        if self.categories != categories {
+            self.categories = categories;
+            self.touch();
+        }
+    }
+
+    /// Get the latest transactions and refills of the account, as last fetched (none before
+    /// the first fetch)
+    pub fn account_activity(&self) -> Option<&AccountActivity> {
+        self.account_activity.as_ref()
+    }
+
+    /// Record the latest transactions and refills of the account
+    pub fn set_account_activity(&mut self, activity: AccountActivity) {
+        if self.account_activity.as_ref() != Some(&activity) {
+            self.account_activity = Some(activity);
+            self.touch();
+        }
+    }
+
+    /// Get the hash of the items of a category, as last diffed
+    pub fn category_hash(&self, category_id: Uuid) -> Option<&str> {
+        self.category_hashes.get(&category_id).map(String::as_str)
+    }
+
+    /// Record the hash of the items of a category, once diffed
+    pub fn set_category_hash(&mut self, category_id: Uuid, hash: String) {
+        if self.category_hashes.get(&category_id) != Some(&hash) {
+            self.category_hashes.insert(category_id, hash);
+            self.touch();
+        }
+    }
+
+    /// Check if a lifecycle transition of an item is pending (e.g. being debounced)
+    pub fn is_settling(&self, item_id: Uuid) -> bool {
+        self.meta(item_id)
+            .is_some_and(|meta| meta.lifecycle.is_pending())
+    }
+
+    /// Get the metadata of a category by its ID, if it is known
+    pub fn category(&self, category_id: Uuid) -> Option<&CategoryMeta> {
+        self.categories.get(&category_id)
+    }
+
+    /// Iterate over the known categories, by ID
+    pub fn categories(&self) -> impl Iterator<Item = (Uuid, &CategoryMeta)> {
+        self.categories.iter().map(|(id, category)| (*id, category))
+    }
+
+    /// Order events by the position of the category of their item, as in the bar UI
+    ///
+    /// The events of the unknown categories come last, and the order of the events of the same
+    /// category is kept.
+    pub fn sort_by_category(&self, events: &mut [EventContext]) {
+        events.sort_by_key(|ctx| {
+            self.category(ctx.item.category_id)
+                .map_or(i64::MAX, |category| category.position)
+        });
+    }
+
+    /// Get the metadata of an item by its ID
+    pub fn meta(&self, item_id: Uuid) -> Option<&ItemMeta> {
+        self.meta.get(&item_id)
+    }
+
+    /// Get a mutable reference to the metadata of an item, creating it if needed
+    ///
+    /// The item is marked changed, prefer [ItemStore::update_meta] to only do so on changes.
+    pub fn meta_mut(&mut self, item_id: Uuid) -> &mut ItemMeta {
+        self.touch_item(item_id);
+        self.meta.entry(item_id).or_default()
+    }
+
+    /// Update the metadata of an item (created if needed), marking it changed on changes
+    pub fn update_meta<R>(&mut self, item_id: Uuid, f: impl FnOnce(&mut ItemMeta) -> R) -> R {
+        let meta = self.meta.entry(item_id).or_default();
+        let before = meta.clone();
+        let result = f(meta);
+        if *meta != before {
+            self.touch_item(item_id);
+        }
+        result
+    }
+
+    /// Check if an item is muted at the given time
+    pub fn is_muted(&self, item_id: Uuid, now: DateTime<Utc>) -> bool {
+        self.meta(item_id).is_some_and(|m| m.is_muted(now))
+    }
+
+    /// Get the tags of an item by its ID (empty if unknown)
+    pub fn tags(&self, item_id: Uuid) -> BTreeSet<String> {
+        self.meta(item_id)
+            .map(|m| m.tags.clone())
+            .unwrap_or_default()
     }
 
     /// Append an item to the store
@@ -41,6 +786,7 @@ impl ItemStore {
             }
             Err(index) => {
                 // Item does not exist, insert it
+                self.touch_item(item.id);
                 self.items.insert(index, item);
                 true
             }
@@ -57,25 +803,283 @@ impl ItemStore {
     }
 
     /// Find a mutable reference to an item by its ID
+    ///
+    /// The item is marked changed if it is found, prefer [ItemStore::replace]
+    /// to only do so on changes.
     pub fn find_mut(&mut self, item_id: Uuid) -> Option<&mut Item> {
         match self.items.binary_search_by_key(&item_id, |item| item.id) {
-            Ok(index) => Some(&mut self.items[index]),
+            Ok(index) => {
+                self.touch_item(item_id);
+                Some(&mut self.items[index])
+            }
             Err(_) => None,
         }
     }
 
-    /// Replace an item in the store by its ID
+    /// Get the components of a menu that are currently unavailable
+    ///
+    /// A component is unavailable if it is unbuyable or out of stock. Components
+    /// unknown to the store are ignored.
+    pub fn unavailable_components(&self, menu: &Item) -> Vec<&Item> {
+        diff::unavailable_components(menu, |id| self.find(id))
+    }
+
+    /// Replace an item in the store by its ID (it is only marked changed if it differs)
+    #[allow(clippy::result_unit_err)]
     pub fn replace(&mut self, new_item: Item) -> Result<Item, ()> {
         match self
             .items
             .binary_search_by_key(&new_item.id, |item| item.id)
         {
             Ok(index) => {
-                let old_item = self.items[index].clone();
-                self.items[index] = new_item;
+                let old_item = std::mem::replace(&mut self.items[index], new_item);
+                if old_item != self.items[index] {
+                    self.touch_item(old_item.id);
+                }
                 Ok(old_item)
             }
             Err(_) => Err(()),
         }
     }
 }
+
+impl<'a> IntoIterator for &'a ItemStore {
+    type Item = &'a Item;
+    type IntoIter = std::slice::Iter<'a, Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl IntoIterator for ItemStore {
+    type Item = Item;
+    type IntoIter = std::vec::IntoIter<Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{ItemPrices, ItemState};
+
+    use super::*;
+
+    fn item(n: u128) -> Item {
+        Item::new(
+            Uuid::from_u128(n),
+            ItemPrices::new(100, 100, 100, 100, 100, 150),
+            5,
+            10,
+            Uuid::from_u128(0),
+            format!("Item {}", n),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        )
+    }
+
+    #[test]
+    fn removes_the_items_and_their_metadata() {
+        let mut store = ItemStore::new();
+        for n in 1..=4 {
+            store.append(item(n));
+            store
+                .meta_mut(Uuid::from_u128(n))
+                .tags
+                .insert("beer".to_string());
+        }
+        let generation = store.generation();
+
+        assert_eq!(store.remove(Uuid::from_u128(1)), Some(item(1)));
+        assert_eq!(store.remove(Uuid::from_u128(1)), None);
+        assert!(store.meta(Uuid::from_u128(1)).is_none());
+        assert_eq!(
+            store.changed_since(generation),
+            BTreeSet::from([Uuid::from_u128(1)])
+        );
+
+        let generation = store.generation();
+        store.retain(|item| item.id != Uuid::from_u128(3));
+        assert_eq!(store.len(), 2);
+        assert!(store.find(Uuid::from_u128(3)).is_none());
+        assert!(store.meta(Uuid::from_u128(3)).is_none());
+        assert!(store.meta(Uuid::from_u128(2)).is_some());
+        assert_eq!(
+            store.changed_since(generation),
+            BTreeSet::from([Uuid::from_u128(3)])
+        );
+    }
+
+    #[test]
+    fn stores_round_trip_in_every_format() {
+        let mut store = ItemStore::new();
+        store.append(item(1));
+        store.append(item(2));
+        store.bind("https://bar.example.org");
+        store.mark_seen(Uuid::from_u128(1), DateTime::UNIX_EPOCH);
+        for format in [
+            StoreFormat::Json,
+            StoreFormat::CompactJson,
+            StoreFormat::Cbor,
+            StoreFormat::MessagePack,
+        ] {
+            let data = format.encode(&store).unwrap();
+            let detected = StoreFormat::detect(&data).unwrap();
+            match format {
+                StoreFormat::CompactJson => assert_eq!(detected, StoreFormat::Json),
+                _ => assert_eq!(detected, format),
+            }
+            let decoded = StoreFormat::decode(&data).unwrap();
+            assert_eq!(decoded.items(), store.items());
+            assert_eq!(
+                decoded.meta(Uuid::from_u128(1)).unwrap().last_seen,
+                Some(DateTime::UNIX_EPOCH)
+            );
+            assert_eq!(decoded.mismatch("https://bar.example.org"), None);
+        }
+        assert!(StoreFormat::decode(b"[]").is_err());
+    }
+
+    #[test]
+    fn detects_the_stores_of_other_instances() {
+        let mut store = ItemStore::new();
+        assert_eq!(store.mismatch("https://bar.example.org"), None);
+        store.append(item(1));
+        // Unknown instance (older store)
+        assert_eq!(store.mismatch("https://bar.example.org"), None);
+
+        store.bind("https://bar.example.org/");
+        assert_eq!(store.mismatch("https://bar.example.org"), None);
+        assert_eq!(
+            store.mismatch("https://other.example.org"),
+            Some(StoreMismatch::Instance {
+                stored: "https://bar.example.org/".to_string(),
+                configured: "https://other.example.org".to_string(),
+            })
+        );
+
+        store.schema = STORE_SCHEMA + 1;
+        assert_eq!(
+            store.mismatch("https://bar.example.org/"),
+            Some(StoreMismatch::Schema {
+                stored: STORE_SCHEMA + 1
+            })
+        );
+
+        store.set_account_activity(AccountActivity::default());
+        store.reset();
+        assert!(store.is_empty() && store.last_run().is_none());
+        // The history of the account belongs to the previous instance too
+        assert!(store.account_activity().is_none());
+        assert_eq!(store.mismatch("https://other.example.org"), None);
+    }
+
+    #[test]
+    fn prunes_the_stale_items() {
+        let now = DateTime::UNIX_EPOCH + Duration::days(365);
+        let mut store = ItemStore::new();
+        for n in 1..=3 {
+            store.append(item(n));
+        }
+        store.mark_seen(Uuid::from_u128(1), now - Duration::days(100));
+        store.mark_seen(Uuid::from_u128(2), now - Duration::days(10));
+        // Item 3 was never seen: kept, and considered seen now
+
+        let pruned = store.prune_older_than(Duration::days(30), now, |_| false);
+        assert_eq!(pruned, vec![item(1)]);
+        assert!(store.meta(Uuid::from_u128(1)).is_none());
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store
+                .meta(Uuid::from_u128(3))
+                .and_then(|meta| meta.last_seen),
+            Some(now)
+        );
+
+        let later = now + Duration::days(31);
+        let spared = |item: &Item| item.id == Uuid::from_u128(2);
+        assert_eq!(
+            store.prune_older_than(Duration::days(30), later, spared),
+            vec![item(3)]
+        );
+        assert_eq!(
+            store
+                .prune_older_than(Duration::days(30), later, |_| false)
+                .len(),
+            1
+        );
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_last_message_of_each_target_and_event() {
+        let message = |target: &str, event: &str, id: &str| MessageRef {
+            target: target.to_string(),
+            event: event.to_string(),
+            message_id: id.to_string(),
+            at: DateTime::UNIX_EPOCH,
+        };
+        let mut meta = ItemMeta::default();
+        meta.record_message(message("a", "out_of_stock", "1"));
+        meta.record_message(message("b", "out_of_stock", "2"));
+        meta.record_message(message("a", "become_buyable", "3"));
+        meta.record_message(message("a", "out_of_stock", "4"));
+
+        assert_eq!(meta.messages.len(), 3);
+        assert_eq!(
+            meta.last_message("a", "out_of_stock").unwrap().message_id,
+            "4"
+        );
+        assert_eq!(
+            meta.last_message("b", "out_of_stock").unwrap().message_id,
+            "2"
+        );
+        assert!(meta.last_message("b", "become_buyable").is_none());
+    }
+
+    #[test]
+    fn orders_the_events_by_category_position() {
+        let mut store = ItemStore::new();
+        store.set_categories(&[
+            Category::new(
+                Uuid::from_u128(10),
+                "Snacks".into(),
+                String::new(),
+                2,
+                false,
+                false,
+            ),
+            Category::new(
+                Uuid::from_u128(20),
+                "Beers".into(),
+                String::new(),
+                1,
+                false,
+                false,
+            ),
+        ]);
+        let event = |n: u128, category: u128| EventContext {
+            event: crate::event::ItemEvent::OutOfStock,
+            item: Item {
+                category_id: Uuid::from_u128(category),
+                ..item(n)
+            },
+            category_name: String::new(),
+            tags: BTreeSet::new(),
+            restricted: false,
+            sales_rate: None,
+            price: None,
+            severity: Default::default(),
+            correction: None,
+        };
+        let mut events = vec![event(1, 30), event(2, 10), event(3, 20), event(4, 10)];
+        store.sort_by_category(&mut events);
+
+        let order: Vec<u128> = events.iter().map(|ctx| ctx.item.id.as_u128()).collect();
+        assert_eq!(order, vec![3, 2, 4, 1]);
+        assert_eq!(store.category(Uuid::from_u128(20)).unwrap().name, "Beers");
+    }
+}
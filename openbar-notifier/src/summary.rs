@@ -0,0 +1,145 @@
+//! Run summaries of OpenBar Notifier
+//!
+//! At the end of each check of an instance, a summary (categories and items scanned, events
+//! emitted, API errors, categories unchanged, failed or left for the next check, undelivered messages, duration) is logged and, if configured, sent to the summary targets
+//! (e.g. an "ops" channel), so that the operators can verify the notifier is actually working
+//! even on quiet days.
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::runner::CheckOutcome;
+
+/// Statistics of a check of an instance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunStats {
+    /// Categories scanned
+    pub categories: usize,
+    /// Items scanned
+    pub items: usize,
+    /// Item events emitted (i.e. passed to the targets or held for later)
+    pub events: usize,
+    /// Failed API requests
+    pub api_errors: usize,
+    /// Categories left unfetched, the deadline of the check being exceeded
+    pub unfetched_categories: usize,
+    /// Categories whose items could not be fetched, their stored items keeping their last known
+    /// state
+    pub failed_categories: usize,
+    /// Categories whose items did not change since the previous check, their diff being skipped
+    pub unchanged_categories: usize,
+    /// Messages the targets failed to accept (or timed out on)
+    pub undelivered_messages: usize,
+    /// Messages rejected for good by their target (e.g. a revoked webhook), among the
+    /// undelivered ones
+    pub rejected_messages: usize,
+}
+
+/// Summary of a check of an instance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Name of the instance
+    pub instance: String,
+    /// Outcome of the check
+    pub outcome: CheckOutcome,
+    /// Statistics of the check
+    pub stats: RunStats,
+    /// Duration of the check
+    pub duration: Duration,
+}
+
+impl RunSummary {
+    /// Render the summary as a notification message
+    pub fn render(&self) -> String {
+        format!("**Run summary of {}**\n{}\n", self.instance, self)
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} categories, {} items scanned, {} events emitted, {} API errors in {:.1}s",
+            self.outcome,
+            self.stats.categories,
+            self.stats.items,
+            self.stats.events,
+            self.stats.api_errors,
+            self.duration.as_secs_f64()
+        )?;
+        if self.stats.unchanged_categories > 0 {
+            write!(
+                f,
+                " ({} categories unchanged)",
+                self.stats.unchanged_categories
+            )?;
+        }
+        if self.stats.failed_categories > 0 {
+            write!(
+                f,
+                " (warning: {} categories could not be fetched, their items are stale)",
+                self.stats.failed_categories
+            )?;
+        }
+        if self.stats.unfetched_categories > 0 {
+            write!(
+                f,
+                " (deadline exceeded, {} categories left for the next run)",
+                self.stats.unfetched_categories
+            )?;
+        }
+        if self.stats.undelivered_messages > 0 {
+            write!(
+                f,
+                " ({} messages undelivered, {} rejected)",
+                self.stats.undelivered_messages, self.stats.rejected_messages
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_the_summary() {
+        let summary = RunSummary {
+            instance: "bar".to_string(),
+            outcome: CheckOutcome::Failed,
+            stats: RunStats {
+                categories: 4,
+                items: 52,
+                events: 3,
+                api_errors: 1,
+                unfetched_categories: 0,
+                failed_categories: 0,
+                unchanged_categories: 0,
+                undelivered_messages: 0,
+                rejected_messages: 0,
+            },
+            duration: Duration::from_millis(2345),
+        };
+        assert_eq!(
+            summary.render(),
+            "**Run summary of bar**\nfailed: 4 categories, 52 items scanned, 3 events emitted, 1 API errors in 2.3s\n"
+        );
+
+        let truncated = RunSummary {
+            outcome: CheckOutcome::Ok,
+            stats: RunStats {
+                unfetched_categories: 2,
+                failed_categories: 1,
+                unchanged_categories: 1,
+                undelivered_messages: 1,
+                ..summary.stats
+            },
+            ..summary
+        };
+        assert_eq!(
+            truncated.to_string(),
+            "ok: 4 categories, 52 items scanned, 3 events emitted, 1 API errors in 2.3s (1 categories unchanged) (warning: 1 categories could not be fetched, their items are stale) (deadline exceeded, 2 categories left for the next run) (1 messages undelivered, 0 rejected)"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Item tags for OpenBar Notifier
+//!
+//! OpenBar does not expose free-form tags on its items, so tags are derived here:
+//! some come from the item attributes (menu, promotion, buy limit), the others from
+//! user-defined rules (e.g. tag "alcohol" for all the items of the "Beers" category).
+//! Tags are stored alongside the items and can be used to route notifications.
+
+use std::collections::BTreeSet;
+
+use openbar_api::models::Item;
+
+use crate::pattern::ItemPattern;
+
+/// Tag given to menus
+pub const TAG_MENU: &str = "menu";
+/// Tag given to items currently in promotion
+pub const TAG_PROMOTION: &str = "promotion";
+/// Tag given to items with a buy limit
+pub const TAG_LIMITED: &str = "limited";
+
+/// A user-defined tagging rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagRule {
+    /// Tag to apply (lowercase)
+    pub tag: String,
+    /// The tag is applied if any of these patterns matches the item
+    pub patterns: Vec<ItemPattern>,
+}
+
+/// Compute the tags of an item, from its attributes and the given rules
+pub fn item_tags(item: &Item, category_name: &str, rules: &[TagRule]) -> BTreeSet<String> {
+    let mut tags = BTreeSet::new();
+    if item.is_menu {
+        tags.insert(TAG_MENU.to_string());
+    }
+    if item.promotion.is_some() {
+        tags.insert(TAG_PROMOTION.to_string());
+    }
+    if item.buy_limit.is_some() {
+        tags.insert(TAG_LIMITED.to_string());
+    }
+    for rule in rules {
+        if rule.patterns.iter().any(|p| p.matches(item, category_name)) {
+            tags.insert(rule.tag.clone());
+        }
+    }
+    tags
+}
@@ -0,0 +1,279 @@
+//! Message themes of OpenBar Notifier
+//!
+//! A theme is a bundle of message templates shipped with the crate, selectable per target
+//! (e.g. `theme=emoji`). Templates use the following placeholders:
+//! - `{name}`, `{id}` and `{category}`: the item name, ID and category name;
+//! - `{stock}` and `{price}`: the stock left and the (member) price of the item;
+//! - `{bundle}` and `{buy_limit}`: the units per bundle of the item and its maximal units per
+//!   purchase, empty if not exposed by the API;
+//! - `{details}`: the bundle and purchase limit as a list continuing the previous one (e.g.
+//!   `, 24 per bundle, max 2 per purchase`), empty if not exposed by the API;
+//! - `{components}`: the components of a menu (all of them when its composition changed,
+//!   the unavailable ones when it became unavailable);
+//! - `{discount}`: the discount of a promotion (e.g. `30%`);
+//! - `{price_history}`: how the price of a promotion compares to the history of the item, as a
+//!   list continuing the previous one (e.g. `, lowest price ever, 25% below the 30-day
+//!   average`), empty if there is nothing to tell (see [crate::prices]);
+//! - `{eta}`: the projected time until a stock-out (e.g. `~5 hours`);
+//! - `{dropped}`: the number of units gone since the previous poll (stock drops);
+//! - `{severity}`: the severity of the event (`info`, `warning` or `critical`);
+//! - `{changes}`: the events of an item grouped into a single line (see [crate::layout]), e.g.
+//!   `is buyable again and was restocked to 24`.
+//!
+//! The static variables of the target (e.g. `channel`, `bar`, `footer`) are also available,
+//! so that one theme can serve several channels with slightly different wording. The header
+//! and footer of the messages are only rendered when all the variables they use are defined.
+//!
+//! The templates are written in English, and translated into the language of the messages
+//! (see [crate::i18n]).
+
+use std::collections::BTreeMap;
+
+use crate::i18n;
+
+/// Static variables of a target, by name
+pub type Variables = BTreeMap<String, String>;
+
+/// Message theme of a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Theme {
+    /// One plain line per event, with the item ID
+    #[default]
+    Default,
+    /// Emoji-heavy, for community chats
+    Emoji,
+    /// As short as possible
+    Minimal,
+    /// Complete sentences, for official announcements
+    Formal,
+}
+
+/// Templates of a theme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThemeTemplates {
+    /// An item was added
+    pub added: &'static str,
+    /// An item became buyable
+    pub become_buyable: &'static str,
+    /// An item became unbuyable
+    pub become_unbuyable: &'static str,
+    /// An item is out of stock
+    pub out_of_stock: &'static str,
+    /// An item is low on stock
+    pub low_stock: &'static str,
+    /// An item is no longer listed
+    pub removed: &'static str,
+    /// The composition of a menu changed
+    pub menu_changed: &'static str,
+    /// A menu became unavailable
+    pub menu_unavailable: &'static str,
+    /// A promotion started on an item
+    pub promotion_started: &'static str,
+    /// The promotion of an item ended
+    pub promotion_ended: &'static str,
+    /// An item is projected to run out of stock soon
+    pub predicted_stock_out: &'static str,
+    /// The stock of an item dropped sharply since the previous poll
+    pub stock_dropped: &'static str,
+    /// The stock of an item changed since the previous poll
+    pub stock_changed: &'static str,
+    /// Several events of an item, grouped into a single line
+    pub grouped: &'static str,
+    /// Heading of the events held during quiet hours
+    pub held_heading: &'static str,
+    /// Heading of the new events, following the held ones
+    pub new_heading: &'static str,
+    /// Header of the messages
+    pub header: &'static str,
+    /// Footer of the messages
+    pub footer: &'static str,
+}
+
+const DEFAULT: ThemeTemplates = ThemeTemplates {
+    added: "- {name} ({id}) added (price: {price}{details}).\n",
+    become_buyable: "- {name} ({id}) became buyable (stock: {stock}, price: {price}{details}).\n",
+    become_unbuyable: "- {name} ({id}) became unbuyable.\n",
+    out_of_stock: "- {name} ({id}) is out of stock.\n",
+    low_stock: "- {name} ({id}) is low on stock (stock: {stock}).\n",
+    removed: "- {name} ({id}) removed.\n",
+    menu_changed: "- {name} ({id}) menu composition changed: {components}.\n",
+    menu_unavailable: "- {name} ({id}) is unavailable (unavailable components: {components}).\n",
+    promotion_started: "- {name} ({id}) is on promotion (-{discount}{price_history}).\n",
+    promotion_ended: "- {name} ({id}) promotion ended.\n",
+    predicted_stock_out: "- {name} ({id}) will run out of stock in {eta} (stock: {stock}).\n",
+    stock_dropped: "- {name} ({id}) stock dropped by {dropped} (stock: {stock}).\n",
+    stock_changed: "- {name} ({id}) stock changed by {change} (stock: {stock}).\n",
+    grouped: "- {name} ({id}) {changes}.\n",
+    held_heading: "Held during quiet hours:\n",
+    new_heading: "New:\n",
+    header: "**{bar}**\n",
+    footer: "{footer}\n",
+};
+
+const EMOJI: ThemeTemplates = ThemeTemplates {
+    added: "🆕 **{name}** just landed in {category}, for {price}!\n",
+    become_buyable: "✅ **{name}** is back! {stock} left at {price}{details} 🎉\n",
+    become_unbuyable: "⛔ **{name}** can't be bought anymore\n",
+    out_of_stock: "😢 **{name}** is sold out\n",
+    low_stock: "📉 **{name}** is running low, only {stock} left\n",
+    removed: "👋 **{name}** left the menu\n",
+    menu_changed: "🔄 **{name}** now comes with {components}\n",
+    menu_unavailable: "🚫 **{name}** is unavailable (missing: {components})\n",
+    promotion_started: "🏷️ **{name}** is {discount} off{price_history}! 🤑\n",
+    promotion_ended: "⌛ **{name}** is back to its regular price\n",
+    predicted_stock_out: "⏳ **{name}** is going fast, only {stock} left ({eta} to go)\n",
+    stock_dropped: "🏃 **{name}** is flying off the shelves: {dropped} gone, {stock} left\n",
+    stock_changed: "📦 **{name}**: {change}, {stock} left\n",
+    grouped: "📣 **{name}** {changes}!\n",
+    held_heading: "🌙 While you were sleeping:\n",
+    new_heading: "✨ Fresh news:\n",
+    header: "🍻 **{bar}** 🍻\n",
+    footer: "_{footer}_\n",
+};
+
+const MINIMAL: ThemeTemplates = ThemeTemplates {
+    added: "+ {name} ({price})\n",
+    become_buyable: "↑ {name} ({stock})\n",
+    become_unbuyable: "↓ {name}\n",
+    out_of_stock: "0 {name}\n",
+    low_stock: "↘ {name} ({stock})\n",
+    removed: "- {name}\n",
+    menu_changed: "~ {name}: {components}\n",
+    menu_unavailable: "! {name}: {components}\n",
+    promotion_started: "% {name} -{discount}\n",
+    promotion_ended: "% {name} ended\n",
+    predicted_stock_out: "⏳ {name} {eta}\n",
+    stock_dropped: "↓↓ {name} -{dropped} ({stock})\n",
+    stock_changed: "± {name} {change} ({stock})\n",
+    grouped: "* {name}: {changes}\n",
+    held_heading: "Held:\n",
+    new_heading: "New:\n",
+    header: "{bar}:\n",
+    footer: "{footer}\n",
+};
+
+const FORMAL: ThemeTemplates = ThemeTemplates {
+    added: "The item \"{name}\" ({category}) has been added to the catalog, at a price of {price}{details}.\n",
+    become_buyable: "The item \"{name}\" is available again: {stock} units are in stock, at a price of {price}{details}.\n",
+    become_unbuyable: "The item \"{name}\" is no longer available for purchase.\n",
+    out_of_stock: "The item \"{name}\" is out of stock.\n",
+    low_stock: "The item \"{name}\" is low on stock, with {stock} units left.\n",
+    removed: "The item \"{name}\" has been removed from the catalog.\n",
+    menu_changed: "The composition of the menu \"{name}\" has changed, it now includes: {components}.\n",
+    menu_unavailable: "The menu \"{name}\" is currently unavailable, due to the following items: {components}.\n",
+    promotion_started: "The item \"{name}\" is on promotion, with a discount of {discount}{price_history}.\n",
+    promotion_ended: "The promotion on the item \"{name}\" has ended.\n",
+    predicted_stock_out: "The item \"{name}\" is expected to run out of stock in {eta}, with {stock} units left.\n",
+    stock_dropped: "The stock of the item \"{name}\" dropped by {dropped} units, with {stock} units left.\n",
+    stock_changed: "The stock of the item \"{name}\" changed by {change} units, with {stock} units left.\n",
+    grouped: "The item \"{name}\" {changes}.\n",
+    held_heading: "The following changes occurred during the quiet hours:\n",
+    new_heading: "The following changes occurred since:\n",
+    header: "Announcement from {bar}:\n",
+    footer: "--\n{footer}\n",
+};
+
+impl ThemeTemplates {
+    /// Get the templates translated into the given language (e.g. `fr`)
+    pub fn localized(&self, language: &str) -> ThemeTemplates {
+        let tr = |template| i18n::translate(language, template);
+        ThemeTemplates {
+            added: tr(self.added),
+            become_buyable: tr(self.become_buyable),
+            become_unbuyable: tr(self.become_unbuyable),
+            out_of_stock: tr(self.out_of_stock),
+            low_stock: tr(self.low_stock),
+            removed: tr(self.removed),
+            menu_changed: tr(self.menu_changed),
+            menu_unavailable: tr(self.menu_unavailable),
+            promotion_started: tr(self.promotion_started),
+            promotion_ended: tr(self.promotion_ended),
+            predicted_stock_out: tr(self.predicted_stock_out),
+            stock_dropped: tr(self.stock_dropped),
+            stock_changed: tr(self.stock_changed),
+            grouped: tr(self.grouped),
+            held_heading: tr(self.held_heading),
+            new_heading: tr(self.new_heading),
+            header: tr(self.header),
+            footer: tr(self.footer),
+        }
+    }
+}
+
+impl Theme {
+    /// Get the templates of the theme (in English)
+    pub fn templates(&self) -> &'static ThemeTemplates {
+        match self {
+            Theme::Default => &DEFAULT,
+            Theme::Emoji => &EMOJI,
+            Theme::Minimal => &MINIMAL,
+            Theme::Formal => &FORMAL,
+        }
+    }
+}
+
+impl std::str::FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "default" => Ok(Theme::Default),
+            "emoji" => Ok(Theme::Emoji),
+            "minimal" => Ok(Theme::Minimal),
+            "formal" => Ok(Theme::Formal),
+            _ => Err(format!(
+                "unknown theme '{}' (expected default, emoji, minimal or formal)",
+                s
+            )),
+        }
+    }
+}
+
+/// Fill the placeholders of a template with the given values, then the target variables
+///
+/// Unknown placeholders are left as-is.
+pub fn fill(template: &str, values: &[(&str, &str)], variables: &Variables) -> String {
+    render(template, values, variables, false).unwrap_or_default()
+}
+
+/// Fill the placeholders of a template, only if they are all known
+pub fn try_fill(template: &str, values: &[(&str, &str)], variables: &Variables) -> Option<String> {
+    render(template, values, variables, true)
+}
+
+/// Fill the placeholders of a template, failing on unknown ones if `strict`
+fn render(
+    template: &str,
+    values: &[(&str, &str)],
+    variables: &Variables,
+    strict: bool,
+) -> Option<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let key = &after[..end];
+            values
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| *v)
+                .or_else(|| variables.get(key).map(String::as_str))
+                .map(|v| (v, end))
+        });
+        match value {
+            Some((value, end)) => {
+                output.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None if strict => return None,
+            None => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    Some(output)
+}
@@ -0,0 +1,174 @@
+//! Account transactions monitoring of OpenBar Notifier
+//!
+//! To follow the spending of a shared card (e.g. for parents or treasurers), the latest
+//! transactions and refills of the logged-in account are fetched after each check, and the new
+//! purchases and refills are notified to the transaction targets (the ones of the instance by
+//! default). The IDs of the latest finished transactions and valid refills are kept in the
+//! store: the first check only records them, without notifying the history of the account.
+
+use std::collections::BTreeSet;
+
+use openbar_api::models::{Refill, RefillState, Transaction, TransactionState};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::render::Formatting;
+
+/// Latest transactions and refills of an account, as last fetched
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountActivity {
+    /// IDs of the finished transactions
+    #[serde(default)]
+    pub transactions: BTreeSet<Uuid>,
+    /// IDs of the valid refills
+    #[serde(default)]
+    pub refills: BTreeSet<Uuid>,
+}
+
+impl AccountActivity {
+    /// Get the activity of an account from its latest transactions and refills
+    ///
+    /// Only the finished transactions and the valid refills are kept, so that a transaction
+    /// still in progress is notified once finished.
+    pub fn of(transactions: &[Transaction], refills: &[Refill]) -> Self {
+        AccountActivity {
+            transactions: transactions
+                .iter()
+                .filter(|t| t.state == TransactionState::TransactionFinished)
+                .map(|t| t.id)
+                .collect(),
+            refills: refills
+                .iter()
+                .filter(|r| r.state == RefillState::RefillValid)
+                .map(|r| r.id)
+                .collect(),
+        }
+    }
+
+    /// Get the transactions and refills of `latest` not in this activity, oldest first
+    pub fn new_since<'a>(
+        &self,
+        latest: &Self,
+        transactions: &'a [Transaction],
+        refills: &'a [Refill],
+    ) -> (Vec<&'a Transaction>, Vec<&'a Refill>) {
+        let mut transactions: Vec<&Transaction> = transactions
+            .iter()
+            .filter(|t| latest.transactions.contains(&t.id) && !self.transactions.contains(&t.id))
+            .collect();
+        transactions.sort_by_key(|t| t.created_at);
+        let mut refills: Vec<&Refill> = refills
+            .iter()
+            .filter(|r| latest.refills.contains(&r.id) && !self.refills.contains(&r.id))
+            .collect();
+        refills.sort_by_key(|r| r.issued_at);
+        (transactions, refills)
+    }
+}
+
+/// Render a purchase of the account, e.g. `New purchase on bar: 2 × Chouffe (€5.00)`
+pub fn render_transaction(
+    instance: &str,
+    transaction: &Transaction,
+    formatting: &Formatting,
+) -> String {
+    let items: Vec<String> = transaction
+        .items
+        .iter()
+        .map(|item| format!("{} × {}", item.item_amount, item.item_name))
+        .collect();
+    let author = transaction
+        .author_name
+        .as_ref()
+        .map(|author| format!(", served by {}", author))
+        .unwrap_or_default();
+    format!(
+        "**New purchase on {}**: {} ({}{})",
+        instance,
+        items.join(", "),
+        formatting.format_price(transaction.total_cost),
+        author
+    )
+}
+
+/// Render a refill of the account, e.g. `New refill on bar: €20.00 (card)`
+pub fn render_refill(instance: &str, refill: &Refill, formatting: &Formatting) -> String {
+    let kind = refill
+        .r#type
+        .map(|kind| format!(" ({})", kind))
+        .unwrap_or_default();
+    format!(
+        "**New refill on {}**: {}{}",
+        instance,
+        formatting.format_price(refill.amount),
+        kind
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{RefillType, TransactionItem};
+
+    use super::*;
+
+    fn transaction(n: u128, state: TransactionState, created_at: i64) -> Transaction {
+        let item = TransactionItem::new(
+            Uuid::from_u128(100),
+            "Chouffe".to_string(),
+            2,
+            250,
+            500,
+            state,
+        );
+        Transaction::new(
+            Uuid::from_u128(n),
+            Uuid::nil(),
+            vec![item],
+            500,
+            state,
+            created_at,
+        )
+    }
+
+    #[test]
+    fn notifies_the_new_purchases_and_refills() {
+        let transactions = vec![transaction(1, TransactionState::TransactionFinished, 10)];
+        let refills = vec![Refill::new(
+            Uuid::from_u128(2),
+            Uuid::nil(),
+            2000,
+            RefillState::RefillValid,
+            5,
+        )];
+        let previous = AccountActivity::of(&transactions, &refills);
+
+        let mut refill = Refill::new(
+            Uuid::from_u128(5),
+            Uuid::nil(),
+            1000,
+            RefillState::RefillValid,
+            30,
+        );
+        refill.r#type = Some(RefillType::RefillCard);
+        let transactions = vec![
+            transaction(4, TransactionState::TransactionStarted, 40),
+            transaction(3, TransactionState::TransactionFinished, 20),
+            transaction(1, TransactionState::TransactionFinished, 10),
+        ];
+        let refills = vec![refill, refills[0].clone()];
+        let latest = AccountActivity::of(&transactions, &refills);
+        let (new_transactions, new_refills) = previous.new_since(&latest, &transactions, &refills);
+        assert_eq!(new_transactions, [&transactions[1]]);
+        assert_eq!(new_refills, [&refills[0]]);
+
+        let formatting = Formatting::default();
+        assert_eq!(
+            render_transaction("bar", new_transactions[0], &formatting),
+            "**New purchase on bar**: 2 × Chouffe (€5.00)"
+        );
+        assert_eq!(
+            render_refill("bar", new_refills[0], &formatting),
+            "**New refill on bar**: €10.00 (card)"
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Interactive dashboard of OpenBar Notifier (`openbar-notifier tui`)
+//!
+//! For the operators logged into the box, the dashboard gives a quick overview: for each
+//! instance, the items of its store, its recent item events and the countdown to its next
+//! check, along with the health of the targets (their last delivery attempt). It reads the
+//! [Dashboard] from the HTTP API of a running daemon (`--url`, see `GET /status`), or from a
+//! runner checking the instances in-process.
+//!
+//! Keys: `←`/`→` (or `Tab`) switch the instance, `↑`/`↓` scroll the items, `q` (or `Esc`) quits.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table, TableState, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+use url::Url;
+
+use crate::delivery::DeliveryLog;
+use crate::status::{Dashboard, InstanceStatus, StatusBoard};
+
+/// Interval between two refreshes of the dashboard
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval between two reads of the keyboard
+const INPUT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Source of the dashboard
+pub enum DashboardSource {
+    /// The HTTP API of a running daemon
+    Remote {
+        http: reqwest::Client,
+        /// Base URL of the HTTP API (e.g. `http://127.0.0.1:8080`)
+        url: Url,
+    },
+    /// A runner checking the instances in-process
+    Local {
+        status: Arc<StatusBoard>,
+        deliveries: Arc<DeliveryLog>,
+    },
+}
+
+impl DashboardSource {
+    /// Get the current dashboard
+    pub async fn fetch(&self) -> Result<Dashboard, String> {
+        match self {
+            DashboardSource::Remote { http, url } => {
+                let url = url.join("status").map_err(|e| e.to_string())?;
+                http.get(url)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| e.to_string())?
+                    .json()
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            DashboardSource::Local { status, deliveries } => Ok(status.dashboard(deliveries)),
+        }
+    }
+}
+
+/// State of the dashboard
+#[derive(Default)]
+struct App {
+    dashboard: Dashboard,
+    /// Error of the last refresh, if it failed
+    error: Option<String>,
+    /// Index of the shown instance
+    selected: usize,
+    /// Index of the highlighted item
+    item: usize,
+}
+
+impl App {
+    fn instance(&self) -> Option<&InstanceStatus> {
+        self.dashboard.instances.get(self.selected)
+    }
+
+    /// Handle a key press, returns `false` to quit
+    fn on_key(&mut self, key: KeyCode) -> bool {
+        let instances = self.dashboard.instances.len().max(1);
+        let items = self.instance().map_or(0, |status| status.items.len());
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Right | KeyCode::Tab => {
+                self.selected = (self.selected + 1) % instances;
+                self.item = 0;
+            }
+            KeyCode::Left | KeyCode::BackTab => {
+                self.selected = (self.selected + instances - 1) % instances;
+                self.item = 0;
+            }
+            KeyCode::Down => self.item = (self.item + 1).min(items.saturating_sub(1)),
+            KeyCode::Up => self.item = self.item.saturating_sub(1),
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Show the dashboard in the terminal, until the operator quits
+pub async fn run(source: DashboardSource) -> std::io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, &source).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_app(terminal: &mut DefaultTerminal, source: &DashboardSource) -> std::io::Result<()> {
+    let mut app = App::default();
+    let mut last_refresh: Option<Instant> = None;
+    loop {
+        if last_refresh.is_none_or(|at| at.elapsed() >= REFRESH_INTERVAL) {
+            match source.fetch().await {
+                Ok(dashboard) => {
+                    app.dashboard = dashboard;
+                    app.error = None;
+                }
+                Err(e) => app.error = Some(e),
+            }
+            app.selected = app
+                .selected
+                .min(app.dashboard.instances.len().saturating_sub(1));
+            last_refresh = Some(Instant::now());
+        }
+        terminal.draw(|frame| draw(frame, &app, Utc::now()))?;
+        // Let the in-process runner progress between the reads of the keyboard
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !app.on_key(key.code)
+            {
+                return Ok(());
+            }
+        }
+        tokio::time::sleep(INPUT_INTERVAL).await;
+    }
+}
+
+fn draw(frame: &mut Frame, app: &App, now: DateTime<Utc>) {
+    let [tabs, body, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let names = app
+        .dashboard
+        .instances
+        .iter()
+        .map(|status| status.instance.clone());
+    frame.render_widget(
+        Tabs::new(names)
+            .select(app.selected)
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .block(Block::new().borders(Borders::ALL).title(" Instances ")),
+        tabs,
+    );
+
+    let [items, side] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(body);
+    let [check, events, targets] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Min(0),
+        Constraint::Length(app.dashboard.targets.len().clamp(1, 8) as u16 + 2),
+    ])
+    .areas(side);
+    match app.instance() {
+        Some(status) => {
+            draw_items(frame, status, app.item, items);
+            draw_check(frame, status, now, check);
+            draw_events(frame, status, events);
+        }
+        None => frame.render_widget(
+            Paragraph::new("No instance checked yet.")
+                .block(Block::new().borders(Borders::ALL).title(" Items ")),
+            items,
+        ),
+    }
+    draw_targets(frame, app, targets);
+
+    let help = match &app.error {
+        Some(error) => Line::styled(
+            format!("Error refreshing the dashboard: {}", error),
+            Style::new().fg(Color::Red),
+        ),
+        None => Line::raw("←/→ instance · ↑/↓ items · q quit"),
+    };
+    frame.render_widget(Paragraph::new(help), footer);
+}
+
+fn draw_items(frame: &mut Frame, status: &InstanceStatus, highlighted: usize, area: Rect) {
+    let rows = status.items.iter().map(|item| {
+        let style = if !item.buyable || item.amount_left <= 0 {
+            Style::new().fg(Color::DarkGray)
+        } else {
+            Style::new()
+        };
+        Row::new([
+            item.name.clone(),
+            item.category.clone(),
+            item.amount_left.to_string(),
+            if item.buyable { "yes" } else { "no" }.to_string(),
+        ])
+        .style(style)
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Percentage(45),
+            Constraint::Percentage(35),
+            Constraint::Length(6),
+            Constraint::Length(8),
+        ],
+    )
+    .header(
+        Row::new(["Item", "Category", "Left", "Buyable"])
+            .style(Style::new().add_modifier(Modifier::BOLD)),
+    )
+    .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+    .block(
+        Block::new()
+            .borders(Borders::ALL)
+            .title(format!(" Items ({}) ", status.items.len())),
+    );
+    let mut state = TableState::default().with_selected(Some(highlighted));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn draw_check(frame: &mut Frame, status: &InstanceStatus, now: DateTime<Utc>, area: Rect) {
+    let last = match (status.last_check, &status.outcome) {
+        (Some(at), Some(outcome)) => format!(
+            "{} ({} ago)",
+            outcome,
+            format_duration((now - at).num_seconds())
+        ),
+        _ => "never".to_string(),
+    };
+    let next = match status.next_check {
+        Some(at) => countdown(at, now),
+        None => "not scheduled".to_string(),
+    };
+    let lines = vec![
+        Line::from(vec![Span::raw("Last check: "), Span::raw(last)]),
+        Line::from(vec![Span::raw("Next check: "), Span::raw(next)]),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::new().borders(Borders::ALL).title(" Check ")),
+        area,
+    );
+}
+
+fn draw_events(frame: &mut Frame, status: &InstanceStatus, area: Rect) {
+    let events: Vec<ListItem> = status
+        .events
+        .iter()
+        .map(|event| {
+            ListItem::new(format!(
+                "{} {} {}",
+                event.at.format("%H:%M:%S"),
+                event.item,
+                event.event
+            ))
+        })
+        .collect();
+    frame.render_widget(
+        List::new(events).block(Block::new().borders(Borders::ALL).title(" Recent events ")),
+        area,
+    );
+}
+
+fn draw_targets(frame: &mut Frame, app: &App, area: Rect) {
+    let targets: Vec<ListItem> = app
+        .dashboard
+        .targets
+        .iter()
+        .map(|target| {
+            let color = if target.healthy {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled("● ", Style::new().fg(color)),
+                Span::raw(format!("{} ({})", target.target, target.status)),
+            ]))
+        })
+        .collect();
+    frame.render_widget(
+        List::new(targets).block(Block::new().borders(Borders::ALL).title(" Targets ")),
+        area,
+    );
+}
+
+/// Render the time left until the next check
+fn countdown(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (at - now).num_seconds();
+    if secs <= 0 {
+        "due".to_string()
+    } else {
+        format!("in {}", format_duration(secs))
+    }
+}
+
+/// Render a duration in seconds, e.g. `1h 05m`, `2m 30s` or `12s`
+fn format_duration(secs: i64) -> String {
+    let secs = secs.max(0);
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    #[test]
+    fn counts_down_to_the_next_check() {
+        let now = DateTime::UNIX_EPOCH;
+        assert_eq!(countdown(now + TimeDelta::seconds(12), now), "in 12s");
+        assert_eq!(countdown(now + TimeDelta::seconds(150), now), "in 2m 30s");
+        assert_eq!(countdown(now + TimeDelta::seconds(3900), now), "in 1h 05m");
+        assert_eq!(countdown(now - TimeDelta::seconds(5), now), "due");
+    }
+}
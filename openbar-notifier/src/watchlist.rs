@@ -0,0 +1,218 @@
+//! Watchlists of OpenBar Notifier
+//!
+//! Watch rules give some items their own event subscriptions and thresholds, instead of the
+//! global ones, e.g. to follow every stock change of the house beer while only the stock-outs
+//! of the other items are notified:
+//!
+//! ```toml
+//! [notify]
+//! on_out_of_stock = true
+//!
+//! [[notify.watchlist]]
+//! items = ["Chouffe"]
+//! events = ["stock_changed", "out_of_stock"]
+//!
+//! [[notify.watchlist]]
+//! items = ["Kegs"]
+//! events = ["low_stock", "stock_dropped"]
+//! low_stock_ratio = 0.3
+//! stock_drop_amount = 5
+//! ```
+//!
+//! The first rule matching an item applies to it. The events of the watched items are notified
+//! whatever the include/exclude filters (the muted items stay muted).
+
+use openbar_api::models::Item;
+use serde::Deserialize;
+
+use crate::diff::StockDropConfig;
+use crate::event::ItemEvent;
+use crate::pattern::ItemPattern;
+
+/// A watch rule, giving some items their own event subscriptions and thresholds
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchRule {
+    /// Watched items
+    pub items: Vec<ItemPattern>,
+    /// Types of the events notified for the watched items (e.g. `stock_changed`), the globally
+    /// enabled ones if empty
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Low-stock ratio of the watched items, instead of the global one (and of the special
+    /// events)
+    #[serde(default)]
+    pub low_stock_ratio: Option<f64>,
+    /// Minimal stock drop of the watched items, as a percentage of their previous stock
+    #[serde(default)]
+    pub stock_drop_percent: Option<f64>,
+    /// Minimal stock drop of the watched items, in units
+    #[serde(default)]
+    pub stock_drop_amount: Option<i64>,
+}
+
+impl WatchRule {
+    /// Parse a watch rule from its textual representation
+    ///
+    /// The format is a whitespace-separated list of options:
+    /// - `items=a+b` (required): watch the items matching the patterns `a` or `b` (see
+    ///   [ItemPattern])
+    /// - `events=a+b`: only notify the events of type `a` or `b` (e.g. `stock_changed`)
+    /// - `low_stock_ratio=<ratio>`, `stock_drop_percent=<percent>`, `stock_drop_amount=<units>`:
+    ///   thresholds of the watched items
+    ///
+    /// For instance: `items=Chouffe events=stock_changed+out_of_stock`
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut rule = WatchRule {
+            items: Vec::new(),
+            events: Vec::new(),
+            low_stock_ratio: None,
+            stock_drop_percent: None,
+            stock_drop_amount: None,
+        };
+        for option in s.split_whitespace() {
+            let list = |list: &str| -> Vec<String> {
+                list.split('+')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            };
+            match option.split_once('=') {
+                Some(("items", patterns)) => {
+                    rule.items = list(patterns)
+                        .iter()
+                        .map(|p| ItemPattern::parse(p))
+                        .collect::<Result<_, _>>()
+                        .map_err(|e| format!("invalid pattern: {}", e))?
+                }
+                Some(("events", events)) => {
+                    rule.events = list(events).into_iter().map(|e| e.to_lowercase()).collect()
+                }
+                Some(("low_stock_ratio", ratio)) => {
+                    rule.low_stock_ratio = Some(parse_number(option, ratio)?)
+                }
+                Some(("stock_drop_percent", percent)) => {
+                    rule.stock_drop_percent = Some(parse_number(option, percent)?)
+                }
+                Some(("stock_drop_amount", amount)) => {
+                    rule.stock_drop_amount = Some(parse_number(option, amount)?)
+                }
+                _ => return Err(format!("unknown watch rule option '{}'", option)),
+            }
+        }
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Check that the rule watches some items, that its event types exist and that its
+    /// thresholds are valid
+    pub fn validate(&self) -> Result<(), String> {
+        if self.items.is_empty() {
+            return Err("no watched items, set `items`".to_string());
+        }
+        if let Some(event) = self
+            .events
+            .iter()
+            .find(|e| !ItemEvent::KINDS.contains(&e.as_str()))
+        {
+            return Err(format!(
+                "unknown event type '{}' (expected one of {})",
+                event,
+                ItemEvent::KINDS.join(", ")
+            ));
+        }
+        if self
+            .low_stock_ratio
+            .is_some_and(|ratio| !(0.0..=1.0).contains(&ratio))
+        {
+            return Err("the low-stock ratio is not between 0 and 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// Check if the rule watches an item (in the given category)
+    pub fn matches(&self, item: &Item, category_name: &str) -> bool {
+        self.items.iter().any(|p| p.matches(item, category_name))
+    }
+
+    /// Check if an event of the watched items is notified, given whether it is globally enabled
+    pub fn is_event_notified(&self, event: &ItemEvent, enabled: bool) -> bool {
+        if self.events.is_empty() {
+            enabled
+        } else {
+            self.events.iter().any(|e| e == event.kind())
+        }
+    }
+
+    /// Get the stock drop thresholds of the watched items, given the global ones
+    pub fn stock_drop(&self, global: &StockDropConfig) -> StockDropConfig {
+        StockDropConfig {
+            percent: self.stock_drop_percent.unwrap_or(global.percent),
+            amount: self.stock_drop_amount.unwrap_or(global.amount),
+            severity: global.severity,
+        }
+    }
+}
+
+/// Parse the number of a watch rule option
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number in watch rule option '{}'", option))
+}
+
+/// Find the watch rule of an item (in the given category): the first one matching it
+pub fn find<'a>(rules: &'a [WatchRule], item: &Item, category_name: &str) -> Option<&'a WatchRule> {
+    rules.iter().find(|rule| rule.matches(item, category_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use openbar_api::models::{ItemPrices, ItemState};
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn item(name: &str) -> Item {
+        Item::new(
+            Uuid::from_u128(1),
+            ItemPrices::new(100, 100, 100, 100, 100, 150),
+            0,
+            10,
+            Uuid::from_u128(0),
+            name.to_string(),
+            String::new(),
+            false,
+            ItemState::ItemBuyable,
+        )
+    }
+
+    #[test]
+    fn applies_the_first_matching_rule() {
+        let rules = vec![
+            WatchRule::parse("items=Chouffe events=stock_changed+out_of_stock").unwrap(),
+            WatchRule::parse("items=Ch* stock_drop_amount=5 low_stock_ratio=0.5").unwrap(),
+        ];
+        let chouffe = find(&rules, &item("Chouffe"), "Beers").unwrap();
+        let change = ItemEvent::StockChanged { change: -1 };
+        assert!(chouffe.is_event_notified(&change, false));
+        assert!(!chouffe.is_event_notified(&ItemEvent::LowStock, true));
+
+        let chips = find(&rules, &item("Chips"), "Snacks").unwrap();
+        assert!(chips.is_event_notified(&ItemEvent::LowStock, true));
+        assert!(!chips.is_event_notified(&change, false));
+        let global = StockDropConfig {
+            percent: 50.0,
+            ..StockDropConfig::default()
+        };
+        assert_eq!(chips.stock_drop(&global).amount, 5);
+        assert_eq!(chips.stock_drop(&global).percent, 50.0);
+        assert_eq!(chips.low_stock_ratio, Some(0.5));
+
+        assert!(find(&rules, &item("Soda"), "Softs").is_none());
+        assert!(WatchRule::parse("events=stock_changed").is_err());
+        assert!(WatchRule::parse("items=Chouffe events=sold_out").is_err());
+        assert!(WatchRule::parse("items=Chouffe low_stock_ratio=2").is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Read-only web dashboard of the daemon mode
+//!
+//! A minimal page, meant to be left open on a display of the bar: for each instance, the items
+//! of its store and their stock, its recent item events and the outcome of its last check,
+//! along with the health of the targets (see [crate::status]). The page has no script, it simply
+//! refreshes itself every [REFRESH_SECS] seconds.
+//!
+//! The feed targets (see [crate::feed]) are also served, by file name: `GET /feeds/<file name>`
+//! (e.g. `/feeds/bar.xml` for `file:///srv/bar.xml`).
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use askama::Template;
+use axum::Router;
+use axum::extract::{Path, State};
+use axum::http::{StatusCode, header};
+use axum::response::{Html, IntoResponse};
+use axum::routing::get;
+use chrono::{DateTime, Utc};
+use log::error;
+
+use crate::config::GlobalConfig;
+use crate::delivery::DeliveryLog;
+use crate::payload::PayloadFormat;
+use crate::status::{Dashboard, StatusBoard, TargetHealth};
+
+/// Interval between two refreshes of the page
+pub const REFRESH_SECS: u64 = 30;
+
+/// Shared state of the web dashboard
+#[derive(Debug, Clone, Default)]
+pub struct WebState {
+    /// Audit log of the delivery attempts, for the health of the targets
+    pub deliveries: Arc<DeliveryLog>,
+    /// Live status of the instances, updated by the runner
+    pub status: Arc<StatusBoard>,
+    /// Files of the feed targets (and their format), by file name
+    pub feeds: BTreeMap<String, (PathBuf, PayloadFormat)>,
+}
+
+/// Get the files of the feed targets of a configuration (and their format), by file name
+///
+/// When several feeds have the same file name, the first one is served.
+pub fn feeds(config: &GlobalConfig) -> BTreeMap<String, (PathBuf, PayloadFormat)> {
+    let mut feeds = BTreeMap::new();
+    for target in config.instances.iter().flat_map(|i| &i.targets) {
+        if !target.format.is_feed() {
+            continue;
+        }
+        if let Some(path) = target.file_path()
+            && let Some(name) = path.file_name()
+        {
+            feeds
+                .entry(name.to_string_lossy().into_owned())
+                .or_insert((path, target.format));
+        }
+    }
+    feeds
+}
+
+/// An item of a store, as shown on the page
+struct ItemView {
+    name: String,
+    category: String,
+    amount_left: i64,
+    /// CSS class of the row (`out` when unbuyable or out of stock)
+    class: &'static str,
+}
+
+/// A recent item event, as shown on the page
+struct EventView {
+    at: String,
+    item: String,
+    event: String,
+}
+
+/// An instance, as shown on the page
+struct InstanceView {
+    name: String,
+    last_check: String,
+    outcome: String,
+    /// CSS class of the outcome (`ok` or `failed`)
+    class: &'static str,
+    next_check: String,
+    items: Vec<ItemView>,
+    events: Vec<EventView>,
+}
+
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+struct DashboardPage {
+    refresh_secs: u64,
+    generated: String,
+    instances: Vec<InstanceView>,
+    targets: Vec<TargetHealth>,
+}
+
+/// Format a time for the page, `-` if unknown
+fn format_time(at: Option<DateTime<Utc>>) -> String {
+    at.map(|at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl DashboardPage {
+    fn new(dashboard: Dashboard, now: DateTime<Utc>) -> Self {
+        let instances = dashboard
+            .instances
+            .into_iter()
+            .map(|status| InstanceView {
+                class: match status.outcome.as_deref() {
+                    Some("ok" | "skipped") | None => "ok",
+                    Some(_) => "failed",
+                },
+                outcome: status.outcome.unwrap_or_else(|| "-".to_string()),
+                last_check: format_time(status.last_check),
+                next_check: format_time(status.next_check),
+                items: status
+                    .items
+                    .into_iter()
+                    .map(|item| ItemView {
+                        class: if item.buyable && item.amount_left > 0 {
+                            ""
+                        } else {
+                            "out"
+                        },
+                        name: item.name,
+                        category: item.category,
+                        amount_left: item.amount_left,
+                    })
+                    .collect(),
+                events: status
+                    .events
+                    .into_iter()
+                    .map(|event| EventView {
+                        at: format_time(Some(event.at)),
+                        item: event.item,
+                        event: event.event.replace('_', " "),
+                    })
+                    .collect(),
+                name: status.instance,
+            })
+            .collect();
+        DashboardPage {
+            refresh_secs: REFRESH_SECS,
+            generated: format_time(Some(now)),
+            instances,
+            targets: dashboard.targets,
+        }
+    }
+}
+
+/// Build the router of the web dashboard
+pub fn router(state: WebState) -> Router {
+    Router::new()
+        .route("/", get(get_dashboard))
+        .route("/feeds/{name}", get(get_feed))
+        .with_state(state)
+}
+
+/// Serve the web dashboard on the given address, until the `shutdown` future completes
+pub async fn serve(
+    bind: SocketAddr,
+    state: WebState,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+/// Render the dashboard page
+pub fn render(dashboard: Dashboard, now: DateTime<Utc>) -> Result<String, askama::Error> {
+    DashboardPage::new(dashboard, now).render()
+}
+
+/// `GET /`
+async fn get_dashboard(State(state): State<WebState>) -> Result<Html<String>, StatusCode> {
+    let dashboard = state.status.dashboard(&state.deliveries);
+    render(dashboard, Utc::now()).map(Html).map_err(|e| {
+        error!("Error rendering the web dashboard: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// `GET /feeds/{name}`
+async fn get_feed(
+    State(state): State<WebState>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let (path, format) = state.feeds.get(&name).ok_or(StatusCode::NOT_FOUND)?;
+    let content_type = match format {
+        PayloadFormat::Rss => "application/rss+xml; charset=utf-8",
+        _ => "application/atom+xml; charset=utf-8",
+    };
+    match tokio::fs::read(path).await {
+        Ok(feed) => Ok(([(header::CONTENT_TYPE, content_type)], feed)),
+        // Nothing notified yet
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Error reading the feed {:?}: {}", path, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::CheckOutcome;
+
+    #[test]
+    fn renders_the_dashboard() {
+        let board = StatusBoard::default();
+        board.record_check("<kfet>", DateTime::UNIX_EPOCH, CheckOutcome::Failed, None);
+        let page = render(
+            board.dashboard(&DeliveryLog::default()),
+            DateTime::UNIX_EPOCH,
+        )
+        .unwrap();
+        // The names are escaped
+        assert!(!page.contains("<kfet>"));
+        assert!(page.contains("kfet"));
+        assert!(page.contains("1970-01-01 00:00:00 UTC"));
+        assert!(page.contains("class=\"failed\""));
+        assert!(page.contains(&format!("content=\"{}\"", REFRESH_SECS)));
+    }
+}
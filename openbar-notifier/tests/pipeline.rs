@@ -0,0 +1,453 @@
+//! End-to-end test of the fetch-diff-notify pipeline, against a mock OpenBar instance
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::DateTime;
+use openbar_api::models::{Transaction, TransactionItem, TransactionState};
+use openbar_notifier::clock::TestClock;
+
+use openbar_notifier::config::{
+    GlobalConfig, InstanceConfig, LoginMethod, OpenBarConfig, TargetConfig,
+};
+use openbar_notifier::error::NotifierError;
+use openbar_notifier::fixtures::{self, ChangePattern, FixtureOptions};
+use openbar_notifier::journal;
+use openbar_notifier::maintenance::{self, MaintenanceWindow};
+use openbar_notifier::mock::{MOCK_SESSION, MockOpenBar};
+use openbar_notifier::runner::Runner;
+use openbar_notifier::schedule::DigestFrequency;
+use uuid::Uuid;
+
+/// Start a mock instance, and get a configuration notifying its changes
+async fn setup(name: &str) -> (MockOpenBar, PathBuf, GlobalConfig) {
+    let fixtures = fixtures::generate(&FixtureOptions {
+        categories: 2,
+        items_per_category: 3,
+        snapshots: 2,
+        change_rate: 1.0,
+        pattern: ChangePattern::Restock,
+        seed: 42,
+    });
+    let mock = MockOpenBar::start(fixtures).await.unwrap();
+    let dir =
+        std::env::temp_dir().join(format!("openbar-notifier-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut config = GlobalConfig::default();
+    config.notify.item_added = true;
+    config.notify.become_buyable = true;
+    config.notify.rate_limit.per_minute = 0;
+    config.webconfig_cache.ttl_secs = 0;
+    config.instances.push(InstanceConfig {
+        name: "mock".to_string(),
+        openbar: OpenBarConfig {
+            instance_url: mock.url(),
+            card_id: "card".to_string(),
+            pin: "0000".to_string(),
+        },
+        targets: vec![TargetConfig::parse(&mock.webhook_url()).unwrap()],
+        store_file: dir.join("item_store.json"),
+        webconfig_cache_file: dir.join("webconfig.json"),
+    });
+    (mock, dir, config)
+}
+
+#[tokio::test]
+async fn notifies_changes_between_snapshots() {
+    let (mock, dir, config) = setup("pipeline").await;
+    let mut runner = Runner::new(config.clone()).unwrap();
+
+    // First check: every item is new
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert_eq!(messages.matches(" added ").count(), 6, "{}", messages);
+    assert!(dir.join("item_store.json").exists());
+
+    // Overlapping run: the store is locked, the instance is skipped
+    let mut overlapping = Runner::new(config).unwrap();
+    let result = overlapping.run_once().await;
+    assert!(matches!(result, Err(NotifierError::Store { .. })));
+    assert!(mock.take_messages().is_empty());
+
+    // Second check: the items are restocked
+    assert!(mock.advance());
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert!(messages.contains("became buyable"), "{}", messages);
+    assert!(!messages.contains(" added "), "{}", messages);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn dry_run_targets_are_never_notified() {
+    let (mock, dir, mut config) = setup("dry-run").await;
+    let canary = dir.join("canary.jsonl");
+    config.instances[0].targets = vec![
+        TargetConfig::parse(&format!(
+            "{} dry_run_file={}",
+            mock.webhook_url(),
+            canary.display()
+        ))
+        .unwrap(),
+    ];
+    let mut runner = Runner::new(config).unwrap();
+
+    // The messages are rendered into the file, but never sent
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+    let written = std::fs::read_to_string(&canary).unwrap();
+    assert_eq!(written.matches(" added ").count(), 6, "{}", written);
+    assert!(written.lines().all(|line| line.starts_with("{\"at\":")));
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn feed_targets_keep_the_recent_events() {
+    let (mock, dir, mut config) = setup("feed").await;
+    let feed = dir.join("bar.xml");
+    let url = url::Url::from_file_path(&feed).unwrap();
+    config.instances[0]
+        .targets
+        .push(TargetConfig::parse(&format!("{} format=atom", url)).unwrap());
+    let mut runner = Runner::new(config).unwrap();
+
+    runner.run_once().await.unwrap();
+    let written = std::fs::read_to_string(&feed).unwrap();
+    assert!(written.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+    assert_eq!(written.matches("<entry>").count(), 6, "{}", written);
+
+    // The feed keeps the previous entries
+    assert!(mock.advance());
+    runner.run_once().await.unwrap();
+    let written = std::fs::read_to_string(&feed).unwrap();
+    assert!(written.matches("<entry>").count() > 6, "{}", written);
+    assert!(written.contains("became buyable"), "{}", written);
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn jsonl_targets_append_the_events() {
+    let (_mock, dir, mut config) = setup("jsonl").await;
+    let file = dir.join("events.jsonl");
+    let url = url::Url::from_file_path(&file).unwrap();
+    config.instances[0].targets =
+        vec![TargetConfig::parse(&format!("{} format=jsonl rotate_size=100000", url)).unwrap()];
+    let mut runner = Runner::new(config).unwrap();
+
+    // One journal entry per event
+    runner.run_once().await.unwrap();
+    let entries = journal::read(&file, None, None).unwrap();
+    assert_eq!(entries.len(), 6);
+    assert!(entries.iter().all(|entry| entry.instance == "mock"));
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn maintenance_mutes_the_notifications_only() {
+    let (mock, dir, mut config) = setup("maintenance").await;
+    config.maintenance.file = dir.join("maintenance.json");
+    config.journal.file = Some(dir.join("journal.jsonl"));
+    let window = MaintenanceWindow::starting_now(Duration::from_secs(3600), None);
+    maintenance::save(&config.maintenance.file, &window).unwrap();
+    let mut runner = Runner::new(config.clone()).unwrap();
+
+    // The events are journaled, but not notified
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+    let journal_file = config.journal.file.as_ref().unwrap();
+    assert_eq!(journal::read(journal_file, None, None).unwrap().len(), 6);
+
+    // Once the window is over, the next events are notified
+    maintenance::clear(&config.maintenance.file).unwrap();
+    assert!(mock.advance());
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert!(messages.contains("became buyable"), "{}", messages);
+    assert!(!messages.contains(" added "), "{}", messages);
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn login_sessions_are_resumed() {
+    let (mock, dir, mut config) = setup("session").await;
+    config.login.strategies = vec![LoginMethod::Session, LoginMethod::Card];
+    let session_file = config.instances[0].session_file();
+
+    // No session yet: logged in by card, and the session is kept
+    let mut runner = Runner::new(config.clone()).unwrap();
+    runner.run_once().await.unwrap();
+    assert_eq!(mock.card_logins(), 1);
+    assert_eq!(
+        std::fs::read_to_string(&session_file).unwrap(),
+        MOCK_SESSION
+    );
+    drop(runner);
+
+    // A new process resumes the session
+    let mut runner = Runner::new(config.clone()).unwrap();
+    runner.run_once().await.unwrap();
+    assert_eq!(mock.card_logins(), 1);
+    drop(runner);
+
+    // An expired session falls back to the card
+    std::fs::write(&session_file, "BAR_SESS=expired").unwrap();
+    let mut runner = Runner::new(config).unwrap();
+    runner.run_once().await.unwrap();
+    assert_eq!(mock.card_logins(), 2);
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn slow_targets_do_not_delay_the_others() {
+    let (mock, dir, mut config) = setup("slow-target").await;
+    // A webhook accepting the connections, but never responding
+    let slow = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let slow_url = format!("http://{}/hook", slow.local_addr().unwrap());
+    config.instances[0]
+        .targets
+        .insert(0, TargetConfig::parse(&slow_url).unwrap());
+    config.notify.target_timeout_secs = 1;
+    let mut runner = Runner::new(config).unwrap();
+
+    // The other target is notified, the slow one timing out well before the request timeout
+    let started = std::time::Instant::now();
+    let result = runner.run_once().await;
+    assert!(started.elapsed() < Duration::from_secs(10));
+    assert!(
+        matches!(result, Err(NotifierError::Dispatch { undelivered: 1, .. })),
+        "{:?}",
+        result
+    );
+    let messages = mock.take_messages().concat();
+    assert_eq!(messages.matches(" added ").count(), 6, "{}", messages);
+
+    drop(slow);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn injected_failures_are_handled() {
+    let (mock, dir, mut config) = setup("chaos").await;
+    config.chaos.api_failure_rate = 1.0;
+    let mut runner = Runner::new(config.clone()).unwrap();
+
+    // Every API request fails: nothing is stored nor notified
+    let result = runner.run_once().await;
+    assert!(matches!(result, Err(NotifierError::Auth { .. })));
+    assert!(mock.take_messages().is_empty());
+    assert!(!dir.join("item_store.json").exists());
+
+    // Every webhook request fails: the items are stored, but nothing is received
+    config.chaos.api_failure_rate = 0.0;
+    config.chaos.webhook_failure_rate = 1.0;
+    drop(runner);
+    let mut runner = Runner::new(config).unwrap();
+    let result = runner.run_once().await;
+    assert!(matches!(result, Err(NotifierError::Dispatch { .. })));
+    assert!(mock.take_messages().is_empty());
+    assert!(dir.join("item_store.json").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn deadline_carries_over_the_categories() {
+    let (mock, dir, mut config) = setup("deadline").await;
+    config.run.deadline_secs = 1;
+    config.chaos.api_slow_rate = 1.0;
+    config.chaos.api_slow_ms = 400;
+    let mut runner = Runner::new(config.clone()).unwrap();
+
+    // The requests are too slow to fetch any category before the deadline
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+    let store = runner.store("mock").unwrap();
+    assert!(store.is_empty());
+    assert_eq!(store.unfetched_categories().len(), 2);
+
+    // Back to normal: every category is fetched
+    config.chaos.api_slow_rate = 0.0;
+    runner.reconfigure(config).unwrap();
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert_eq!(messages.matches(" added ").count(), 6, "{}", messages);
+    assert!(
+        runner
+            .store("mock")
+            .unwrap()
+            .unfetched_categories()
+            .is_empty()
+    );
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn unchanged_categories_are_skipped() {
+    let (mock, dir, mut config) = setup("unchanged").await;
+    config.run.skip_unchanged_categories = true;
+    let mut runner = Runner::new(config).unwrap();
+
+    runner.run_once().await.unwrap();
+    assert_eq!(mock.take_messages().concat().matches(" added ").count(), 6);
+    let store = runner.store("mock").unwrap();
+    assert!(
+        store
+            .categories()
+            .all(|(id, _)| store.category_hash(id).is_some())
+    );
+
+    // Nothing changed: the categories are skipped, nothing is notified
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+    assert_eq!(runner.store("mock").unwrap().len(), 6);
+
+    // The restocked categories are diffed again
+    assert!(mock.advance());
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert!(messages.contains("became buyable"), "{}", messages);
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn failed_categories_keep_their_items() {
+    let (mock, dir, mut config) = setup("failed-category").await;
+    config.notify.item_removed = true;
+    let mut runner = Runner::new(config).unwrap();
+    runner.run_once().await.unwrap();
+    assert_eq!(mock.take_messages().concat().matches(" added ").count(), 6);
+
+    // The items of the failing category are neither removed nor pruned
+    let (category, _) = runner.store("mock").unwrap().categories().next().unwrap();
+    mock.set_failing_category(Some(category));
+    let result = runner.run_once().await;
+    assert!(matches!(result, Err(NotifierError::Fetch { .. })));
+    let messages = mock.take_messages().concat();
+    assert!(!messages.contains("removed"), "{}", messages);
+    assert_eq!(runner.store("mock").unwrap().len(), 6);
+
+    mock.set_failing_category(None);
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn undelivered_transactions_are_notified_again() {
+    let (mock, dir, mut config) = setup("transactions").await;
+    config.transactions.enabled = true;
+    let mut runner = Runner::new(config.clone()).unwrap();
+
+    // First check: the history of the account is recorded
+    runner.run_once().await.unwrap();
+    mock.take_messages();
+    drop(runner);
+
+    // A purchase, whose notification fails
+    let item = TransactionItem::new(
+        Uuid::from_u128(2),
+        "Chouffe".to_string(),
+        1,
+        250,
+        250,
+        TransactionState::TransactionFinished,
+    );
+    mock.push_transaction(Transaction::new(
+        Uuid::from_u128(1),
+        Uuid::nil(),
+        vec![item],
+        250,
+        TransactionState::TransactionFinished,
+        1_700_000_000,
+    ));
+    config.chaos.webhook_failure_rate = 1.0;
+    let mut runner = Runner::new(config.clone()).unwrap();
+    let _ = runner.run_once().await;
+    assert!(mock.take_messages().is_empty());
+    drop(runner);
+
+    // The next check notifies it, once
+    config.chaos.webhook_failure_rate = 0.0;
+    let mut runner = Runner::new(config).unwrap();
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert!(messages.contains("New purchase on mock"), "{}", messages);
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn read_only_store_is_never_saved() {
+    let (mock, dir, mut config) = setup("read-only").await;
+    config.store.read_only = true;
+    let mut runner = Runner::new(config).unwrap();
+
+    // First check: the items are recorded without being notified
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+    assert_eq!(runner.store("mock").unwrap().len(), 6);
+    assert!(!dir.join("item_store.json").exists());
+    assert!(!dir.join("item_store.json.lock").exists());
+
+    // Second check: the changes are notified, from the store kept in memory
+    assert!(mock.advance());
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert!(messages.contains("became buyable"), "{}", messages);
+    assert!(!dir.join("item_store.json").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn digest_waits_for_its_period() {
+    let (mock, dir, mut config) = setup("digest").await;
+    config.notify.digest = Some(DigestFrequency::Hourly);
+    let clock = Arc::new(TestClock::new(
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+    ));
+    let mut runner = Runner::new(config).unwrap();
+    runner.set_clock(clock.clone());
+
+    // First check: no digest was sent yet, it is due
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert_eq!(messages.matches("3 added").count(), 2, "{}", messages);
+
+    // Second check, 10 minutes later: the changes are held until the next digest
+    assert!(mock.advance());
+    clock.advance(Duration::from_secs(10 * 60));
+    runner.run_once().await.unwrap();
+    assert!(mock.take_messages().is_empty());
+
+    // Third check, an hour after the first digest: the held changes are sent
+    clock.advance(Duration::from_secs(50 * 60));
+    runner.run_once().await.unwrap();
+    let messages = mock.take_messages().concat();
+    assert!(messages.contains("became buyable"), "{}", messages);
+
+    drop(runner);
+    std::fs::remove_dir_all(&dir).unwrap();
+}